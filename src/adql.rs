@@ -0,0 +1,650 @@
+use crate::{
+    api::query::build_cone_search_filter,
+    models::query_models::Unit,
+    spatial::regions::{box_vertices, polygon_filter},
+};
+use mongodb::bson::{doc, Bson, Document};
+use sqlparser::{
+    ast::{
+        BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments,
+        GroupByExpr, SelectItem, SetExpr, Statement, TableFactor, TopQuantity, UnaryOperator,
+        Value,
+    },
+    dialect::GenericDialect,
+    parser::Parser,
+};
+
+// a column of the SELECT clause
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectColumn {
+    // a (dotted) field of the documents
+    Field {
+        name: String,
+        path: String,
+    },
+    // DISTANCE between the position of the documents and a fixed point, in degrees
+    Distance {
+        name: String,
+        ra_field: String,
+        dec_field: String,
+        ra: f64,
+        dec: f64,
+    },
+}
+
+impl SelectColumn {
+    pub fn name(&self) -> &str {
+        match self {
+            SelectColumn::Field { name, .. } => name,
+            SelectColumn::Distance { name, .. } => name,
+        }
+    }
+}
+
+// an ADQL query translated to the pieces of a find
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdqlQuery {
+    // table of the FROM clause, e.g. ["ZTF_alerts"] or ["TAP_SCHEMA", "tables"]
+    pub table: Vec<String>,
+    pub filter: Document,
+    // None for SELECT *
+    pub columns: Option<Vec<SelectColumn>>,
+    pub sort: Option<Document>,
+    pub top: Option<i64>,
+}
+
+impl AdqlQuery {
+    // fields to read from the documents to produce the selected columns
+    pub fn projection(&self) -> Option<Document> {
+        let columns = self.columns.as_ref()?;
+        let mut projection = doc! {};
+        for column in columns {
+            match column {
+                SelectColumn::Field { path, .. } => {
+                    projection.insert(path, 1);
+                }
+                SelectColumn::Distance {
+                    ra_field,
+                    dec_field,
+                    ..
+                } => {
+                    projection.insert(ra_field, 1);
+                    projection.insert(dec_field, 1);
+                }
+            }
+        }
+        if !projection.contains_key("_id") {
+            projection.insert("_id", 0);
+        }
+        Some(projection)
+    }
+}
+
+// position of a POINT: either the columns holding it or a constant
+enum Point {
+    Columns(String, String),
+    Fixed(f64, f64),
+}
+
+struct Translator {
+    // names the columns may be qualified with (table and alias)
+    qualifiers: Vec<String>,
+}
+
+fn unsupported(what: &str, expr: &dyn std::fmt::Display) -> String {
+    format!("unsupported {}: {}", what, expr)
+}
+
+fn function_args(function: &Function) -> Result<Vec<&Expr>, String> {
+    let list = match &function.args {
+        FunctionArguments::List(list) => list,
+        FunctionArguments::None => return Ok(Vec::new()),
+        FunctionArguments::Subquery(_) => return Err(unsupported("subquery", function)),
+    };
+    let mut args = Vec::new();
+    for arg in &list.args {
+        match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => args.push(e),
+            _ => return Err(unsupported("function argument", arg)),
+        }
+    }
+    Ok(args)
+}
+
+fn function_name(function: &Function) -> String {
+    function.name.to_string().to_uppercase()
+}
+
+fn as_function(expr: &Expr) -> Option<&Function> {
+    match expr {
+        Expr::Function(f) => Some(f),
+        Expr::Nested(e) => as_function(e),
+        _ => None,
+    }
+}
+
+fn literal(expr: &Expr) -> Result<Bson, String> {
+    match expr {
+        Expr::Value(Value::Number(n, _)) => match n.parse::<i64>() {
+            Ok(i) => Ok(Bson::Int64(i)),
+            Err(_) => match n.parse::<f64>() {
+                Ok(f) => Ok(Bson::Double(f)),
+                Err(_) => Err(unsupported("number", n)),
+            },
+        },
+        Expr::Value(Value::SingleQuotedString(s)) => Ok(Bson::String(s.clone())),
+        Expr::Value(Value::Boolean(b)) => Ok(Bson::Boolean(*b)),
+        Expr::Value(Value::Null) => Ok(Bson::Null),
+        Expr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match literal(expr)? {
+            Bson::Int64(i) => Ok(Bson::Int64(-i)),
+            Bson::Double(f) => Ok(Bson::Double(-f)),
+            _ => Err(unsupported("literal", expr)),
+        },
+        Expr::UnaryOp {
+            op: UnaryOperator::Plus,
+            expr,
+        } => literal(expr),
+        Expr::Nested(e) => literal(e),
+        _ => Err(unsupported("literal", expr)),
+    }
+}
+
+fn number(expr: &Expr) -> Result<f64, String> {
+    match literal(expr)? {
+        Bson::Int64(i) => Ok(i as f64),
+        Bson::Double(f) => Ok(f),
+        _ => Err(format!("expected a number, got {}", expr)),
+    }
+}
+
+// optional leading coordinate system argument of the geometry functions
+fn skip_coordinate_system<'a>(args: &'a [&'a Expr]) -> Result<&'a [&'a Expr], String> {
+    match args.first() {
+        Some(Expr::Value(Value::SingleQuotedString(system))) => {
+            let system = system.trim().to_uppercase();
+            if system.is_empty() || system.starts_with("ICRS") {
+                Ok(&args[1..])
+            } else {
+                Err(format!(
+                    "unsupported coordinate system {}, only ICRS is supported",
+                    system
+                ))
+            }
+        }
+        _ => Ok(args),
+    }
+}
+
+impl Translator {
+    fn column(&self, expr: &Expr) -> Result<String, String> {
+        let path = match expr {
+            Expr::Identifier(ident) => ident.value.clone(),
+            Expr::CompoundIdentifier(idents) => {
+                let mut parts: Vec<&str> = idents.iter().map(|i| i.value.as_str()).collect();
+                if parts.len() > 1
+                    && self
+                        .qualifiers
+                        .iter()
+                        .any(|q| q.eq_ignore_ascii_case(parts[0]))
+                {
+                    parts.remove(0);
+                }
+                parts.join(".")
+            }
+            Expr::Nested(e) => return self.column(e),
+            _ => return Err(unsupported("column", expr)),
+        };
+        if path.is_empty() || path.split('.').any(|p| p.is_empty() || p.starts_with('$')) {
+            return Err(format!("invalid column name {}", path));
+        }
+        Ok(path)
+    }
+
+    fn point(&self, expr: &Expr) -> Result<Point, String> {
+        let function = match as_function(expr) {
+            Some(f) if function_name(f) == "POINT" => f,
+            _ => return Err(format!("expected a POINT, got {}", expr)),
+        };
+        let args = function_args(function)?;
+        let args = skip_coordinate_system(&args)?;
+        if args.len() != 2 {
+            return Err(format!(
+                "POINT takes a right ascension and a declination: {}",
+                expr
+            ));
+        }
+        match (number(args[0]), number(args[1])) {
+            (Ok(ra), Ok(dec)) => Ok(Point::Fixed(ra, dec)),
+            _ => Ok(Point::Columns(self.column(args[0])?, self.column(args[1])?)),
+        }
+    }
+
+    // filter of the documents whose position lies within a region
+    fn region(&self, expr: &Expr) -> Result<Document, String> {
+        let function = match as_function(expr) {
+            Some(f) => f,
+            None => return Err(format!("expected a region, got {}", expr)),
+        };
+        let args = function_args(function)?;
+        let args = skip_coordinate_system(&args)?;
+        match function_name(function).as_str() {
+            "CIRCLE" => {
+                // CIRCLE(ra, dec, radius) or CIRCLE(POINT(ra, dec), radius)
+                let (ra, dec, radius) = match args.len() {
+                    3 => (number(args[0])?, number(args[1])?, number(args[2])?),
+                    2 => match self.point(args[0])? {
+                        Point::Fixed(ra, dec) => (ra, dec, number(args[1])?),
+                        Point::Columns(..) => return Err(unsupported("CIRCLE", function)),
+                    },
+                    _ => return Err(unsupported("CIRCLE", function)),
+                };
+                if radius < 0.0 {
+                    return Err("CIRCLE radius must be positive".to_string());
+                }
+                Ok(build_cone_search_filter(
+                    doc! {},
+                    (ra, dec),
+                    radius,
+                    Unit::Degrees,
+                ))
+            }
+            "BOX" => {
+                if args.len() != 4 {
+                    return Err(unsupported("BOX", function));
+                }
+                let (ra, dec) = (number(args[0])?, number(args[1])?);
+                let (width, height) = (number(args[2])?, number(args[3])?);
+                if width <= 0.0 || height <= 0.0 || width >= 180.0 || height >= 180.0 {
+                    return Err(
+                        "BOX width and height must be between 0 and 180 degrees".to_string()
+                    );
+                }
+                Ok(polygon_filter(&box_vertices(ra, dec, width, height)))
+            }
+            "POLYGON" => {
+                if args.len() < 6 || args.len() % 2 != 0 {
+                    return Err("POLYGON takes at least three vertices".to_string());
+                }
+                let mut vertices = Vec::new();
+                for pair in args.chunks(2) {
+                    vertices.push((number(pair[0])?, number(pair[1])?));
+                }
+                Ok(polygon_filter(&vertices))
+            }
+            _ => Err(unsupported("region", function)),
+        }
+    }
+
+    // CONTAINS(POINT(ra, dec), <region>) = 1
+    fn contains(&self, function: &Function, value: &Expr) -> Result<Document, String> {
+        if number(value) != Ok(1.0) {
+            return Err(format!("only {} = 1 is supported", function));
+        }
+        let args = function_args(function)?;
+        if args.len() != 2 {
+            return Err(unsupported("CONTAINS", function));
+        }
+        match self.point(args[0])? {
+            Point::Columns(..) => self.region(args[1]),
+            Point::Fixed(..) => Err(format!(
+                "the first argument of CONTAINS must be the position columns: {}",
+                function
+            )),
+        }
+    }
+
+    // the fixed point a DISTANCE is measured from, and the position columns
+    fn distance_point(&self, function: &Function) -> Result<(String, String, f64, f64), String> {
+        let args = function_args(function)?;
+        if args.len() != 2 {
+            return Err(unsupported("DISTANCE", function));
+        }
+        match (self.point(args[0])?, self.point(args[1])?) {
+            (Point::Columns(ra_field, dec_field), Point::Fixed(ra, dec))
+            | (Point::Fixed(ra, dec), Point::Columns(ra_field, dec_field)) => {
+                Ok((ra_field, dec_field, ra, dec))
+            }
+            _ => Err(format!(
+                "DISTANCE must be between the position columns and a fixed point: {}",
+                function
+            )),
+        }
+    }
+
+    // DISTANCE(POINT(ra, dec), POINT(ra0, dec0)) < radius, as a cone search
+    fn distance(
+        &self,
+        function: &Function,
+        op: &BinaryOperator,
+        value: &Expr,
+    ) -> Result<Document, String> {
+        if !matches!(op, BinaryOperator::Lt | BinaryOperator::LtEq) {
+            return Err(format!("only {} < radius is supported", function));
+        }
+        let (_, _, ra, dec) = self.distance_point(function)?;
+        let radius = number(value)?;
+        Ok(build_cone_search_filter(
+            doc! {},
+            (ra, dec),
+            radius,
+            Unit::Degrees,
+        ))
+    }
+
+    fn comparison(
+        &self,
+        left: &Expr,
+        op: &BinaryOperator,
+        right: &Expr,
+    ) -> Result<Document, String> {
+        // spatial functions, on either side
+        for (function_side, other, op) in [(left, right, op.clone()), (right, left, flip(op))] {
+            if let Some(function) = as_function(function_side) {
+                return match function_name(function).as_str() {
+                    "CONTAINS" | "INTERSECTS" if op == BinaryOperator::Eq => {
+                        self.contains(function, other)
+                    }
+                    "DISTANCE" => self.distance(function, &op, other),
+                    _ => Err(unsupported(
+                        "condition",
+                        &format!("{} {} {}", left, op, right),
+                    )),
+                };
+            }
+        }
+
+        let (path, op, value) = match (self.column(left), self.column(right)) {
+            (Ok(path), Err(_)) => (path, op.clone(), literal(right)?),
+            (Err(_), Ok(path)) => (path, flip(op), literal(left)?),
+            _ => {
+                return Err(unsupported(
+                    "condition, comparisons are between a column and a value",
+                    &format!("{} {} {}", left, op, right),
+                ));
+            }
+        };
+        let operator = match op {
+            BinaryOperator::Eq => "$eq",
+            BinaryOperator::NotEq => "$ne",
+            BinaryOperator::Lt => "$lt",
+            BinaryOperator::LtEq => "$lte",
+            BinaryOperator::Gt => "$gt",
+            BinaryOperator::GtEq => "$gte",
+            _ => return Err(unsupported("operator", &op)),
+        };
+        Ok(doc! { path: { operator: value } })
+    }
+
+    fn condition(&self, expr: &Expr) -> Result<Document, String> {
+        match expr {
+            Expr::Nested(e) => self.condition(e),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => Ok(doc! { "$and": [self.condition(left)?, self.condition(right)?] }),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Or,
+                right,
+            } => Ok(doc! { "$or": [self.condition(left)?, self.condition(right)?] }),
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr,
+            } => Ok(doc! { "$nor": [self.condition(expr)?] }),
+            Expr::BinaryOp { left, op, right } => self.comparison(left, op, right),
+            Expr::IsNull(e) => Ok(doc! { self.column(e)?: Bson::Null }),
+            Expr::IsNotNull(e) => Ok(doc! { self.column(e)?: { "$ne": Bson::Null } }),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let between = doc! {
+                    self.column(expr)?: { "$gte": literal(low)?, "$lte": literal(high)? }
+                };
+                if *negated {
+                    Ok(doc! { "$nor": [between] })
+                } else {
+                    Ok(between)
+                }
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let values = list
+                    .iter()
+                    .map(literal)
+                    .collect::<Result<Vec<Bson>, String>>()?;
+                let operator = if *negated { "$nin" } else { "$in" };
+                Ok(doc! { self.column(expr)?: { operator: values } })
+            }
+            Expr::Like {
+                negated,
+                any: false,
+                expr,
+                pattern,
+                escape_char,
+            } => self.like(expr, pattern, escape_char.as_deref(), *negated, ""),
+            Expr::ILike {
+                negated,
+                any: false,
+                expr,
+                pattern,
+                escape_char,
+            } => self.like(expr, pattern, escape_char.as_deref(), *negated, "i"),
+            _ => Err(unsupported("condition", expr)),
+        }
+    }
+
+    fn like(
+        &self,
+        expr: &Expr,
+        pattern: &Expr,
+        escape_char: Option<&str>,
+        negated: bool,
+        options: &str,
+    ) -> Result<Document, String> {
+        let pattern = match literal(pattern)? {
+            Bson::String(p) => p,
+            _ => return Err(format!("LIKE pattern must be a string: {}", pattern)),
+        };
+        let escape = escape_char.and_then(|e| e.chars().next());
+        let mut regex = String::from("^");
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                c if Some(c) == escape => {
+                    if let Some(escaped) = chars.next() {
+                        regex.push_str(&regex_escape(escaped));
+                    }
+                }
+                '%' => regex.push_str(".*"),
+                '_' => regex.push('.'),
+                c => regex.push_str(&regex_escape(c)),
+            }
+        }
+        regex.push('$');
+        let matcher = doc! { "$regex": regex, "$options": options };
+        if negated {
+            Ok(doc! { self.column(expr)?: { "$not": matcher } })
+        } else {
+            Ok(doc! { self.column(expr)?: matcher })
+        }
+    }
+
+    fn select_column(&self, expr: &Expr, alias: Option<&str>) -> Result<SelectColumn, String> {
+        if let Some(function) = as_function(expr) {
+            if function_name(function) == "DISTANCE" {
+                let (ra_field, dec_field, ra, dec) = self.distance_point(function)?;
+                return Ok(SelectColumn::Distance {
+                    name: alias.unwrap_or("distance").to_string(),
+                    ra_field,
+                    dec_field,
+                    ra,
+                    dec,
+                });
+            }
+            return Err(unsupported("function in SELECT", function));
+        }
+        let path = self.column(expr)?;
+        Ok(SelectColumn::Field {
+            name: alias.unwrap_or(&path).to_string(),
+            path,
+        })
+    }
+}
+
+fn regex_escape(c: char) -> String {
+    if "\\.^$|?*+()[]{}".contains(c) {
+        format!("\\{}", c)
+    } else {
+        c.to_string()
+    }
+}
+
+// the operator with its operands swapped, e.g. 5 < x is x > 5
+fn flip(op: &BinaryOperator) -> BinaryOperator {
+    match op {
+        BinaryOperator::Lt => BinaryOperator::Gt,
+        BinaryOperator::LtEq => BinaryOperator::GtEq,
+        BinaryOperator::Gt => BinaryOperator::Lt,
+        BinaryOperator::GtEq => BinaryOperator::LtEq,
+        op => op.clone(),
+    }
+}
+
+fn top_value(expr: &Expr) -> Result<i64, String> {
+    match literal(expr)? {
+        Bson::Int64(n) if n >= 0 => Ok(n),
+        _ => Err(format!("TOP must be a positive integer, got {}", expr)),
+    }
+}
+
+// translates the supported subset of ADQL: a single table SELECT with TOP,
+// WHERE (comparisons, BETWEEN, IN, LIKE, IS NULL, CONTAINS with CIRCLE, BOX
+// or POLYGON, DISTANCE) and ORDER BY
+pub fn parse_adql(adql: &str) -> Result<AdqlQuery, String> {
+    let statements = match Parser::parse_sql(&GenericDialect {}, adql) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("invalid ADQL: {}", e)),
+    };
+    let query = match statements.as_slice() {
+        [Statement::Query(query)] => query,
+        _ => return Err("expected a single SELECT statement".to_string()),
+    };
+    if query.with.is_some() || query.offset.is_some() || query.fetch.is_some() {
+        return Err("WITH, OFFSET and FETCH are not supported".to_string());
+    }
+    let select = match query.body.as_ref() {
+        SetExpr::Select(select) => select,
+        _ => return Err("expected a single SELECT statement".to_string()),
+    };
+    if select.distinct.is_some() {
+        return Err("DISTINCT is not supported".to_string());
+    }
+    match &select.group_by {
+        GroupByExpr::Expressions(exprs, _) if exprs.is_empty() => {}
+        _ => return Err("GROUP BY is not supported".to_string()),
+    }
+    if select.having.is_some() {
+        return Err("HAVING is not supported".to_string());
+    }
+
+    // FROM a single table, with an optional alias
+    let (table, alias) = match select.from.as_slice() {
+        [from] if from.joins.is_empty() => match &from.relation {
+            TableFactor::Table { name, alias, .. } => (
+                name.0
+                    .iter()
+                    .map(|i| i.value.clone())
+                    .collect::<Vec<String>>(),
+                alias.as_ref().map(|a| a.name.value.clone()),
+            ),
+            _ => return Err(unsupported("FROM", &from.relation)),
+        },
+        [] => return Err("FROM is required".to_string()),
+        _ => return Err("queries must be on a single table, JOIN is not supported".to_string()),
+    };
+    let mut qualifiers = vec![table.join(".")];
+    qualifiers.extend(table.last().cloned());
+    qualifiers.extend(alias);
+    let translator = Translator { qualifiers };
+
+    let mut columns = Some(Vec::new());
+    for item in &select.projection {
+        match item {
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => columns = None,
+            SelectItem::UnnamedExpr(expr) => {
+                if let Some(c) = columns.as_mut() {
+                    c.push(translator.select_column(expr, None)?);
+                }
+            }
+            SelectItem::ExprWithAlias { expr, alias } => {
+                if let Some(c) = columns.as_mut() {
+                    c.push(translator.select_column(expr, Some(&alias.value))?);
+                }
+            }
+        }
+    }
+    if columns.is_none() && select.projection.len() > 1 {
+        return Err("* cannot be combined with other columns".to_string());
+    }
+
+    let filter = match &select.selection {
+        Some(expr) => translator.condition(expr)?,
+        None => doc! {},
+    };
+
+    let mut sort: Option<Document> = None;
+    if let Some(order_by) = &query.order_by {
+        let mut sort_doc = doc! {};
+        for order in &order_by.exprs {
+            // columns may be referred to by their alias
+            let selected = columns.as_ref().and_then(|c| {
+                c.iter().find(|column| match &order.expr {
+                    Expr::Identifier(ident) => column.name() == ident.value,
+                    _ => false,
+                })
+            });
+            let path = match selected {
+                Some(SelectColumn::Field { path, .. }) => path.clone(),
+                Some(SelectColumn::Distance { .. }) => {
+                    return Err("ORDER BY a DISTANCE is not supported".to_string());
+                }
+                None => translator.column(&order.expr)?,
+            };
+            sort_doc.insert(path, if order.asc == Some(false) { -1 } else { 1 });
+        }
+        sort = Some(sort_doc);
+    }
+
+    let top = match (&select.top, &query.limit) {
+        (Some(top), _) => {
+            if top.percent || top.with_ties {
+                return Err("TOP PERCENT and WITH TIES are not supported".to_string());
+            }
+            match &top.quantity {
+                Some(TopQuantity::Constant(n)) => Some(*n as i64),
+                Some(TopQuantity::Expr(expr)) => Some(top_value(expr)?),
+                None => None,
+            }
+        }
+        (None, Some(limit)) => Some(top_value(limit)?),
+        (None, None) => None,
+    };
+
+    Ok(AdqlQuery {
+        table,
+        filter,
+        columns,
+        sort,
+        top,
+    })
+}
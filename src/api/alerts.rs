@@ -1,44 +1,93 @@
-use crate::models::response;
-use actix_web::{get, web, HttpResponse};
+use crate::{
+    api::permissions::DataRights,
+    conf::AppConfig,
+    formats::{
+        fits::{Image, FITS_CONTENT_TYPE},
+        image::{render, write_png, PNG_CONTENT_TYPE},
+        table::Table,
+        table_response, OutputFormat,
+    },
+    models::{
+        alert_models::{AlertParams, CutoutParams, ObjectParams, ObjectView},
+        auth_models::AuthenticatedUser,
+        response,
+    },
+    surveys::{self, Survey},
+};
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse};
+use flate2::read::GzDecoder;
 use futures::TryStreamExt;
 use mongodb::{
-    bson::{doc, Document},
+    bson::{doc, Bson, Document},
     Client, Collection,
 };
+use std::io::Read;
 
-const DB_NAME: &str = "boom";
+// parts of an alert that are only returned on request
+const ALERT_INCLUDES: [&str; 2] = ["cutouts", "prv_candidates"];
+// parts of an object that may be returned, and those returned by default
+const OBJECT_INCLUDES: [&str; 4] = ["cutouts", "prv_candidates", "cross_matches", "fp_hists"];
+const DEFAULT_OBJECT_INCLUDES: [&str; 3] = ["cutouts", "prv_candidates", "cross_matches"];
 
+// the newest alert of an object the user may see, with the parts of its aux
+// entry that are asked for. Objects being ingested may lack some of them,
+// which are then reported as missing.
 #[get("/alerts/{survey_name}/get_object/{object_id}")]
 pub async fn get_object(
     client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
     path: web::Path<(String, String)>,
+    params: web::Query<ObjectParams>,
 ) -> HttpResponse {
     let (survey_name, object_id) = path.into_inner();
-    let survey_name = survey_name.to_uppercase(); // (TEMP) to match with "ZTF"
-    let db = client.database(DB_NAME);
-    let alerts_collection: Collection<Document> = db.collection(&format!("{}_alerts", survey_name));
-    let aux_collection: Collection<Document> =
-        db.collection(&format!("{}_alerts_aux", survey_name));
+    let survey = match surveys::survey(&survey_name) {
+        Ok(s) => s,
+        Err(e) => return response::bad_request(&e),
+    };
+    let include = match &params.include {
+        Some(_) => match parse_include(&params.include, &OBJECT_INCLUDES) {
+            Ok(i) => i,
+            Err(e) => return response::bad_request(&e),
+        },
+        None => DEFAULT_OBJECT_INCLUDES.map(String::from).to_vec(),
+    };
+    // tabular formats return the light curve, one row per previous candidate
+    let format = OutputFormat::from_request(&req);
+    let included = |part: &str| include.iter().any(|i| i == part);
+
+    let db = client.database(&config.database.name);
+    let alerts_catalog = survey.alerts_collection();
+    let aux_catalog = survey.aux_collection();
+    let alerts_collection: Collection<Document> = db.collection(&alerts_catalog);
+    let aux_collection: Collection<Document> = db.collection(&aux_catalog);
+    let alerts_rights = DataRights::new(&config, &alerts_catalog, &user);
+    let aux_rights = DataRights::new(&config, &aux_catalog, &user);
+
     // find options for getting most recent alert from alerts collection
+    let mut projection = doc! {
+        "_id": 1,
+        survey.candidate_field(): 1,
+    };
+    if included("cutouts") {
+        for (_, field) in survey.cutout_fields() {
+            projection.insert(*field, 1);
+        }
+    }
     let find_options_recent = mongodb::options::FindOptions::builder()
         .sort(doc! {
-            "candidate.jd": -1,
-        })
-        .projection(doc! {
-            "_id": 1,
-            "candidate": 1,
-            "cutoutScience": 1,
-            "cutoutTemplate": 1,
-            "cutoutDifference": 1,
+            survey.time_field(): -1,
         })
+        .projection(projection)
         .limit(1)
         .build();
 
     // get the most recent alert for the object
     let mut alert_cursor = match alerts_collection
-        .find(doc! {
-            "objectId": object_id.clone(),
-        })
+        .find(alerts_rights.restrict_filter(doc! {
+            survey.object_id_field(): object_id.clone(),
+        }))
         .with_options(find_options_recent)
         .await
     {
@@ -48,24 +97,31 @@ pub async fn get_object(
         }
     };
     let newest_alert = match alert_cursor.try_next().await {
-        Ok(Some(alert)) => alert,
-        Ok(None) => {
-            return response::ok(
-                &format!("no object found with id {}", object_id),
-                serde_json::Value::Null,
-            );
-        }
+        Ok(alert) => alert,
         Err(error) => {
             return response::internal_error(&format!("error getting documents: {}", error));
         }
     };
+    // without an alert the user may see, the aux entry would still reveal
+    // that the object exists
+    if newest_alert.is_none() {
+        return response::not_found(&format!("no object found with id {}", object_id));
+    }
 
+    // restricted arrays are trimmed to the user's program ids
+    let mut projection_aux = doc! { "_id": 1 };
+    for part in ["prv_candidates", "cross_matches", "fp_hists"] {
+        if included(part) || (part == "prv_candidates" && format.is_tabular()) {
+            projection_aux.insert(part, 1);
+        }
+    }
+    for (field, expression) in aux_rights.array_expressions() {
+        if projection_aux.contains_key(&field) {
+            projection_aux.insert(field, expression);
+        }
+    }
     let find_options_aux = mongodb::options::FindOneOptions::builder()
-        .projection(doc! {
-            "_id": 0,
-            "prv_candidates": 1,
-            "cross_matches": 1,
-        })
+        .projection(projection_aux)
         .build();
 
     // get crossmatches and light curve data from aux collection
@@ -76,47 +132,299 @@ pub async fn get_object(
         .with_options(find_options_aux)
         .await
     {
-        Ok(entry) => match entry {
-            Some(doc) => doc,
-            None => {
-                return response::ok("no aux entry found", serde_json::Value::Null);
-            }
-        },
+        Ok(entry) => entry,
         Err(error) => {
             return response::internal_error(&format!("error getting documents: {}", error));
         }
     };
 
-    let mut candidate = doc! {};
-    // organize response
-    candidate.insert("objectId", object_id.clone());
-    candidate.insert(
-        "alert_metadata",
-        newest_alert.get_document("candidate").unwrap(),
-    );
-    candidate.insert(
-        "cutoutScience",
-        newest_alert.get_document("cutoutScience").unwrap(),
-    );
-    candidate.insert(
-        "cutoutTemplate",
-        newest_alert.get_document("cutoutTemplate").unwrap(),
-    );
-    candidate.insert(
-        "cutoutDifference",
-        newest_alert.get_document("cutoutDifference").unwrap(),
-    );
-    candidate.insert(
-        "prv_candidates",
-        aux_entry.get_array("prv_candidates").unwrap(),
-    );
-    candidate.insert(
-        "cross_matches",
-        aux_entry.get_document("cross_matches").unwrap(),
+    if format.is_tabular() {
+        let prv_candidates: Vec<Document> = match aux_entry
+            .as_ref()
+            .and_then(|entry| entry.get_array("prv_candidates").ok())
+        {
+            Some(p) => p.iter().filter_map(|c| c.as_document().cloned()).collect(),
+            None => Vec::new(),
+        };
+        let units = config
+            .catalog(&aux_catalog)
+            .units
+            .into_iter()
+            .filter_map(|(field, unit)| {
+                field
+                    .strip_prefix("prv_candidates.")
+                    .map(|f| (f.to_string(), unit))
+            })
+            .collect();
+        let table = Table::from_documents(&object_id, &prv_candidates, &units);
+        return table_response(format, &table, None);
+    }
+
+    let view = ObjectView::new(
+        survey,
+        &object_id,
+        newest_alert.as_ref(),
+        aux_entry.as_ref(),
+        &include,
     );
+    let message = if view.missing.is_empty() {
+        format!("object found with object_id: {}", object_id)
+    } else {
+        format!(
+            "object found with object_id: {}, missing {}",
+            object_id,
+            view.missing.join(", ")
+        )
+    };
+    response::ok(&message, serde_json::json!(view))
+}
+
+// the FITS file of a cutout, stored as binary data or as a document with a
+// `stampData` field, gzip compressed or not
+pub fn cutout_fits(cutout: &Bson) -> Result<Vec<u8>, String> {
+    let stamp = match cutout {
+        Bson::Binary(binary) => &binary.bytes,
+        Bson::Document(document) => match document.get("stampData") {
+            Some(Bson::Binary(binary)) => &binary.bytes,
+            _ => return Err("the cutout has no stamp data".to_string()),
+        },
+        _ => return Err("the cutout has no stamp data".to_string()),
+    };
+    if !stamp.starts_with(&[0x1f, 0x8b]) {
+        return Ok(stamp.clone());
+    }
+    let mut fits = Vec::new();
+    match GzDecoder::new(stamp.as_slice()).read_to_end(&mut fits) {
+        Ok(_) => Ok(fits),
+        Err(e) => Err(format!("invalid cutout: {}", e)),
+    }
+}
+
+// a cutout of an alert, as the FITS file or rendered as a PNG image
+#[get("/alerts/{survey_name}/candid/{candid}/cutout/{kind}")]
+pub async fn get_cutout(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    path: web::Path<(String, String, String)>,
+    params: web::Query<CutoutParams>,
+) -> HttpResponse {
+    let (survey_name, candid, kind) = path.into_inner();
+    let survey = match surveys::survey(&survey_name) {
+        Ok(s) => s,
+        Err(e) => return response::bad_request(&e),
+    };
+    let candid: i64 = match candid.parse() {
+        Ok(c) => c,
+        Err(_) => return response::bad_request(&format!("invalid candid {}", candid)),
+    };
+    let field = match survey.cutout_field(&kind) {
+        Some(field) => field,
+        None => {
+            let kinds: Vec<&str> = survey.cutout_fields().iter().map(|(k, _)| *k).collect();
+            return response::bad_request(&format!(
+                "unknown cutout {}, expected one of {}",
+                kind,
+                kinds.join(", ")
+            ));
+        }
+    };
+    let png = match params.format.as_deref().map(str::to_lowercase).as_deref() {
+        None | Some("png") => true,
+        Some("fits") => false,
+        Some(format) => {
+            return response::bad_request(&format!(
+                "unknown format {}, expected png or fits",
+                format
+            ));
+        }
+    };
 
-    return response::ok(
-        &format!("object found with object_id: {}", object_id),
-        serde_json::json!(candidate),
+    let alerts_catalog = survey.alerts_collection();
+    let alerts_collection: Collection<Document> = client
+        .database(&config.database.name)
+        .collection(&alerts_catalog);
+    let alerts_rights = DataRights::new(&config, &alerts_catalog, &user);
+    let find_options = mongodb::options::FindOneOptions::builder()
+        .projection(doc! { "_id": 0, field: 1 })
+        .build();
+    let alert = match alerts_collection
+        .find_one(alerts_rights.restrict_filter(doc! { survey.alert_id_field(): candid }))
+        .with_options(find_options)
+        .await
+    {
+        Ok(Some(alert)) => alert,
+        Ok(None) => return response::not_found(&format!("no alert found with candid {}", candid)),
+        Err(error) => {
+            return response::internal_error(&format!("error getting documents: {}", error));
+        }
+    };
+    let fits = match alert.get(field).map(cutout_fits) {
+        Some(Ok(fits)) => fits,
+        Some(Err(e)) => return response::internal_error(&e),
+        None => {
+            return response::not_found(&format!("alert {} has no {} cutout", candid, kind));
+        }
+    };
+
+    let filename = format!("{}_{}", candid, kind);
+    if !png {
+        return HttpResponse::Ok()
+            .content_type(FITS_CONTENT_TYPE)
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}.fits\"", filename),
+            ))
+            .body(fits);
+    }
+    let image = match Image::from_fits(&fits) {
+        Ok(image) => image,
+        Err(e) => return response::internal_error(&format!("invalid cutout: {}", e)),
+    };
+    let rgba = render(
+        &image,
+        params.stretch.unwrap_or_default(),
+        params.colormap.unwrap_or_default(),
     );
+    HttpResponse::Ok()
+        .content_type(PNG_CONTENT_TYPE)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"{}.png\"", filename),
+        ))
+        .body(write_png(image.width, image.height, &rgba))
+}
+
+// the parts named in a comma separated `include` parameter
+pub fn parse_include(include: &Option<String>, allowed: &[&str]) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    for part in include
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        if !allowed.contains(&part) {
+            return Err(format!(
+                "unknown include {}, expected one of {}",
+                part,
+                allowed.join(", ")
+            ));
+        }
+        parts.push(part.to_string());
+    }
+    Ok(parts)
+}
+
+// pipeline returning the alert with a given candid, without its cutouts
+// unless included. The previous candidates of its object are those up to
+// (and including) the alert, trimmed to the program ids of the user as in
+// the filter test pipelines.
+pub fn alert_pipeline(
+    config: &AppConfig,
+    survey: &dyn Survey,
+    user: &AuthenticatedUser,
+    candid: i64,
+    include: &[String],
+) -> Vec<Document> {
+    let aux_catalog = survey.aux_collection();
+    let alerts_rights = DataRights::new(config, &survey.alerts_collection(), user);
+    let aux_rights = DataRights::new(config, &aux_catalog, user);
+    let mut pipeline = vec![
+        doc! { "$match": alerts_rights.restrict_filter(doc! { survey.alert_id_field(): candid }) },
+        doc! { "$limit": 1 },
+    ];
+    if !include.iter().any(|part| part == "cutouts") {
+        let mut projection = doc! {};
+        for (_, field) in survey.cutout_fields() {
+            projection.insert(*field, 0);
+        }
+        pipeline.push(doc! { "$project": projection });
+    }
+    if include.iter().any(|part| part == "prv_candidates") {
+        let mut conditions = vec![Bson::Document(doc! {
+            // only datapoints up to (and including) the alert
+            "$lte": [
+                format!("$$x.{}", survey.history_time_field()),
+                format!("${}", survey.time_field())
+            ]
+        })];
+        if let Some((_, programid)) = aux_rights
+            .arrays
+            .iter()
+            .find(|(array, _)| array == "prv_candidates")
+        {
+            conditions.push(Bson::Document(doc! {
+                "$in": [format!("$$x.{}", programid), &aux_rights.programids]
+            }));
+        }
+        pipeline.push(doc! {
+            "$lookup": {
+                "from": aux_catalog,
+                "localField": survey.object_id_field(),
+                "foreignField": "_id",
+                "as": "aux"
+            }
+        });
+        pipeline.push(doc! {
+            "$set": {
+                "prv_candidates": {
+                    "$filter": {
+                        "input": {
+                            "$ifNull": [{ "$arrayElemAt": ["$aux.prv_candidates", 0] }, []]
+                        },
+                        "as": "x",
+                        "cond": { "$and": conditions }
+                    }
+                }
+            }
+        });
+        pipeline.push(doc! { "$project": { "aux": 0 } });
+    }
+    pipeline
+}
+
+// an alert by candid, optionally with its cutouts and the previous candidates
+// of its object as of the alert
+#[get("/alerts/{survey_name}/candid/{candid}")]
+pub async fn get_alert(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    path: web::Path<(String, String)>,
+    params: web::Query<AlertParams>,
+) -> HttpResponse {
+    let (survey_name, candid) = path.into_inner();
+    let survey = match surveys::survey(&survey_name) {
+        Ok(s) => s,
+        Err(e) => return response::bad_request(&e),
+    };
+    let candid: i64 = match candid.parse() {
+        Ok(c) => c,
+        Err(_) => return response::bad_request(&format!("invalid candid {}", candid)),
+    };
+    let include = match parse_include(&params.include, &ALERT_INCLUDES) {
+        Ok(i) => i,
+        Err(e) => return response::bad_request(&e),
+    };
+
+    let alerts_collection: Collection<Document> = client
+        .database(&config.database.name)
+        .collection(&survey.alerts_collection());
+    let pipeline = alert_pipeline(&config, survey, &user, candid, &include);
+    let mut cursor = match alerts_collection.aggregate(pipeline).await {
+        Ok(cursor) => cursor,
+        Err(error) => {
+            return response::internal_error(&format!("error getting documents: {}", error));
+        }
+    };
+    match cursor.try_next().await {
+        Ok(Some(alert)) => response::ok(
+            &format!("alert found with candid: {}", candid),
+            serde_json::json!(alert),
+        ),
+        Ok(None) => response::not_found(&format!("no alert found with candid {}", candid)),
+        Err(error) => response::internal_error(&format!("error getting documents: {}", error)),
+    }
 }
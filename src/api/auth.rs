@@ -0,0 +1,185 @@
+use crate::{
+    conf::{AppConfig, AuthConfig},
+    models::{
+        auth_models::{AuthenticatedUser, Claims, User},
+        response,
+    },
+};
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error, HttpMessage, HttpResponse,
+};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use mongodb::{
+    bson::{doc, Document},
+    Client, Collection,
+};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub enum AuthError {
+    Unauthorized(String),
+    Internal(String),
+}
+
+impl AuthError {
+    pub fn to_response(&self) -> HttpResponse {
+        match self {
+            AuthError::Unauthorized(message) => response::unauthorized(message),
+            AuthError::Internal(message) => response::internal_error(message),
+        }
+    }
+}
+
+// hex encoded sha256 hash of an API token, as stored in the users collection
+pub fn hash_api_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// creates a signed JWT for a user, valid for auth.token_expiration_secs
+pub fn create_jwt(
+    auth_config: &AuthConfig,
+    username: &str,
+    groups: Vec<String>,
+    programids: Vec<i32>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let claims = Claims {
+        sub: username.to_string(),
+        iat: now,
+        exp: now + auth_config.token_expiration_secs,
+        groups,
+        programids,
+    };
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(auth_config.secret_key.as_bytes()),
+    )
+}
+
+pub fn validate_jwt(auth_config: &AuthConfig, token: &str) -> Result<AuthenticatedUser, AuthError> {
+    match decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(auth_config.secret_key.as_bytes()),
+        &Validation::default(),
+    ) {
+        Ok(data) => Ok(AuthenticatedUser::from(data.claims)),
+        Err(e) => Err(AuthError::Unauthorized(format!("invalid token: {}", e))),
+    }
+}
+
+// looks up the owner of a long-lived API token in the users collection
+pub async fn validate_api_token(
+    client: &Client,
+    config: &AppConfig,
+    token: &str,
+) -> Result<AuthenticatedUser, AuthError> {
+    let collection: Collection<Document> = client
+        .database(&config.database.name)
+        .collection(&config.auth.users_collection);
+    let user_doc = match collection
+        .find_one(doc! { "api_tokens.hash": hash_api_token(token) })
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(AuthError::Unauthorized("invalid token".to_string())),
+        Err(e) => {
+            return Err(AuthError::Internal(format!(
+                "error validating token: {}",
+                e
+            )));
+        }
+    };
+    match mongodb::bson::from_document::<User>(user_doc) {
+        Ok(user) => Ok(AuthenticatedUser {
+            username: user.username,
+            groups: user.groups,
+            programids: user.programids,
+        }),
+        Err(e) => Err(AuthError::Internal(format!("malformed user entry: {}", e))),
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") || token.trim().is_empty() {
+        return None;
+    }
+    Some(token.trim().to_string())
+}
+
+// paths open to requests without a token, see AuthConfig::anonymous_paths
+fn is_anonymous_path(config: &AppConfig, path: &str) -> bool {
+    config
+        .auth
+        .anonymous_paths
+        .iter()
+        .any(|prefix| path.starts_with(prefix.as_str()))
+}
+
+// middleware validating the bearer token of every request. JWTs are verified
+// with the configured key, anything else is treated as an API token. The
+// authenticated user is attached to the request extensions.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let config = match req.app_data::<web::Data<AppConfig>>() {
+        Some(c) => c.clone(),
+        None => {
+            return Ok(req
+                .into_response(response::internal_error("api configuration missing"))
+                .map_into_right_body());
+        }
+    };
+    let token = match bearer_token(&req) {
+        Some(t) => t,
+        None if is_anonymous_path(&config, req.path()) => {
+            let user = AuthenticatedUser {
+                username: "anonymous".to_string(),
+                groups: vec![],
+                programids: config.auth.default_programids.clone(),
+            };
+            req.extensions_mut().insert(user);
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body);
+        }
+        None => {
+            return Ok(req
+                .into_response(response::unauthorized("missing bearer token"))
+                .map_into_right_body());
+        }
+    };
+
+    let user = if token.contains('.') {
+        validate_jwt(&config.auth, &token)
+    } else {
+        match req.app_data::<web::Data<Client>>() {
+            Some(client) => validate_api_token(client, &config, &token).await,
+            None => Err(AuthError::Internal("database client missing".to_string())),
+        }
+    };
+    match user {
+        Ok(mut user) => {
+            user.programids = config.auth.resolve_programids(&user);
+            req.extensions_mut().insert(user);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Err(e) => Ok(req.into_response(e.to_response()).map_into_right_body()),
+    }
+}
@@ -0,0 +1,357 @@
+// crossmatch of an uploaded target list (CSV or VOTable) with a catalog: the
+// closest match of each target within a radius, joined to its row. Large
+// lists are run as query jobs, their rows being stored like job results.
+use crate::{
+    api::{
+        jobs::{submit_crossmatch, JobRunner},
+        permissions::{count_with_rights, DataRights},
+        query::build_cone_search_filter,
+        validation::{validate_document, validate_optional, QUERY_RULES},
+    },
+    conf::AppConfig,
+    formats::{
+        csv::read_csv,
+        ndjson::{ndjson_response, Summary},
+        table::{Table, TextTable},
+        table_response,
+        votable::read_votable,
+        OutputFormat, CSV_CONTENT_TYPE,
+    },
+    models::{
+        auth_models::AuthenticatedUser,
+        query_models::{CrossmatchParams, QueryKwargs, Unit},
+        response,
+    },
+    spatial::{
+        coordinates::{Coordinates, Frame},
+        degrees_from, projection_with_position, Separation, POSITION_FIELD, SEPARATION_FIELD,
+    },
+};
+use actix_web::{post, web, HttpMessage, HttpRequest, HttpResponse};
+use futures::{stream, StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::AggregateOptions,
+    Client, Collection,
+};
+use std::{collections::HashMap, time::Duration};
+
+// targets crossmatched by a single request, larger lists must run as jobs
+pub const MAX_SYNC_TARGETS: usize = 5_000;
+pub const MAX_TARGETS: usize = 100_000;
+// cone searches running at once for a crossmatch
+const CONCURRENCY: usize = 8;
+
+// a target as a document, with its position in ICRS degrees
+pub type Target = (Document, (f64, f64));
+
+// meters per radian of the sphere $geoNear measures GeoJSON distances on
+const EARTH_RADIUS_METERS: f64 = 6_378_100.0;
+
+// a crossmatch ready to run
+pub(crate) struct Crossmatch {
+    pub(crate) catalog: String,
+    collection: Collection<Document>,
+    filter: Document,
+    radius: f64,
+    unit: Unit,
+    projection: Option<Document>,
+    max_time: Option<Duration>,
+    rights: DataRights,
+    separation: Separation,
+    targets: Vec<Target>,
+}
+
+impl Crossmatch {
+    // the closest match within the radius, read with $geoNear. Its query
+    // holds the user filter unless restricted arrays must be trimmed first
+    async fn nearest(&self, center: (f64, f64)) -> Result<Option<Document>, mongodb::error::Error> {
+        let trim_first = !self.rights.arrays.is_empty();
+        let query = if trim_first {
+            self.rights.restrict_filter(doc! {})
+        } else {
+            self.rights.restrict_filter(self.filter.clone())
+        };
+        let max_distance = degrees_from(self.radius, &self.unit).to_radians() * EARTH_RADIUS_METERS;
+        let mut pipeline = vec![doc! {
+            "$geoNear": {
+                "near": { "type": "Point", "coordinates": [center.0 - 180.0, center.1] },
+                "key": POSITION_FIELD,
+                // replaced by the separation in the unit of the crossmatch
+                "distanceField": SEPARATION_FIELD,
+                "maxDistance": max_distance,
+                "spherical": true,
+                "query": query,
+            }
+        }];
+        pipeline.extend(self.rights.pipeline_stages());
+        if trim_first && !self.filter.is_empty() {
+            pipeline.push(doc! { "$match": self.filter.clone() });
+        }
+        pipeline.push(doc! { "$limit": 1 });
+        if let Some(projection) = &self.projection {
+            pipeline.push(doc! { "$project": projection });
+        }
+        let options = AggregateOptions::builder().max_time(self.max_time).build();
+        let mut cursor = self
+            .collection
+            .aggregate(pipeline)
+            .with_options(options)
+            .await?;
+        cursor.try_next().await
+    }
+
+    // the target, its closest match (null if none) with their separation, and
+    // the number of matches within the radius
+    async fn join(&self, target: &Document, center: (f64, f64)) -> Result<Document, String> {
+        let nearest = async {
+            self.nearest(center)
+                .await
+                .map_err(|e| format!("Error finding documents: {:?}", e))
+        };
+        let count = async {
+            let filter = build_cone_search_filter(
+                self.filter.clone(),
+                center,
+                self.radius,
+                self.unit.clone(),
+            );
+            count_with_rights(&self.collection, filter, &self.rights)
+                .await
+                .map_err(|e| format!("Error counting documents: {:?}", e))
+        };
+        let (nearest, count) = futures::try_join!(nearest, count)?;
+        let nearest = self.separation.apply(center, nearest.into_iter().collect());
+        let (best, separation) = match nearest.into_iter().next() {
+            Some(mut best) => {
+                let separation = best.remove(SEPARATION_FIELD).unwrap_or(Bson::Null);
+                (Bson::Document(best), separation)
+            }
+            None => (Bson::Null, Bson::Null),
+        };
+        Ok(doc! {
+            "target": target.clone(),
+            "match": best,
+            "separation": separation,
+            "n_matches": count as i64,
+        })
+    }
+
+    // joined rows in the order of the targets, a few targets being searched
+    // at once
+    pub(crate) fn rows(&self) -> impl futures::Stream<Item = Result<Document, String>> + '_ {
+        stream::iter(&self.targets)
+            .map(|(target, center)| self.join(target, *center))
+            .buffered(CONCURRENCY)
+    }
+
+    async fn run(&self) -> Result<Vec<Document>, String> {
+        self.rows().try_collect().await
+    }
+
+    fn message(&self) -> String {
+        format!(
+            "Crossmatch of {} targets with {} completed",
+            self.targets.len(),
+            self.catalog
+        )
+    }
+}
+
+// the catalog units, for the columns of the matches
+pub(crate) fn match_units(config: &AppConfig, catalog: &str) -> HashMap<String, String> {
+    config
+        .catalog(catalog)
+        .units
+        .into_iter()
+        .map(|(field, unit)| (format!("match.{}", field), unit))
+        .collect()
+}
+
+// the uploaded targets, read as a VOTable when sent as XML (or looking like
+// XML), as CSV otherwise
+fn read_targets(req: &HttpRequest, body: &[u8]) -> Result<TextTable, String> {
+    let text = match std::str::from_utf8(body) {
+        Ok(t) => t,
+        Err(_) => return Err("the target list must be UTF-8 text".to_string()),
+    };
+    let content_type = req.content_type();
+    let xml = content_type.contains("xml")
+        || (content_type != CSV_CONTENT_TYPE && text.trim_start().starts_with('<'));
+    if xml {
+        read_votable(text)
+    } else {
+        read_csv(text)
+    }
+}
+
+// the rows of the target list with their positions
+pub fn target_positions(
+    table: &TextTable,
+    ra_column: &str,
+    dec_column: &str,
+    frame: &Frame,
+) -> Result<Vec<Target>, String> {
+    let ra = table
+        .column(ra_column)
+        .ok_or_else(|| format!("missing column {}", ra_column))?;
+    let dec = table
+        .column(dec_column)
+        .ok_or_else(|| format!("missing column {}", dec_column))?;
+    let mut targets = Vec::with_capacity(table.rows.len());
+    for (index, row) in table.rows.iter().enumerate() {
+        let coordinates = Coordinates::Pair([row[ra].clone(), row[dec].clone()]);
+        match coordinates.to_icrs(frame) {
+            Ok(position) => targets.push((table.row_document(row), position)),
+            Err(e) => return Err(format!("row {}: {}", index + 1, e)),
+        }
+    }
+    Ok(targets)
+}
+
+// crossmatches the targets uploaded in the body with a catalog. Each target
+// is returned with its closest match within the radius, in the order of the
+// list. With async=true, responds 202 with a query job (see api::jobs).
+#[post("/query/crossmatch")]
+pub async fn crossmatch(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    runner: web::Data<JobRunner>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    params: web::Query<CrossmatchParams>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let params = params.into_inner();
+    let catalog = match &params.catalog {
+        Some(c) => c.clone(),
+        None => return response::bad_request("catalog required for crossmatch"),
+    };
+    if let Err(e) = config.check_catalog(&catalog) {
+        return response::bad_request(&e);
+    }
+    let radius = match params.radius {
+        Some(r) if r > 0.0 && r.is_finite() => r,
+        Some(_) => return response::bad_request("radius must be a positive number"),
+        None => return response::bad_request("radius required for crossmatch"),
+    };
+    let unit = params.unit.clone().unwrap_or(Unit::Arcseconds);
+    let max_radius = config.api.max_crossmatch_radius_arcsec;
+    if degrees_from(radius, &unit) * 3600.0 > max_radius {
+        return response::bad_request(&format!("radius must be at most {} arcseconds", max_radius));
+    }
+    let (filter, projection) = match params.documents() {
+        Ok(d) => d,
+        Err(e) => return response::bad_request(&e),
+    };
+    if let Err(e) = validate_document(&filter, "filter", &QUERY_RULES) {
+        return response::bad_request(&format!("Invalid filter: {}", e));
+    }
+    if let Err(e) = validate_optional(&projection, "projection", &QUERY_RULES) {
+        return response::bad_request(&format!("Invalid projection: {}", e));
+    }
+    let run_async = params.run_async.unwrap_or(false);
+    let format = match OutputFormat::from_request(&req).with_override(params.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    if format.is_columnar() {
+        return response::bad_request(
+            "parquet and arrow outputs are only supported by find and aggregate",
+        );
+    }
+
+    let table = match read_targets(&req, &body) {
+        Ok(t) => t,
+        Err(e) => return response::bad_request(&format!("Invalid target list: {}", e)),
+    };
+    if table.rows.len() > MAX_TARGETS {
+        return response::bad_request(&format!(
+            "at most {} targets can be crossmatched",
+            MAX_TARGETS
+        ));
+    }
+    if table.rows.len() > MAX_SYNC_TARGETS && !run_async {
+        return response::bad_request(&format!(
+            "lists of more than {} targets must be crossmatched with async=true",
+            MAX_SYNC_TARGETS
+        ));
+    }
+    let frame = params.frame.unwrap_or(Frame::Icrs);
+    let targets = match target_positions(
+        &table,
+        params.ra_column.as_deref().unwrap_or("ra"),
+        params.dec_column.as_deref().unwrap_or("dec"),
+        &frame,
+    ) {
+        Ok(t) => t,
+        Err(e) => return response::bad_request(&format!("Invalid target list: {}", e)),
+    };
+
+    // the closest match is read with $geoNear and the matches counted, so
+    // that the limit does not apply: only the timeout, that of jobs when run
+    // as one
+    let mut kwargs = QueryKwargs::default();
+    if run_async {
+        kwargs.max_time_ms = config.jobs.max_time_ms;
+    }
+    let kwargs = config.apply_catalog_timeout(&catalog, kwargs);
+    let (projection, strip) = projection_with_position(projection);
+    let crossmatch = Crossmatch {
+        collection: client.database(&config.database.name).collection(&catalog),
+        rights: DataRights::new(&config, &catalog, &user),
+        catalog,
+        filter,
+        radius,
+        projection,
+        max_time: kwargs.max_time_ms.map(Duration::from_millis),
+        separation: Separation {
+            unit: unit.clone(),
+            sort: true,
+            k: Some(1),
+            strip,
+        },
+        unit,
+        targets,
+    };
+
+    if run_async {
+        return submit_crossmatch(&client, &config, &runner, &user, crossmatch).await;
+    }
+
+    if format == OutputFormat::Ndjson {
+        return ndjson_response(move |mut writer| async move {
+            let message = crossmatch.message();
+            let mut rows = crossmatch.rows();
+            let mut count = 0;
+            let mut error = None;
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(row) => {
+                        if !writer.write(&row).await {
+                            return;
+                        }
+                        count += 1;
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+            writer
+                .write(&Summary::new(&message, count, error, None))
+                .await;
+        });
+    }
+    let rows = match crossmatch.run().await {
+        Ok(r) => r,
+        Err(e) => return response::internal_error(&e),
+    };
+    if format.is_tabular() {
+        let units = match_units(&config, &crossmatch.catalog);
+        let table = Table::from_documents("crossmatch", &rows, &units);
+        return table_response(format, &table, None);
+    }
+    response::ok(&crossmatch.message(), serde_json::json!(rows))
+}
@@ -1,4 +1,9 @@
-use crate::models::filter_models::*;
+use crate::{
+    api::pipeline::validate_pipeline,
+    conf::AppConfig,
+    models::{auth_models::AuthenticatedUser, filter_models::*},
+    surveys::{self, Survey},
+};
 use actix_web::{patch, post, web, HttpResponse};
 use mongodb::{
     bson::{doc, Document},
@@ -7,8 +12,6 @@ use mongodb::{
 use std::vec;
 use uuid::Uuid;
 
-const DB_NAME: &str = "boom";
-
 struct Filter {
     pub pipeline: Vec<mongodb::bson::Document>,
     pub permissions: Vec<i32>,
@@ -17,10 +20,21 @@ struct Filter {
 }
 
 fn build_test_pipeline(
-    filter_catalog: String,
+    survey: &dyn Survey,
     filter_perms: Vec<i32>,
     mut filter_pipeline: Vec<Document>,
 ) -> Vec<Document> {
+    let time_field = format!("${}", survey.time_field());
+    let history_time_field = format!("$$x.{}", survey.history_time_field());
+    let mut conditions = vec![
+        // maximum 1 year of past data
+        doc! { "$lt": [{ "$subtract": [&time_field, &history_time_field] }, 365] },
+        // only datapoints up to (and including) current alert
+        doc! { "$lte": [&history_time_field, &time_field] },
+    ];
+    if let Some(field) = survey.permissions_field() {
+        conditions.insert(0, doc! { "$in": [format!("$$x.{}", field), &filter_perms] });
+    }
     let mut out_pipeline = vec![
         doc! {
             "$match": doc! {
@@ -29,16 +43,16 @@ fn build_test_pipeline(
         },
         doc! {
             "$lookup": doc! {
-                "from": format!("{}_aux", filter_catalog),
-                "localField": "objectId",
+                "from": survey.aux_collection(),
+                "localField": survey.object_id_field(),
                 "foreignField": "_id",
                 "as": "aux"
             }
         },
         doc! {
             "$project": doc! {
-                "objectId": 1,
-                "candidate": 1,
+                survey.object_id_field(): 1,
+                survey.candidate_field(): 1,
                 "classifications": 1,
                 "coordinates": 1,
                 "cross_matches": doc! {
@@ -57,32 +71,7 @@ fn build_test_pipeline(
                         },
                         "as": "x",
                         "cond": doc! {
-                            "$and": [
-                                {
-                                    "$in": [
-                                        "$$x.programid",
-                                        &filter_perms
-                                    ]
-                                },
-                                { // maximum 1 year of past data
-                                    "$lt": [
-                                        {
-                                            "$subtract": [
-                                                "$candidate.jd",
-                                                "$$x.jd"
-                                            ]
-                                        },
-                                        365
-                                    ]
-                                },
-                                { // only datapoints up to (and including) current alert
-                                    "$lte": [
-                                        "$$x.jd",
-                                        "$candidate.jd"
-                                    ]
-                                }
-
-                            ]
+                            "$and": conditions
                         }
                     }
                 },
@@ -95,13 +84,12 @@ fn build_test_pipeline(
 
 // tests the functionality of a filter by running it on alerts in database
 async fn run_test_pipeline(
-    client: web::Data<Client>,
-    catalog: String,
+    db: mongodb::Database,
+    survey: &dyn Survey,
     pipeline: Vec<mongodb::bson::Document>,
 ) -> Result<(), mongodb::error::Error> {
-    let collection: Collection<mongodb::bson::Document> = client
-        .database(DB_NAME)
-        .collection(format!("{}_alerts", catalog).as_str());
+    let collection: Collection<mongodb::bson::Document> =
+        db.collection(&survey.alerts_collection());
 
     let result = collection.aggregate(pipeline).await;
     match result {
@@ -143,9 +131,24 @@ fn build_filter_bson(filter: Filter) -> Result<mongodb::bson::Document, mongodb:
     Ok(database_filter_bson)
 }
 
+// a filter cannot see more data than the user submitting it
+fn check_filter_permissions(user: &AuthenticatedUser, permissions: &[i32]) -> Option<HttpResponse> {
+    permissions
+        .iter()
+        .find(|perm| !user.programids.contains(perm))
+        .map(|perm| {
+            HttpResponse::Forbidden().body(format!(
+                "user {} does not have access to program id {}",
+                user.username, perm
+            ))
+        })
+}
+
 #[patch("/filters/{filter_id}")]
 pub async fn add_filter_version(
     client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
     filter_id: web::Path<i32>,
     body: web::Json<FilterSubmissionBody>,
 ) -> HttpResponse {
@@ -157,8 +160,13 @@ pub async fn add_filter_version(
                 .body("pipeline not provided. pipeline required for adding a filter version");
         }
     };
+    // the same stages and catalogs as /query/aggregate
+    if let Err(e) = validate_pipeline(&config, &pipeline) {
+        return HttpResponse::BadRequest().body(format!("Invalid filter submitted: {}", e));
+    }
 
-    let collection: Collection<Document> = client.database(DB_NAME).collection("filters");
+    let db = client.database(&config.database.name);
+    let collection: Collection<Document> = db.collection("filters");
     let owner_filter = match collection.find_one(doc! {"filter_id": filter_id}).await {
         Ok(Some(filter)) => filter,
         Ok(None) => {
@@ -172,16 +180,52 @@ pub async fn add_filter_version(
             ));
         }
     };
-    let catalog = owner_filter.get_str("catalog").unwrap();
-    let permissions = owner_filter.get_array("permissions").unwrap();
-    let permissions: Vec<i32> = permissions
-        .iter()
-        .map(|perm| perm.as_i32().unwrap())
-        .collect();
+    // filters are stored by post_filter, so a malformed one is a server error
+    let catalog = match owner_filter.get_str("catalog") {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!(
+                "invalid catalog of filter with id {}. error: {}",
+                filter_id, e
+            ));
+        }
+    };
+    let survey = match surveys::filter_survey(catalog) {
+        Ok(survey) => survey,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!(
+                "invalid catalog of filter with id {}. error: {}",
+                filter_id, e
+            ));
+        }
+    };
+    let permissions = match owner_filter.get_array("permissions").map(|permissions| {
+        permissions
+            .iter()
+            .map(|perm| perm.as_i32())
+            .collect::<Option<Vec<i32>>>()
+    }) {
+        Ok(Some(permissions)) => permissions,
+        Ok(None) => {
+            return HttpResponse::InternalServerError().body(format!(
+                "invalid permissions of filter with id {}, expected program ids",
+                filter_id
+            ));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!(
+                "invalid permissions of filter with id {}. error: {}",
+                filter_id, e
+            ));
+        }
+    };
+    if let Some(forbidden) = check_filter_permissions(&user, &permissions) {
+        return forbidden;
+    }
     // create test version of filter and test it
-    let test_pipeline = build_test_pipeline(catalog.to_string(), permissions, pipeline.clone());
+    let test_pipeline = build_test_pipeline(survey, permissions, pipeline.clone());
 
-    match run_test_pipeline(client.clone(), catalog.to_string(), test_pipeline).await {
+    match run_test_pipeline(db.clone(), survey, test_pipeline).await {
         Ok(()) => {}
         Err(e) => {
             return HttpResponse::BadRequest().body(format!(
@@ -227,6 +271,8 @@ pub async fn add_filter_version(
 #[post("/filters")]
 pub async fn post_filter(
     client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
     body: web::Json<FilterSubmissionBody>,
 ) -> HttpResponse {
     let body = body.clone();
@@ -237,6 +283,10 @@ pub async fn post_filter(
             return HttpResponse::BadRequest().body("catalog not provided");
         }
     };
+    let survey = match surveys::filter_survey(&catalog) {
+        Ok(survey) => survey,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
     let id = match body.id {
         Some(id) => id,
         None => {
@@ -249,19 +299,27 @@ pub async fn post_filter(
             return HttpResponse::BadRequest().body("permissions not provided");
         }
     };
+    if let Some(forbidden) = check_filter_permissions(&user, &permissions) {
+        return forbidden;
+    }
     let pipeline = match body.pipeline {
         Some(pipeline) => pipeline,
         None => {
             return HttpResponse::BadRequest().body("pipeline not provided");
         }
     };
+    // the same stages and catalogs as /query/aggregate
+    if let Err(e) = validate_pipeline(&config, &pipeline) {
+        return HttpResponse::BadRequest().body(format!("Invalid filter submitted: {}", e));
+    }
 
     // Test filter received from user
     // create production version of filter
-    let test_pipeline = build_test_pipeline(catalog.clone(), permissions.clone(), pipeline.clone());
+    let test_pipeline = build_test_pipeline(survey, permissions.clone(), pipeline.clone());
 
     // perform test run to ensure no errors
-    match run_test_pipeline(client.clone(), catalog.clone(), test_pipeline).await {
+    let db = client.database(&config.database.name);
+    match run_test_pipeline(db.clone(), survey, test_pipeline).await {
         Ok(()) => {}
         Err(e) => {
             return HttpResponse::BadRequest().body(format!(
@@ -272,12 +330,11 @@ pub async fn post_filter(
     }
 
     // save original filter to database
-    let filter_collection: Collection<mongodb::bson::Document> =
-        client.database(DB_NAME).collection("filters");
+    let filter_collection: Collection<mongodb::bson::Document> = db.collection("filters");
     let database_filter = Filter {
         pipeline,
         permissions,
-        catalog,
+        catalog: survey.name().to_string(),
         id,
     };
     let filter_bson = match build_filter_bson(database_filter) {
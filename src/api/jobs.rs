@@ -0,0 +1,618 @@
+// asynchronous query jobs, in the style of IVOA UWS: find and aggregate
+// queries (and large crossmatches) are submitted, run in the background, and
+// their results stored in MongoDB to be fetched page by page. Jobs and results
+// expire with TTL indexes.
+use crate::{
+    api::{
+        crossmatch::{match_units, Crossmatch},
+        pagination::Pagination,
+        permissions::{find_with_rights, DataRights},
+        query::{aggregate_query, build_options, build_pipeline, find_query},
+    },
+    conf::AppConfig,
+    formats::{
+        columnar::columnar_response,
+        ndjson::{ndjson_response, write_cursor, Summary},
+        table::Table,
+        table_response, OutputFormat,
+    },
+    models::{
+        auth_models::AuthenticatedUser,
+        job_models::{Job, JobBody, JobPhase, JobResultsParams},
+        query_models::QueryKwargs,
+        response::{self, ApiResponseBody},
+    },
+};
+use actix_web::{delete, get, http::header, post, web, HttpRequest, HttpResponse};
+use futures::{
+    future::{abortable, AbortHandle},
+    stream::LocalBoxStream,
+    StreamExt, TryStreamExt,
+};
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    options::{AggregateOptions, FindOptions, IndexOptions},
+    Client, Collection, Cursor, IndexModel,
+};
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+
+// results are stored in batches of this size
+const RESULTS_BATCH: usize = 1000;
+// default and largest pages of results
+const JOB_PAGE_SIZE: i64 = 1000;
+const MAX_JOB_PAGE_SIZE: i64 = 10_000;
+
+// the query of a job, validated at submission
+enum JobQuery {
+    Find {
+        filter: Document,
+        options: Box<FindOptions>,
+        rights: DataRights,
+    },
+    Aggregate {
+        pipeline: Vec<Document>,
+        options: Box<AggregateOptions>,
+    },
+    // a target list, searched in its own catalog
+    Crossmatch(Box<Crossmatch>),
+}
+
+// the kwargs of the query of a job: the timeout of jobs unless one is set,
+// then the limits of the catalog
+fn job_kwargs(
+    config: &AppConfig,
+    catalog: &str,
+    mut kwargs: QueryKwargs,
+) -> Result<QueryKwargs, String> {
+    if Pagination::from_kwargs(&kwargs)?.is_some() {
+        return Err(
+            "pagination is not supported by jobs, their results are fetched by pages".to_string(),
+        );
+    }
+    if kwargs.max_time_ms.is_none() {
+        kwargs.max_time_ms = config.jobs.max_time_ms;
+    }
+    Ok(config.apply_catalog_limits(catalog, kwargs))
+}
+
+impl JobQuery {
+    // the kind and catalog of a job, with its query
+    fn from_body(
+        config: &AppConfig,
+        user: &AuthenticatedUser,
+        body: JobBody,
+    ) -> Result<(&'static str, String, JobQuery), String> {
+        match (body.find, body.aggregate) {
+            (Some(find), None) => {
+                let (catalog, filter, projection) =
+                    find_query(config, find.query.unwrap_or_default())?;
+                let kwargs = job_kwargs(config, &catalog, find.kwargs.unwrap_or_default())?;
+                let query = JobQuery::Find {
+                    filter,
+                    options: Box::new(build_options(projection, kwargs)),
+                    rights: DataRights::new(config, &catalog, user),
+                };
+                Ok(("find", catalog, query))
+            }
+            (None, Some(mut aggregate)) => {
+                let kwargs = aggregate.kwargs.take().unwrap_or_default();
+                let (catalog, pipeline) = aggregate_query(config, aggregate)?;
+                let kwargs = job_kwargs(config, &catalog, kwargs)?;
+                let (pipeline, options) = build_pipeline(config, &catalog, user, pipeline, &kwargs);
+                Ok((
+                    "aggregate",
+                    catalog,
+                    JobQuery::Aggregate {
+                        pipeline,
+                        options: Box::new(options),
+                    },
+                ))
+            }
+            _ => Err("a job takes one of find or aggregate".to_string()),
+        }
+    }
+
+    // the results of the query, as they are read
+    async fn rows(
+        &self,
+        collection: &Collection<Document>,
+    ) -> Result<LocalBoxStream<'_, Result<Document, String>>, String> {
+        let cursor: Cursor<Document> = match self {
+            JobQuery::Find {
+                filter,
+                options,
+                rights,
+            } => find_with_rights(collection, filter.clone(), *options.clone(), rights)
+                .await
+                .map_err(|e| format!("Error finding documents: {:?}", e))?,
+            JobQuery::Aggregate { pipeline, options } => collection
+                .aggregate(pipeline.clone())
+                .with_options(*options.clone())
+                .await
+                .map_err(|e| format!("Error running aggregation: {:?}", e))?,
+            JobQuery::Crossmatch(crossmatch) => return Ok(crossmatch.rows().boxed_local()),
+        };
+        Ok(cursor
+            .map_err(|e| format!("Error collecting documents: {:?}", e))
+            .boxed_local())
+    }
+}
+
+// runs the jobs submitted to this server, a few at a time
+pub struct JobRunner {
+    permits: Semaphore,
+    // jobs queued or executing here, to abort them
+    running: Mutex<HashMap<String, AbortHandle>>,
+}
+
+impl JobRunner {
+    pub fn new(max_running: usize) -> JobRunner {
+        JobRunner {
+            permits: Semaphore::new(max_running.max(1)),
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // the map stays usable if a thread panicked while holding it, as it is
+    // never left half updated
+    fn running(&self) -> MutexGuard<'_, HashMap<String, AbortHandle>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // stops the job if it runs here
+    fn abort(&self, job_id: &str) {
+        if let Some(handle) = self.running().remove(job_id) {
+            handle.abort();
+        }
+    }
+}
+
+fn jobs_collection(client: &Client, config: &AppConfig) -> Collection<Job> {
+    client
+        .database(&config.jobs.database)
+        .collection(&config.jobs.collection)
+}
+
+fn results_collection(client: &Client, config: &AppConfig) -> Collection<Document> {
+    client
+        .database(&config.jobs.database)
+        .collection(&config.jobs.results_collection)
+}
+
+// TTL indexes deleting the jobs and results at their destruction time, and
+// the index of the results of a job
+async fn create_indexes(
+    jobs: &Collection<Job>,
+    results: &Collection<Document>,
+) -> Result<(), mongodb::error::Error> {
+    let ttl = IndexModel::builder()
+        .keys(doc! { "destruction": 1 })
+        .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+        .build();
+    jobs.create_index(ttl.clone()).await?;
+    results.create_index(ttl).await?;
+    results
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "job_id": 1, "index": 1 })
+                .build(),
+        )
+        .await?;
+    Ok(())
+}
+
+// run once at startup: creates the indexes, and fails the jobs left queued or
+// executing by the previous run, whose queries were only held in memory
+pub async fn init_jobs(client: &Client, config: &AppConfig) -> Result<(), mongodb::error::Error> {
+    let jobs = jobs_collection(client, config);
+    create_indexes(&jobs, &results_collection(client, config)).await?;
+    let active = [JobPhase::Queued.name(), JobPhase::Executing.name()];
+    jobs.update_many(
+        doc! { "phase": { "$in": active.as_slice() } },
+        doc! { "$set": {
+            "phase": JobPhase::Error.name(),
+            "finished_at": DateTime::now(),
+            "error": "interrupted by a restart of the server",
+        } },
+    )
+    .await?;
+    Ok(())
+}
+
+// stores the results of the query of a job, returning their number
+async fn write_results(
+    job: &Job,
+    query: &JobQuery,
+    catalog: &Collection<Document>,
+    results: &Collection<Document>,
+) -> Result<i64, String> {
+    let mut rows = query.rows(catalog).await?;
+    let mut batch = Vec::with_capacity(RESULTS_BATCH);
+    let mut count = 0;
+    let mut done = false;
+    while !done {
+        match rows.try_next().await {
+            Ok(Some(document)) => {
+                batch.push(doc! {
+                    "job_id": &job.id,
+                    "index": count,
+                    "data": document,
+                    "destruction": job.destruction,
+                });
+                count += 1;
+                if batch.len() < RESULTS_BATCH {
+                    continue;
+                }
+            }
+            Ok(None) => done = true,
+            Err(e) => return Err(e),
+        }
+        if !batch.is_empty() {
+            if let Err(e) = results.insert_many(std::mem::take(&mut batch)).await {
+                return Err(format!("Error storing results: {:?}", e));
+            }
+        }
+    }
+    Ok(count)
+}
+
+// runs a job once a slot is free, unless it was aborted in the meantime
+async fn execute(
+    runner: web::Data<JobRunner>,
+    job: Job,
+    query: JobQuery,
+    jobs: Collection<Job>,
+    catalog: Collection<Document>,
+    results: Collection<Document>,
+) {
+    let _permit = match runner.permits.acquire().await {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+    let started = jobs
+        .update_one(
+            doc! { "_id": &job.id, "phase": JobPhase::Queued.name() },
+            doc! { "$set": {
+                "phase": JobPhase::Executing.name(),
+                "started_at": DateTime::now(),
+            } },
+        )
+        .await;
+    if !started.is_ok_and(|r| r.modified_count == 1) {
+        return;
+    }
+    let update = match write_results(&job, &query, &catalog, &results).await {
+        Ok(count) => doc! { "$set": {
+            "phase": JobPhase::Completed.name(),
+            "finished_at": DateTime::now(),
+            "count": count,
+        } },
+        Err(e) => doc! { "$set": {
+            "phase": JobPhase::Error.name(),
+            "finished_at": DateTime::now(),
+            "error": e,
+        } },
+    };
+    // a failure leaves the job executing until it expires, there is no one
+    // to report it to
+    let _ = jobs
+        .update_one(
+            doc! { "_id": &job.id, "phase": JobPhase::Executing.name() },
+            update,
+        )
+        .await;
+}
+
+// a job of the user, or the response to send when there is none
+async fn find_job(
+    jobs: &Collection<Job>,
+    job_id: &str,
+    user: &AuthenticatedUser,
+) -> Result<Job, HttpResponse> {
+    match jobs
+        .find_one(doc! { "_id": job_id, "owner": &user.username })
+        .await
+    {
+        Ok(Some(job)) => Ok(job),
+        Ok(None) => Err(response::not_found(&format!("Job {} not found", job_id))),
+        Err(e) => Err(response::internal_error(&format!(
+            "Error finding job: {:?}",
+            e
+        ))),
+    }
+}
+
+// queues a find or aggregate query, responding 202 with the job, whose
+// status is at the Location header
+#[post("/jobs")]
+pub async fn submit_job(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    runner: web::Data<JobRunner>,
+    user: web::ReqData<AuthenticatedUser>,
+    body: web::Json<JobBody>,
+) -> HttpResponse {
+    let (kind, catalog, query) = match JobQuery::from_body(&config, &user, body.into_inner()) {
+        Ok(q) => q,
+        Err(e) => return response::bad_request(&e),
+    };
+    queue_job(&client, &config, &runner, &user, kind, catalog, query).await
+}
+
+// a crossmatch too large to run within a request, see api::crossmatch
+pub(crate) async fn submit_crossmatch(
+    client: &Client,
+    config: &AppConfig,
+    runner: &web::Data<JobRunner>,
+    user: &AuthenticatedUser,
+    crossmatch: Crossmatch,
+) -> HttpResponse {
+    let catalog = crossmatch.catalog.clone();
+    let query = JobQuery::Crossmatch(Box::new(crossmatch));
+    queue_job(client, config, runner, user, "crossmatch", catalog, query).await
+}
+
+// stores a validated job, queued until a slot is free, and responds 202 with
+// the job, whose status is at the Location header
+async fn queue_job(
+    client: &Client,
+    config: &AppConfig,
+    runner: &web::Data<JobRunner>,
+    user: &AuthenticatedUser,
+    kind: &str,
+    catalog: String,
+    query: JobQuery,
+) -> HttpResponse {
+    let jobs = jobs_collection(client, config);
+    let results = results_collection(client, config);
+    let now = DateTime::now();
+    let job = Job {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner: user.username.clone(),
+        kind: kind.to_string(),
+        catalog: catalog.clone(),
+        phase: JobPhase::Queued,
+        created_at: now,
+        started_at: None,
+        finished_at: None,
+        destruction: DateTime::from_millis(
+            now.timestamp_millis() + 1000 * config.jobs.retention_secs as i64,
+        ),
+        count: 0,
+        error: None,
+    };
+    if let Err(e) = jobs.insert_one(&job).await {
+        return response::internal_error(&format!("Error submitting job: {:?}", e));
+    }
+
+    let catalog = client.database(&config.database.name).collection(&catalog);
+    let (task, handle) = abortable(execute(
+        runner.clone(),
+        job.clone(),
+        query,
+        jobs,
+        catalog,
+        results,
+    ));
+    runner.running().insert(job.id.clone(), handle);
+    let job_id = job.id.clone();
+    let done = runner.clone();
+    actix_web::rt::spawn(async move {
+        let _ = task.await;
+        done.running().remove(&job_id);
+    });
+    HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("/jobs/{}", job.id)))
+        .json(ApiResponseBody::ok(
+            &format!("Job {} submitted", job.id),
+            job.status(),
+        ))
+}
+
+// jobs of the user, the most recent first
+#[get("/jobs")]
+pub async fn list_jobs(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+) -> HttpResponse {
+    let cursor = match jobs_collection(&client, &config)
+        .find(doc! { "owner": &user.username })
+        .sort(doc! { "created_at": -1 })
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => return response::internal_error(&format!("Error finding jobs: {:?}", e)),
+    };
+    match cursor.try_collect::<Vec<Job>>().await {
+        Ok(jobs) => {
+            let statuses: Vec<serde_json::Value> = jobs.iter().map(Job::status).collect();
+            response::ok(
+                &format!("{} job(s)", statuses.len()),
+                serde_json::json!(statuses),
+            )
+        }
+        Err(e) => response::internal_error(&format!("Error collecting jobs: {:?}", e)),
+    }
+}
+
+#[get("/jobs/{job_id}")]
+pub async fn get_job(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    job_id: web::Path<String>,
+) -> HttpResponse {
+    let jobs = jobs_collection(&client, &config);
+    match find_job(&jobs, &job_id, &user).await {
+        Ok(job) => response::ok(
+            &format!("Job {} is {}", job.id, job.phase.name()),
+            job.status(),
+        ),
+        Err(response) => response,
+    }
+}
+
+// a page of the results of a completed job, starting at `offset`. `next` is
+// the offset of the following page, if any.
+#[get("/jobs/{job_id}/results")]
+pub async fn get_job_results(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    job_id: web::Path<String>,
+    params: web::Query<JobResultsParams>,
+) -> HttpResponse {
+    let format = match OutputFormat::from_request(&req).with_override(params.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    let offset = params.offset.unwrap_or(0) as i64;
+    let limit = params.limit.unwrap_or(JOB_PAGE_SIZE);
+    if !(1..=MAX_JOB_PAGE_SIZE).contains(&limit) {
+        return response::bad_request(&format!(
+            "limit must be between 1 and {}",
+            MAX_JOB_PAGE_SIZE
+        ));
+    }
+    let jobs = jobs_collection(&client, &config);
+    let job = match find_job(&jobs, &job_id, &user).await {
+        Ok(j) => j,
+        Err(response) => return response,
+    };
+    if job.phase != JobPhase::Completed {
+        return response::bad_request(&format!(
+            "Job {} is {}, its results are available once COMPLETED",
+            job.id,
+            job.phase.name()
+        ));
+    }
+    let pipeline = vec![
+        doc! { "$match": { "job_id": &job.id, "index": { "$gte": offset } } },
+        doc! { "$sort": { "index": 1 } },
+        doc! { "$limit": limit },
+        doc! { "$replaceRoot": { "newRoot": "$data" } },
+    ];
+    let cursor = match results_collection(&client, &config)
+        .aggregate(pipeline)
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => return response::internal_error(&format!("Error finding results: {:?}", e)),
+    };
+    let total = job.count;
+    let next = move |count: u64| {
+        let end = offset + count as i64;
+        (end < total).then(|| end.to_string())
+    };
+    let message = format!("Results of job {}", job.id);
+
+    if format.is_columnar() {
+        return columnar_response(format, cursor);
+    }
+    if format == OutputFormat::Ndjson {
+        return ndjson_response(move |mut writer| async move {
+            let outcome = write_cursor(&mut writer, cursor, None).await;
+            if !outcome.disconnected {
+                let next = next(outcome.count);
+                let summary = Summary::new(&message, outcome.count, outcome.error, next);
+                writer.write(&summary).await;
+            }
+        });
+    }
+    let docs = match cursor.try_collect::<Vec<Document>>().await {
+        Ok(d) => d,
+        Err(e) => {
+            return response::internal_error(&format!("Error collecting results: {:?}", e));
+        }
+    };
+    let next = next(docs.len() as u64);
+    if format.is_tabular() {
+        // crossmatch rows hold the target and its match
+        let table = match job.kind.as_str() {
+            "crossmatch" => {
+                Table::from_documents("crossmatch", &docs, &match_units(&config, &job.catalog))
+            }
+            _ => Table::from_documents(&job.catalog, &docs, &config.catalog(&job.catalog).units),
+        };
+        return table_response(format, &table, next);
+    }
+    response::ok_with_next(&message, serde_json::json!(docs), next)
+}
+
+// stops a queued or executing job, discarding its partial results
+#[post("/jobs/{job_id}/abort")]
+pub async fn abort_job(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    runner: web::Data<JobRunner>,
+    user: web::ReqData<AuthenticatedUser>,
+    job_id: web::Path<String>,
+) -> HttpResponse {
+    let jobs = jobs_collection(&client, &config);
+    let job = match find_job(&jobs, &job_id, &user).await {
+        Ok(j) => j,
+        Err(response) => return response,
+    };
+    if !job.phase.is_active() {
+        return response::bad_request(&format!("Job {} is already {}", job.id, job.phase.name()));
+    }
+    runner.abort(&job.id);
+    let active = [JobPhase::Queued.name(), JobPhase::Executing.name()];
+    let update = jobs
+        .find_one_and_update(
+            doc! { "_id": &job.id, "phase": { "$in": active.as_slice() } },
+            doc! { "$set": {
+                "phase": JobPhase::Aborted.name(),
+                "finished_at": DateTime::now(),
+            } },
+        )
+        .return_document(mongodb::options::ReturnDocument::After)
+        .await;
+    let job = match update {
+        Ok(Some(j)) => j,
+        // finished in the meantime
+        Ok(None) => return response::bad_request(&format!("Job {} is already finished", job.id)),
+        Err(e) => return response::internal_error(&format!("Error aborting job: {:?}", e)),
+    };
+    if let Err(e) = results_collection(&client, &config)
+        .delete_many(doc! { "job_id": &job.id })
+        .await
+    {
+        return response::internal_error(&format!("Error deleting results: {:?}", e));
+    }
+    response::ok(&format!("Job {} aborted", job.id), job.status())
+}
+
+// deletes a job and its results, aborting it first if needed
+#[delete("/jobs/{job_id}")]
+pub async fn delete_job(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    runner: web::Data<JobRunner>,
+    user: web::ReqData<AuthenticatedUser>,
+    job_id: web::Path<String>,
+) -> HttpResponse {
+    let jobs = jobs_collection(&client, &config);
+    let job = match find_job(&jobs, &job_id, &user).await {
+        Ok(j) => j,
+        Err(response) => return response,
+    };
+    runner.abort(&job.id);
+    if let Err(e) = jobs.delete_one(doc! { "_id": &job.id }).await {
+        return response::internal_error(&format!("Error deleting job: {:?}", e));
+    }
+    if let Err(e) = results_collection(&client, &config)
+        .delete_many(doc! { "job_id": &job.id })
+        .await
+    {
+        return response::internal_error(&format!("Error deleting results: {:?}", e));
+    }
+    response::ok(&format!("Job {} deleted", job.id), serde_json::Value::Null)
+}
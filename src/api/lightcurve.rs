@@ -0,0 +1,329 @@
+// light curves of objects, merging the detections of their alerts with the
+// previous candidates and non-detections of the aux collection
+use crate::{
+    api::permissions::DataRights,
+    conf::AppConfig,
+    formats::{
+        ndjson::{ndjson_response, Summary},
+        table::{Datatype, Table},
+        table_response, OutputFormat,
+    },
+    models::{alert_models::LightcurveParams, auth_models::AuthenticatedUser, response},
+    surveys::{self, Survey},
+};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::{FindOneOptions, FindOptions},
+    Client, Collection,
+};
+use std::collections::{HashMap, HashSet};
+
+// jd of the MJD origin
+pub const MJD_OFFSET: f64 = 2_400_000.5;
+// AB magnitude of a flux of 1 µJy
+const MICROJANSKY_ZEROPOINT: f64 = 23.9;
+
+// a point of a light curve. Non-detections only have a limiting magnitude.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Photometry {
+    pub candid: Option<i64>,
+    pub jd: f64,
+    pub mjd: f64,
+    pub filter: String,
+    pub mag: Option<f64>,
+    pub magerr: Option<f64>,
+    pub limmag: Option<f64>,
+    // difference flux in µJy, negative when the source faded from the
+    // reference image
+    pub flux: Option<f64>,
+    pub fluxerr: Option<f64>,
+    pub programid: Option<i64>,
+}
+
+fn number(document: &Document, key: &str) -> Option<f64> {
+    match document.get(key)? {
+        Bson::Double(d) if d.is_finite() => Some(*d),
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+fn integer(document: &Document, key: &str) -> Option<i64> {
+    match document.get(key)? {
+        Bson::Int32(i) => Some(*i as i64),
+        Bson::Int64(i) => Some(*i),
+        _ => None,
+    }
+}
+
+impl Photometry {
+    // point of a candidate, a detection when it has a magpsf. None without a
+    // jd or a filter band of the survey.
+    pub fn from_candidate(survey: &dyn Survey, candidate: &Document) -> Option<Photometry> {
+        let jd = number(candidate, "jd")?;
+        let filter = survey.band(candidate)?;
+        let mag = number(candidate, "magpsf");
+        let magerr = mag.and_then(|_| number(candidate, "sigmapsf"));
+        // a string for ZTF, a boolean for LSST
+        let negative = match candidate.get("isdiffpos") {
+            Some(Bson::String(s)) => matches!(s.as_str(), "f" | "0" | "false"),
+            Some(Bson::Boolean(b)) => !b,
+            _ => false,
+        };
+        let flux = mag.map(|mag| {
+            let flux = 10f64.powf(-0.4 * (mag - MICROJANSKY_ZEROPOINT));
+            if negative {
+                -flux
+            } else {
+                flux
+            }
+        });
+        let fluxerr = match (flux, magerr) {
+            (Some(flux), Some(magerr)) => Some(flux.abs() * 0.4 * std::f64::consts::LN_10 * magerr),
+            _ => None,
+        };
+        Some(Photometry {
+            candid: integer(candidate, "candid"),
+            jd,
+            mjd: jd - MJD_OFFSET,
+            filter: filter.to_string(),
+            mag,
+            magerr,
+            limmag: number(candidate, "diffmaglim"),
+            flux,
+            fluxerr,
+            programid: integer(candidate, "programid"),
+        })
+    }
+
+    pub fn is_detection(&self) -> bool {
+        self.mag.is_some()
+    }
+}
+
+// merges candidates into a light curve sorted by jd. The same detection may
+// be found in several alerts, so detections are unique by candid (or by jd
+// and filter without one), and non-detections by jd and filter, dropping
+// those at the time of a detection.
+pub fn lightcurve<'a>(
+    survey: &dyn Survey,
+    candidates: impl IntoIterator<Item = &'a Document>,
+) -> Vec<Photometry> {
+    let mut points: Vec<Photometry> = candidates
+        .into_iter()
+        .filter_map(|candidate| Photometry::from_candidate(survey, candidate))
+        .collect();
+    // detections first, to be kept over the non-detections they duplicate
+    points.sort_by_key(|point| !point.is_detection());
+    let mut candids = HashSet::new();
+    let mut epochs = HashSet::new();
+    points.retain(|point| {
+        let epoch = (point.jd.to_bits(), point.filter.clone());
+        match point.candid {
+            Some(candid) if point.is_detection() => {
+                epochs.insert(epoch);
+                candids.insert(candid)
+            }
+            _ => epochs.insert(epoch),
+        }
+    });
+    points.sort_by(|a, b| a.jd.total_cmp(&b.jd));
+    points
+}
+
+// bands selected by the comma separated `filters` parameter
+fn selected_bands(
+    survey: &dyn Survey,
+    filters: &Option<String>,
+) -> Result<Option<Vec<String>>, String> {
+    let filters = match filters {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    let mut bands = Vec::new();
+    for name in filters.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        if !survey.bands().contains(&name) {
+            return Err(format!(
+                "unknown filter {}, expected one of {}",
+                name,
+                survey.bands().join(", ")
+            ));
+        }
+        bands.push(name.to_string());
+    }
+    Ok(Some(bands))
+}
+
+fn units() -> HashMap<String, String> {
+    [
+        ("jd", "d"),
+        ("mjd", "d"),
+        ("mag", "mag"),
+        ("magerr", "mag"),
+        ("limmag", "mag"),
+        ("flux", "uJy"),
+        ("fluxerr", "uJy"),
+    ]
+    .into_iter()
+    .map(|(column, unit)| (column.to_string(), unit.to_string()))
+    .collect()
+}
+
+// photometry of an object: detections of its alerts and of the previous
+// candidates of its aux entry, and its non-detections
+#[get("/alerts/{survey_name}/objects/{object_id}/lightcurve")]
+pub async fn get_lightcurve(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    params: web::Query<LightcurveParams>,
+) -> HttpResponse {
+    let (survey_name, object_id) = path.into_inner();
+    let survey = match surveys::survey(&survey_name) {
+        Ok(s) => s,
+        Err(e) => return response::bad_request(&e),
+    };
+    let format = match OutputFormat::from_request(&req).with_override(params.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    if format.is_columnar() {
+        return response::bad_request(
+            "parquet and arrow outputs are only supported by find and aggregate",
+        );
+    }
+    let bands = match selected_bands(survey, &params.filters) {
+        Ok(b) => b,
+        Err(e) => return response::bad_request(&e),
+    };
+    if let (Some(min), Some(max)) = (params.jd_min, params.jd_max) {
+        if min > max {
+            return response::bad_request("jd_min must be smaller than jd_max");
+        }
+    }
+
+    let db = client.database(&config.database.name);
+    let alerts_catalog = survey.alerts_collection();
+    let aux_catalog = survey.aux_collection();
+    let alerts_collection: Collection<Document> = db.collection(&alerts_catalog);
+    let aux_collection: Collection<Document> = db.collection(&aux_catalog);
+    let alerts_rights = DataRights::new(&config, &alerts_catalog, &user);
+    let aux_rights = DataRights::new(&config, &aux_catalog, &user);
+
+    // the candidates of the alerts, without their cutouts
+    let find_options = FindOptions::builder()
+        .projection(doc! { "_id": 0, survey.candidate_field(): 1 })
+        .build();
+    let cursor = match alerts_collection
+        .find(alerts_rights.restrict_filter(doc! { survey.object_id_field(): &object_id }))
+        .with_options(find_options)
+        .await
+    {
+        Ok(cursor) => cursor,
+        Err(error) => {
+            return response::internal_error(&format!("error getting documents: {}", error));
+        }
+    };
+    let alerts: Vec<Document> = match cursor.try_collect().await {
+        Ok(alerts) => alerts,
+        Err(error) => {
+            return response::internal_error(&format!("error getting documents: {}", error));
+        }
+    };
+
+    // restricted arrays are trimmed to the user's program ids
+    let mut projection_aux = doc! {
+        "_id": 0,
+        "prv_candidates": 1,
+        "prv_nondetections": 1,
+    };
+    for (field, expression) in aux_rights.array_expressions() {
+        if projection_aux.contains_key(&field) {
+            projection_aux.insert(field, expression);
+        }
+    }
+    let aux_entry = match aux_collection
+        .find_one(doc! { "_id": &object_id })
+        .with_options(FindOneOptions::builder().projection(projection_aux).build())
+        .await
+    {
+        Ok(entry) => entry,
+        Err(error) => {
+            return response::internal_error(&format!("error getting documents: {}", error));
+        }
+    };
+    if alerts.is_empty() && aux_entry.is_none() {
+        return response::not_found(&format!("no object found with id {}", object_id));
+    }
+
+    let aux_entry = aux_entry.unwrap_or_default();
+    let aux_candidates = ["prv_candidates", "prv_nondetections"]
+        .into_iter()
+        .filter_map(|field| aux_entry.get_array(field).ok())
+        .flatten()
+        .filter_map(Bson::as_document);
+    let candidates = alerts
+        .iter()
+        .filter_map(|alert| alert.get_document(survey.candidate_field()).ok())
+        .chain(aux_candidates);
+    let points: Vec<Photometry> = lightcurve(survey, candidates)
+        .into_iter()
+        .filter(|point| match &bands {
+            Some(bands) => bands.contains(&point.filter),
+            None => true,
+        })
+        .filter(|point| params.jd_min.is_none_or(|min| point.jd >= min))
+        .filter(|point| params.jd_max.is_none_or(|max| point.jd <= max))
+        .collect();
+    let detections = points.iter().filter(|point| point.is_detection()).count();
+    let message = format!(
+        "light curve of {}: {} detection(s), {} non-detection(s)",
+        object_id,
+        detections,
+        points.len() - detections
+    );
+
+    if format == OutputFormat::Ndjson {
+        return ndjson_response(move |mut writer| async move {
+            for point in &points {
+                if !writer.write(point).await {
+                    return;
+                }
+            }
+            writer
+                .write(&Summary::new(&message, points.len() as u64, None, None))
+                .await;
+        });
+    }
+    if format.is_tabular() {
+        let documents: Vec<Document> = points
+            .iter()
+            .filter_map(|point| mongodb::bson::to_document(point).ok())
+            .collect();
+        let units = units();
+        let mut table = Table::from_documents(&object_id, &documents, &units);
+        // an empty light curve still has its columns
+        table.lead_columns(&[
+            ("candid", Datatype::Long),
+            ("jd", Datatype::Double),
+            ("mjd", Datatype::Double),
+            ("filter", Datatype::Char),
+            ("mag", Datatype::Double),
+            ("magerr", Datatype::Double),
+            ("limmag", Datatype::Double),
+            ("flux", Datatype::Double),
+            ("fluxerr", Datatype::Double),
+            ("programid", Datatype::Long),
+        ]);
+        for column in table.columns.iter_mut() {
+            column.unit = units.get(&column.name).cloned();
+        }
+        return table_response(format, &table, None);
+    }
+    response::ok(&message, serde_json::json!(points))
+}
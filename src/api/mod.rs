@@ -1,3 +1,13 @@
 pub mod alerts;
+pub mod auth;
+pub mod crossmatch;
 pub mod filters;
+pub mod jobs;
+pub mod lightcurve;
+pub mod pagination;
+pub mod permissions;
+pub mod pipeline;
 pub mod query;
+pub mod scs;
+pub mod tap;
+pub mod validation;
@@ -0,0 +1,218 @@
+use crate::{
+    api::validation::{validate_document, QUERY_RULES},
+    models::query_models::QueryKwargs,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use mongodb::bson::{doc, Bson, Document};
+use std::collections::HashMap;
+
+// keyset pagination over a single sort key, with _id as a tie-breaker so that
+// pages are deterministic even when many documents share the same key
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pagination {
+    pub key: String,
+    // 1 for ascending, -1 for descending
+    pub direction: i32,
+}
+
+// position of the last document of a page
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    pub value: Bson,
+    pub id: Bson,
+}
+
+// positions carried by a continuation token, keyed by result set (the object
+// names of a cone search, or "" for find)
+pub type Positions = HashMap<String, Position>;
+
+fn sort_direction(value: &Bson) -> Option<i32> {
+    match value {
+        Bson::Int32(1) | Bson::Int64(1) => Some(1),
+        Bson::Int32(-1) | Bson::Int64(-1) => Some(-1),
+        Bson::Double(d) if *d == 1.0 => Some(1),
+        Bson::Double(d) if *d == -1.0 => Some(-1),
+        _ => None,
+    }
+}
+
+// a plain (possibly dotted) field path, that cannot be read as an operator
+fn is_field_path(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .split('.')
+            .all(|part| !part.is_empty() && !part.starts_with('$') && !part.contains('\0'))
+}
+
+// reads a (possibly dotted) field path from a document
+pub fn get_path<'a>(document: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut current = document;
+    let mut parts = path.split('.').peekable();
+    while let Some(part) = parts.next() {
+        let value = current.get(part)?;
+        if parts.peek().is_none() {
+            return Some(value);
+        }
+        current = value.as_document()?;
+    }
+    None
+}
+
+impl Pagination {
+    // pagination requested through kwargs, either explicitly with `paginate`
+    // or implicitly by passing a continuation token in `after`. Returns the
+    // pagination and, when resuming, the positions carried by the token.
+    pub fn from_kwargs(kwargs: &QueryKwargs) -> Result<Option<(Pagination, Positions)>, String> {
+        let sort_pagination = match &kwargs.sort {
+            Some(sort) => {
+                let keys: Vec<(&String, &Bson)> = sort
+                    .iter()
+                    .filter(|(key, _)| key.as_str() != "_id")
+                    .collect();
+                match keys.len() {
+                    0 => Some(Pagination {
+                        key: "_id".to_string(),
+                        direction: sort.get("_id").and_then(sort_direction).unwrap_or(1),
+                    }),
+                    1 if !is_field_path(keys[0].0) => {
+                        return Err(format!("invalid sort key {}", keys[0].0));
+                    }
+                    1 => match sort_direction(keys[0].1) {
+                        Some(direction) => Some(Pagination {
+                            key: keys[0].0.clone(),
+                            direction,
+                        }),
+                        None => return Err("sort direction must be 1 or -1".to_string()),
+                    },
+                    _ => return Err("pagination supports a single sort key".to_string()),
+                }
+            }
+            None => None,
+        };
+
+        match &kwargs.after {
+            Some(token) => {
+                let (pagination, positions) = decode_token(token)?;
+                if let Some(sort_pagination) = sort_pagination {
+                    if sort_pagination != pagination {
+                        return Err("sort does not match the continuation token".to_string());
+                    }
+                }
+                Ok(Some((pagination, positions)))
+            }
+            None if kwargs.paginate.unwrap_or(false) => Ok(Some((
+                sort_pagination.unwrap_or(Pagination {
+                    key: "_id".to_string(),
+                    direction: 1,
+                }),
+                HashMap::new(),
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn sort(&self) -> Document {
+        if self.key == "_id" {
+            doc! { "_id": self.direction }
+        } else {
+            doc! { &self.key: self.direction, "_id": self.direction }
+        }
+    }
+
+    // restricts a filter to the documents after a position. The position
+    // comes from the client, so the merged filter is validated again
+    pub fn continuation_filter(
+        &self,
+        filter: Document,
+        position: Option<&Position>,
+    ) -> Result<Document, String> {
+        let position = match position {
+            Some(p) => p,
+            None => return Ok(filter),
+        };
+        let op = if self.direction == 1 { "$gt" } else { "$lt" };
+        let after = if self.key == "_id" {
+            doc! { "_id": { op: position.id.clone() } }
+        } else {
+            doc! {
+                "$or": [
+                    { &self.key: { op: position.value.clone() } },
+                    { &self.key: position.value.clone(), "_id": { op: position.id.clone() } }
+                ]
+            }
+        };
+        let filter = if filter.is_empty() {
+            after
+        } else {
+            doc! { "$and": [filter, after] }
+        };
+        validate_document(&filter, "filter", &QUERY_RULES)?;
+        Ok(filter)
+    }
+
+    pub fn position_of(&self, document: &Document) -> Result<Position, String> {
+        let id = match document.get("_id") {
+            Some(id) => id.clone(),
+            None => return Err("_id must be included in the projection to paginate".to_string()),
+        };
+        let value = match get_path(document, &self.key) {
+            Some(v) => v.clone(),
+            None if self.key == "_id" => id.clone(),
+            None => {
+                return Err(format!(
+                    "{} must be included in the projection to paginate",
+                    self.key
+                ));
+            }
+        };
+        Ok(Position { value, id })
+    }
+}
+
+// opaque token: base64url encoded BSON, so that the exact key types (ObjectId,
+// dates, doubles...) survive the round trip
+pub fn encode_token(pagination: &Pagination, positions: &[(String, Position)]) -> String {
+    let mut last = doc! {};
+    for (name, position) in positions {
+        last.insert(
+            name,
+            doc! { "value": position.value.clone(), "_id": position.id.clone() },
+        );
+    }
+    let token = doc! {
+        "key": &pagination.key,
+        "direction": pagination.direction,
+        "last": last,
+    };
+    let mut bytes = Vec::new();
+    // writing a document to a Vec cannot fail
+    let _ = token.to_writer(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn decode_token(token: &str) -> Result<(Pagination, Positions), String> {
+    let invalid = || "invalid continuation token".to_string();
+    let bytes = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+    let token = Document::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+    let key = token.get_str("key").map_err(|_| invalid())?;
+    let direction = token.get_i32("direction").map_err(|_| invalid())?;
+    if !is_field_path(key) || (direction != 1 && direction != -1) {
+        return Err(invalid());
+    }
+    let pagination = Pagination {
+        key: key.to_string(),
+        direction,
+    };
+    let mut positions = HashMap::new();
+    for (name, position) in token.get_document("last").map_err(|_| invalid())? {
+        let position = position.as_document().ok_or_else(invalid)?;
+        positions.insert(
+            name.clone(),
+            Position {
+                value: position.get("value").cloned().ok_or_else(invalid)?,
+                id: position.get("_id").cloned().ok_or_else(invalid)?,
+            },
+        );
+    }
+    Ok((pagination, positions))
+}
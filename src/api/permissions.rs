@@ -0,0 +1,195 @@
+use crate::{conf::AppConfig, models::auth_models::AuthenticatedUser, surveys};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::{AggregateOptions, FindOptions},
+    Collection, Cursor,
+};
+
+// data rights of a user on a given catalog
+#[derive(Clone, Debug, Default)]
+pub struct DataRights {
+    // path of the program id of each document, if the catalog has one
+    pub field: Option<String>,
+    // (array field, program id field of its elements) pairs to trim
+    pub arrays: Vec<(String, String)>,
+    pub programids: Vec<i32>,
+}
+
+impl DataRights {
+    // the rights of the survey collections are derived from the program id
+    // field of their survey, those of other catalogs are configured
+    pub fn new(config: &AppConfig, catalog: &str, user: &AuthenticatedUser) -> DataRights {
+        if let Some(survey) = surveys::collection_survey(catalog) {
+            let programid_field = match survey.permissions_field() {
+                Some(field) => field,
+                None => return DataRights::default(),
+            };
+            if catalog == survey.alerts_collection() {
+                return DataRights {
+                    field: Some(format!("{}.{}", survey.candidate_field(), programid_field)),
+                    arrays: vec![],
+                    programids: user.programids.clone(),
+                };
+            }
+            let mut arrays: Vec<(String, String)> = survey
+                .history_fields()
+                .iter()
+                .map(|array| (array.to_string(), programid_field.to_string()))
+                .collect();
+            arrays.sort();
+            return DataRights {
+                field: None,
+                arrays,
+                programids: user.programids.clone(),
+            };
+        }
+        let rights_config = config.catalog(catalog).data_rights.unwrap_or_default();
+        let mut arrays: Vec<(String, String)> = rights_config.arrays.into_iter().collect();
+        arrays.sort();
+        DataRights {
+            field: rights_config.field,
+            arrays,
+            programids: user.programids.clone(),
+        }
+    }
+
+    // restricts a filter to the documents the user is allowed to see
+    pub fn restrict_filter(&self, filter: Document) -> Document {
+        let field = match &self.field {
+            Some(f) => f,
+            None => return filter,
+        };
+        let rights_filter = doc! { field: { "$in": &self.programids } };
+        if filter.is_empty() {
+            rights_filter
+        } else {
+            doc! { "$and": [filter, rights_filter] }
+        }
+    }
+
+    // aggregation expressions trimming the restricted arrays, keyed by array
+    // field. Usable both in find projections and in $set stages.
+    pub fn array_expressions(&self) -> Document {
+        let mut expressions = doc! {};
+        for (array, programid_field) in &self.arrays {
+            expressions.insert(
+                array,
+                doc! {
+                    "$cond": [
+                        { "$isArray": format!("${}", array) },
+                        {
+                            "$filter": {
+                                "input": format!("${}", array),
+                                "as": "x",
+                                "cond": {
+                                    "$in": [
+                                        format!("$$x.{}", programid_field),
+                                        &self.programids
+                                    ]
+                                }
+                            }
+                        },
+                        "$$REMOVE"
+                    ]
+                },
+            );
+        }
+        expressions
+    }
+
+    // pipeline stages trimming the restricted arrays, to run before any user stage
+    pub fn pipeline_stages(&self) -> Vec<Document> {
+        if self.arrays.is_empty() {
+            return vec![];
+        }
+        vec![doc! { "$set": self.array_expressions() }]
+    }
+
+    // stages selecting the documents of a filter as the user sees them: the
+    // restricted arrays are trimmed before the filter is matched, as in
+    // restrict_pipeline, so that it cannot match elements the user may not see
+    pub fn match_stages(&self, filter: Document) -> Vec<Document> {
+        let mut pipeline = Vec::new();
+        if self.field.is_some() {
+            pipeline.push(doc! { "$match": self.restrict_filter(doc! {}) });
+        }
+        pipeline.extend(self.pipeline_stages());
+        if !filter.is_empty() {
+            pipeline.push(doc! { "$match": filter });
+        }
+        pipeline
+    }
+
+    // aggregation pipeline equivalent to a find query
+    pub fn find_pipeline(&self, filter: Document, options: &FindOptions) -> Vec<Document> {
+        let mut pipeline = self.match_stages(filter);
+        if let Some(sort) = &options.sort {
+            pipeline.push(doc! { "$sort": sort });
+        }
+        if let Some(skip) = options.skip {
+            pipeline.push(doc! { "$skip": skip as i64 });
+        }
+        // a negative limit means a single batch in find, use its magnitude.
+        // a limit of 0 means no limit
+        if let Some(limit) = options.limit.filter(|l| *l != 0) {
+            pipeline.push(doc! { "$limit": limit.abs() });
+        }
+        if let Some(projection) = &options.projection {
+            if !projection.is_empty() {
+                pipeline.push(doc! { "$project": projection });
+            }
+        }
+        pipeline
+    }
+}
+
+// runs a find query with the user's data rights applied. Catalogs with
+// restricted arrays go through an equivalent aggregation pipeline, so that
+// the arrays are trimmed before the user filter, sort and projection apply.
+pub async fn find_with_rights(
+    collection: &Collection<Document>,
+    filter: Document,
+    options: FindOptions,
+    rights: &DataRights,
+) -> Result<Cursor<Document>, mongodb::error::Error> {
+    if rights.arrays.is_empty() {
+        let filter = rights.restrict_filter(filter);
+        return collection.find(filter).with_options(options).await;
+    }
+
+    let pipeline = rights.find_pipeline(filter, &options);
+    let aggregate_options = AggregateOptions::builder()
+        .max_time(options.max_time)
+        .build();
+    collection
+        .aggregate(pipeline)
+        .with_options(aggregate_options)
+        .await
+}
+
+// counts the documents of a filter with the user's data rights applied,
+// through the same trimmed pipeline as find_with_rights
+pub async fn count_with_rights(
+    collection: &Collection<Document>,
+    filter: Document,
+    rights: &DataRights,
+) -> Result<u64, mongodb::error::Error> {
+    if rights.arrays.is_empty() {
+        return collection
+            .count_documents(rights.restrict_filter(filter))
+            .await;
+    }
+
+    let mut pipeline = rights.match_stages(filter);
+    pipeline.push(doc! { "$count": "count" });
+    let mut cursor = collection.aggregate(pipeline).await?;
+    if !cursor.advance().await? {
+        return Ok(0);
+    }
+    let count = cursor.deserialize_current()?;
+    Ok(match count.get("count") {
+        Some(Bson::Int32(n)) => *n as u64,
+        Some(Bson::Int64(n)) => *n as u64,
+        _ => 0,
+    })
+}
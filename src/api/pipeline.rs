@@ -0,0 +1,166 @@
+use crate::{
+    api::{
+        permissions::DataRights,
+        validation::{validate_bson, PIPELINE_RULES},
+    },
+    conf::AppConfig,
+    models::auth_models::AuthenticatedUser,
+};
+use mongodb::bson::{doc, Bson, Document};
+
+// aggregation stages users may run through /query/aggregate
+pub const ALLOWED_STAGES: &[&str] = &[
+    "$addFields",
+    "$bucket",
+    "$bucketAuto",
+    "$count",
+    "$facet",
+    "$group",
+    "$limit",
+    "$lookup",
+    "$match",
+    "$project",
+    "$replaceRoot",
+    "$replaceWith",
+    "$sample",
+    "$set",
+    "$skip",
+    "$sort",
+    "$sortByCount",
+    "$unset",
+    "$unwind",
+];
+
+fn validate_sub_pipeline(config: &AppConfig, value: &Bson, path: &str) -> Result<(), String> {
+    match value {
+        Bson::Array(stages) => {
+            let mut sub_pipeline = Vec::new();
+            for stage in stages {
+                match stage {
+                    Bson::Document(d) => sub_pipeline.push(d.clone()),
+                    _ => return Err(format!("{} must be an array of stages", path)),
+                }
+            }
+            validate_stages(config, &sub_pipeline, path)
+        }
+        _ => Err(format!("{} must be an array of stages", path)),
+    }
+}
+
+fn validate_stages(config: &AppConfig, pipeline: &[Document], path: &str) -> Result<(), String> {
+    for (i, stage) in pipeline.iter().enumerate() {
+        let stage_path = format!("{}.{}", path, i);
+        if stage.len() != 1 {
+            return Err(format!(
+                "{} must contain exactly one stage operator",
+                stage_path
+            ));
+        }
+        let (name, body) = stage.iter().next().unwrap();
+        if !ALLOWED_STAGES.contains(&name.as_str()) {
+            return Err(format!("stage {} is not allowed ({})", name, stage_path));
+        }
+        match name.as_str() {
+            "$lookup" => {
+                let lookup = match body.as_document() {
+                    Some(l) => l,
+                    None => return Err(format!("{}.$lookup must be a document", stage_path)),
+                };
+                // only catalogs can be joined
+                match lookup.get("from") {
+                    Some(Bson::String(from)) => {
+                        if let Err(e) = config.check_catalog(from) {
+                            return Err(format!("{} ({}.$lookup.from)", e, stage_path));
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "{}.$lookup.from must be the name of a catalog",
+                            stage_path
+                        ));
+                    }
+                }
+                if let Some(sub_pipeline) = lookup.get("pipeline") {
+                    validate_sub_pipeline(
+                        config,
+                        sub_pipeline,
+                        &format!("{}.$lookup.pipeline", stage_path),
+                    )?;
+                }
+            }
+            "$facet" => {
+                let facet = match body.as_document() {
+                    Some(f) => f,
+                    None => return Err(format!("{}.$facet must be a document", stage_path)),
+                };
+                for (facet_name, sub_pipeline) in facet {
+                    validate_sub_pipeline(
+                        config,
+                        sub_pipeline,
+                        &format!("{}.$facet.{}", stage_path, facet_name),
+                    )?;
+                }
+            }
+            _ => {}
+        }
+        validate_bson(body, &format!("{}.{}", stage_path, name), &PIPELINE_RULES)?;
+    }
+    Ok(())
+}
+
+// checks a user submitted aggregation pipeline against the stage allow-list
+// and the catalogs of the configuration
+pub fn validate_pipeline(config: &AppConfig, pipeline: &[Document]) -> Result<(), String> {
+    validate_stages(config, pipeline, "pipeline")
+}
+
+// applies the user's data rights to a validated pipeline: the source catalog
+// is restricted up front and every $lookup into a restricted catalog gets the
+// equivalent stages prepended to its own pipeline
+pub fn restrict_pipeline(
+    config: &AppConfig,
+    catalog: &str,
+    user: &AuthenticatedUser,
+    pipeline: Vec<Document>,
+) -> Vec<Document> {
+    let rights = DataRights::new(config, catalog, user);
+    let mut out_pipeline = Vec::new();
+    if rights.field.is_some() {
+        out_pipeline.push(doc! { "$match": rights.restrict_filter(doc! {}) });
+    }
+    out_pipeline.extend(rights.pipeline_stages());
+    for stage in pipeline {
+        out_pipeline.push(restrict_stage(config, user, stage));
+    }
+    out_pipeline
+}
+
+fn restrict_stage(config: &AppConfig, user: &AuthenticatedUser, mut stage: Document) -> Document {
+    if let Ok(lookup) = stage.get_document_mut("$lookup") {
+        let from = lookup.get_str("from").unwrap_or_default().to_string();
+        let inner_pipeline = match lookup.get_array("pipeline") {
+            Ok(p) => p
+                .iter()
+                .filter_map(|s| s.as_document().cloned())
+                .collect::<Vec<Document>>(),
+            Err(_) => Vec::new(),
+        };
+        let restricted = restrict_pipeline(config, &from, user, inner_pipeline);
+        if !restricted.is_empty() {
+            lookup.insert("pipeline", restricted);
+        }
+    } else if let Ok(facet) = stage.get_document_mut("$facet") {
+        // facets run on the (already restricted) input documents, only their
+        // own $lookup stages need restricting
+        for (_, sub_pipeline) in facet.iter_mut() {
+            if let Bson::Array(stages) = sub_pipeline {
+                for sub_stage in stages.iter_mut() {
+                    if let Bson::Document(d) = sub_stage {
+                        *d = restrict_stage(config, user, d.clone());
+                    }
+                }
+            }
+        }
+    }
+    stage
+}
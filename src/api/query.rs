@@ -1,13 +1,68 @@
-use crate::models::{query_models::*, response};
-use actix_web::{get, web, HttpResponse};
-use futures::TryStreamExt;
+use crate::{
+    api::{
+        pagination::{encode_token, Pagination, Position},
+        permissions::{count_with_rights, find_with_rights, DataRights},
+        pipeline::{restrict_pipeline, validate_pipeline},
+        validation::{validate_document, validate_optional, QUERY_RULES},
+    },
+    conf::AppConfig,
+    formats::{
+        columnar::columnar_response,
+        fits::FITS_CONTENT_TYPE,
+        ndjson::{ndjson_response, write_cursor, CursorOutcome, NdjsonWriter, Summary},
+        table::Table,
+        table_response, OutputFormat,
+    },
+    models::{auth_models::AuthenticatedUser, query_models::*, response},
+    spatial::{
+        document_position, healpix, moc::Moc, projection_with_position, regions::Region,
+        skymap::Skymap, Separation, POSITION_FIELD,
+    },
+};
+use actix_web::{get, guard::GuardContext, http::header, post, web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{
+    future::join_all,
+    stream::{FuturesUnordered, StreamExt},
+    TryStreamExt,
+};
 use mongodb::{
     bson::{doc, Document},
-    Client, Collection, IndexModel,
+    Client, Collection, Cursor, IndexModel,
+};
+use std::{
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use std::collections::HashMap;
 
-const DB_NAME: &str = "boom";
+// legacy GET requests carry their query as a JSON body
+fn json_body(ctx: &GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"))
+}
+
+fn no_json_body(ctx: &GuardContext) -> bool {
+    !json_body(ctx)
+}
+
+// position of the last document of a full page, from which the next page starts
+fn next_position(
+    pagination: &Pagination,
+    last: Option<&Document>,
+    count: usize,
+    limit: Option<i64>,
+) -> Result<Option<Position>, String> {
+    match (limit, last) {
+        (Some(limit), Some(last)) if limit > 0 && count as i64 >= limit => {
+            pagination.position_of(last).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
 
 // builds find options for mongo query
 pub fn build_options(
@@ -61,8 +116,10 @@ pub fn build_cone_search_filter(
     filter
 }
 
+// names of the configured catalogs found in the database
 pub async fn get_catalog_names(
     db: mongodb::Database,
+    config: &AppConfig,
 ) -> Result<Vec<String>, mongodb::error::Error> {
     // get collection names in alphabetical order
     let collection_names = match db.list_collection_names().await {
@@ -71,7 +128,7 @@ pub async fn get_catalog_names(
     };
     let mut data = collection_names
         .iter()
-        .filter(|name| !name.starts_with("system."))
+        .filter(|name| config.check_catalog(name).is_ok())
         .cloned()
         .collect::<Vec<String>>();
     data.sort();
@@ -125,12 +182,13 @@ pub async fn get_db_info(db: mongodb::Database) -> Result<Document, mongodb::err
     return Ok(data);
 }
 
-// retrieves a sample of a database collection
-pub async fn get_collection_sample(
+// cursor over a sample of a database collection
+pub async fn get_collection_sample_cursor(
     collection: Collection<Document>,
     size: i64,
-) -> Result<Option<Vec<Document>>, mongodb::error::Error> {
-    if size > 1000 || size < 0 {
+    rights: &DataRights,
+) -> Result<Cursor<Document>, mongodb::error::Error> {
+    if !(0..=1000).contains(&size) {
         return Err(mongodb::error::Error::from(std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
             "sample size must be between 0 and 1000",
@@ -142,7 +200,16 @@ pub async fn get_collection_sample(
     };
     // use find to get a sample of the collection
     let options = build_options(None, kwargs_sample);
-    let cursor = match collection.find(doc! {}).with_options(options).await {
+    find_with_rights(&collection, doc! {}, options, rights).await
+}
+
+// retrieves a sample of a database collection
+pub async fn get_collection_sample(
+    collection: Collection<Document>,
+    size: i64,
+    rights: &DataRights,
+) -> Result<Option<Vec<Document>>, mongodb::error::Error> {
+    let cursor = match get_collection_sample_cursor(collection, size, rights).await {
         Ok(c) => c,
         Err(e) => {
             return Err(e);
@@ -157,9 +224,9 @@ pub async fn get_collection_sample(
     return Ok(Some(docs));
 }
 
-#[get("/query/info")]
-pub async fn get_info(client: web::Data<Client>, body: web::Json<InfoQueryBody>) -> HttpResponse {
-    let db = client.database(DB_NAME);
+// runs an info query, shared by the POST, GET and legacy routes
+async fn run_info(client: &Client, config: &AppConfig, body: InfoQueryBody) -> HttpResponse {
+    let db = client.database(&config.database.name);
     let command = match body.command.clone() {
         Some(c) => c,
         None => {
@@ -168,7 +235,7 @@ pub async fn get_info(client: web::Data<Client>, body: web::Json<InfoQueryBody>)
     };
     // get collection names in alphabetical order
     if command == "catalog_names" {
-        let data = match get_catalog_names(db.clone()).await {
+        let data = match get_catalog_names(db.clone(), config).await {
             Ok(d) => d,
             Err(e) => {
                 return response::internal_error(&format!("Error getting catalog names: {:?}", e));
@@ -183,6 +250,9 @@ pub async fn get_info(client: web::Data<Client>, body: web::Json<InfoQueryBody>)
                 return response::bad_request("catalog(s) required for catalog_info");
             }
         };
+        if let Some(e) = catalogs.iter().find_map(|c| config.check_catalog(c).err()) {
+            return response::bad_request(&e);
+        }
         let data = match get_catalog_info(db.clone(), catalogs.clone()).await {
             Ok(d) => d,
             Err(e) => {
@@ -201,6 +271,9 @@ pub async fn get_info(client: web::Data<Client>, body: web::Json<InfoQueryBody>)
                 return response::bad_request("catalog(s) required for index_info");
             }
         };
+        if let Some(e) = catalogs.iter().find_map(|c| config.check_catalog(c).err()) {
+            return response::bad_request(&e);
+        }
         let data = get_index_info(db.clone(), catalogs.clone()).await;
         match data {
             Ok(d) => {
@@ -226,17 +299,78 @@ pub async fn get_info(client: web::Data<Client>, body: web::Json<InfoQueryBody>)
     }
 }
 
-#[get("/query/sample")]
-pub async fn sample(client: web::Data<Client>, body: web::Json<QueryBody>) -> HttpResponse {
-    let this_query = body.query.clone().unwrap_or_default();
+#[post("/query/info")]
+pub async fn get_info(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    body: web::Json<InfoQueryBody>,
+) -> HttpResponse {
+    run_info(&client, &config, body.into_inner()).await
+}
+
+#[get("/query/info", guard = "no_json_body")]
+pub async fn get_info_get(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    params: web::Query<InfoParams>,
+) -> HttpResponse {
+    match params.into_inner().into_body() {
+        Ok(body) => run_info(&client, &config, body).await,
+        Err(e) => response::bad_request(&e),
+    }
+}
+
+// deprecated: GET with a JSON body, kept for existing clients
+#[get("/query/info", guard = "json_body")]
+pub async fn get_info_legacy(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    body: web::Json<InfoQueryBody>,
+) -> HttpResponse {
+    response::deprecated(
+        run_info(&client, &config, body.into_inner()).await,
+        "/query/info",
+    )
+}
+
+// runs a sample query, shared by the POST, GET and legacy routes
+async fn run_sample(
+    client: &Client,
+    config: &AppConfig,
+    user: &AuthenticatedUser,
+    body: QueryBody,
+    format: OutputFormat,
+) -> HttpResponse {
+    let this_query = body.query.unwrap_or_default();
     let catalog = match this_query.catalog {
         Some(c) => c,
         None => return response::bad_request("catalog name required for sample"),
     };
+    if let Err(e) = config.check_catalog(&catalog) {
+        return response::bad_request(&e);
+    }
 
-    let collection: Collection<Document> = client.database(DB_NAME).collection(&catalog);
+    let collection: Collection<Document> =
+        client.database(&config.database.name).collection(&catalog);
     let size = this_query.size.unwrap_or(1);
-    let docs = match get_collection_sample(collection, size).await {
+    let rights = DataRights::new(config, &catalog, user);
+    if format == OutputFormat::Ndjson {
+        let cursor = match get_collection_sample_cursor(collection, size, &rights).await {
+            Ok(c) => c,
+            Err(e) => {
+                return response::internal_error(&format!("Error getting sample: {:?}", e));
+            }
+        };
+        let message = format!("Sample of collection: {}", catalog);
+        return ndjson_response(move |mut writer| async move {
+            let outcome = write_cursor(&mut writer, cursor, None).await;
+            if !outcome.disconnected {
+                let summary = Summary::new(&message, outcome.count, outcome.error, None);
+                writer.write(&summary).await;
+            }
+        });
+    }
+    let docs = match get_collection_sample(collection, size, &rights).await {
         Ok(d) => d,
         Err(e) => {
             return response::internal_error(&format!("Error getting sample: {:?}", e));
@@ -248,19 +382,92 @@ pub async fn sample(client: web::Data<Client>, body: web::Json<QueryBody>) -> Ht
     );
 }
 
-#[get("/query/count_documents")]
-pub async fn count_documents(
+#[post("/query/sample")]
+pub async fn sample(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<QueryBody>,
+) -> HttpResponse {
+    run_sample(
+        &client,
+        &config,
+        &user,
+        body.into_inner(),
+        OutputFormat::from_request(&req),
+    )
+    .await
+}
+
+#[get("/query/sample", guard = "no_json_body")]
+pub async fn sample_get(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    params: web::Query<SampleParams>,
+) -> HttpResponse {
+    match params.into_inner().into_body() {
+        Ok(body) => {
+            run_sample(
+                &client,
+                &config,
+                &user,
+                body,
+                OutputFormat::from_request(&req),
+            )
+            .await
+        }
+        Err(e) => response::bad_request(&e),
+    }
+}
+
+// deprecated: GET with a JSON body, kept for existing clients
+#[get("/query/sample", guard = "json_body")]
+pub async fn sample_legacy(
     client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
     body: web::Json<QueryBody>,
 ) -> HttpResponse {
-    let this_query = body.query.clone().unwrap_or_default();
+    response::deprecated(
+        run_sample(
+            &client,
+            &config,
+            &user,
+            body.into_inner(),
+            OutputFormat::from_request(&req),
+        )
+        .await,
+        "/query/sample",
+    )
+}
+
+// runs a count query, shared by the POST, GET and legacy routes
+async fn run_count_documents(
+    client: &Client,
+    config: &AppConfig,
+    user: &AuthenticatedUser,
+    body: QueryBody,
+) -> HttpResponse {
+    let this_query = body.query.unwrap_or_default();
     let catalog = match this_query.catalog {
         Some(c) => c,
         None => return response::bad_request("catalog name required for count_documents"),
     };
-    let collection: Collection<Document> = client.database(DB_NAME).collection(&catalog);
-    let filter = this_query.filter.unwrap_or(doc! {});
-    let doc_count = collection.count_documents(filter).await;
+    if let Err(e) = config.check_catalog(&catalog) {
+        return response::bad_request(&e);
+    }
+    let collection: Collection<Document> =
+        client.database(&config.database.name).collection(&catalog);
+    if let Err(e) = validate_optional(&this_query.filter, "filter", &QUERY_RULES) {
+        return response::bad_request(&format!("Invalid filter: {}", e));
+    }
+    let rights = DataRights::new(config, &catalog, user);
+    let filter = this_query.filter.unwrap_or_default();
+    let doc_count = count_with_rights(&collection, filter, &rights).await;
     match doc_count {
         Err(e) => {
             return response::internal_error(&format!("Error counting documents: {:?}", e));
@@ -274,32 +481,143 @@ pub async fn count_documents(
     }
 }
 
-#[get("/query/find")]
-pub async fn find(client: web::Data<Client>, body: web::Json<QueryBody>) -> HttpResponse {
-    let this_query = body.query.clone().unwrap_or_default();
-    let filter = match this_query.filter {
+#[post("/query/count_documents")]
+pub async fn count_documents(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    body: web::Json<QueryBody>,
+) -> HttpResponse {
+    run_count_documents(&client, &config, &user, body.into_inner()).await
+}
+
+#[get("/query/count_documents", guard = "no_json_body")]
+pub async fn count_documents_get(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    params: web::Query<CountParams>,
+) -> HttpResponse {
+    match params.into_inner().into_body() {
+        Ok(body) => run_count_documents(&client, &config, &user, body).await,
+        Err(e) => response::bad_request(&e),
+    }
+}
+
+// deprecated: GET with a JSON body, kept for existing clients
+#[get("/query/count_documents", guard = "json_body")]
+pub async fn count_documents_legacy(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    body: web::Json<QueryBody>,
+) -> HttpResponse {
+    response::deprecated(
+        run_count_documents(&client, &config, &user, body.into_inner()).await,
+        "/query/count_documents",
+    )
+}
+
+// catalog, filter and projection of a find query, validated
+pub fn find_query(
+    config: &AppConfig,
+    query: Query,
+) -> Result<(String, Document, Option<Document>), String> {
+    let filter = match query.filter {
         Some(f) => f,
-        None => {
-            return response::bad_request("filter required for find");
-        }
+        None => return Err("filter required for find".to_string()),
     };
-    let catalog = match this_query.catalog {
+    let catalog = match query.catalog {
         Some(c) => c,
-        None => {
-            return response::bad_request("catalog name required for find");
-        }
+        None => return Err("catalog name required for find".to_string()),
     };
-    let find_options = build_options(
-        this_query.projection,
-        body.kwargs.clone().unwrap_or_default(),
-    );
-    let collection: Collection<Document> = client.database(DB_NAME).collection(&catalog);
-    let cursor = match collection.find(filter).with_options(find_options).await {
+    config.check_catalog(&catalog)?;
+    if let Err(e) = validate_document(&filter, "filter", &QUERY_RULES) {
+        return Err(format!("Invalid filter: {}", e));
+    }
+    if let Err(e) = validate_optional(&query.projection, "projection", &QUERY_RULES) {
+        return Err(format!("Invalid projection: {}", e));
+    }
+    Ok((catalog, filter, query.projection))
+}
+
+// runs a find query, shared by the POST, GET and legacy routes
+async fn run_find(
+    client: &Client,
+    config: &AppConfig,
+    user: &AuthenticatedUser,
+    body: QueryBody,
+    format: OutputFormat,
+) -> HttpResponse {
+    let (catalog, filter, projection) = match find_query(config, body.query.unwrap_or_default()) {
+        Ok(q) => q,
+        Err(e) => return response::bad_request(&e),
+    };
+    let kwargs = body.kwargs.unwrap_or_default();
+    let format = match format.with_override(kwargs.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    // bulk exports are not capped by max_limit, their memory use is bounded
+    let mut kwargs = if format.is_columnar() {
+        config.apply_catalog_timeout(&catalog, kwargs)
+    } else {
+        config.apply_catalog_limits(&catalog, kwargs)
+    };
+    let pagination = match Pagination::from_kwargs(&kwargs) {
+        Ok(p) => p,
+        Err(e) => return response::bad_request(&e),
+    };
+    // binary formats have nowhere to put a continuation token
+    if format.is_columnar() && pagination.is_some() {
+        return response::bad_request("pagination is not supported with parquet or arrow outputs");
+    }
+    let mut filter = filter;
+    if let Some((pagination, positions)) = &pagination {
+        kwargs.sort = Some(pagination.sort());
+        filter = match pagination.continuation_filter(filter, positions.get("")) {
+            Ok(f) => f,
+            Err(e) => return response::bad_request(&format!("Invalid filter: {}", e)),
+        };
+    }
+    let limit = kwargs.limit;
+    let find_options = build_options(projection, kwargs);
+    let collection: Collection<Document> =
+        client.database(&config.database.name).collection(&catalog);
+    let rights = DataRights::new(config, &catalog, user);
+    let cursor = match find_with_rights(&collection, filter, find_options, &rights).await {
         Ok(c) => c,
         Err(e) => {
             return response::internal_error(&format!("Error finding documents: {:?}", e));
         }
     };
+    if format.is_columnar() {
+        return columnar_response(format, cursor);
+    }
+    if format == OutputFormat::Ndjson {
+        let message = format!("Found document(s) in {}", catalog);
+        return ndjson_response(move |mut writer| async move {
+            let outcome = write_cursor(&mut writer, cursor, None).await;
+            if outcome.disconnected {
+                return;
+            }
+            let mut error = outcome.error;
+            let mut next = None;
+            if let (Some((pagination, _)), None) = (&pagination, &error) {
+                let count = outcome.count as usize;
+                match next_position(pagination, outcome.last.as_ref(), count, limit) {
+                    Ok(Some(position)) => {
+                        next = Some(encode_token(pagination, &[(String::new(), position)]));
+                    }
+                    Ok(None) => {}
+                    Err(e) => error = Some(e),
+                }
+            }
+            writer
+                .write(&Summary::new(&message, outcome.count, error, next))
+                .await;
+        });
+    }
 
     let docs = match cursor.try_collect::<Vec<mongodb::bson::Document>>().await {
         Ok(d) => d,
@@ -307,18 +625,254 @@ pub async fn find(client: web::Data<Client>, body: web::Json<QueryBody>) -> Http
             return response::internal_error(&format!("Error collecting documents: {:?}", e));
         }
     };
-    return response::ok(
+    let next = match &pagination {
+        Some((pagination, _)) => match next_position(pagination, docs.last(), docs.len(), limit) {
+            Ok(Some(position)) => Some(encode_token(pagination, &[(String::new(), position)])),
+            Ok(None) => None,
+            Err(e) => return response::bad_request(&e),
+        },
+        None => None,
+    };
+    if format.is_tabular() {
+        let table = Table::from_documents(&catalog, &docs, &config.catalog(&catalog).units);
+        return table_response(format, &table, next);
+    }
+    response::ok_with_next(
         &format!("Found document(s) in {}", catalog),
         serde_json::json!(docs),
-    );
+        next,
+    )
 }
 
-#[get("/query/cone_search")]
-pub async fn cone_search(
+#[post("/query/find")]
+pub async fn find(
     client: web::Data<Client>,
-    body: web::Json<ConeSearchBody>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<QueryBody>,
+) -> HttpResponse {
+    run_find(
+        &client,
+        &config,
+        &user,
+        body.into_inner(),
+        OutputFormat::from_request(&req),
+    )
+    .await
+}
+
+#[get("/query/find", guard = "no_json_body")]
+pub async fn find_get(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    params: web::Query<FindParams>,
+) -> HttpResponse {
+    match params.into_inner().into_body() {
+        Ok(body) => {
+            run_find(
+                &client,
+                &config,
+                &user,
+                body,
+                OutputFormat::from_request(&req),
+            )
+            .await
+        }
+        Err(e) => response::bad_request(&e),
+    }
+}
+
+// deprecated: GET with a JSON body, kept for existing clients
+#[get("/query/find", guard = "json_body")]
+pub async fn find_legacy(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<QueryBody>,
+) -> HttpResponse {
+    response::deprecated(
+        run_find(
+            &client,
+            &config,
+            &user,
+            body.into_inner(),
+            OutputFormat::from_request(&req),
+        )
+        .await,
+        "/query/find",
+    )
+}
+
+// writes the matches of an object with their separation, which requires
+// buffering them to sort them (only the k closest with k)
+async fn write_separated(
+    writer: &mut NdjsonWriter,
+    cursor: Cursor<Document>,
+    separation: &Separation,
+    center: (f64, f64),
+    object_name: &str,
+) -> CursorOutcome {
+    let mut outcome = CursorOutcome {
+        count: 0,
+        last: None,
+        error: None,
+        disconnected: false,
+    };
+    let documents = match separation.collect(center, cursor).await {
+        Ok(d) => d,
+        Err(e) => {
+            outcome.error = Some(format!("Error collecting documents: {:?}", e));
+            return outcome;
+        }
+    };
+    for document in documents {
+        let line = serde_json::json!({ "object": object_name, "data": &document });
+        if !writer.write(&line).await {
+            outcome.disconnected = true;
+            return outcome;
+        }
+        outcome.count += 1;
+        outcome.last = Some(document);
+    }
+    outcome
+}
+
+// cone searches of a set of objects in one catalog
+struct CatalogSearch {
+    catalog: String,
+    collection: Collection<Document>,
+    // position and cone search filter of each object
+    searches: Vec<(String, (f64, f64), Document)>,
+    find_options: mongodb::options::FindOptions,
+    rights: DataRights,
+    limit: Option<i64>,
+    separation: Option<Separation>,
+}
+
+impl CatalogSearch {
+    // runs the searches one object after the other, buffering the results
+    async fn run(&self) -> Result<Vec<(String, Vec<Document>)>, String> {
+        let mut results = Vec::new();
+        for (object_name, center, filter) in &self.searches {
+            let cursor = match find_with_rights(
+                &self.collection,
+                filter.clone(),
+                self.find_options.clone(),
+                &self.rights,
+            )
+            .await
+            {
+                Ok(c) => c,
+                Err(e) => return Err(format!("Error finding documents: {:?}", e)),
+            };
+            let data = match &self.separation {
+                Some(separation) => separation.collect(*center, cursor).await,
+                None => cursor.try_collect::<Vec<Document>>().await,
+            };
+            let data = match data {
+                Ok(d) => d,
+                Err(e) => return Err(format!("Error collecting documents: {:?}", e)),
+            };
+            results.push((object_name.clone(), data));
+        }
+        Ok(results)
+    }
+}
+
+// cone search on several catalogs at once, the catalogs being searched
+// concurrently. Results are keyed by catalog, then object name.
+async fn run_catalogs_cone_search(
+    config: &AppConfig,
+    searches: Vec<CatalogSearch>,
+    format: OutputFormat,
+) -> HttpResponse {
+    let names: Vec<&str> = searches.iter().map(|s| s.catalog.as_str()).collect();
+    let message = format!("Cone Search on {} completed", names.join(", "));
+
+    if format == OutputFormat::Ndjson {
+        // each catalog is written as soon as its searches are done, lines
+        // are wrapped as {"catalog": ..., "object": ..., "data": <document>}
+        return ndjson_response(move |mut writer| async move {
+            let mut pending: FuturesUnordered<_> = searches
+                .iter()
+                .map(|search| async move { (search.catalog.as_str(), search.run().await) })
+                .collect();
+            let mut count = 0;
+            let mut error = None;
+            while let Some((catalog, results)) = pending.next().await {
+                let results = match results {
+                    Ok(r) => r,
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                };
+                for (object_name, data) in results {
+                    for document in data {
+                        let line = serde_json::json!({
+                            "catalog": catalog,
+                            "object": object_name,
+                            "data": document,
+                        });
+                        if !writer.write(&line).await {
+                            return;
+                        }
+                        count += 1;
+                    }
+                }
+            }
+            writer
+                .write(&Summary::new(&message, count, error, None))
+                .await;
+        });
+    }
+
+    let results = join_all(searches.iter().map(|search| search.run())).await;
+    let mut docs: HashMap<String, HashMap<String, Vec<Document>>> = HashMap::new();
+    for (search, result) in searches.iter().zip(results) {
+        match result {
+            Ok(r) => {
+                docs.insert(search.catalog.clone(), r.into_iter().collect());
+            }
+            Err(e) => return response::internal_error(&e),
+        }
+    }
+    if format.is_tabular() {
+        // one row per match, prefixed with the catalog and the searched object
+        let mut rows = Vec::new();
+        let mut units = HashMap::new();
+        for search in &searches {
+            units.extend(config.catalog(&search.catalog).units);
+            let objects = &docs[&search.catalog];
+            let mut object_names: Vec<&String> = objects.keys().collect();
+            object_names.sort();
+            for object_name in object_names {
+                for document in &objects[object_name] {
+                    let mut row = doc! { "catalog": &search.catalog, "object": object_name };
+                    row.extend(document.clone());
+                    rows.push(row);
+                }
+            }
+        }
+        let table = Table::from_documents("cone_search", &rows, &units);
+        return table_response(format, &table, None);
+    }
+    response::ok(&message, serde_json::json!(docs))
+}
+
+// runs a cone search, shared by the POST, GET and legacy routes
+async fn run_cone_search(
+    client: &Client,
+    config: &AppConfig,
+    user: &AuthenticatedUser,
+    body: ConeSearchBody,
+    format: OutputFormat,
 ) -> HttpResponse {
-    let this_body = body.clone();
+    let this_body = body;
     let radius = match this_body.radius {
         Some(r) => r,
         None => return response::bad_request("radius required for cone_search"),
@@ -333,57 +887,930 @@ pub async fn cone_search(
             return response::bad_request("object_coordinates required for cone_search");
         }
     };
-    let catalog_details = match this_body.catalog {
-        Some(c) => c,
-        None => {
+    // positions in ICRS degrees, validated before any query
+    let frame = this_body.frame.unwrap_or_default();
+    let mut positions = Vec::new();
+    for (object_name, coordinates) in object_coordinates {
+        match coordinates.to_icrs(&frame) {
+            Ok(radec) => positions.push((object_name, radec)),
+            Err(e) => {
+                return response::bad_request(&format!(
+                    "Invalid coordinates for {}: {}",
+                    object_name, e
+                ));
+            }
+        }
+    }
+    // (catalog, filter, projection, path of the catalog in the body)
+    let multiple_catalogs = this_body.catalogs.is_some();
+    let catalogs = match (this_body.catalog, this_body.catalogs) {
+        (Some(_), Some(_)) => {
+            return response::bad_request("catalog and catalogs cannot be used together");
+        }
+        (Some(catalog_details), None) => match catalog_details.catalog_name {
+            Some(c) => vec![(
+                c,
+                catalog_details.filter,
+                catalog_details.projection,
+                "catalog".to_string(),
+            )],
+            None => {
+                return response::bad_request("catalog_name required for catalog_details");
+            }
+        },
+        (None, Some(catalogs)) if !catalogs.is_empty() => {
+            let mut catalogs: Vec<_> = catalogs
+                .into_iter()
+                .map(|(name, c)| {
+                    let path = format!("catalogs.{}", name);
+                    (name, c.filter, c.projection, path)
+                })
+                .collect();
+            catalogs.sort_by(|a, b| a.0.cmp(&b.0));
+            catalogs
+        }
+        _ => {
             return response::bad_request("catalog(s) required for cone_search");
         }
     };
-    let catalog = match catalog_details.catalog_name {
-        Some(c) => c,
-        None => {
-            return response::bad_request("catalog_name required for catalog_details");
+    for (catalog, filter, projection, path) in &catalogs {
+        if let Err(e) = config.check_catalog(catalog) {
+            return response::bad_request(&e);
+        }
+        if let Err(e) = validate_optional(filter, &format!("{}.filter", path), &QUERY_RULES) {
+            return response::bad_request(&format!("Invalid filter: {}", e));
+        }
+        if let Err(e) = validate_optional(projection, &format!("{}.projection", path), &QUERY_RULES)
+        {
+            return response::bad_request(&format!("Invalid projection: {}", e));
         }
+    }
+
+    let mut kwargs = this_body.kwargs.unwrap_or_default();
+    let format = match format.with_override(kwargs.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
     };
+    if format.is_columnar() {
+        return response::bad_request(
+            "parquet and arrow outputs are only supported by find and aggregate",
+        );
+    }
+    let pagination = match Pagination::from_kwargs(&kwargs) {
+        Ok(p) => p,
+        Err(e) => return response::bad_request(&e),
+    };
+    if multiple_catalogs && pagination.is_some() {
+        return response::bad_request("pagination is only supported on a single catalog");
+    }
+    let k = match (this_body.k, this_body.nearest_only) {
+        (Some(0), _) => return response::bad_request("k must be a positive integer"),
+        (Some(k), _) => Some(k),
+        (None, Some(true)) => Some(1),
+        _ => None,
+    };
+    let sort_by_separation = this_body.sort_by_separation.unwrap_or(false);
+    if pagination.is_some() && (sort_by_separation || k.is_some()) {
+        // pages follow the pagination sort, not the separation
+        return response::bad_request(
+            "pagination cannot be combined with sort_by_separation, nearest_only or k",
+        );
+    }
+    let with_separation =
+        this_body.separation.unwrap_or(false) || sort_by_separation || k.is_some();
+    if let Some((pagination, _)) = &pagination {
+        kwargs.sort = Some(pagination.sort());
+    }
 
-    let collection: Collection<Document> = client.database(DB_NAME).collection(&catalog);
+    let db = client.database(&config.database.name);
+    let mut catalog_searches = Vec::new();
+    for (catalog, filter, projection, _) in catalogs {
+        let mut catalog_kwargs = config.apply_catalog_limits(&catalog, kwargs.clone());
+        let limit = catalog_kwargs.limit;
+        // the k nearest are chosen among every match within the radius, only
+        // k of them being held at once. The limit caps k instead of the
+        // matches read
+        let k = k.map(|k| match limit {
+            Some(limit) if limit > 0 => k.min(limit as usize),
+            _ => k,
+        });
+        if k.is_some() {
+            catalog_kwargs.limit = None;
+        }
+        let input_filter = filter.unwrap_or_default();
+        // the separation is computed from the position of the matches
+        let (projection, separation) = if with_separation {
+            let (projection, strip) = projection_with_position(projection);
+            let separation = Separation {
+                unit: unit.clone(),
+                sort: sort_by_separation,
+                k,
+                strip,
+            };
+            (projection, Some(separation))
+        } else {
+            (projection, None)
+        };
 
-    let projection = catalog_details.projection;
-    let input_filter = catalog_details.filter.unwrap_or(doc! {});
+        // build the cone search filter of each set of object coordinates
+        let mut searches = Vec::new();
+        for (object_name, radec) in &positions {
+            let mut filter =
+                build_cone_search_filter(input_filter.clone(), *radec, radius, unit.clone());
+            if let Some((pagination, positions)) = &pagination {
+                // when resuming, only the objects with more results are searched
+                if !positions.is_empty() && !positions.contains_key(object_name) {
+                    continue;
+                }
+                filter = match pagination.continuation_filter(filter, positions.get(object_name)) {
+                    Ok(f) => f,
+                    Err(e) => return response::bad_request(&format!("Invalid filter: {}", e)),
+                };
+            }
+            searches.push((object_name.clone(), *radec, filter));
+        }
+        catalog_searches.push(CatalogSearch {
+            collection: db.collection(&catalog),
+            rights: DataRights::new(config, &catalog, user),
+            find_options: build_options(projection, catalog_kwargs),
+            catalog,
+            searches,
+            limit,
+            separation,
+        });
+    }
 
-    let kwargs = this_body.kwargs.unwrap_or_default();
-    let find_options = build_options(projection, kwargs);
+    if multiple_catalogs {
+        return run_catalogs_cone_search(config, catalog_searches, format).await;
+    }
+    let search = match catalog_searches.pop() {
+        Some(s) => s,
+        None => return response::bad_request("catalog(s) required for cone_search"),
+    };
+
+    if format == OutputFormat::Ndjson {
+        let CatalogSearch {
+            catalog,
+            collection,
+            searches,
+            find_options,
+            rights,
+            limit,
+            separation,
+        } = search;
+        let message = format!("Cone Search on {} completed", catalog);
+        // objects are searched one after the other, each cursor streamed as it is read
+        return ndjson_response(move |mut writer| async move {
+            let mut count = 0;
+            let mut error = None;
+            let mut next_positions = Vec::new();
+            for (object_name, center, filter) in searches {
+                let cursor = match find_with_rights(
+                    &collection,
+                    filter,
+                    find_options.clone(),
+                    &rights,
+                )
+                .await
+                {
+                    Ok(c) => c,
+                    Err(e) => {
+                        error = Some(format!("Error finding documents: {:?}", e));
+                        break;
+                    }
+                };
+                let outcome = match &separation {
+                    Some(separation) => {
+                        write_separated(&mut writer, cursor, separation, center, &object_name).await
+                    }
+                    None => write_cursor(&mut writer, cursor, Some(&object_name)).await,
+                };
+                if outcome.disconnected {
+                    return;
+                }
+                count += outcome.count;
+                if outcome.error.is_some() {
+                    error = outcome.error;
+                    break;
+                }
+                if let Some((pagination, _)) = &pagination {
+                    let object_count = outcome.count as usize;
+                    match next_position(pagination, outcome.last.as_ref(), object_count, limit) {
+                        Ok(Some(position)) => next_positions.push((object_name, position)),
+                        Ok(None) => {}
+                        Err(e) => {
+                            error = Some(e);
+                            break;
+                        }
+                    }
+                }
+            }
+            let next = match &pagination {
+                Some((pagination, _)) if !next_positions.is_empty() => {
+                    Some(encode_token(pagination, &next_positions))
+                }
+                _ => None,
+            };
+            writer
+                .write(&Summary::new(&message, count, error, next))
+                .await;
+        });
+    }
 
     // perform cone search over each set of object coordinates
+    let results = match search.run().await {
+        Ok(r) => r,
+        Err(e) => return response::internal_error(&e),
+    };
     let mut docs: HashMap<String, Vec<mongodb::bson::Document>> = HashMap::new();
-    for (object_name, radec) in object_coordinates {
-        let filter = build_cone_search_filter(
-            input_filter.clone(),
-            (radec[0], radec[1]),
-            radius,
-            unit.clone(),
-        );
-        let cursor = match collection
-            .find(filter)
-            .with_options(find_options.clone())
+    let mut next_positions = Vec::new();
+    for (object_name, data) in results {
+        if let Some((pagination, _)) = &pagination {
+            match next_position(pagination, data.last(), data.len(), search.limit) {
+                Ok(Some(position)) => next_positions.push((object_name.clone(), position)),
+                Ok(None) => {}
+                Err(e) => return response::bad_request(&e),
+            }
+        }
+        docs.insert(object_name, data);
+    }
+    let next = match &pagination {
+        Some((pagination, _)) if !next_positions.is_empty() => {
+            Some(encode_token(pagination, &next_positions))
+        }
+        _ => None,
+    };
+    if format.is_tabular() {
+        // one row per match, prefixed with the name of the searched object
+        let mut object_names: Vec<&String> = docs.keys().collect();
+        object_names.sort();
+        let mut rows = Vec::new();
+        for object_name in object_names {
+            for document in &docs[object_name] {
+                let mut row = doc! { "object": object_name };
+                row.extend(document.clone());
+                rows.push(row);
+            }
+        }
+        let units = config.catalog(&search.catalog).units;
+        let table = Table::from_documents(&search.catalog, &rows, &units);
+        return table_response(format, &table, next);
+    }
+    response::ok_with_next(
+        &format!("Cone Search on {} completed", search.catalog),
+        serde_json::json!(docs),
+        next,
+    )
+}
+
+#[post("/query/cone_search")]
+pub async fn cone_search(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<ConeSearchBody>,
+) -> HttpResponse {
+    run_cone_search(
+        &client,
+        &config,
+        &user,
+        body.into_inner(),
+        OutputFormat::from_request(&req),
+    )
+    .await
+}
+
+#[get("/query/cone_search", guard = "no_json_body")]
+pub async fn cone_search_get(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    params: web::Query<ConeSearchParams>,
+) -> HttpResponse {
+    match params.into_inner().into_body() {
+        Ok(body) => {
+            run_cone_search(
+                &client,
+                &config,
+                &user,
+                body,
+                OutputFormat::from_request(&req),
+            )
             .await
-        {
-            Ok(c) => c,
+        }
+        Err(e) => response::bad_request(&e),
+    }
+}
+
+// deprecated: GET with a JSON body, kept for existing clients
+#[get("/query/cone_search", guard = "json_body")]
+pub async fn cone_search_legacy(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<ConeSearchBody>,
+) -> HttpResponse {
+    response::deprecated(
+        run_cone_search(
+            &client,
+            &config,
+            &user,
+            body.into_inner(),
+            OutputFormat::from_request(&req),
+        )
+        .await,
+        "/query/cone_search",
+    )
+}
+
+// finds the documents within a sky region. Boxes and ellipses are queried
+// with a larger GeoJSON shape, their matches being checked one by one.
+#[post("/query/region_search")]
+pub async fn region_search(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<RegionSearchBody>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let unit = body.unit.unwrap_or(Unit::Degrees);
+    let region = match body.region {
+        Some(r) => r.in_degrees(&unit),
+        None => return response::bad_request("region required for region_search"),
+    };
+    if let Err(e) = region.validate() {
+        return response::bad_request(&format!("Invalid region: {}", e));
+    }
+    let catalog_details = match body.catalog {
+        Some(c) => c,
+        None => return response::bad_request("catalog required for region_search"),
+    };
+    let catalog = match catalog_details.catalog_name {
+        Some(c) => c,
+        None => return response::bad_request("catalog_name required for catalog_details"),
+    };
+    if let Err(e) = config.check_catalog(&catalog) {
+        return response::bad_request(&e);
+    }
+    if let Err(e) = validate_optional(&catalog_details.filter, "catalog.filter", &QUERY_RULES) {
+        return response::bad_request(&format!("Invalid filter: {}", e));
+    }
+    if let Err(e) = validate_optional(
+        &catalog_details.projection,
+        "catalog.projection",
+        &QUERY_RULES,
+    ) {
+        return response::bad_request(&format!("Invalid projection: {}", e));
+    }
+
+    let mut kwargs = config.apply_catalog_limits(&catalog, body.kwargs.unwrap_or_default());
+    let format = match OutputFormat::from_request(&req).with_override(kwargs.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    if format.is_columnar() {
+        return response::bad_request(
+            "parquet and arrow outputs are only supported by find and aggregate",
+        );
+    }
+    match Pagination::from_kwargs(&kwargs) {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return response::bad_request("pagination is not supported by region_search");
+        }
+        Err(e) => return response::bad_request(&e),
+    }
+    let filter = match catalog_details.filter {
+        Some(f) if !f.is_empty() => doc! { "$and": [f, region.filter()] },
+        _ => region.filter(),
+    };
+    // the limit applies to the matches left by the post filter, which needs
+    // the position of the documents
+    let (projection, strip, limit) = if region.needs_post_filter() {
+        let (projection, strip) = projection_with_position(catalog_details.projection);
+        (projection, strip, kwargs.limit.take().filter(|l| *l > 0))
+    } else {
+        (catalog_details.projection, None, None)
+    };
+    let find_options = build_options(projection, kwargs);
+    let collection: Collection<Document> =
+        client.database(&config.database.name).collection(&catalog);
+    let rights = DataRights::new(&config, &catalog, &user);
+    let mut cursor = match find_with_rights(&collection, filter, find_options, &rights).await {
+        Ok(c) => c,
+        Err(e) => {
+            return response::internal_error(&format!("Error finding documents: {:?}", e));
+        }
+    };
+    let message = format!("Region Search on {} completed", catalog);
+    let full = move |count: usize| limit.is_some_and(|l| count as i64 >= l);
+
+    if format == OutputFormat::Ndjson {
+        return ndjson_response(move |mut writer| async move {
+            let mut count = 0;
+            let mut error = None;
+            while !full(count) {
+                match cursor.try_next().await {
+                    Ok(Some(document)) => {
+                        if let Some(document) = region.post_filter(document, strip.as_deref()) {
+                            if !writer.write(&document).await {
+                                return;
+                            }
+                            count += 1;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        error = Some(format!("Error collecting documents: {:?}", e));
+                        break;
+                    }
+                }
+            }
+            writer
+                .write(&Summary::new(&message, count as u64, error, None))
+                .await;
+        });
+    }
+
+    let mut docs = Vec::new();
+    while !full(docs.len()) {
+        match cursor.try_next().await {
+            Ok(Some(document)) => docs.extend(region.post_filter(document, strip.as_deref())),
+            Ok(None) => break,
             Err(e) => {
-                return response::internal_error(&format!("Error finding documents: {:?}", e));
+                return response::internal_error(&format!("Error collecting documents: {:?}", e));
             }
-        };
-        // create map entry for this object's cone search
-        let data = match cursor.try_collect::<Vec<mongodb::bson::Document>>().await {
-            Ok(d) => d,
+        }
+    }
+    if format.is_tabular() {
+        let table = Table::from_documents(&catalog, &docs, &config.catalog(&catalog).units);
+        return table_response(format, &table, None);
+    }
+    response::ok(&message, serde_json::json!(docs))
+}
+
+// default and deepest HEALPix orders of catalog coverages
+const COVERAGE_ORDER: u8 = 8;
+const MAX_COVERAGE_ORDER: u8 = 12;
+// default of api.coverage_cache_secs
+const COVERAGE_CACHE_SECS: u64 = 3600;
+
+// coverage of a catalog at an order, for the documents matching a data
+// rights filter
+type CoverageKey = (String, u8, String);
+// a coverage and when it was computed, empty until the first request is done
+type CoverageSlot = Arc<tokio::sync::Mutex<Option<(Instant, Moc)>>>;
+
+// coverages computed recently, shared by the workers. Each one reads every
+// position of a catalog, so a single request computes it while the others
+// with the same key wait for its result.
+#[derive(Default)]
+pub struct CoverageCache {
+    entries: Mutex<HashMap<CoverageKey, CoverageSlot>>,
+}
+
+impl CoverageCache {
+    fn slot(&self, key: CoverageKey) -> CoverageSlot {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.entry(key).or_default().clone()
+    }
+}
+
+// MOC of the cells containing the positions of the documents of a catalog
+async fn compute_coverage(
+    collection: &Collection<Document>,
+    find_options: mongodb::options::FindOptions,
+    rights: &DataRights,
+    order: u8,
+) -> Result<Moc, String> {
+    let filter = doc! { POSITION_FIELD: { "$exists": true } };
+    let mut cursor = find_with_rights(collection, filter, find_options, rights)
+        .await
+        .map_err(|e| format!("Error finding documents: {:?}", e))?;
+    let mut pixels = std::collections::BTreeSet::new();
+    while let Some(document) = cursor
+        .try_next()
+        .await
+        .map_err(|e| format!("Error collecting documents: {:?}", e))?
+    {
+        if let Some((ra, dec)) = document_position(&document) {
+            pixels.insert(healpix::pixel(order, ra, dec));
+        }
+    }
+    Moc::from_cells(pixels.into_iter().map(|pixel| (order, pixel)))
+}
+
+// MOC of the cells containing the documents of a catalog visible to the user.
+// Every position is read, within the max_time_ms of the catalog, and the
+// result reused for api.coverage_cache_secs.
+#[get("/query/coverage/{catalog}")]
+pub async fn coverage(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    cache: web::Data<CoverageCache>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    catalog: web::Path<String>,
+    params: web::Query<CoverageParams>,
+) -> HttpResponse {
+    let catalog = catalog.into_inner();
+    if let Err(e) = config.check_catalog(&catalog) {
+        return response::bad_request(&e);
+    }
+    let order = params.order.unwrap_or(COVERAGE_ORDER);
+    if order > MAX_COVERAGE_ORDER {
+        return response::bad_request(&format!("order must be at most {}", MAX_COVERAGE_ORDER));
+    }
+    let accepts_fits = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains(FITS_CONTENT_TYPE));
+    let fits = match params.format.as_deref() {
+        Some("fits") => true,
+        Some("json") => false,
+        Some(f) => {
+            return response::bad_request(&format!("unknown format {}, expected json or fits", f));
+        }
+        None => accepts_fits,
+    };
+
+    let rights = DataRights::new(&config, &catalog, &user);
+    // users seeing the same documents share their coverages
+    let key = (
+        catalog.clone(),
+        order,
+        rights.restrict_filter(doc! {}).to_string(),
+    );
+    let ttl = Duration::from_secs(
+        config
+            .api
+            .coverage_cache_secs
+            .unwrap_or(COVERAGE_CACHE_SECS),
+    );
+    let slot = cache.slot(key);
+    let mut entry = slot.lock().await;
+    let moc = match entry.as_ref() {
+        Some((computed, moc)) if computed.elapsed() < ttl => moc.clone(),
+        _ => {
+            // the limit of the catalog does not apply, only its max_time_ms
+            let mut kwargs = config.apply_catalog_limits(&catalog, QueryKwargs::default());
+            kwargs.limit = None;
+            let find_options = build_options(Some(doc! { "_id": 0, POSITION_FIELD: 1 }), kwargs);
+            let collection: Collection<Document> =
+                client.database(&config.database.name).collection(&catalog);
+            match compute_coverage(&collection, find_options, &rights, order).await {
+                Ok(moc) => {
+                    *entry = Some((Instant::now(), moc.clone()));
+                    moc
+                }
+                Err(e) => return response::internal_error(&e),
+            }
+        }
+    };
+    drop(entry);
+    if fits {
+        return HttpResponse::Ok()
+            .content_type(FITS_CONTENT_TYPE)
+            .body(moc.to_fits());
+    }
+    response::ok(
+        &format!("Coverage of {} at order {}", catalog, order),
+        serde_json::json!({
+            "order": order,
+            "sky_fraction": moc.sky_fraction(),
+            "moc": moc.to_json(),
+        }),
+    )
+}
+
+// alerts searched by skymap_crossmatch, and the field added to them
+const SKYMAP_CATALOG: &str = "ZTF_alerts";
+const SKYMAP_FIELD: &str = "skymap";
+// default of skymaps.max_matches
+const SKYMAP_MAX_MATCHES: usize = 10_000;
+
+// an alert within a skymap, ordered by credible level so that the least
+// probable one sits at the top of a heap
+struct SkymapMatch {
+    credible_level: f64,
+    document: Document,
+}
+
+impl PartialEq for SkymapMatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for SkymapMatch {}
+
+impl PartialOrd for SkymapMatch {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SkymapMatch {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.credible_level.total_cmp(&other.credible_level)
+    }
+}
+
+// finds the alerts within the credible region of a gravitational-wave skymap,
+// queried as a MOC, adding the probability density and credible level at
+// their position. The most probable alerts come first, the limit applying
+// after sorting; only that many (at most skymaps.max_matches) are held in
+// memory at once.
+#[post("/query/skymap_crossmatch")]
+pub async fn skymap_crossmatch(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<SkymapCrossmatchBody>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let level = body.credible_level.unwrap_or(0.9);
+    if !(level > 0.0 && level <= 1.0) {
+        return response::bad_request("credible_level must be between 0 and 1");
+    }
+    if let (Some(min), Some(max)) = (body.jd_min, body.jd_max) {
+        if min > max {
+            return response::bad_request("jd_min must be smaller than jd_max");
+        }
+    }
+    if let Err(e) = validate_optional(&body.filter, "filter", &QUERY_RULES) {
+        return response::bad_request(&format!("Invalid filter: {}", e));
+    }
+    if let Err(e) = validate_optional(&body.projection, "projection", &QUERY_RULES) {
+        return response::bad_request(&format!("Invalid projection: {}", e));
+    }
+    let mut kwargs = config.apply_catalog_limits(SKYMAP_CATALOG, body.kwargs.unwrap_or_default());
+    let format = match OutputFormat::from_request(&req).with_override(kwargs.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    if format.is_columnar() {
+        return response::bad_request(
+            "parquet and arrow outputs are only supported by find and aggregate",
+        );
+    }
+    match Pagination::from_kwargs(&kwargs) {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return response::bad_request("pagination is not supported by skymap_crossmatch");
+        }
+        Err(e) => return response::bad_request(&e),
+    }
+
+    let skymap = match body.skymap {
+        Some(SkymapSource {
+            fits: Some(fits),
+            path: None,
+        }) => match STANDARD.decode(fits.trim()) {
+            Ok(bytes) => Skymap::from_fits(&bytes),
+            Err(_) => Err("fits must be base64 encoded".to_string()),
+        },
+        Some(SkymapSource {
+            fits: None,
+            path: Some(path),
+        }) => match &config.skymaps.directory {
+            Some(directory) => Skymap::from_path(directory, &path),
+            None => Err("skymap paths are not enabled on this server".to_string()),
+        },
+        Some(_) => Err("a skymap takes one of fits or path".to_string()),
+        None => return response::bad_request("skymap required for skymap_crossmatch"),
+    };
+    let skymap = match skymap {
+        Ok(s) => s,
+        Err(e) => return response::bad_request(&format!("Invalid skymap: {}", e)),
+    };
+    let moc = match skymap.credible_region(level) {
+        Ok(m) => m,
+        Err(e) => return response::bad_request(&format!("Invalid skymap: {}", e)),
+    };
+    // in square degrees
+    let area =
+        moc.sky_fraction() * 4.0 * std::f64::consts::PI * (180.0 / std::f64::consts::PI).powi(2);
+    let region = Region::Moc(moc);
+
+    let mut filters = Vec::new();
+    if let Some(f) = body.filter.filter(|f| !f.is_empty()) {
+        filters.push(f);
+    }
+    let mut jd = Document::new();
+    if let Some(min) = body.jd_min {
+        jd.insert("$gte", min);
+    }
+    if let Some(max) = body.jd_max {
+        jd.insert("$lte", max);
+    }
+    if !jd.is_empty() {
+        filters.push(doc! { "candidate.jd": jd });
+    }
+    filters.push(region.filter());
+    let filter = match filters.len() {
+        1 => filters.remove(0),
+        _ => doc! { "$and": filters },
+    };
+    // every match is read to be sorted, the limit applying afterwards
+    let max_matches = config.skymaps.max_matches.unwrap_or(SKYMAP_MAX_MATCHES);
+    let keep = match kwargs.limit.take().filter(|l| *l > 0) {
+        Some(limit) => (limit as usize).min(max_matches),
+        None => max_matches,
+    };
+    let (projection, strip) = projection_with_position(body.projection);
+    let find_options = build_options(projection, kwargs);
+    let collection: Collection<Document> = client
+        .database(&config.database.name)
+        .collection(SKYMAP_CATALOG);
+    let rights = DataRights::new(&config, SKYMAP_CATALOG, &user);
+    let mut cursor = match find_with_rights(&collection, filter, find_options, &rights).await {
+        Ok(c) => c,
+        Err(e) => {
+            return response::internal_error(&format!("Error finding documents: {:?}", e));
+        }
+    };
+    let mut matches = BinaryHeap::new();
+    let mut total: u64 = 0;
+    loop {
+        match cursor.try_next().await {
+            Ok(Some(document)) => {
+                let (ra, dec) = match document_position(&document) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                if let (Some((probdensity, credible_level)), Some(mut document)) = (
+                    skymap.at(ra, dec),
+                    region.post_filter(document, strip.as_deref()),
+                ) {
+                    document.insert(
+                        SKYMAP_FIELD,
+                        doc! {
+                            "probdensity": probdensity,
+                            "credible_level": credible_level,
+                        },
+                    );
+                    total += 1;
+                    matches.push(SkymapMatch {
+                        credible_level,
+                        document,
+                    });
+                    // drop the least probable alert once over the limit
+                    if matches.len() > keep {
+                        matches.pop();
+                    }
+                }
+            }
+            Ok(None) => break,
             Err(e) => {
                 return response::internal_error(&format!("Error collecting documents: {:?}", e));
             }
-        };
-        docs.insert(object_name, data);
+        }
     }
-    return response::ok(
-        &format!("Cone Search on {} completed", catalog),
-        serde_json::json!(docs),
+    let docs: Vec<Document> = matches
+        .into_sorted_vec()
+        .into_iter()
+        .map(|m| m.document)
+        .collect();
+    let message = format!(
+        "Skymap Crossmatch on {} completed, {:.1} deg2 within the {}% credible region, {} of {} alerts returned",
+        SKYMAP_CATALOG,
+        area,
+        level * 100.0,
+        docs.len(),
+        total
     );
+
+    if format == OutputFormat::Ndjson {
+        return ndjson_response(move |mut writer| async move {
+            for document in &docs {
+                if !writer.write(document).await {
+                    return;
+                }
+            }
+            writer
+                .write(&Summary::new(&message, docs.len() as u64, None, None))
+                .await;
+        });
+    }
+    if format.is_tabular() {
+        let table =
+            Table::from_documents(SKYMAP_CATALOG, &docs, &config.catalog(SKYMAP_CATALOG).units);
+        return table_response(format, &table, None);
+    }
+    response::ok(&message, serde_json::json!(docs))
+}
+
+// catalog and pipeline of an aggregation, validated
+pub fn aggregate_query(
+    config: &AppConfig,
+    body: AggregateBody,
+) -> Result<(String, Vec<Document>), String> {
+    let catalog = match body.catalog {
+        Some(c) => c,
+        None => return Err("catalog name required for aggregate".to_string()),
+    };
+    config.check_catalog(&catalog)?;
+    let pipeline = match body.pipeline {
+        Some(p) => p,
+        None => return Err("pipeline required for aggregate".to_string()),
+    };
+    if let Err(e) = validate_pipeline(config, &pipeline) {
+        return Err(format!("Invalid pipeline: {}", e));
+    }
+    Ok((catalog, pipeline))
+}
+
+// the pipeline restricted to the data of the user, followed by the sort, skip
+// and limit of the kwargs, and the options of the aggregation
+pub fn build_pipeline(
+    config: &AppConfig,
+    catalog: &str,
+    user: &AuthenticatedUser,
+    pipeline: Vec<Document>,
+    kwargs: &QueryKwargs,
+) -> (Vec<Document>, mongodb::options::AggregateOptions) {
+    let mut pipeline = restrict_pipeline(config, catalog, user, pipeline);
+    if let Some(sort) = &kwargs.sort {
+        pipeline.push(doc! { "$sort": sort });
+    }
+    if let Some(skip) = kwargs.skip {
+        pipeline.push(doc! { "$skip": skip as i64 });
+    }
+    if let Some(limit) = kwargs.limit.filter(|l| *l > 0) {
+        pipeline.push(doc! { "$limit": limit });
+    }
+    let options = mongodb::options::AggregateOptions::builder()
+        .max_time(kwargs.max_time_ms.map(std::time::Duration::from_millis))
+        .build();
+    (pipeline, options)
+}
+
+// runs a user supplied aggregation pipeline, restricted to the allowed stages.
+// sort, skip and limit from the kwargs are applied after the pipeline.
+#[post("/query/aggregate")]
+pub async fn aggregate(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    req: HttpRequest,
+    body: web::Json<AggregateBody>,
+) -> HttpResponse {
+    let mut body = body.into_inner();
+    let kwargs = body.kwargs.take().unwrap_or_default();
+    let (catalog, pipeline) = match aggregate_query(&config, body) {
+        Ok(q) => q,
+        Err(e) => return response::bad_request(&e),
+    };
+
+    let format = match OutputFormat::from_request(&req).with_override(kwargs.format.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return response::bad_request(&e),
+    };
+    // bulk exports are not capped by max_limit, their memory use is bounded
+    let kwargs = if format.is_columnar() {
+        config.apply_catalog_timeout(&catalog, kwargs)
+    } else {
+        config.apply_catalog_limits(&catalog, kwargs)
+    };
+    let (pipeline, options) = build_pipeline(&config, &catalog, &user, pipeline, &kwargs);
+
+    let collection: Collection<Document> =
+        client.database(&config.database.name).collection(&catalog);
+    let cursor = match collection.aggregate(pipeline).with_options(options).await {
+        Ok(c) => c,
+        Err(e) => {
+            return response::internal_error(&format!("Error running aggregation: {:?}", e));
+        }
+    };
+    let message = format!("Aggregation on {} completed", catalog);
+    if format.is_columnar() {
+        return columnar_response(format, cursor);
+    }
+    if format == OutputFormat::Ndjson {
+        return ndjson_response(move |mut writer| async move {
+            let outcome = write_cursor(&mut writer, cursor, None).await;
+            if !outcome.disconnected {
+                let summary = Summary::new(&message, outcome.count, outcome.error, None);
+                writer.write(&summary).await;
+            }
+        });
+    }
+    let docs = match cursor.try_collect::<Vec<mongodb::bson::Document>>().await {
+        Ok(d) => d,
+        Err(e) => {
+            return response::internal_error(&format!("Error collecting documents: {:?}", e));
+        }
+    };
+    if format.is_tabular() {
+        let table = Table::from_documents(&catalog, &docs, &config.catalog(&catalog).units);
+        return table_response(format, &table, None);
+    }
+    response::ok(&message, serde_json::json!(docs))
 }
@@ -0,0 +1,177 @@
+use crate::{
+    api::{
+        permissions::{find_with_rights, DataRights},
+        query::{build_cone_search_filter, build_options},
+    },
+    conf::{AppConfig, ScsConfig},
+    formats::{
+        table::{Datatype, Table},
+        vosi::{write_availability, write_capabilities},
+        votable::{escape, write_votable, write_votable_error},
+        VOTABLE_CONTENT_TYPE,
+    },
+    models::{
+        auth_models::AuthenticatedUser,
+        query_models::{QueryKwargs, ScsParams, Unit},
+    },
+};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    Client, Collection,
+};
+
+// SCS reports errors in a VOTable with a 200 status
+fn votable_error(message: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(VOTABLE_CONTENT_TYPE)
+        .body(write_votable_error(message))
+}
+
+fn projection(scs: &ScsConfig, verb: u8) -> Option<Document> {
+    if verb >= 3 {
+        return None;
+    }
+    let mut projection = doc! {};
+    if scs.id_field != "_id" {
+        projection.insert("_id", 0);
+    }
+    for field in [&scs.id_field, &scs.ra_field, &scs.dec_field] {
+        projection.insert(field, 1);
+    }
+    if verb == 2 {
+        for field in &scs.columns {
+            projection.insert(field, 1);
+        }
+    }
+    Some(projection)
+}
+
+// base url of the api, as seen by the client
+pub fn base_url(req: &HttpRequest) -> String {
+    let info = req.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
+// IVOA Simple Cone Search 1.03: RA, DEC and SR in degrees, VERB for the
+// number of columns. Results are a VOTable whose first three columns are the
+// id and position of the sources.
+#[get("/scs/{catalog}")]
+pub async fn scs_query(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    path: web::Path<String>,
+    params: web::Query<ScsParams>,
+) -> HttpResponse {
+    let catalog = path.into_inner();
+    let catalog_config = config.catalog(&catalog);
+    let scs = match &catalog_config.scs {
+        Some(s) => s,
+        None => {
+            return votable_error(&format!(
+                "catalog {} is not available through cone search",
+                catalog
+            ));
+        }
+    };
+    let query = match params.into_inner().into_query(scs.max_sr.unwrap_or(180.0)) {
+        Ok(q) => q,
+        Err(e) => return votable_error(&e),
+    };
+
+    let filter = build_cone_search_filter(doc! {}, (query.ra, query.dec), query.sr, Unit::Degrees);
+    let kwargs = config.apply_catalog_limits(&catalog, QueryKwargs::default());
+    let limit = kwargs.limit;
+    let options = build_options(projection(scs, query.verb), kwargs);
+    let collection: Collection<Document> =
+        client.database(&config.database.name).collection(&catalog);
+    let rights = DataRights::new(&config, &catalog, &user);
+    let cursor = match find_with_rights(&collection, filter, options, &rights).await {
+        Ok(c) => c,
+        Err(e) => return votable_error(&format!("Error finding documents: {:?}", e)),
+    };
+    let docs = match cursor.try_collect::<Vec<Document>>().await {
+        Ok(d) => d,
+        Err(e) => return votable_error(&format!("Error collecting documents: {:?}", e)),
+    };
+
+    let mut table = Table::from_documents(&catalog, &docs, &catalog_config.units);
+    table.lead_columns(&[
+        (&scs.id_field, Datatype::Char),
+        (&scs.ra_field, Datatype::Double),
+        (&scs.dec_field, Datatype::Double),
+    ]);
+    for (column, ucd) in
+        table
+            .columns
+            .iter_mut()
+            .zip(["ID_MAIN", "POS_EQ_RA_MAIN", "POS_EQ_DEC_MAIN"])
+    {
+        column.ucd = Some(ucd.to_string());
+    }
+    // the results were truncated to the catalog limit
+    let overflow = matches!(limit, Some(l) if l > 0 && docs.len() as i64 >= l);
+    let infos = if overflow {
+        vec![("QUERY_STATUS", "OVERFLOW")]
+    } else {
+        vec![]
+    };
+    HttpResponse::Ok()
+        .content_type(VOTABLE_CONTENT_TYPE)
+        .body(write_votable(&table, &infos))
+}
+
+// VOSI capabilities of the cone search service of a catalog
+#[get("/scs/{catalog}/capabilities")]
+pub async fn scs_capabilities(
+    config: web::Data<AppConfig>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let catalog = path.into_inner();
+    let catalog_config = config.catalog(&catalog);
+    let scs = match &catalog_config.scs {
+        Some(s) => s,
+        None => {
+            return HttpResponse::NotFound().body(format!(
+                "catalog {} is not available through cone search",
+                catalog
+            ));
+        }
+    };
+    let url = format!("{}/scs/{}", base_url(&req), catalog);
+    let mut capability = format!(
+        "<capability standardID=\"ivo://ivoa.net/std/ConeSearch\" xsi:type=\"cs:ConeSearch\">\n\
+         <interface xsi:type=\"vs:ParamHTTP\" role=\"std\"><accessURL use=\"base\">{}?</accessURL></interface>\n\
+         <maxSR>{}</maxSR>\n",
+        escape(&url),
+        scs.max_sr.unwrap_or(180.0)
+    );
+    if let Some(max_records) = catalog_config.max_limit {
+        capability.push_str(&format!("<maxRecords>{}</maxRecords>\n", max_records));
+    }
+    capability.push_str("<verbosity>true</verbosity>\n</capability>\n");
+    let body = write_capabilities(
+        &url,
+        " xmlns:cs=\"http://www.ivoa.net/xml/ConeSearch/v1.0\"",
+        &capability,
+    );
+    HttpResponse::Ok().content_type("text/xml").body(body)
+}
+
+// VOSI availability: the service is up when the database answers
+#[get("/scs/{catalog}/availability")]
+pub async fn scs_availability(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+) -> HttpResponse {
+    let available = client
+        .database(&config.database.name)
+        .run_command(doc! { "ping": 1 })
+        .await
+        .is_ok();
+    let body = write_availability(available);
+    HttpResponse::Ok().content_type("text/xml").body(body)
+}
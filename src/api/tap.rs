@@ -0,0 +1,788 @@
+use crate::{
+    adql::{parse_adql, AdqlQuery, SelectColumn},
+    api::{
+        pagination::get_path,
+        permissions::{find_with_rights, DataRights},
+        query::{build_options, get_catalog_names, get_index_info},
+        scs::base_url,
+        validation::{validate_document, QUERY_RULES},
+    },
+    conf::AppConfig,
+    formats::{
+        csv::write_csv,
+        table::{Datatype, Table},
+        vosi::{write_availability, write_capabilities},
+        votable::{escape, write_votable, write_votable_error},
+        OutputFormat, VOTABLE_CONTENT_TYPE,
+    },
+    models::{
+        auth_models::AuthenticatedUser,
+        query_models::{QueryKwargs, TapQuery},
+    },
+    spatial::angular_distance,
+};
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Client, Collection, Database,
+};
+use std::{cmp::Ordering, collections::HashMap};
+
+pub const TAP_SCHEMA: &str = "TAP_SCHEMA";
+
+// columns of the TAP_SCHEMA tables (TAP 1.1 section 4)
+const SCHEMAS_COLUMNS: &[(&str, Datatype)] = &[
+    ("schema_name", Datatype::Char),
+    ("utype", Datatype::Char),
+    ("description", Datatype::Char),
+    ("schema_index", Datatype::Int),
+];
+const TABLES_COLUMNS: &[(&str, Datatype)] = &[
+    ("schema_name", Datatype::Char),
+    ("table_name", Datatype::Char),
+    ("table_type", Datatype::Char),
+    ("utype", Datatype::Char),
+    ("description", Datatype::Char),
+    ("table_index", Datatype::Int),
+];
+const COLUMNS_COLUMNS: &[(&str, Datatype)] = &[
+    ("table_name", Datatype::Char),
+    ("column_name", Datatype::Char),
+    ("datatype", Datatype::Char),
+    ("arraysize", Datatype::Char),
+    ("xtype", Datatype::Char),
+    ("size", Datatype::Int),
+    ("description", Datatype::Char),
+    ("utype", Datatype::Char),
+    ("unit", Datatype::Char),
+    ("ucd", Datatype::Char),
+    ("indexed", Datatype::Int),
+    ("principal", Datatype::Int),
+    ("std", Datatype::Int),
+    ("column_index", Datatype::Int),
+];
+const KEYS_COLUMNS: &[(&str, Datatype)] = &[
+    ("key_id", Datatype::Char),
+    ("from_table", Datatype::Char),
+    ("target_table", Datatype::Char),
+    ("utype", Datatype::Char),
+    ("description", Datatype::Char),
+];
+const KEY_COLUMNS_COLUMNS: &[(&str, Datatype)] = &[
+    ("key_id", Datatype::Char),
+    ("from_column", Datatype::Char),
+    ("target_column", Datatype::Char),
+];
+
+const TAP_SCHEMA_TABLES: &[(&str, &[(&str, Datatype)])] = &[
+    ("schemas", SCHEMAS_COLUMNS),
+    ("tables", TABLES_COLUMNS),
+    ("columns", COLUMNS_COLUMNS),
+    ("keys", KEYS_COLUMNS),
+    ("key_columns", KEY_COLUMNS_COLUMNS),
+];
+
+// a column of a table published through TAP
+#[derive(Clone, Debug)]
+pub struct SchemaColumn {
+    pub name: String,
+    pub datatype: Datatype,
+    pub unit: Option<String>,
+    pub indexed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct SchemaTable {
+    pub schema: String,
+    pub name: String,
+    pub columns: Vec<SchemaColumn>,
+}
+
+impl SchemaTable {
+    // fully qualified name, e.g. boom.ZTF_alerts
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", self.schema, self.name)
+    }
+}
+
+// TAP errors are reported in a VOTable (DALI 1.1 section 4.4)
+fn tap_error(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type(VOTABLE_CONTENT_TYPE)
+        .body(write_votable_error(message))
+}
+
+fn tap_response(format: OutputFormat, table: &Table, overflow: bool) -> HttpResponse {
+    match format {
+        OutputFormat::Csv => HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .body(write_csv(table)),
+        _ => {
+            let infos = if overflow {
+                vec![("QUERY_STATUS", "OVERFLOW")]
+            } else {
+                vec![]
+            };
+            HttpResponse::Ok()
+                .content_type(VOTABLE_CONTENT_TYPE)
+                .body(write_votable(table, &infos))
+        }
+    }
+}
+
+// the TAP_SCHEMA tables themselves, which are part of the published schema
+fn tap_schema_tables() -> Vec<SchemaTable> {
+    TAP_SCHEMA_TABLES
+        .iter()
+        .map(|(name, columns)| SchemaTable {
+            schema: TAP_SCHEMA.to_string(),
+            name: name.to_string(),
+            columns: columns
+                .iter()
+                .map(|(column, datatype)| SchemaColumn {
+                    name: column.to_string(),
+                    datatype: *datatype,
+                    unit: None,
+                    indexed: false,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+// columns of a catalog, from the fields of one of its documents. Fields of
+// the other documents are unknown to TAP_SCHEMA, but can still be queried.
+async fn catalog_table(
+    db: &Database,
+    config: &AppConfig,
+    catalog: &str,
+    indexes: &[mongodb::IndexModel],
+) -> Result<SchemaTable, mongodb::error::Error> {
+    let collection: Collection<Document> = db.collection(catalog);
+    let sample = collection.find_one(doc! {}).await?;
+    let units = config.catalog(catalog).units;
+    let table = Table::from_documents(catalog, sample.as_slice(), &units);
+    let columns = table
+        .columns
+        .into_iter()
+        .map(|column| SchemaColumn {
+            indexed: indexes
+                .iter()
+                .any(|index| index.keys.keys().next() == Some(&column.name)),
+            name: column.name,
+            datatype: column.datatype,
+            unit: column.unit,
+        })
+        .collect();
+    Ok(SchemaTable {
+        schema: db.name().to_string(),
+        name: catalog.to_string(),
+        columns,
+    })
+}
+
+// the published tables: one per catalog, and the TAP_SCHEMA tables
+pub async fn get_tap_schema(
+    db: &Database,
+    config: &AppConfig,
+) -> Result<Vec<SchemaTable>, mongodb::error::Error> {
+    let catalogs = get_catalog_names(db.clone(), config).await?;
+    let indexes = get_index_info(db.clone(), catalogs.clone()).await?;
+    let mut tables = Vec::new();
+    for (catalog, catalog_indexes) in catalogs.iter().zip(&indexes) {
+        tables.push(catalog_table(db, config, catalog, catalog_indexes).await?);
+    }
+    tables.extend(tap_schema_tables());
+    Ok(tables)
+}
+
+// rows of a TAP_SCHEMA table, as documents
+pub fn tap_schema_documents(tables: &[SchemaTable], name: &str) -> Option<Vec<Document>> {
+    let mut schemas: Vec<&str> = Vec::new();
+    for table in tables {
+        if !schemas.contains(&table.schema.as_str()) {
+            schemas.push(&table.schema);
+        }
+    }
+    let documents = match name.to_lowercase().as_str() {
+        "schemas" => schemas
+            .iter()
+            .enumerate()
+            .map(|(i, schema)| {
+                doc! {
+                    "schema_name": *schema,
+                    "utype": Bson::Null,
+                    "description": Bson::Null,
+                    "schema_index": i as i32,
+                }
+            })
+            .collect(),
+        "tables" => tables
+            .iter()
+            .enumerate()
+            .map(|(i, table)| {
+                doc! {
+                    "schema_name": &table.schema,
+                    "table_name": table.qualified_name(),
+                    "table_type": "table",
+                    "utype": Bson::Null,
+                    "description": Bson::Null,
+                    "table_index": i as i32,
+                }
+            })
+            .collect(),
+        "columns" => tables
+            .iter()
+            .flat_map(|table| {
+                table.columns.iter().enumerate().map(move |(i, column)| {
+                    let arraysize = match column.datatype {
+                        Datatype::Char => Bson::String("*".to_string()),
+                        _ => Bson::Null,
+                    };
+                    doc! {
+                        "table_name": table.qualified_name(),
+                        "column_name": &column.name,
+                        "datatype": column.datatype.name(),
+                        "arraysize": arraysize,
+                        "xtype": Bson::Null,
+                        "size": Bson::Null,
+                        "description": Bson::Null,
+                        "utype": Bson::Null,
+                        "unit": column.unit.clone().map(Bson::String).unwrap_or(Bson::Null),
+                        "ucd": Bson::Null,
+                        "indexed": column.indexed as i32,
+                        "principal": 0,
+                        "std": (table.schema == TAP_SCHEMA) as i32,
+                        "column_index": i as i32,
+                    }
+                })
+            })
+            .collect(),
+        "keys" | "key_columns" => Vec::new(),
+        _ => return None,
+    };
+    Some(documents)
+}
+
+fn as_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(d) => Some(*d),
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    match (a, b) {
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::Null, Bson::Null) => Some(Ordering::Equal),
+        _ => as_number(a)?.partial_cmp(&as_number(b)?),
+    }
+}
+
+// numbers are equal by value, whatever their type
+fn equals(a: &Bson, b: &Bson) -> bool {
+    a == b || compare(a, b) == Some(Ordering::Equal)
+}
+
+// matches the anchored patterns produced from LIKE by the ADQL translation:
+// literals (escaped or not), `.` and `.*`
+fn like_matches(regex: &str, options: &str, text: &str) -> bool {
+    enum Token {
+        Literal(char),
+        One,
+        Any,
+    }
+    let case_insensitive = options.contains('i');
+    let regex = regex.trim_start_matches('^').trim_end_matches('$');
+    let mut tokens = Vec::new();
+    let mut chars = regex.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => tokens.push(Token::Literal(chars.next().unwrap_or('\\'))),
+            '.' if chars.peek() == Some(&'*') => {
+                chars.next();
+                tokens.push(Token::Any);
+            }
+            '.' => tokens.push(Token::One),
+            c => tokens.push(Token::Literal(c)),
+        }
+    }
+    let text: Vec<char> = text.chars().collect();
+    let same = |a: char, b: char| {
+        if case_insensitive {
+            a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    };
+    // matched[j]: the tokens so far match the first j characters
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+    for token in &tokens {
+        let mut next = vec![false; text.len() + 1];
+        for j in 0..=text.len() {
+            match token {
+                Token::Any => next[j] = matched[j] || (j > 0 && next[j - 1]),
+                Token::One => next[j] = j > 0 && matched[j - 1],
+                Token::Literal(c) => next[j] = j > 0 && matched[j - 1] && same(*c, text[j - 1]),
+            }
+        }
+        matched = next;
+    }
+    matched[text.len()]
+}
+
+fn value_matches(value: Option<&Bson>, condition: &Bson) -> bool {
+    let operators = match condition {
+        Bson::Document(d) if d.keys().next().is_some_and(|k| k.starts_with('$')) => d,
+        _ => return equals(value.unwrap_or(&Bson::Null), condition),
+    };
+    let actual = value.unwrap_or(&Bson::Null);
+    operators
+        .iter()
+        .all(|(operator, operand)| match operator.as_str() {
+            "$eq" => equals(actual, operand),
+            "$ne" => !equals(actual, operand),
+            "$lt" => compare(actual, operand) == Some(Ordering::Less),
+            "$lte" => matches!(
+                compare(actual, operand),
+                Some(Ordering::Less | Ordering::Equal)
+            ),
+            "$gt" => compare(actual, operand) == Some(Ordering::Greater),
+            "$gte" => matches!(
+                compare(actual, operand),
+                Some(Ordering::Greater | Ordering::Equal)
+            ),
+            "$in" => operand
+                .as_array()
+                .is_some_and(|a| a.iter().any(|v| equals(actual, v))),
+            "$nin" => operand
+                .as_array()
+                .is_some_and(|a| !a.iter().any(|v| equals(actual, v))),
+            "$regex" => match (actual, operand) {
+                (Bson::String(text), Bson::String(regex)) => {
+                    like_matches(regex, operators.get_str("$options").unwrap_or(""), text)
+                }
+                _ => false,
+            },
+            "$options" => true,
+            "$not" => !value_matches(value, operand),
+            _ => false,
+        })
+}
+
+// evaluates a translated ADQL filter against a document, for the tables
+// that are not collections (TAP_SCHEMA)
+pub fn matches_filter(document: &Document, filter: &Document) -> bool {
+    filter.iter().all(|(key, condition)| {
+        let conditions = || {
+            condition
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|c| c.as_document())
+        };
+        match key.as_str() {
+            "$and" => conditions().all(|c| matches_filter(document, c)),
+            "$or" => conditions().any(|c| matches_filter(document, c)),
+            "$nor" => !conditions().any(|c| matches_filter(document, c)),
+            _ => value_matches(get_path(document, key), condition),
+        }
+    })
+}
+
+// documents holding the selected columns, named after their alias
+fn select_documents(
+    documents: Vec<Document>,
+    columns: &Option<Vec<SelectColumn>>,
+) -> Vec<Document> {
+    let columns = match columns {
+        Some(c) => c,
+        None => return documents,
+    };
+    documents
+        .iter()
+        .map(|document| {
+            let mut selected = Document::new();
+            for column in columns {
+                let value = match column {
+                    SelectColumn::Field { path, .. } => get_path(document, path).cloned(),
+                    SelectColumn::Distance {
+                        ra_field,
+                        dec_field,
+                        ra,
+                        dec,
+                        ..
+                    } => match (
+                        get_path(document, ra_field).and_then(as_number),
+                        get_path(document, dec_field).and_then(as_number),
+                    ) {
+                        (Some(ra_value), Some(dec_value)) => Some(Bson::Double(angular_distance(
+                            ra_value, dec_value, *ra, *dec,
+                        ))),
+                        _ => None,
+                    },
+                };
+                if let Some(value) = value {
+                    selected.insert(column.name(), value);
+                }
+            }
+            selected
+        })
+        .collect()
+}
+
+// table of the results, whose columns are in the order of the SELECT clause.
+// `known` gives the datatype of columns that may have no values, units are
+// looked up by field path.
+fn result_table(
+    name: &str,
+    documents: Vec<Document>,
+    query: &AdqlQuery,
+    known: &[SchemaColumn],
+    units: &HashMap<String, String>,
+) -> Table {
+    let documents = select_documents(documents, &query.columns);
+    let mut table = Table::from_documents(name, &documents, &HashMap::new());
+    let datatype = |path: &str| {
+        known
+            .iter()
+            .find(|c| c.name == path)
+            .map_or(Datatype::Char, |c| c.datatype)
+    };
+    let lead: Vec<(&str, Datatype)> = match &query.columns {
+        Some(columns) => columns
+            .iter()
+            .map(|column| match column {
+                SelectColumn::Field { name, path } => (name.as_str(), datatype(path)),
+                SelectColumn::Distance { name, .. } => (name.as_str(), Datatype::Double),
+            })
+            .collect(),
+        None => known
+            .iter()
+            .map(|c| (c.name.as_str(), c.datatype))
+            .collect(),
+    };
+    table.lead_columns(&lead);
+
+    for column in table.columns.iter_mut() {
+        let selected = query
+            .columns
+            .as_ref()
+            .and_then(|columns| columns.iter().find(|c| c.name() == column.name));
+        column.unit = match selected {
+            Some(SelectColumn::Distance { .. }) => Some("deg".to_string()),
+            Some(SelectColumn::Field { path, .. }) => units.get(path).cloned(),
+            None => units.get(&column.name).cloned(),
+        };
+    }
+    table
+}
+
+// the smallest of the requested limits, None when there is none
+fn min_limit(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// TAP_SCHEMA tables are built in memory, and queried as such
+fn query_tap_schema(
+    tables: &[SchemaTable],
+    query: &AdqlQuery,
+    maxrec: Option<i64>,
+) -> Result<(Table, bool), String> {
+    let name = match query.table.as_slice() {
+        [_, name] => name,
+        _ => return Err(format!("unknown table {}", query.table.join("."))),
+    };
+    let schema_table = tap_schema_tables()
+        .into_iter()
+        .find(|t| t.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown table {}", query.table.join(".")))?;
+    let mut documents: Vec<Document> = tap_schema_documents(tables, &schema_table.name)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|d| matches_filter(d, &query.filter))
+        .collect();
+    if let Some(sort) = &query.sort {
+        documents.sort_by(|a, b| {
+            for (key, direction) in sort {
+                let (a, b) = (
+                    get_path(a, key).unwrap_or(&Bson::Null),
+                    get_path(b, key).unwrap_or(&Bson::Null),
+                );
+                let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+                let ordering = if direction.as_i32() == Some(-1) {
+                    ordering.reverse()
+                } else {
+                    ordering
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+    }
+    let mut overflow = false;
+    if let Some(limit) = min_limit(query.top, maxrec) {
+        overflow = documents.len() as i64 > limit && query.top.is_none_or(|top| limit < top);
+        documents.truncate(limit as usize);
+    }
+    let table = result_table(
+        &schema_table.qualified_name(),
+        documents,
+        query,
+        &schema_table.columns,
+        &HashMap::new(),
+    );
+    Ok((table, overflow))
+}
+
+// name of the catalog queried by a table name, either qualified with the
+// database name (boom.ZTF_alerts) or not. Names are case-insensitive.
+pub fn resolve_catalog(
+    table: &[String],
+    database: &str,
+    catalogs: &[String],
+) -> Result<String, String> {
+    let name = match table {
+        [name] => name,
+        [schema, name] if schema.eq_ignore_ascii_case(database) => name,
+        _ => return Err(format!("unknown table {}", table.join("."))),
+    };
+    catalogs
+        .iter()
+        .find(|catalog| catalog.eq_ignore_ascii_case(name))
+        .cloned()
+        .ok_or_else(|| format!("unknown table {}", table.join(".")))
+}
+
+async fn run_tap_sync(
+    client: &Client,
+    config: &AppConfig,
+    user: &AuthenticatedUser,
+    params: &HashMap<String, String>,
+) -> HttpResponse {
+    let tap_query = match TapQuery::from_params(params) {
+        Ok(q) => q,
+        Err(e) => return tap_error(&e),
+    };
+    let query = match parse_adql(&tap_query.query) {
+        Ok(q) => q,
+        Err(e) => return tap_error(&e),
+    };
+    // the service limit applies to queries without MAXREC, and caps the others
+    let maxrec = Some(
+        tap_query
+            .maxrec
+            .map_or(config.api.tap_maxrec, |m| m.min(config.api.tap_maxrec)),
+    );
+    let db = client.database(&config.database.name);
+
+    if query.table.len() == 2 && query.table[0].eq_ignore_ascii_case(TAP_SCHEMA) {
+        let tables = match get_tap_schema(&db, config).await {
+            Ok(t) => t,
+            Err(e) => return tap_error(&format!("Error reading the schema: {:?}", e)),
+        };
+        return match query_tap_schema(&tables, &query, maxrec) {
+            Ok((table, overflow)) => tap_response(tap_query.format, &table, overflow),
+            Err(e) => tap_error(&e),
+        };
+    }
+
+    let catalogs = match get_catalog_names(db.clone(), config).await {
+        Ok(c) => c,
+        Err(e) => return tap_error(&format!("Error getting catalog names: {:?}", e)),
+    };
+    let catalog = match resolve_catalog(&query.table, db.name(), &catalogs) {
+        Ok(c) => c,
+        Err(e) => return tap_error(&e),
+    };
+    if let Err(e) = validate_document(&query.filter, "filter", &QUERY_RULES) {
+        return tap_error(&e);
+    }
+
+    // MAXREC=0 (or TOP 0) only asks for the columns
+    let requested = min_limit(query.top, maxrec);
+    let mut documents = Vec::new();
+    let mut overflow = false;
+    if requested != Some(0) {
+        let kwargs = config.apply_catalog_limits(
+            &catalog,
+            QueryKwargs {
+                limit: requested,
+                sort: query.sort.clone(),
+                ..Default::default()
+            },
+        );
+        let limit = kwargs.limit;
+        let options = build_options(query.projection(), kwargs);
+        let collection: Collection<Document> = db.collection(&catalog);
+        let rights = DataRights::new(config, &catalog, user);
+        let cursor =
+            match find_with_rights(&collection, query.filter.clone(), options, &rights).await {
+                Ok(c) => c,
+                Err(e) => return tap_error(&format!("Error finding documents: {:?}", e)),
+            };
+        documents = match cursor.try_collect::<Vec<Document>>().await {
+            Ok(d) => d,
+            Err(e) => return tap_error(&format!("Error collecting documents: {:?}", e)),
+        };
+        // truncated by MAXREC or the catalog limit, rather than by TOP
+        overflow = matches!(limit, Some(l) if documents.len() as i64 >= l
+            && query.top.is_none_or(|top| l < top));
+    }
+
+    let units = config.catalog(&catalog).units;
+    let table = result_table(&catalog, documents, &query, &[], &units);
+    tap_response(tap_query.format, &table, overflow)
+}
+
+// IVOA TAP 1.1 synchronous query: LANG=ADQL, QUERY, and optionally
+// RESPONSEFORMAT (votable or csv) and MAXREC. Parameter names are
+// case-insensitive.
+#[get("/tap/sync")]
+pub async fn tap_sync_get(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    params: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    run_tap_sync(&client, &config, &user, &params).await
+}
+
+#[post("/tap/sync")]
+pub async fn tap_sync(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+    user: web::ReqData<AuthenticatedUser>,
+    params: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    run_tap_sync(&client, &config, &user, &params).await
+}
+
+// VOSI tableset of the published tables
+pub fn write_tableset(tables: &[SchemaTable]) -> String {
+    let mut body = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <vosi:tableset xmlns:vosi=\"http://www.ivoa.net/xml/VOSITables/v1.0\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xmlns:vs=\"http://www.ivoa.net/xml/VODataService/v1.1\">\n",
+    );
+    let mut schema: Option<&str> = None;
+    for table in tables {
+        if schema != Some(table.schema.as_str()) {
+            if schema.is_some() {
+                body.push_str("</schema>\n");
+            }
+            body.push_str(&format!(
+                "<schema>\n<name>{}</name>\n",
+                escape(&table.schema)
+            ));
+            schema = Some(&table.schema);
+        }
+        body.push_str(&format!(
+            "<table type=\"output\">\n<name>{}</name>\n",
+            escape(&table.qualified_name())
+        ));
+        for column in &table.columns {
+            body.push_str(&format!(
+                "<column>\n<name>{}</name>\n",
+                escape(&column.name)
+            ));
+            if let Some(unit) = &column.unit {
+                body.push_str(&format!("<unit>{}</unit>\n", escape(unit)));
+            }
+            let arraysize = match column.datatype {
+                Datatype::Char => " arraysize=\"*\"",
+                _ => "",
+            };
+            body.push_str(&format!(
+                "<dataType xsi:type=\"vs:VOTableType\"{}>{}</dataType>\n",
+                arraysize,
+                column.datatype.name()
+            ));
+            if column.indexed {
+                body.push_str("<flag>indexed</flag>\n");
+            }
+            body.push_str("</column>\n");
+        }
+        body.push_str("</table>\n");
+    }
+    if schema.is_some() {
+        body.push_str("</schema>\n");
+    }
+    body.push_str("</vosi:tableset>\n");
+    body
+}
+
+// VOSI tables of the TAP service
+#[get("/tap/tables")]
+pub async fn tap_tables(client: web::Data<Client>, config: web::Data<AppConfig>) -> HttpResponse {
+    let db = client.database(&config.database.name);
+    match get_tap_schema(&db, &config).await {
+        Ok(tables) => HttpResponse::Ok()
+            .content_type("text/xml")
+            .body(write_tableset(&tables)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Error reading the schema: {:?}", e))
+        }
+    }
+}
+
+// TAPRegExt capability of the TAP service at `url`
+pub fn tap_capability(url: &str, maxrec: i64) -> String {
+    format!(
+        "<capability standardID=\"ivo://ivoa.net/std/VOSI#tables\">\n\
+         <interface xsi:type=\"vs:ParamHTTP\"><accessURL use=\"full\">{url}/tables</accessURL></interface>\n\
+         </capability>\n\
+         <capability standardID=\"ivo://ivoa.net/std/TAP\" xsi:type=\"tr:TableAccess\">\n\
+         <interface xsi:type=\"vs:ParamHTTP\" role=\"std\" version=\"1.1\"><accessURL use=\"base\">{url}</accessURL></interface>\n\
+         <language>\n\
+         <name>ADQL</name>\n\
+         <version ivo-id=\"ivo://ivoa.net/std/ADQL#v2.0\">2.0</version>\n\
+         <description>single table SELECT with TOP, WHERE and ORDER BY. \
+         CONTAINS with CIRCLE, BOX or POLYGON, and DISTANCE, in ICRS</description>\n\
+         </language>\n\
+         <outputFormat><mime>application/x-votable+xml</mime><alias>votable</alias></outputFormat>\n\
+         <outputFormat><mime>text/csv</mime><alias>csv</alias></outputFormat>\n\
+         <outputLimit><default unit=\"row\">{maxrec}</default><hard unit=\"row\">{maxrec}</hard></outputLimit>\n\
+         </capability>\n",
+        url = escape(url),
+        maxrec = maxrec,
+    )
+}
+
+// VOSI capabilities of the TAP service
+#[get("/tap/capabilities")]
+pub async fn tap_capabilities(config: web::Data<AppConfig>, req: HttpRequest) -> HttpResponse {
+    let url = format!("{}/tap", base_url(&req));
+    let body = write_capabilities(
+        &url,
+        " xmlns:tr=\"http://www.ivoa.net/xml/TAPRegExt/v1.0\"",
+        &tap_capability(&url, config.api.tap_maxrec),
+    );
+    HttpResponse::Ok().content_type("text/xml").body(body)
+}
+
+// VOSI availability: the service is up when the database answers
+#[get("/tap/availability")]
+pub async fn tap_availability(
+    client: web::Data<Client>,
+    config: web::Data<AppConfig>,
+) -> HttpResponse {
+    let available = client
+        .database(&config.database.name)
+        .run_command(doc! { "ping": 1 })
+        .await
+        .is_ok();
+    HttpResponse::Ok()
+        .content_type("text/xml")
+        .body(write_availability(available))
+}
@@ -0,0 +1,107 @@
+use mongodb::bson::{Bson, Document};
+
+// what a user supplied document may contain
+pub struct ValidationRules {
+    pub forbidden_operators: &'static [&'static str],
+    pub max_depth: usize,
+}
+
+// filters and projections of find, count_documents and cone_search
+pub const QUERY_RULES: ValidationRules = ValidationRules {
+    forbidden_operators: &["$where", "$function", "$accumulator", "$expr"],
+    max_depth: 32,
+};
+
+// aggregation pipelines (aggregate endpoint and filters). $expr is needed for
+// $lookup sub-pipelines and $match stages comparing fields, and cannot run
+// JavaScript once $function and $accumulator are rejected
+pub const PIPELINE_RULES: ValidationRules = ValidationRules {
+    forbidden_operators: &["$where", "$function", "$accumulator"],
+    max_depth: 32,
+};
+
+fn validate_value(
+    value: &Bson,
+    path: &str,
+    depth: usize,
+    rules: &ValidationRules,
+) -> Result<(), String> {
+    match value {
+        Bson::Document(d) => validate_fields(d, path, depth + 1, rules),
+        Bson::Array(a) => {
+            if depth + 1 > rules.max_depth {
+                return Err(format!(
+                    "maximum nesting depth of {} exceeded at {}",
+                    rules.max_depth, path
+                ));
+            }
+            for (i, v) in a.iter().enumerate() {
+                validate_value(v, &format!("{}.{}", path, i), depth + 1, rules)?;
+            }
+            Ok(())
+        }
+        Bson::JavaScriptCode(_) | Bson::JavaScriptCodeWithScope(_) => {
+            Err(format!("JavaScript code is not allowed at {}", path))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_fields(
+    document: &Document,
+    path: &str,
+    depth: usize,
+    rules: &ValidationRules,
+) -> Result<(), String> {
+    if depth > rules.max_depth {
+        return Err(format!(
+            "maximum nesting depth of {} exceeded at {}",
+            rules.max_depth, path
+        ));
+    }
+    for (key, value) in document {
+        let child_path = format!("{}.{}", path, key);
+        if rules.forbidden_operators.contains(&key.as_str()) {
+            return Err(format!("operator {} is not allowed at {}", key, child_path));
+        }
+        validate_value(value, &child_path, depth, rules)?;
+    }
+    Ok(())
+}
+
+// walks a user supplied document, rejecting forbidden operators and excessive
+// nesting. errors name the offending path, prefixed with `path`
+pub fn validate_document(
+    document: &Document,
+    path: &str,
+    rules: &ValidationRules,
+) -> Result<(), String> {
+    validate_fields(document, path, 1, rules)
+}
+
+pub fn validate_bson(value: &Bson, path: &str, rules: &ValidationRules) -> Result<(), String> {
+    validate_value(value, path, 0, rules)
+}
+
+// validates an optional document, e.g. a filter or projection of a request body
+pub fn validate_optional(
+    document: &Option<Document>,
+    path: &str,
+    rules: &ValidationRules,
+) -> Result<(), String> {
+    match document {
+        Some(d) => validate_document(d, path, rules),
+        None => Ok(()),
+    }
+}
+
+pub fn validate_documents(
+    documents: &[Document],
+    path: &str,
+    rules: &ValidationRules,
+) -> Result<(), String> {
+    for (i, document) in documents.iter().enumerate() {
+        validate_document(document, &format!("{}.{}", path, i), rules)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use crate::models::{auth_models::AuthenticatedUser, query_models::QueryKwargs};
+use config::{Config, ConfigError, Environment, File};
+use mongodb::{options::ClientOptions, Client};
+use std::{collections::HashMap, time::Duration};
+
+// default location of the configuration file, relative to the working directory
+pub const DEFAULT_CONFIG_PATH: &str = "config.yaml";
+// prefix for environment variable overrides, e.g. BOOM_API__DATABASE__NAME=boom
+const ENV_PREFIX: &str = "BOOM_API";
+const ENV_SEPARATOR: &str = "__";
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct AppConfig {
+    pub database: DatabaseConfig,
+    pub api: ApiConfig,
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub catalogs: HashMap<String, CatalogConfig>,
+    #[serde(default)]
+    pub skymaps: SkymapsConfig,
+    pub jobs: JobsConfig,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DatabaseConfig {
+    // full connection string; takes precedence over host/port/credentials
+    pub uri: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub name: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub connect_timeout_ms: Option<u64>,
+    pub server_selection_timeout_ms: Option<u64>,
+    pub max_pool_size: Option<u32>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ApiConfig {
+    pub host: String,
+    pub port: u16,
+    // number of actix workers, defaults to the number of physical cores
+    pub workers: Option<usize>,
+    pub client_request_timeout_ms: u64,
+    pub keep_alive_secs: u64,
+    pub shutdown_timeout_secs: u64,
+    // applied to queries that do not set max_time_ms themselves
+    pub default_max_time_ms: Option<u64>,
+    // largest JSON body accepted, in bytes, e.g. for uploaded skymaps.
+    // Defaults to the 32 KiB of actix.
+    pub max_json_bytes: Option<usize>,
+    // largest raw body accepted, in bytes, e.g. for uploaded target lists.
+    // Defaults to the 256 KiB of actix.
+    pub max_upload_bytes: Option<usize>,
+    // how long catalog coverages are reused before being computed again,
+    // defaults to an hour
+    pub coverage_cache_secs: Option<u64>,
+    // rows returned by a TAP query that sets neither TOP nor MAXREC, and the
+    // most MAXREC can ask for
+    pub tap_maxrec: i64,
+    // largest radius of /query/crossmatch
+    pub max_crossmatch_radius_arcsec: f64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct AuthConfig {
+    // HMAC key used to sign and verify JWTs, never set in the config file
+    #[serde(default)]
+    pub secret_key: String,
+    // lifetime of the JWTs issued by the api
+    pub token_expiration_secs: u64,
+    // collection holding users and their hashed API tokens
+    pub users_collection: String,
+    // program ids every authenticated user has access to (e.g. public data)
+    #[serde(default)]
+    pub default_programids: Vec<i32>,
+    // program ids granted to the members of a group
+    #[serde(default)]
+    pub group_programids: HashMap<String, Vec<i32>>,
+    // path prefixes reachable without a token (e.g. /scs/ for VO tools), as an
+    // anonymous user with the default program ids only
+    #[serde(default)]
+    pub anonymous_paths: Vec<String>,
+}
+
+// asynchronous query jobs, stored with their results in MongoDB
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct JobsConfig {
+    // kept apart from the catalogs, so that results are never queried as
+    // collections of the catalog database
+    pub database: String,
+    pub collection: String,
+    pub results_collection: String,
+    // jobs and their results are deleted this long after their submission
+    pub retention_secs: u64,
+    // jobs executing at once on each server, the others being queued
+    pub max_running: usize,
+    // applied to the queries of jobs that do not set max_time_ms, instead
+    // of the default timeouts of the api and the catalogs
+    pub max_time_ms: Option<u64>,
+}
+
+// gravitational-wave skymaps read from the server by skymap_crossmatch
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct SkymapsConfig {
+    // directory the skymap paths are relative to. Without it, skymaps can
+    // only be uploaded.
+    pub directory: Option<String>,
+    // most probable alerts held in memory while sorting, the upper bound of
+    // the limit of skymap_crossmatch
+    pub max_matches: Option<usize>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct CatalogConfig {
+    // upper bound on the number of documents returned by a single query
+    pub max_limit: Option<i64>,
+    // overrides api.default_max_time_ms for this catalog
+    pub max_time_ms: Option<u64>,
+    pub data_rights: Option<DataRightsConfig>,
+    // units of the (dotted) fields, reported in tabular outputs
+    #[serde(default)]
+    pub units: HashMap<String, String>,
+    // exposes the catalog through the IVOA Simple Cone Search endpoint
+    pub scs: Option<ScsConfig>,
+}
+
+// fields of a catalog used by Simple Cone Search
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ScsConfig {
+    pub id_field: String,
+    pub ra_field: String,
+    pub dec_field: String,
+    // returned with VERB=2 (the default) on top of id, ra and dec. VERB=3
+    // returns every field.
+    #[serde(default)]
+    pub columns: Vec<String>,
+    // largest search radius, in degrees
+    pub max_sr: Option<f64>,
+}
+
+// describes where program ids live in the documents of a catalog
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct DataRightsConfig {
+    // documents whose value at this path is not an allowed program id are hidden
+    pub field: Option<String>,
+    // array fields whose elements are trimmed, mapped to the program id field
+    // of each element, e.g. prv_candidates: programid
+    #[serde(default)]
+    pub arrays: HashMap<String, String>,
+}
+
+impl AuthConfig {
+    // the api refuses to start without a key
+    pub fn check_secret_key(&self) -> Result<(), ConfigError> {
+        if self.secret_key.trim().is_empty() {
+            return Err(ConfigError::Message(
+                "auth.secret_key must be set, e.g. with BOOM_API__AUTH__SECRET_KEY".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // union of the user's own program ids, those of their groups and the defaults
+    pub fn resolve_programids(&self, user: &AuthenticatedUser) -> Vec<i32> {
+        let mut programids = self.default_programids.clone();
+        programids.extend(user.programids.iter());
+        for group in &user.groups {
+            if let Some(group_ids) = self.group_programids.get(group) {
+                programids.extend(group_ids.iter());
+            }
+        }
+        programids.sort();
+        programids.dedup();
+        programids
+    }
+}
+
+impl DatabaseConfig {
+    pub fn connection_uri(&self) -> String {
+        if let Some(uri) = &self.uri {
+            return uri.clone();
+        }
+        match (&self.username, &self.password) {
+            (Some(user), Some(pass)) => {
+                format!("mongodb://{}:{}@{}:{}", user, pass, self.host, self.port)
+            }
+            _ => format!("mongodb://{}:{}", self.host, self.port),
+        }
+    }
+
+    pub async fn client(&self) -> Result<Client, mongodb::error::Error> {
+        let mut options = ClientOptions::parse(self.connection_uri()).await?;
+        if let Some(ms) = self.connect_timeout_ms {
+            options.connect_timeout = Some(Duration::from_millis(ms));
+        }
+        if let Some(ms) = self.server_selection_timeout_ms {
+            options.server_selection_timeout = Some(Duration::from_millis(ms));
+        }
+        if let Some(size) = self.max_pool_size {
+            options.max_pool_size = Some(size);
+        }
+        Client::with_options(options)
+    }
+}
+
+impl AppConfig {
+    // loads the configuration in layers, each overriding the previous one:
+    // built-in defaults, the config file (toml or yaml), BOOM_API__* environment
+    // variables and finally explicit overrides (typically CLI flags)
+    pub fn load(
+        path: Option<&str>,
+        overrides: Vec<(&str, String)>,
+    ) -> Result<AppConfig, ConfigError> {
+        let mut builder = Config::builder()
+            .set_default("database.host", "localhost")?
+            .set_default("database.port", 27017)?
+            .set_default("database.name", "boom")?
+            .set_default("api.host", "0.0.0.0")?
+            .set_default("api.port", 4000)?
+            .set_default("api.client_request_timeout_ms", 5000)?
+            .set_default("api.keep_alive_secs", 5)?
+            .set_default("api.shutdown_timeout_secs", 30)?
+            .set_default("api.tap_maxrec", 10000)?
+            .set_default("api.max_crossmatch_radius_arcsec", 60.0)?
+            .set_default("auth.token_expiration_secs", 86400)?
+            .set_default("auth.users_collection", "users")?
+            .set_default("jobs.database", "boom_jobs")?
+            .set_default("jobs.collection", "jobs")?
+            .set_default("jobs.results_collection", "job_results")?
+            .set_default("jobs.retention_secs", 86400)?
+            .set_default("jobs.max_running", 4)?;
+
+        builder = match path {
+            Some(p) => builder.add_source(File::with_name(p)),
+            None => builder.add_source(File::with_name(DEFAULT_CONFIG_PATH).required(false)),
+        };
+        builder = builder.add_source(
+            Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator(ENV_SEPARATOR)
+                .separator(ENV_SEPARATOR),
+        );
+        // MONGODB_URI predates the config file and is still honored
+        if let Ok(uri) = std::env::var("MONGODB_URI") {
+            builder = builder.set_override("database.uri", uri)?;
+        }
+        for (key, value) in overrides {
+            builder = builder.set_override(key, value)?;
+        }
+        let config: AppConfig = builder.build()?.try_deserialize()?;
+        config.auth.check_secret_key()?;
+        config.check_jobs_database()?;
+        Ok(config)
+    }
+
+    fn check_jobs_database(&self) -> Result<(), ConfigError> {
+        if self.jobs.database == self.database.name {
+            return Err(ConfigError::Message(
+                "jobs.database must not be the database of the catalogs".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // catalogs are the configured collections, the only ones users can query.
+    // The other collections of the database (users, filters) are not
+    // exposed.
+    pub fn check_catalog(&self, catalog: &str) -> Result<(), String> {
+        if self.catalogs.contains_key(catalog) {
+            Ok(())
+        } else {
+            Err(format!("unknown catalog {}", catalog))
+        }
+    }
+
+    pub fn catalog(&self, catalog: &str) -> CatalogConfig {
+        self.catalogs.get(catalog).cloned().unwrap_or_default()
+    }
+
+    // applies the per-catalog limit cap and default query timeout to user kwargs
+    pub fn apply_catalog_limits(&self, catalog: &str, mut kwargs: QueryKwargs) -> QueryKwargs {
+        if let Some(max_limit) = self.catalog(catalog).max_limit {
+            kwargs.limit = Some(match kwargs.limit {
+                Some(limit) if limit > 0 => limit.min(max_limit),
+                _ => max_limit,
+            });
+        }
+        self.apply_catalog_timeout(catalog, kwargs)
+    }
+
+    // applies only the default query timeout, for the parquet and arrow
+    // exports that stream any number of documents
+    pub fn apply_catalog_timeout(&self, catalog: &str, mut kwargs: QueryKwargs) -> QueryKwargs {
+        if kwargs.max_time_ms.is_none() {
+            kwargs.max_time_ms = self
+                .catalog(catalog)
+                .max_time_ms
+                .or(self.api.default_max_time_ms);
+        }
+        kwargs
+    }
+}
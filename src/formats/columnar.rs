@@ -0,0 +1,383 @@
+use crate::formats::{
+    table::{cell_text, flatten_document},
+    OutputFormat,
+};
+use actix_web::{web::Bytes, HttpResponse};
+use arrow::{
+    array::{
+        ArrayRef, BinaryArray, BooleanArray, Float64Array, Int32Array, Int64Array, StringArray,
+        TimestampMillisecondArray,
+    },
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::ArrowError,
+    ipc::writer::StreamWriter,
+    record_batch::RecordBatch,
+};
+use futures::{channel::mpsc, SinkExt, Stream, TryStreamExt};
+use mongodb::bson::{Bson, Document};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+pub const PARQUET_CONTENT_TYPE: &str = "application/vnd.apache.parquet";
+pub const ARROW_STREAM_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+
+// documents per record batch (and parquet row group)
+pub const BATCH_SIZE: usize = 10_000;
+
+fn arrow_type(value: &Bson) -> Option<DataType> {
+    match value {
+        Bson::Null | Bson::Undefined => None,
+        Bson::Boolean(_) => Some(DataType::Boolean),
+        Bson::Int32(_) => Some(DataType::Int32),
+        Bson::Int64(_) => Some(DataType::Int64),
+        Bson::Double(_) => Some(DataType::Float64),
+        Bson::DateTime(_) => Some(DataType::Timestamp(
+            TimeUnit::Millisecond,
+            Some("UTC".into()),
+        )),
+        Bson::Binary(_) => Some(DataType::Binary),
+        _ => Some(DataType::Utf8),
+    }
+}
+
+// narrowest type holding the values of both
+fn merge_types(a: DataType, b: DataType) -> DataType {
+    match (&a, &b) {
+        _ if a == b => a,
+        (DataType::Int32, DataType::Int64) | (DataType::Int64, DataType::Int32) => DataType::Int64,
+        (
+            DataType::Int32 | DataType::Int64 | DataType::Float64,
+            DataType::Int32 | DataType::Int64 | DataType::Float64,
+        ) => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+// types of the flattened documents (candidate.magpsf, ...) in the order they
+// are first seen, widened as documents are added. None for the fields that
+// are only ever null
+#[derive(Default)]
+pub struct SchemaBuilder {
+    names: Vec<String>,
+    types: HashMap<String, Option<DataType>>,
+}
+
+impl SchemaBuilder {
+    pub fn add(&mut self, document: &Document) {
+        for (name, value) in flatten_document(document) {
+            let value_type = arrow_type(&value);
+            match self.types.get_mut(&name) {
+                Some(current) => {
+                    if let Some(value_type) = value_type {
+                        *current = Some(match current.take() {
+                            Some(t) => merge_types(t, value_type),
+                            None => value_type,
+                        });
+                    }
+                }
+                None => {
+                    self.names.push(name.clone());
+                    self.types.insert(name, value_type);
+                }
+            }
+        }
+    }
+
+    // every field is nullable since documents may miss some of them
+    pub fn schema(&self) -> Schema {
+        let fields: Vec<Field> = self
+            .names
+            .iter()
+            .map(|name| {
+                let data_type = self.types.get(name).cloned().flatten();
+                Field::new(name, data_type.unwrap_or(DataType::Utf8), true)
+            })
+            .collect();
+        Schema::new(fields)
+    }
+}
+
+// schema of the flattened documents
+pub fn infer_schema(documents: &[Document]) -> Schema {
+    let mut builder = SchemaBuilder::default();
+    for document in documents {
+        builder.add(document);
+    }
+    builder.schema()
+}
+
+fn as_i64(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(i) => Some(*i as i64),
+        Bson::Int64(i) => Some(*i),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(d) => Some(*d),
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+fn build_array(data_type: &DataType, values: &[Bson]) -> ArrayRef {
+    match data_type {
+        DataType::Boolean => Arc::new(BooleanArray::from(
+            values.iter().map(|v| v.as_bool()).collect::<Vec<_>>(),
+        )),
+        DataType::Int32 => Arc::new(Int32Array::from(
+            values
+                .iter()
+                .map(|v| as_i64(v).and_then(|i| i32::try_from(i).ok()))
+                .collect::<Vec<_>>(),
+        )),
+        DataType::Int64 => Arc::new(Int64Array::from(
+            values.iter().map(as_i64).collect::<Vec<_>>(),
+        )),
+        DataType::Float64 => Arc::new(Float64Array::from(
+            values.iter().map(as_f64).collect::<Vec<_>>(),
+        )),
+        DataType::Timestamp(_, _) => Arc::new(
+            TimestampMillisecondArray::from(
+                values
+                    .iter()
+                    .map(|v| v.as_datetime().map(|dt| dt.timestamp_millis()))
+                    .collect::<Vec<_>>(),
+            )
+            .with_timezone("UTC"),
+        ),
+        DataType::Binary => Arc::new(BinaryArray::from(
+            values
+                .iter()
+                .map(|v| match v {
+                    Bson::Binary(b) => Some(b.bytes.as_slice()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        )),
+        _ => Arc::new(StringArray::from(
+            values.iter().map(cell_text).collect::<Vec<_>>(),
+        )),
+    }
+}
+
+// record batch of documents, following the schema: fields missing from the
+// schema are dropped, values that do not fit the type of their field are null
+pub fn record_batch(schema: &SchemaRef, documents: &[Document]) -> Result<RecordBatch, ArrowError> {
+    let indices: HashMap<&str, usize> = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| (field.name().as_str(), i))
+        .collect();
+    let mut columns = vec![vec![Bson::Null; documents.len()]; schema.fields().len()];
+    for (row, document) in documents.iter().enumerate() {
+        for (name, value) in flatten_document(document) {
+            if let Some(i) = indices.get(name.as_str()) {
+                columns[*i][row] = value;
+            }
+        }
+    }
+    let arrays = schema
+        .fields()
+        .iter()
+        .zip(&columns)
+        .map(|(field, values)| build_array(field.data_type(), values))
+        .collect();
+    RecordBatch::try_new(schema.clone(), arrays)
+}
+
+// in-memory sink shared with a writer, drained after every batch
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Bytes {
+        let mut buffer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        Bytes::from(std::mem::take(&mut *buffer))
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut buffer = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum BatchWriter {
+    Parquet(ArrowWriter<SharedBuffer>),
+    Arrow(StreamWriter<SharedBuffer>),
+}
+
+impl BatchWriter {
+    fn new(format: OutputFormat, buffer: SharedBuffer, schema: SchemaRef) -> Result<Self, String> {
+        match format {
+            OutputFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                ArrowWriter::try_new(buffer, schema, Some(properties))
+                    .map(BatchWriter::Parquet)
+                    .map_err(|e| e.to_string())
+            }
+            _ => StreamWriter::try_new(buffer, &schema)
+                .map(BatchWriter::Arrow)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), String> {
+        match self {
+            // every batch is its own row group, so that it can be sent right away
+            BatchWriter::Parquet(writer) => writer
+                .write(batch)
+                .and_then(|_| writer.flush())
+                .map_err(|e| e.to_string()),
+            BatchWriter::Arrow(writer) => writer.write(batch).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            BatchWriter::Parquet(writer) => writer.close().map(|_| ()).map_err(|e| e.to_string()),
+            BatchWriter::Arrow(mut writer) => writer.finish().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+// documents of an export, written to a temporary file while the schema is
+// inferred, so that memory stays bounded however many documents are read.
+// The file is removed when the spool is dropped.
+struct Spool {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl Spool {
+    fn new() -> Result<Spool, String> {
+        let path = std::env::temp_dir().join(format!("boom-api-export-{}", Uuid::new_v4()));
+        let file = File::create(&path).map_err(|e| format!("Error creating spool: {}", e))?;
+        Ok(Spool {
+            path,
+            writer: Some(BufWriter::new(file)),
+        })
+    }
+
+    fn push(&mut self, document: &Document) -> Result<(), String> {
+        let writer = self.writer.as_mut().ok_or("spool already read")?;
+        document
+            .to_writer(writer)
+            .map_err(|e| format!("Error writing spool: {}", e))
+    }
+
+    // reader over the documents written so far
+    fn reader(&mut self) -> Result<BufReader<File>, String> {
+        if let Some(mut writer) = self.writer.take() {
+            writer
+                .flush()
+                .map_err(|e| format!("Error writing spool: {}", e))?;
+        }
+        let file = File::open(&self.path).map_err(|e| format!("Error reading spool: {}", e))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        self.writer = None;
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// reads the cursor to infer the schema of every document, then writes them
+// batch by batch. Types are widened over the whole export (integers to
+// floats, anything to text), and fields missing from a document are null.
+async fn write_batches<S>(
+    format: OutputFormat,
+    mut cursor: S,
+    sender: &mut mpsc::Sender<Result<Bytes, actix_web::Error>>,
+) -> Result<(), String>
+where
+    S: Stream<Item = Result<Document, mongodb::error::Error>> + Unpin,
+{
+    let mut spool = Spool::new()?;
+    let mut builder = SchemaBuilder::default();
+    let mut count = 0;
+    while let Some(document) = cursor
+        .try_next()
+        .await
+        .map_err(|e| format!("Error collecting documents: {:?}", e))?
+    {
+        builder.add(&document);
+        spool.push(&document)?;
+        count += 1;
+    }
+
+    let schema = Arc::new(builder.schema());
+    let buffer = SharedBuffer::default();
+    let mut writer = BatchWriter::new(format, buffer.clone(), schema.clone())?;
+    let mut reader = spool.reader()?;
+    let mut documents = Vec::with_capacity(BATCH_SIZE.min(count));
+    for i in 0..count {
+        let document = Document::from_reader(&mut reader)
+            .map_err(|e| format!("Error reading spool: {}", e))?;
+        documents.push(document);
+        if documents.len() < BATCH_SIZE && i + 1 < count {
+            continue;
+        }
+        let batch = record_batch(&schema, &documents).map_err(|e| e.to_string())?;
+        writer.write(&batch)?;
+        documents.clear();
+        let bytes = buffer.take();
+        if !bytes.is_empty() && sender.send(Ok(bytes)).await.is_err() {
+            // the client went away
+            return Ok(());
+        }
+    }
+    writer.finish()?;
+    let bytes = buffer.take();
+    if !bytes.is_empty() {
+        let _ = sender.send(Ok(bytes)).await;
+    }
+    Ok(())
+}
+
+// streams a cursor as parquet or an arrow IPC stream. The status is sent
+// before the documents are read, so an error while streaming aborts the
+// response, leaving the client with a truncated (invalid) file.
+pub fn columnar_response<S>(format: OutputFormat, cursor: S) -> HttpResponse
+where
+    S: Stream<Item = Result<Document, mongodb::error::Error>> + Unpin + 'static,
+{
+    let (mut sender, receiver) = mpsc::channel(4);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = write_batches(format, cursor, &mut sender).await {
+            let _ = sender
+                .send(Err(actix_web::error::ErrorInternalServerError(e)))
+                .await;
+        }
+    });
+    let content_type = match format {
+        OutputFormat::Parquet => PARQUET_CONTENT_TYPE,
+        _ => ARROW_STREAM_CONTENT_TYPE,
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .streaming(receiver)
+}
@@ -0,0 +1,112 @@
+use crate::formats::table::{cell_text, Table, TextTable};
+
+// quotes a field when it contains a separator, a quote or a line break (RFC 4180)
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// header line with the column names, then one line per row. Null values are
+// empty fields.
+pub fn write_csv(table: &Table) -> String {
+    let mut out = String::new();
+    let header: Vec<String> = table.columns.iter().map(|c| escape(&c.name)).collect();
+    out.push_str(&header.join(","));
+    out.push_str("\r\n");
+    for row in &table.rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|value| cell_text(value).map(|t| escape(&t)).unwrap_or_default())
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+// fields of the records of a CSV file (RFC 4180), quoted fields spanning
+// line breaks. Blank lines and lines starting with # are skipped.
+fn read_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    let mut line = 1;
+    loop {
+        // start of a record
+        if record.is_empty() && field.is_empty() {
+            match chars.peek() {
+                None => break,
+                Some('\r' | '\n') => {
+                    if chars.next() == Some('\n') {
+                        line += 1;
+                    }
+                    continue;
+                }
+                Some('#') => {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            line += 1;
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        // quoted field
+        if field.is_empty() && chars.peek() == Some(&'"') {
+            chars.next();
+            let start = line;
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        field.push(c);
+                    }
+                    None => return Err(format!("unterminated quoted field on line {}", start)),
+                }
+            }
+        }
+        match chars.next() {
+            Some(',') => record.push(std::mem::take(&mut field)),
+            Some('\r') if chars.peek() == Some(&'\n') => {}
+            Some('\n') | None => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+                if chars.peek().is_none() {
+                    break;
+                }
+            }
+            Some(c) => field.push(c),
+        }
+    }
+    Ok(records)
+}
+
+// table of a CSV file whose first record holds the column names
+pub fn read_csv(text: &str) -> Result<TextTable, String> {
+    let mut records = read_records(text)?.into_iter();
+    let columns = match records.next() {
+        Some(header) => header.into_iter().map(|c| c.trim().to_string()).collect(),
+        None => return Err("the CSV file is empty".to_string()),
+    };
+    let rows = records
+        .map(|record| record.into_iter().map(|f| f.trim().to_string()).collect())
+        .collect();
+    let table = TextTable { columns, rows };
+    table.validate()?;
+    Ok(table)
+}
@@ -0,0 +1,413 @@
+// minimal FITS support: reading the first binary table of a file (MOCs,
+// multi-order skymaps) or its primary image (alert cutouts), and writing
+// binary tables of 64 bit columns and images
+use std::collections::BTreeMap;
+
+pub const FITS_CONTENT_TYPE: &str = "application/fits";
+
+// FITS files are made of blocks of 36 cards of 80 characters
+const FITS_BLOCK: usize = 2880;
+const FITS_CARD: usize = 80;
+
+// value of a header card, without the quotes of strings and the comment
+fn card_value(card: &str) -> Option<(&str, &str)> {
+    let key = card.get(..8)?.trim_end();
+    if card.get(8..10) != Some("= ") {
+        return Some((key, ""));
+    }
+    let value = card[10..].trim_start();
+    let value = match value.strip_prefix('\'') {
+        Some(quoted) => quoted.split('\'').next().unwrap_or_default().trim_end(),
+        None => value.split('/').next().unwrap_or_default().trim(),
+    };
+    Some((key, value))
+}
+
+// header of the HDU starting at `offset`, and the offset of its data
+fn read_header(
+    bytes: &[u8],
+    mut offset: usize,
+) -> Result<(BTreeMap<String, String>, usize), String> {
+    let mut header = BTreeMap::new();
+    loop {
+        let block = offset
+            .checked_add(FITS_BLOCK)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or("truncated FITS header")?;
+        offset += FITS_BLOCK;
+        for card in block.chunks(FITS_CARD) {
+            let card = std::str::from_utf8(card).map_err(|_| "invalid FITS header")?;
+            match card_value(card) {
+                Some(("END", _)) => return Ok((header, offset)),
+                Some((key, value)) if !key.is_empty() => {
+                    header.insert(key.to_string(), value.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn header_int(header: &BTreeMap<String, String>, key: &str) -> Result<usize, String> {
+    header
+        .get(key)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("missing or invalid FITS keyword {}", key))
+}
+
+// size of the data of an HDU, padded to whole blocks
+fn data_size(header: &BTreeMap<String, String>) -> Result<usize, String> {
+    let naxis = header_int(header, "NAXIS")?;
+    if naxis == 0 {
+        return Ok(0);
+    }
+    let bitpix = header
+        .get("BITPIX")
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or("missing or invalid FITS keyword BITPIX")?;
+    let too_large = || "FITS data too large".to_string();
+    let mut size = bitpix.unsigned_abs() as usize / 8;
+    for axis in 1..=naxis {
+        size = size
+            .checked_mul(header_int(header, &format!("NAXIS{}", axis))?)
+            .ok_or_else(too_large)?;
+    }
+    size = size
+        .checked_add(header_int(header, "PCOUNT").unwrap_or(0))
+        .ok_or_else(too_large)?;
+    size.div_ceil(FITS_BLOCK)
+        .checked_mul(FITS_BLOCK)
+        .ok_or_else(too_large)
+}
+
+// type code and size in bytes of a column, from its TFORM (e.g. 1K, D, 16A)
+fn column_form(form: &str) -> Result<(char, usize), String> {
+    let invalid = || format!("invalid TFORM {}", form);
+    let digits = form.chars().take_while(|c| c.is_ascii_digit()).count();
+    let repeat: usize = match &form[..digits] {
+        "" => 1,
+        count => count.parse().map_err(|_| invalid())?,
+    };
+    let code = form[digits..].chars().next().ok_or_else(invalid)?;
+    let size = match code {
+        'L' | 'B' | 'A' => Some(repeat),
+        'X' => Some(repeat.div_ceil(8)),
+        'I' => repeat.checked_mul(2),
+        'J' | 'E' => repeat.checked_mul(4),
+        'K' | 'D' | 'C' | 'P' => repeat.checked_mul(8),
+        'M' | 'Q' => repeat.checked_mul(16),
+        _ => None,
+    };
+    // columns are read from their first value, which a zero repeat leaves out
+    match size {
+        Some(size) if size > 0 => Ok((code, size)),
+        _ => Err(invalid()),
+    }
+}
+
+// a column of a binary table: name, type code and offset within the rows
+struct Column {
+    name: String,
+    code: char,
+    offset: usize,
+}
+
+// the first binary table extension of a FITS file
+pub struct BinTable {
+    pub header: BTreeMap<String, String>,
+    columns: Vec<Column>,
+    row_size: usize,
+    data: Vec<u8>,
+}
+
+impl BinTable {
+    pub fn from_fits(bytes: &[u8]) -> Result<BinTable, String> {
+        if !bytes.starts_with(b"SIMPLE  =") {
+            return Err("not a FITS file".to_string());
+        }
+        let (primary, offset) = read_header(bytes, 0)?;
+        let (header, offset) = read_header(bytes, offset + data_size(&primary)?)?;
+        if header.get("XTENSION").map(String::as_str) != Some("BINTABLE") {
+            return Err("expected a binary table extension".to_string());
+        }
+        let row_size = header_int(&header, "NAXIS1")?;
+        if row_size == 0 {
+            return Err("invalid FITS keyword NAXIS1".to_string());
+        }
+        let rows = header_int(&header, "NAXIS2")?;
+        let mut columns = Vec::new();
+        let mut column_offset = 0;
+        for index in 1..=header_int(&header, "TFIELDS")? {
+            let form = header
+                .get(&format!("TFORM{}", index))
+                .ok_or_else(|| format!("missing FITS keyword TFORM{}", index))?;
+            let (code, size) = column_form(form)?;
+            columns.push(Column {
+                name: header
+                    .get(&format!("TTYPE{}", index))
+                    .cloned()
+                    .unwrap_or_default(),
+                code,
+                offset: column_offset,
+            });
+            // every column must fit within the rows
+            column_offset = column_offset
+                .checked_add(size)
+                .filter(|end| *end <= row_size)
+                .ok_or("invalid FITS keyword NAXIS1")?;
+        }
+        let data = row_size
+            .checked_mul(rows)
+            .and_then(|size| offset.checked_add(size))
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or("truncated FITS data")?
+            .to_vec();
+        Ok(BinTable {
+            header,
+            columns,
+            row_size,
+            data,
+        })
+    }
+
+    // the first value of each row in a numeric column, by name (case
+    // insensitive) or the first column when `name` is None
+    pub fn column(&self, name: Option<&str>) -> Result<Vec<f64>, String> {
+        let column = self.find(name)?;
+        self.values(column, |bytes, code| match code {
+            'I' => Some(i16::from_be_bytes(bytes[..2].try_into().unwrap()) as f64),
+            'J' => Some(i32::from_be_bytes(bytes[..4].try_into().unwrap()) as f64),
+            'K' => Some(i64::from_be_bytes(bytes[..8].try_into().unwrap()) as f64),
+            'E' => Some(f32::from_be_bytes(bytes[..4].try_into().unwrap()) as f64),
+            'D' => Some(f64::from_be_bytes(bytes[..8].try_into().unwrap())),
+            _ => None,
+        })
+    }
+
+    // an integer column, read without going through floats, which would lose
+    // the precision of 64 bit values
+    pub fn int_column(&self, name: Option<&str>) -> Result<Vec<i64>, String> {
+        let column = self.find(name)?;
+        self.values(column, |bytes, code| match code {
+            'I' => Some(i16::from_be_bytes(bytes[..2].try_into().unwrap()) as i64),
+            'J' => Some(i32::from_be_bytes(bytes[..4].try_into().unwrap()) as i64),
+            'K' => Some(i64::from_be_bytes(bytes[..8].try_into().unwrap())),
+            _ => None,
+        })
+    }
+
+    // a column by name (case insensitive), or the first one
+    fn find(&self, name: Option<&str>) -> Result<&Column, String> {
+        match name {
+            Some(name) => self
+                .columns
+                .iter()
+                .find(|c| c.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("missing column {}", name)),
+            None => self
+                .columns
+                .first()
+                .ok_or_else(|| "the table has no columns".to_string()),
+        }
+    }
+
+    fn values<T>(
+        &self,
+        column: &Column,
+        read: impl Fn(&[u8], char) -> Option<T>,
+    ) -> Result<Vec<T>, String> {
+        let mut values = Vec::with_capacity(self.data.len() / self.row_size.max(1));
+        for row in self.data.chunks(self.row_size) {
+            match read(&row[column.offset..], column.code) {
+                Some(value) => values.push(value),
+                None => {
+                    return Err(format!(
+                        "column {} has an unsupported type {}",
+                        column.name, column.code
+                    ))
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+// a header card, padded to 80 characters
+fn card(key: &str, value: &str) -> String {
+    format!("{:<8}= {:<70}", key, value)
+}
+
+pub fn string_card(key: &str, value: &str) -> String {
+    card(key, &format!("'{:<8}'", value))
+}
+
+pub fn number_card(key: &str, value: impl std::fmt::Display) -> String {
+    card(key, &format!("{:>20}", value))
+}
+
+pub fn logical_card(key: &str, value: bool) -> String {
+    card(key, &format!("{:>20}", if value { "T" } else { "F" }))
+}
+
+// header cards, ended and padded to whole blocks
+fn header_block(cards: Vec<String>) -> Vec<u8> {
+    let mut header: Vec<u8> = cards.concat().into_bytes();
+    header.extend(format!("{:<80}", "END").as_bytes());
+    header.resize(header.len().div_ceil(FITS_BLOCK) * FITS_BLOCK, b' ');
+    header
+}
+
+// values of a column written to a binary table
+pub enum TableColumn<'a> {
+    Int(&'a [i64]),
+    Double(&'a [f64]),
+}
+
+impl TableColumn<'_> {
+    fn len(&self) -> usize {
+        match self {
+            TableColumn::Int(values) => values.len(),
+            TableColumn::Double(values) => values.len(),
+        }
+    }
+
+    fn write(&self, row: usize, fits: &mut Vec<u8>) {
+        match self {
+            TableColumn::Int(values) => fits.extend(values[row].to_be_bytes()),
+            TableColumn::Double(values) => fits.extend(values[row].to_be_bytes()),
+        }
+    }
+}
+
+// FITS file with a binary table of 64 bit columns, of as many rows as the
+// shortest one, `cards` being added to its header
+pub fn write_table(columns: &[(&str, TableColumn)], cards: Vec<String>) -> Vec<u8> {
+    let rows = columns
+        .iter()
+        .map(|(_, values)| values.len())
+        .min()
+        .unwrap_or(0);
+    let mut fits = header_block(vec![
+        logical_card("SIMPLE", true),
+        number_card("BITPIX", 8),
+        number_card("NAXIS", 0),
+        logical_card("EXTEND", true),
+    ]);
+    let mut extension = vec![
+        string_card("XTENSION", "BINTABLE"),
+        number_card("BITPIX", 8),
+        number_card("NAXIS", 2),
+        number_card("NAXIS1", 8 * columns.len()),
+        number_card("NAXIS2", rows),
+        number_card("PCOUNT", 0),
+        number_card("GCOUNT", 1),
+        number_card("TFIELDS", columns.len()),
+    ];
+    for (index, (name, values)) in columns.iter().enumerate() {
+        let form = match values {
+            TableColumn::Int(_) => "1K",
+            TableColumn::Double(_) => "1D",
+        };
+        extension.push(string_card(&format!("TTYPE{}", index + 1), name));
+        extension.push(string_card(&format!("TFORM{}", index + 1), form));
+    }
+    extension.extend(cards);
+    fits.extend(header_block(extension));
+    let start = fits.len();
+    for row in 0..rows {
+        for (_, values) in columns {
+            values.write(row, &mut fits);
+        }
+    }
+    let size = (fits.len() - start).div_ceil(FITS_BLOCK) * FITS_BLOCK;
+    fits.resize(start + size, 0);
+    fits
+}
+
+// the primary 2D image of a FITS file, scaled to physical values. Rows start
+// at the bottom of the image, blank pixels are NaN.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<f64>,
+}
+
+impl Image {
+    pub fn from_fits(bytes: &[u8]) -> Result<Image, String> {
+        if !bytes.starts_with(b"SIMPLE  =") {
+            return Err("not a FITS file".to_string());
+        }
+        let (header, offset) = read_header(bytes, 0)?;
+        if header_int(&header, "NAXIS")? != 2 {
+            return Err("expected a 2D image".to_string());
+        }
+        let width = header_int(&header, "NAXIS1")?;
+        let height = header_int(&header, "NAXIS2")?;
+        let bitpix = header
+            .get("BITPIX")
+            .and_then(|value| value.parse::<i64>().ok())
+            .ok_or("missing or invalid FITS keyword BITPIX")?;
+        if ![8, 16, 32, 64, -32, -64].contains(&bitpix) {
+            return Err(format!("invalid FITS keyword BITPIX {}", bitpix));
+        }
+        let number = |key: &str, default: f64| {
+            header
+                .get(key)
+                .and_then(|value| value.parse::<f64>().ok())
+                .unwrap_or(default)
+        };
+        let (bscale, bzero) = (number("BSCALE", 1.0), number("BZERO", 0.0));
+        let blank = header
+            .get("BLANK")
+            .and_then(|value| value.parse::<i64>().ok());
+        let size = bitpix.unsigned_abs() as usize / 8;
+        let data = size
+            .checked_mul(width)
+            .and_then(|size| size.checked_mul(height))
+            .and_then(|size| offset.checked_add(size))
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or("truncated FITS data")?;
+        let integer = |value: i64| match blank {
+            Some(blank) if blank == value => f64::NAN,
+            _ => value as f64,
+        };
+        let mut pixels = Vec::with_capacity(width * height);
+        for value in data.chunks(size) {
+            let value = match bitpix {
+                8 => integer(value[0] as i64),
+                16 => integer(i16::from_be_bytes(value.try_into().unwrap()) as i64),
+                32 => integer(i32::from_be_bytes(value.try_into().unwrap()) as i64),
+                64 => integer(i64::from_be_bytes(value.try_into().unwrap())),
+                -32 => f32::from_be_bytes(value.try_into().unwrap()) as f64,
+                _ => f64::from_be_bytes(value.try_into().unwrap()),
+            };
+            pixels.push(bzero + bscale * value);
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+// FITS file with a primary image of 64 bit floats, rows starting at the bottom
+pub fn write_image(width: usize, height: usize, pixels: &[f64]) -> Vec<u8> {
+    let mut fits = header_block(vec![
+        logical_card("SIMPLE", true),
+        number_card("BITPIX", -64),
+        number_card("NAXIS", 2),
+        number_card("NAXIS1", width),
+        number_card("NAXIS2", height),
+    ]);
+    let start = fits.len();
+    for value in pixels.iter().take(width * height) {
+        fits.extend(value.to_be_bytes());
+    }
+    fits.resize(
+        start + (width * height * 8).div_ceil(FITS_BLOCK) * FITS_BLOCK,
+        0,
+    );
+    fits
+}
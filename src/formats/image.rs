@@ -0,0 +1,252 @@
+// rendering of FITS images (alert cutouts) as PNG: the pixel values are
+// mapped to [0, 1] by a stretch, then to colors by a colormap
+use crate::formats::fits::Image;
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::Write;
+
+pub const PNG_CONTENT_TYPE: &str = "image/png";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+// parameters of the stretches, as in astropy.visualization
+const LOG_A: f64 = 1000.0;
+const ASINH_A: f64 = 0.1;
+// parameters of the IRAF zscale algorithm
+const ZSCALE_SAMPLES: usize = 1000;
+const ZSCALE_CONTRAST: f64 = 0.25;
+const ZSCALE_MAX_REJECT: f64 = 0.5;
+const ZSCALE_MIN_PIXELS: usize = 5;
+const ZSCALE_KREJ: f64 = 2.5;
+const ZSCALE_ITERATIONS: usize = 5;
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stretch {
+    // between the smallest and the largest values
+    #[default]
+    Linear,
+    Log,
+    Asinh,
+    // linear between the zscale limits, which ignore outliers
+    Zscale,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Colormap {
+    #[default]
+    #[serde(alias = "grey")]
+    Gray,
+    Viridis,
+    Inferno,
+}
+
+// colors at 0, 1/8, ..., 1, interpolated in between
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 140, 10],
+    [249, 201, 50],
+    [252, 255, 164],
+];
+
+impl Stretch {
+    // the value of a pixel, normalized to [0, 1] between the limits
+    fn apply(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            Stretch::Linear | Stretch::Zscale => x,
+            Stretch::Log => (LOG_A * x + 1.0).log10() / (LOG_A + 1.0).log10(),
+            Stretch::Asinh => (x / ASINH_A).asinh() / (1.0 / ASINH_A).asinh(),
+        }
+    }
+
+    // values mapped to 0 and 1, None without finite pixels
+    pub fn limits(&self, pixels: &[f64]) -> Option<(f64, f64)> {
+        match self {
+            Stretch::Zscale => zscale(pixels),
+            _ => pixels
+                .iter()
+                .filter(|p| p.is_finite())
+                .fold(None, |limits, p| match limits {
+                    Some((min, max)) => Some((p.min(min), p.max(max))),
+                    None => Some((*p, *p)),
+                }),
+        }
+    }
+}
+
+impl Colormap {
+    pub fn color(&self, x: f64) -> [u8; 3] {
+        let table = match self {
+            Colormap::Gray => {
+                let level = (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+                return [level; 3];
+            }
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+        };
+        let position = x.clamp(0.0, 1.0) * (table.len() - 1) as f64;
+        let index = (position.floor() as usize).min(table.len() - 2);
+        let fraction = position - index as f64;
+        let mut color = [0; 3];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let (a, b) = (
+                table[index][channel] as f64,
+                table[index + 1][channel] as f64,
+            );
+            *value = (a + (b - a) * fraction).round() as u8;
+        }
+        color
+    }
+}
+
+// least squares line through the points that are not rejected
+fn fit_line(samples: &[f64], rejected: &[bool]) -> (f64, f64) {
+    let (mut n, mut sx, mut sy, mut sxx, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (i, (y, _)) in samples
+        .iter()
+        .zip(rejected)
+        .enumerate()
+        .filter(|(_, (_, rejected))| !**rejected)
+    {
+        let x = i as f64;
+        n += 1.0;
+        sx += x;
+        sy += y;
+        sxx += x * x;
+        sxy += x * y;
+    }
+    let denominator = n * sxx - sx * sx;
+    if denominator == 0.0 {
+        return (0.0, sy / n);
+    }
+    let slope = (n * sxy - sx * sy) / denominator;
+    (slope, (sy - slope * sx) / n)
+}
+
+// limits of the IRAF zscale algorithm: a line is fitted to the sorted values
+// of a sample of the pixels, rejecting outliers, and its slope reduced by the
+// contrast around the median
+pub fn zscale(pixels: &[f64]) -> Option<(f64, f64)> {
+    let finite: Vec<f64> = pixels.iter().copied().filter(|p| p.is_finite()).collect();
+    let stride = finite.len().div_ceil(ZSCALE_SAMPLES).max(1);
+    let mut samples: Vec<f64> = finite.into_iter().step_by(stride).collect();
+    samples.sort_by(f64::total_cmp);
+    let npix = samples.len();
+    let (mut vmin, mut vmax) = (*samples.first()?, *samples.last()?);
+
+    let min_pixels = ZSCALE_MIN_PIXELS.max((npix as f64 * ZSCALE_MAX_REJECT) as usize);
+    let grow = ((npix as f64 * 0.01) as usize).max(1);
+    let mut rejected = vec![false; npix];
+    let mut good = npix;
+    let mut last_good = npix + 1;
+    let mut fit = None;
+    for _ in 0..ZSCALE_ITERATIONS {
+        if good >= last_good || good < min_pixels {
+            break;
+        }
+        let (slope, intercept) = fit_line(&samples, &rejected);
+        fit = Some(slope);
+        let residuals: Vec<f64> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, y)| y - (intercept + slope * i as f64))
+            .collect();
+        let kept: Vec<f64> = residuals
+            .iter()
+            .zip(&rejected)
+            .filter(|(_, rejected)| !**rejected)
+            .map(|(r, _)| *r)
+            .collect();
+        let mean = kept.iter().sum::<f64>() / kept.len() as f64;
+        let deviation =
+            (kept.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / kept.len() as f64).sqrt();
+        let threshold = ZSCALE_KREJ * deviation;
+        let outliers: Vec<bool> = residuals.iter().map(|r| r.abs() > threshold).collect();
+        // outliers are rejected with their neighbours
+        for (i, outlier) in outliers.iter().enumerate() {
+            if *outlier {
+                let start = i.saturating_sub(grow / 2);
+                let end = (i + (grow - 1) / 2 + 1).min(npix);
+                rejected[start..end].iter_mut().for_each(|r| *r = true);
+            }
+        }
+        last_good = good;
+        good = rejected.iter().filter(|r| !**r).count();
+    }
+    if let (Some(slope), true) = (fit, good >= min_pixels) {
+        let slope = slope / ZSCALE_CONTRAST;
+        let center = (npix - 1) / 2;
+        let median = samples[center];
+        vmin = vmin.max(median - (center as f64 - 1.0) * slope);
+        vmax = vmax.min(median + (npix - center) as f64 * slope);
+    }
+    Some((vmin, vmax))
+}
+
+// RGBA pixels of an image, top row first. Blank pixels are transparent.
+pub fn render(image: &Image, stretch: Stretch, colormap: Colormap) -> Vec<u8> {
+    let (min, max) = stretch.limits(&image.pixels).unwrap_or((0.0, 1.0));
+    let range = if max > min { max - min } else { 1.0 };
+    let mut rgba = Vec::with_capacity(4 * image.width * image.height);
+    // FITS rows start at the bottom, PNG rows at the top
+    for row in image.pixels.chunks(image.width.max(1)).rev() {
+        for pixel in row {
+            if pixel.is_finite() {
+                rgba.extend(colormap.color(stretch.apply((pixel - min) / range)));
+                rgba.push(255);
+            } else {
+                rgba.extend([0; 4]);
+            }
+        }
+    }
+    rgba
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32fast::hash(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+// PNG file of 8 bit RGBA pixels, top row first
+pub fn write_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // bit depth 8, RGBA, deflate, no filter, no interlace
+    header.extend([8, 6, 0, 0, 0]);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in rgba.chunks(4 * width.max(1)).take(height) {
+        // each row starts with its filter type, none
+        let _ = encoder.write_all(&[0]);
+        let _ = encoder.write_all(row);
+    }
+    let data = encoder.finish().unwrap_or_default();
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
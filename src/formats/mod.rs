@@ -0,0 +1,118 @@
+pub mod columnar;
+pub mod csv;
+pub mod fits;
+pub mod image;
+pub mod ndjson;
+pub mod table;
+pub mod vosi;
+pub mod votable;
+
+use crate::formats::{
+    columnar::{ARROW_STREAM_CONTENT_TYPE, PARQUET_CONTENT_TYPE},
+    table::Table,
+};
+use actix_web::{http::header, HttpRequest, HttpResponse};
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+pub const VOTABLE_CONTENT_TYPE: &str = "application/x-votable+xml";
+
+// header carrying the continuation token of tabular responses, which have no
+// envelope to put it in
+pub const NEXT_TOKEN_HEADER: &str = "X-Next-Token";
+
+// representation of query results requested by the client
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // standard response envelope, results buffered
+    Json,
+    // one document per line, streamed from the cursor
+    Ndjson,
+    // flattened documents, one row per document
+    Csv,
+    // flattened documents as an IVOA VOTable
+    Votable,
+    // flattened documents as parquet, streamed in row groups
+    Parquet,
+    // flattened documents as an Arrow IPC stream, streamed in record batches
+    Arrow,
+}
+
+impl OutputFormat {
+    // negotiates the output format from the Accept header, the first
+    // supported media type wins
+    pub fn from_request(req: &HttpRequest) -> OutputFormat {
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        for media_type in accept.split(',') {
+            let media_type = media_type.split(';').next().unwrap_or_default().trim();
+            match media_type {
+                NDJSON_CONTENT_TYPE => return OutputFormat::Ndjson,
+                CSV_CONTENT_TYPE => return OutputFormat::Csv,
+                VOTABLE_CONTENT_TYPE => return OutputFormat::Votable,
+                PARQUET_CONTENT_TYPE => return OutputFormat::Parquet,
+                ARROW_STREAM_CONTENT_TYPE => return OutputFormat::Arrow,
+                "application/json" => return OutputFormat::Json,
+                _ => {}
+            }
+        }
+        OutputFormat::Json
+    }
+
+    // format passed by name, e.g. in the `format` kwarg
+    pub fn from_name(name: &str) -> Result<OutputFormat, String> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "csv" => Ok(OutputFormat::Csv),
+            "votable" => Ok(OutputFormat::Votable),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" => Ok(OutputFormat::Arrow),
+            _ => Err(format!(
+                "unknown format {}, expected one of json, ndjson, csv, votable, parquet or arrow",
+                name
+            )),
+        }
+    }
+
+    // an explicit format name takes precedence over the negotiated one
+    pub fn with_override(self, name: Option<&str>) -> Result<OutputFormat, String> {
+        match name {
+            Some(name) => OutputFormat::from_name(name),
+            None => Ok(self),
+        }
+    }
+
+    pub fn is_tabular(&self) -> bool {
+        matches!(self, OutputFormat::Csv | OutputFormat::Votable)
+    }
+
+    pub fn is_columnar(&self) -> bool {
+        matches!(self, OutputFormat::Parquet | OutputFormat::Arrow)
+    }
+}
+
+// responds with a table in a tabular format (csv or votable)
+pub fn table_response(format: OutputFormat, table: &Table, next: Option<String>) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    if let Some(next) = &next {
+        builder.insert_header((NEXT_TOKEN_HEADER, next.as_str()));
+    }
+    match format {
+        OutputFormat::Csv => builder
+            .content_type("text/csv; charset=utf-8")
+            .body(csv::write_csv(table)),
+        _ => {
+            let infos = match &next {
+                Some(next) => vec![("next", next.as_str())],
+                None => vec![],
+            };
+            builder
+                .content_type(VOTABLE_CONTENT_TYPE)
+                .body(votable::write_votable(table, &infos))
+        }
+    }
+}
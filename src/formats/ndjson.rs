@@ -0,0 +1,124 @@
+use crate::formats::NDJSON_CONTENT_TYPE;
+use actix_web::{web::Bytes, HttpResponse};
+use futures::{channel::mpsc, SinkExt, TryStreamExt};
+use mongodb::{bson::Document, Cursor};
+use std::future::Future;
+
+// number of lines buffered ahead of the client
+const CHANNEL_CAPACITY: usize = 64;
+
+// writes lines to a streaming response
+pub struct NdjsonWriter {
+    sender: mpsc::Sender<Result<Bytes, actix_web::Error>>,
+}
+
+impl NdjsonWriter {
+    // serializes a value as one line. Returns false once the client is gone,
+    // in which case the producer should stop.
+    pub async fn write<T: serde::Serialize>(&mut self, value: &T) -> bool {
+        let mut line = match serde_json::to_vec(value) {
+            Ok(l) => l,
+            Err(e) => match serde_json::to_vec(&serde_json::json!({
+                "status": "error",
+                "message": format!("error serializing document: {}", e),
+            })) {
+                Ok(l) => l,
+                Err(_) => return false,
+            },
+        };
+        line.push(b'\n');
+        self.sender.send(Ok(Bytes::from(line))).await.is_ok()
+    }
+}
+
+// last line of a stream, carrying the outcome of the query
+#[derive(serde::Serialize)]
+pub struct Summary {
+    pub status: String,
+    pub message: String,
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+impl Summary {
+    pub fn new(message: &str, count: u64, error: Option<String>, next: Option<String>) -> Self {
+        match error {
+            Some(e) => Summary {
+                status: "error".to_string(),
+                message: e,
+                count,
+                next: None,
+            },
+            None => Summary {
+                status: "success".to_string(),
+                message: message.to_string(),
+                count,
+                next,
+            },
+        }
+    }
+}
+
+// chunked response fed by `producer`, which runs as a separate task and
+// writes lines as it reads them from the database
+pub fn ndjson_response<F, Fut>(producer: F) -> HttpResponse
+where
+    F: FnOnce(NdjsonWriter) -> Fut,
+    Fut: Future<Output = ()> + 'static,
+{
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    actix_web::rt::spawn(producer(NdjsonWriter { sender }));
+    HttpResponse::Ok()
+        .content_type(NDJSON_CONTENT_TYPE)
+        .streaming(receiver)
+}
+
+// what happened while streaming a cursor
+pub struct CursorOutcome {
+    pub count: u64,
+    pub last: Option<Document>,
+    pub error: Option<String>,
+    // the client went away, nothing more should be written
+    pub disconnected: bool,
+}
+
+// writes every document of a cursor as a line. Cone search results are
+// wrapped as {"object": <object_name>, "data": <document>}.
+pub async fn write_cursor(
+    writer: &mut NdjsonWriter,
+    mut cursor: Cursor<Document>,
+    object: Option<&str>,
+) -> CursorOutcome {
+    let mut outcome = CursorOutcome {
+        count: 0,
+        last: None,
+        error: None,
+        disconnected: false,
+    };
+    loop {
+        match cursor.try_next().await {
+            Ok(Some(document)) => {
+                let written = match object {
+                    Some(name) => {
+                        writer
+                            .write(&serde_json::json!({ "object": name, "data": &document }))
+                            .await
+                    }
+                    None => writer.write(&document).await,
+                };
+                if !written {
+                    outcome.disconnected = true;
+                    return outcome;
+                }
+                outcome.count += 1;
+                outcome.last = Some(document);
+            }
+            Ok(None) => return outcome,
+            Err(e) => {
+                outcome.error = Some(format!("Error collecting documents: {:?}", e));
+                return outcome;
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use mongodb::bson::{Bson, Document};
+use std::collections::HashMap;
+
+// type of a column, named after the VOTable datatypes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Datatype {
+    Boolean,
+    Int,
+    Long,
+    Double,
+    Char,
+}
+
+impl Datatype {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Datatype::Boolean => "boolean",
+            Datatype::Int => "int",
+            Datatype::Long => "long",
+            Datatype::Double => "double",
+            Datatype::Char => "char",
+        }
+    }
+
+    fn of(value: &Bson) -> Option<Datatype> {
+        match value {
+            Bson::Null | Bson::Undefined => None,
+            Bson::Boolean(_) => Some(Datatype::Boolean),
+            Bson::Int32(_) => Some(Datatype::Int),
+            Bson::Int64(_) => Some(Datatype::Long),
+            Bson::Double(_) => Some(Datatype::Double),
+            _ => Some(Datatype::Char),
+        }
+    }
+
+    // narrowest type holding the values of both
+    fn merge(self, other: Datatype) -> Datatype {
+        use Datatype::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Int, Long) | (Long, Int) => Long,
+            (Int | Long | Double, Int | Long | Double) => Double,
+            _ => Char,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub name: String,
+    pub datatype: Datatype,
+    pub unit: Option<String>,
+    // unified content descriptor, e.g. POS_EQ_RA_MAIN
+    pub ucd: Option<String>,
+}
+
+// documents flattened into rows, with dotted column names (candidate.magpsf)
+#[derive(Clone, Debug)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Bson>>,
+}
+
+fn flatten_into(document: &Document, prefix: &str, out: &mut Vec<(String, Bson)>) {
+    for (key, value) in document {
+        let name = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            Bson::Document(d) => flatten_into(d, &name, out),
+            _ => out.push((name, value.clone())),
+        }
+    }
+}
+
+// flattens nested documents into dotted fields, arrays are kept as values
+pub fn flatten_document(document: &Document) -> Vec<(String, Bson)> {
+    let mut out = Vec::new();
+    flatten_into(document, "", &mut out);
+    out
+}
+
+// text of a cell, None for null values
+pub fn cell_text(value: &Bson) -> Option<String> {
+    match value {
+        Bson::Null | Bson::Undefined => None,
+        Bson::String(s) => Some(s.clone()),
+        Bson::Boolean(b) => Some(b.to_string()),
+        Bson::Int32(i) => Some(i.to_string()),
+        Bson::Int64(i) => Some(i.to_string()),
+        Bson::Double(d) => Some(d.to_string()),
+        Bson::ObjectId(id) => Some(id.to_hex()),
+        Bson::DateTime(dt) => Some(
+            dt.try_to_rfc3339_string()
+                .unwrap_or_else(|_| dt.timestamp_millis().to_string()),
+        ),
+        _ => Some(value.clone().into_relaxed_extjson().to_string()),
+    }
+}
+
+impl Table {
+    // columns are the union of the fields of all documents, in order of first
+    // appearance. units are looked up by column name.
+    pub fn from_documents(
+        name: &str,
+        documents: &[Document],
+        units: &HashMap<String, String>,
+    ) -> Table {
+        let mut columns: Vec<Column> = Vec::new();
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        let mut flat_documents = Vec::new();
+        for document in documents {
+            let fields = flatten_document(document);
+            for (field, _) in &fields {
+                if !indices.contains_key(field) {
+                    indices.insert(field.clone(), columns.len());
+                    columns.push(Column {
+                        name: field.clone(),
+                        datatype: Datatype::Char,
+                        unit: units.get(field).cloned(),
+                        ucd: None,
+                    });
+                }
+            }
+            flat_documents.push(fields);
+        }
+
+        // the datatype of a column must hold all of its values
+        let mut datatypes: Vec<Option<Datatype>> = vec![None; columns.len()];
+        let mut rows = Vec::new();
+        for fields in flat_documents {
+            let mut row = vec![Bson::Null; columns.len()];
+            for (field, value) in fields {
+                let index = indices[&field];
+                if let Some(datatype) = Datatype::of(&value) {
+                    datatypes[index] = Some(match datatypes[index] {
+                        Some(current) => current.merge(datatype),
+                        None => datatype,
+                    });
+                }
+                row[index] = value;
+            }
+            rows.push(row);
+        }
+        for (column, datatype) in columns.iter_mut().zip(datatypes) {
+            column.datatype = datatype.unwrap_or(Datatype::Char);
+        }
+
+        Table {
+            name: name.to_string(),
+            columns,
+            rows,
+        }
+    }
+
+    // moves the named columns to the front, in order. Missing columns are
+    // added with the given datatype and null values, so that they are present
+    // even when there are no rows.
+    pub fn lead_columns(&mut self, columns: &[(&str, Datatype)]) {
+        let mut leading: Vec<usize> = Vec::new();
+        for (name, datatype) in columns {
+            match self.columns.iter().position(|c| c.name == *name) {
+                Some(i) => leading.push(i),
+                None => {
+                    self.columns.push(Column {
+                        name: name.to_string(),
+                        datatype: *datatype,
+                        unit: None,
+                        ucd: None,
+                    });
+                    for row in self.rows.iter_mut() {
+                        row.push(Bson::Null);
+                    }
+                    leading.push(self.columns.len() - 1);
+                }
+            }
+        }
+        let mut indices = leading.clone();
+        indices.extend((0..self.columns.len()).filter(|i| !leading.contains(i)));
+
+        self.columns = indices.iter().map(|i| self.columns[*i].clone()).collect();
+        for row in self.rows.iter_mut() {
+            *row = indices.iter().map(|i| row[*i].clone()).collect();
+        }
+    }
+}
+
+// table read from an uploaded CSV or VOTable, its values kept as text
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl TextTable {
+    // index of a column, by name (case insensitive)
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+    }
+
+    // a row as a document: empty values are null, numbers are numbers unless
+    // padded with zeros (as ids often are), everything else is a string
+    pub fn row_document(&self, row: &[String]) -> Document {
+        let mut document = Document::new();
+        for (name, text) in self.columns.iter().zip(row) {
+            let digits = text.trim_start_matches(['+', '-']).as_bytes();
+            let padded = digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit();
+            let value = if text.is_empty() {
+                Bson::Null
+            } else if padded {
+                Bson::String(text.clone())
+            } else if let Ok(i) = text.parse::<i64>() {
+                Bson::Int64(i)
+            } else if let Some(d) = text.parse::<f64>().ok().filter(|d| d.is_finite()) {
+                Bson::Double(d)
+            } else {
+                Bson::String(text.clone())
+            };
+            document.insert(name, value);
+        }
+        document
+    }
+
+    // checks that every row has a value per column
+    pub fn validate(&self) -> Result<(), String> {
+        if self.columns.is_empty() {
+            return Err("the table has no columns".to_string());
+        }
+        for (index, row) in self.rows.iter().enumerate() {
+            if row.len() != self.columns.len() {
+                return Err(format!(
+                    "row {} has {} values, expected {}",
+                    index + 1,
+                    row.len(),
+                    self.columns.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
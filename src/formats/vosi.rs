@@ -0,0 +1,34 @@
+use crate::formats::votable::escape;
+
+// VOSI capabilities document of the service at `url`: the capabilities and
+// availability endpoints, followed by the capability of the service itself
+pub fn write_capabilities(url: &str, namespaces: &str, capability: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <vosi:capabilities xmlns:vosi=\"http://www.ivoa.net/xml/VOSICapabilities/v1.0\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xmlns:vs=\"http://www.ivoa.net/xml/VODataService/v1.1\"{namespaces}>\n\
+         <capability standardID=\"ivo://ivoa.net/std/VOSI#capabilities\">\n\
+         <interface xsi:type=\"vs:ParamHTTP\"><accessURL use=\"full\">{url}/capabilities</accessURL></interface>\n\
+         </capability>\n\
+         <capability standardID=\"ivo://ivoa.net/std/VOSI#availability\">\n\
+         <interface xsi:type=\"vs:ParamHTTP\"><accessURL use=\"full\">{url}/availability</accessURL></interface>\n\
+         </capability>\n\
+         {capability}\
+         </vosi:capabilities>\n",
+        url = escape(url),
+        namespaces = namespaces,
+        capability = capability,
+    )
+}
+
+// VOSI availability document
+pub fn write_availability(available: bool) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <vosi:availability xmlns:vosi=\"http://www.ivoa.net/xml/VOSIAvailability/v1.0\">\n\
+         <vosi:available>{}</vosi:available>\n\
+         </vosi:availability>\n",
+        available
+    )
+}
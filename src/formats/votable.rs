@@ -0,0 +1,206 @@
+use crate::formats::table::{cell_text, Datatype, Table, TextTable};
+use mongodb::bson::Bson;
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// reverses `escape`, along with numeric character references
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(name, end)| {
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => match name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#').and_then(|d| d.parse().ok()),
+                }
+                .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// value of an attribute of a start tag, e.g. name in `FIELD name="ra"`
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(index) = rest.find(name) {
+        let before = rest[..index].chars().next_back();
+        let after = rest[index + name.len()..].trim_start();
+        rest = &rest[index + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let value = match after.strip_prefix('=') {
+            Some(v) => v.trim_start(),
+            None => continue,
+        };
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let end = value[1..].find(quote)?;
+        return Some(unescape(&value[1..end + 1]));
+    }
+    None
+}
+
+// the fields and TABLEDATA rows of the first table of a VOTable. Namespace
+// prefixes are ignored, and binary serializations are not supported.
+pub fn read_votable(text: &str) -> Result<TextTable, String> {
+    let mut table = TextTable::default();
+    let mut row: Option<Vec<String>> = None;
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").ok_or("unterminated XML comment")?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        let end = rest.find('>').ok_or("unterminated XML tag")?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        let name = name.rsplit(':').next().unwrap_or_default();
+        match (name, closing) {
+            ("FIELD", false) => match attribute(tag, "name") {
+                Some(field) => table.columns.push(field),
+                None => return Err("FIELD without a name".to_string()),
+            },
+            ("BINARY" | "BINARY2" | "FITS", false) => {
+                return Err("only TABLEDATA serializations are supported".to_string());
+            }
+            ("TR", false) => row = Some(Vec::new()),
+            ("TR", true) => table.rows.extend(row.take()),
+            ("TD", false) => {
+                let cells = row.as_mut().ok_or("TD outside of a TR")?;
+                if tag.ends_with('/') {
+                    cells.push(String::new());
+                    continue;
+                }
+                let end = rest.find("</").ok_or("unterminated TD")?;
+                cells.push(unescape(&rest[..end]).trim().to_string());
+                rest = &rest[end..];
+            }
+            // only the first table is read
+            ("TABLE", true) => break,
+            _ => {}
+        }
+    }
+    table.validate()?;
+    Ok(table)
+}
+
+// text of a TD element, empty for null values
+fn cell(value: &Bson, datatype: Datatype) -> String {
+    match (value, datatype) {
+        (Bson::Boolean(b), Datatype::Boolean) => if *b { "T" } else { "F" }.to_string(),
+        (Bson::Double(d), _) if d.is_nan() => "NaN".to_string(),
+        (Bson::Double(d), _) if d.is_infinite() => {
+            if *d > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+        }
+        _ => cell_text(value).map(|t| escape(&t)).unwrap_or_default(),
+    }
+}
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<VOTABLE version=\"1.4\" xmlns=\"http://www.ivoa.net/xml/VOTable/v1.3\">\n\
+<RESOURCE type=\"results\">\n";
+
+// VOTable 1.4 document with a single TABLEDATA table. `infos` are added to
+// the resource as INFO elements, after the query status (OK unless a
+// QUERY_STATUS info is given, e.g. OVERFLOW).
+pub fn write_votable(table: &Table, infos: &[(&str, &str)]) -> String {
+    let mut out = String::from(HEADER);
+    if !infos.iter().any(|(name, _)| *name == "QUERY_STATUS") {
+        out.push_str("<INFO name=\"QUERY_STATUS\" value=\"OK\"/>\n");
+    }
+    for (name, value) in infos {
+        out.push_str(&format!(
+            "<INFO name=\"{}\" value=\"{}\"/>\n",
+            escape(name),
+            escape(value)
+        ));
+    }
+    out.push_str(&format!("<TABLE name=\"{}\">\n", escape(&table.name)));
+    for column in &table.columns {
+        out.push_str(&format!(
+            "<FIELD name=\"{}\" datatype=\"{}\"",
+            escape(&column.name),
+            column.datatype.name()
+        ));
+        if column.datatype == Datatype::Char {
+            out.push_str(" arraysize=\"*\"");
+        }
+        if let Some(unit) = &column.unit {
+            out.push_str(&format!(" unit=\"{}\"", escape(unit)));
+        }
+        if let Some(ucd) = &column.ucd {
+            out.push_str(&format!(" ucd=\"{}\"", escape(ucd)));
+        }
+        out.push_str("/>\n");
+    }
+    out.push_str("<DATA>\n<TABLEDATA>\n");
+    for row in &table.rows {
+        out.push_str("<TR>");
+        for (value, column) in row.iter().zip(&table.columns) {
+            out.push_str("<TD>");
+            out.push_str(&cell(value, column.datatype));
+            out.push_str("</TD>");
+        }
+        out.push_str("</TR>\n");
+    }
+    out.push_str("</TABLEDATA>\n</DATA>\n</TABLE>\n</RESOURCE>\n</VOTABLE>\n");
+    out
+}
+
+// VOTable reporting a failed query, with both the DALI query status and the
+// Error INFO expected by Simple Cone Search clients
+pub fn write_votable_error(message: &str) -> String {
+    let message = escape(message);
+    format!(
+        "{}<INFO name=\"QUERY_STATUS\" value=\"ERROR\">{}</INFO>\n\
+         <INFO ID=\"Error\" name=\"Error\" value=\"{}\"/>\n\
+         </RESOURCE>\n</VOTABLE>\n",
+        HEADER, message, message
+    )
+}
@@ -1,2 +1,7 @@
+pub mod adql;
 pub mod api;
+pub mod conf;
+pub mod formats;
 pub mod models;
+pub mod spatial;
+pub mod surveys;
@@ -0,0 +1,174 @@
+use crate::{
+    formats::image::{Colormap, Stretch},
+    surveys::Survey,
+};
+use mongodb::bson::{Bson, Document};
+
+// query string parameters of GET /alerts/{survey_name}/objects/{object_id}/lightcurve
+#[derive(serde::Deserialize, Clone)]
+pub struct LightcurveParams {
+    // comma separated filter bands, e.g. ztfg,ztfr. All bands by default.
+    pub filters: Option<String>,
+    // range of jd, both ends included
+    pub jd_min: Option<f64>,
+    pub jd_max: Option<f64>,
+    // json (default), csv or votable
+    pub format: Option<String>,
+}
+
+// query string parameters of GET /alerts/{survey_name}/candid/{candid}/cutout/{kind}
+#[derive(serde::Deserialize, Clone)]
+pub struct CutoutParams {
+    // png (default) or fits
+    pub format: Option<String>,
+    pub stretch: Option<Stretch>,
+    pub colormap: Option<Colormap>,
+}
+
+// query string parameters of GET /alerts/{survey_name}/candid/{candid}
+#[derive(serde::Deserialize, Clone)]
+pub struct AlertParams {
+    // comma separated parts to add to the alert: cutouts, prv_candidates
+    pub include: Option<String>,
+}
+
+// query string parameters of GET /alerts/{survey_name}/get_object/{object_id}
+#[derive(serde::Deserialize, Clone)]
+pub struct ObjectParams {
+    // comma separated parts to return: cutouts, prv_candidates, cross_matches,
+    // fp_hists. All but fp_hists by default.
+    pub include: Option<String>,
+}
+
+// an object: the newest of its alerts and the parts of its aux entry that
+// were asked for. Parts not asked for are left out, those asked for but not
+// found are null and listed in `missing`.
+#[derive(serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ObjectView {
+    #[serde(rename = "objectId")]
+    pub object_id: String,
+    // candidate of the newest alert
+    pub alert_metadata: Option<Document>,
+    #[serde(rename = "cutoutScience", skip_serializing_if = "Part::is_omitted")]
+    pub cutout_science: Part<Bson>,
+    #[serde(rename = "cutoutTemplate", skip_serializing_if = "Part::is_omitted")]
+    pub cutout_template: Part<Bson>,
+    #[serde(rename = "cutoutDifference", skip_serializing_if = "Part::is_omitted")]
+    pub cutout_difference: Part<Bson>,
+    #[serde(skip_serializing_if = "Part::is_omitted")]
+    pub prv_candidates: Part<Vec<Document>>,
+    #[serde(skip_serializing_if = "Part::is_omitted")]
+    pub cross_matches: Part<Document>,
+    #[serde(skip_serializing_if = "Part::is_omitted")]
+    pub fp_hists: Part<Vec<Document>>,
+    pub missing: Vec<String>,
+}
+
+// a part of an object view, serialized as its value or null
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Part<T> {
+    #[default]
+    Omitted,
+    Missing,
+    Found(T),
+}
+
+impl<T> Part<T> {
+    pub fn is_omitted(&self) -> bool {
+        matches!(self, Part::Omitted)
+    }
+
+    pub fn found(&self) -> Option<&T> {
+        match self {
+            Part::Found(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for Part<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Part::Found(value) => value.serialize(serializer),
+            _ => serializer.serialize_none(),
+        }
+    }
+}
+
+// array of documents of an aux entry, ignoring malformed elements
+fn documents(entry: &Document, field: &str) -> Option<Vec<Document>> {
+    entry.get_array(field).ok().map(|array| {
+        array
+            .iter()
+            .filter_map(|v| v.as_document().cloned())
+            .collect()
+    })
+}
+
+impl ObjectView {
+    // the view of an object from its newest alert and aux entry, either of
+    // which may be absent (e.g. an object being ingested)
+    pub fn new(
+        survey: &dyn Survey,
+        object_id: &str,
+        alert: Option<&Document>,
+        aux_entry: Option<&Document>,
+        include: &[String],
+    ) -> ObjectView {
+        let mut view = ObjectView {
+            object_id: object_id.to_string(),
+            alert_metadata: alert
+                .and_then(|alert| alert.get_document(survey.candidate_field()).ok().cloned()),
+            ..Default::default()
+        };
+        if view.alert_metadata.is_none() {
+            view.missing.push("alert_metadata".to_string());
+        }
+        let included = |part: &str| include.iter().any(|i| i == part);
+        if included("cutouts") {
+            for (kind, part) in [
+                ("science", &mut view.cutout_science),
+                ("template", &mut view.cutout_template),
+                ("difference", &mut view.cutout_difference),
+            ] {
+                let field = survey.cutout_field(kind).unwrap_or_default();
+                *part = match alert.and_then(|alert| alert.get(field)) {
+                    Some(cutout) if *cutout != Bson::Null => Part::Found(cutout.clone()),
+                    _ => {
+                        view.missing.push(field.to_string());
+                        Part::Missing
+                    }
+                };
+            }
+        }
+        if included("prv_candidates") {
+            view.prv_candidates = match aux_entry.and_then(|e| documents(e, "prv_candidates")) {
+                Some(p) => Part::Found(p),
+                None => {
+                    view.missing.push("prv_candidates".to_string());
+                    Part::Missing
+                }
+            };
+        }
+        if included("cross_matches") {
+            view.cross_matches = match aux_entry.and_then(|e| e.get_document("cross_matches").ok())
+            {
+                Some(c) => Part::Found(c.clone()),
+                None => {
+                    view.missing.push("cross_matches".to_string());
+                    Part::Missing
+                }
+            };
+        }
+        if included("fp_hists") {
+            view.fp_hists = match aux_entry.and_then(|e| documents(e, "fp_hists")) {
+                Some(f) => Part::Found(f),
+                None => {
+                    view.missing.push("fp_hists".to_string());
+                    Part::Missing
+                }
+            };
+        }
+        view
+    }
+}
@@ -0,0 +1,51 @@
+// claims carried by the JWTs accepted by the api
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: u64,
+    pub iat: u64,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    // ZTF program ids (data rights) granted to the user on top of those of
+    // their groups
+    #[serde(default)]
+    pub programids: Vec<i32>,
+}
+
+// user attached to every authenticated request, retrieve it in handlers
+// with web::ReqData<AuthenticatedUser>
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub groups: Vec<String>,
+    // effective program ids the user may access, see conf::AuthConfig::resolve_programids
+    pub programids: Vec<i32>,
+}
+
+impl From<Claims> for AuthenticatedUser {
+    fn from(claims: Claims) -> Self {
+        AuthenticatedUser {
+            username: claims.sub,
+            groups: claims.groups,
+            programids: claims.programids,
+        }
+    }
+}
+
+// user entry in the users collection. API tokens are stored as sha256 hashes
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct User {
+    pub username: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub programids: Vec<i32>,
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ApiToken {
+    pub name: Option<String>,
+    pub hash: String,
+}
@@ -0,0 +1,90 @@
+use crate::models::query_models::{AggregateBody, QueryBody};
+use mongodb::bson::DateTime;
+
+// a query run as a job, exactly one of find or aggregate, with the same body
+// as POST /query/find and POST /query/aggregate
+#[derive(serde::Deserialize)]
+pub struct JobBody {
+    pub find: Option<QueryBody>,
+    pub aggregate: Option<AggregateBody>,
+}
+
+// query string parameters of GET /jobs/{job_id}/results
+#[derive(serde::Deserialize, Clone)]
+pub struct JobResultsParams {
+    // index of the first result, defaults to 0
+    pub offset: Option<u64>,
+    // results per page, defaults to 1000
+    pub limit: Option<i64>,
+    pub format: Option<String>,
+}
+
+// UWS execution phases
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JobPhase {
+    Queued,
+    Executing,
+    Completed,
+    Error,
+    Aborted,
+}
+
+impl JobPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobPhase::Queued => "QUEUED",
+            JobPhase::Executing => "EXECUTING",
+            JobPhase::Completed => "COMPLETED",
+            JobPhase::Error => "ERROR",
+            JobPhase::Aborted => "ABORTED",
+        }
+    }
+
+    // whether the job is queued or executing
+    pub fn is_active(&self) -> bool {
+        matches!(self, JobPhase::Queued | JobPhase::Executing)
+    }
+}
+
+// a job as stored in the jobs collection
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Job {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub owner: String,
+    // find, aggregate or crossmatch
+    pub kind: String,
+    pub catalog: String,
+    pub phase: JobPhase,
+    pub created_at: DateTime,
+    pub started_at: Option<DateTime>,
+    pub finished_at: Option<DateTime>,
+    // when the job and its results are deleted (TTL index)
+    pub destruction: DateTime,
+    // number of results, once completed
+    pub count: i64,
+    pub error: Option<String>,
+}
+
+fn date_text(date: &Option<DateTime>) -> Option<String> {
+    date.and_then(|d| d.try_to_rfc3339_string().ok())
+}
+
+impl Job {
+    // the job as returned by the api, dates being RFC 3339 strings
+    pub fn status(&self) -> serde_json::Value {
+        serde_json::json!({
+            "job_id": self.id,
+            "kind": self.kind,
+            "catalog": self.catalog,
+            "phase": self.phase,
+            "created_at": date_text(&Some(self.created_at)),
+            "started_at": date_text(&self.started_at),
+            "finished_at": date_text(&self.finished_at),
+            "destruction": date_text(&Some(self.destruction)),
+            "count": self.count,
+            "error": self.error,
+        })
+    }
+}
@@ -1,3 +1,6 @@
+pub mod alert_models;
+pub mod auth_models;
 pub mod filter_models;
+pub mod job_models;
 pub mod query_models;
 pub mod response;
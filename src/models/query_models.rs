@@ -1,4 +1,11 @@
-use mongodb::bson::doc;
+use crate::{
+    formats::OutputFormat,
+    spatial::{
+        coordinates::{Coordinates, Frame},
+        regions::Region,
+    },
+};
+use mongodb::bson::Document;
 use std::{collections::HashMap, fmt};
 
 #[derive(serde::Deserialize, Clone)]
@@ -34,13 +41,27 @@ impl fmt::Debug for Unit {
     }
 }
 
-// TODO: update to multi-catalog (later)
 #[derive(serde::Deserialize, Clone)]
 pub struct ConeSearchBody {
     pub radius: Option<f64>,
     pub unit: Option<Unit>,
-    pub object_coordinates: Option<HashMap<String, [f64; 2]>>,
+    // decimal degrees or sexagesimal strings, see `Coordinates`
+    pub object_coordinates: Option<HashMap<String, Coordinates>>,
+    // frame of the object coordinates, defaults to ICRS
+    pub frame: Option<Frame>,
+    // a single catalog, results keyed by object name
     pub catalog: Option<CatalogDetails>,
+    // several catalogs by name, searched concurrently, results keyed by
+    // catalog then object name
+    pub catalogs: Option<HashMap<String, ConeSearchCatalog>>,
+    // add the separation of each match from its object, in `unit`
+    pub separation: Option<bool>,
+    // sort the matches of each object by increasing separation
+    pub sort_by_separation: Option<bool>,
+    // keep only the closest match of each object
+    pub nearest_only: Option<bool>,
+    // keep only the k closest matches of each object
+    pub k: Option<usize>,
     pub kwargs: Option<QueryKwargs>,
 }
 
@@ -51,6 +72,86 @@ pub struct CatalogDetails {
     pub projection: Option<mongodb::bson::Document>,
 }
 
+// filter and projection of one of the catalogs of a cone search
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct ConeSearchCatalog {
+    pub filter: Option<mongodb::bson::Document>,
+    pub projection: Option<mongodb::bson::Document>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RegionSearchBody {
+    // circle, box, polygon or ellipse, in ICRS degrees
+    pub region: Option<Region>,
+    // unit of circle radii and ellipse semi-axes, defaults to Degrees
+    pub unit: Option<Unit>,
+    pub catalog: Option<CatalogDetails>,
+    pub kwargs: Option<QueryKwargs>,
+}
+
+// a multi-order skymap, exactly one of:
+// - `fits`: a base64 encoded FITS file
+// - `path`: a FITS file relative to the skymaps directory of the server
+#[derive(serde::Deserialize, Clone)]
+pub struct SkymapSource {
+    pub fits: Option<String>,
+    pub path: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SkymapCrossmatchBody {
+    pub skymap: Option<SkymapSource>,
+    // probability enclosed by the searched region, defaults to 0.9
+    pub credible_level: Option<f64>,
+    // range of candidate.jd, both ends included
+    pub jd_min: Option<f64>,
+    pub jd_max: Option<f64>,
+    pub filter: Option<mongodb::bson::Document>,
+    pub projection: Option<mongodb::bson::Document>,
+    pub kwargs: Option<QueryKwargs>,
+}
+
+// query string parameters of POST /query/crossmatch, whose body is the
+// uploaded target list (CSV or VOTable)
+#[derive(serde::Deserialize, Clone)]
+pub struct CrossmatchParams {
+    pub catalog: Option<String>,
+    pub radius: Option<f64>,
+    // unit of the radius and of the separations, defaults to Arcseconds
+    pub unit: Option<Unit>,
+    // columns holding the positions of the targets, default ra and dec
+    pub ra_column: Option<String>,
+    pub dec_column: Option<String>,
+    // frame of the positions, defaults to ICRS
+    pub frame: Option<Frame>,
+    // JSON encoded filter and projection of the catalog
+    pub filter: Option<String>,
+    pub projection: Option<String>,
+    pub format: Option<String>,
+    // runs the crossmatch as a job, polled at /query/crossmatch/{job_id}
+    #[serde(rename = "async")]
+    pub run_async: Option<bool>,
+}
+
+impl CrossmatchParams {
+    // the filter (defaults to {}) and projection, decoded
+    pub fn documents(&self) -> Result<(Document, Option<Document>), String> {
+        Ok((
+            parse_json_param("filter", self.filter.clone())?.unwrap_or_default(),
+            parse_json_param("projection", self.projection.clone())?,
+        ))
+    }
+}
+
+// query string parameters of GET /query/coverage/{catalog}
+#[derive(serde::Deserialize, Clone)]
+pub struct CoverageParams {
+    // HEALPix order of the cells, defaults to 8
+    pub order: Option<u8>,
+    // json (default) or fits
+    pub format: Option<String>,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct Query {
     pub object_coordinates: Option<HashMap<String, [f64; 2]>>,
@@ -88,6 +189,13 @@ pub struct QueryKwargs {
     pub skip: Option<u64>,
     pub sort: Option<mongodb::bson::Document>,
     pub max_time_ms: Option<u64>,
+    // return a `next` continuation token with each full page
+    pub paginate: Option<bool>,
+    // continuation token of the previous page
+    pub after: Option<String>,
+    // output format (json, ndjson, csv or votable), takes precedence over
+    // the Accept header
+    pub format: Option<String>,
 }
 
 impl Default for QueryKwargs {
@@ -97,6 +205,9 @@ impl Default for QueryKwargs {
             skip: None,
             sort: None,
             max_time_ms: None,
+            paginate: None,
+            after: None,
+            format: None,
         }
     }
 }
@@ -105,8 +216,14 @@ impl fmt::Debug for QueryKwargs {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{:?},\n{:?},\n{:?},\n{:?}\n",
-            self.limit, self.skip, self.sort, self.max_time_ms
+            "{:?},\n{:?},\n{:?},\n{:?},\n{:?},\n{:?},\n{:?}\n",
+            self.limit,
+            self.skip,
+            self.sort,
+            self.max_time_ms,
+            self.paginate,
+            self.after,
+            self.format
         )
     }
 }
@@ -116,3 +233,304 @@ pub struct QueryBody {
     pub query: Option<Query>,
     pub kwargs: Option<QueryKwargs>,
 }
+
+// parses an optional JSON document passed as a query string parameter
+fn parse_json_param(name: &str, value: Option<String>) -> Result<Option<Document>, String> {
+    match value {
+        Some(v) => match serde_json::from_str::<Document>(&v) {
+            Ok(d) => Ok(Some(d)),
+            Err(e) => Err(format!("invalid JSON for {}: {}", name, e)),
+        },
+        None => Ok(None),
+    }
+}
+
+// query string parameters of GET /query/info
+#[derive(serde::Deserialize, Clone)]
+pub struct InfoParams {
+    pub command: Option<String>,
+    // comma separated catalog names
+    pub catalogs: Option<String>,
+}
+
+impl InfoParams {
+    pub fn into_body(self) -> Result<InfoQueryBody, String> {
+        Ok(InfoQueryBody {
+            command: self.command,
+            catalogs: self
+                .catalogs
+                .map(|c| c.split(',').map(|name| name.trim().to_string()).collect()),
+        })
+    }
+}
+
+// query string parameters of GET /query/sample
+#[derive(serde::Deserialize, Clone)]
+pub struct SampleParams {
+    pub catalog: Option<String>,
+    pub size: Option<i64>,
+}
+
+impl SampleParams {
+    pub fn into_body(self) -> Result<QueryBody, String> {
+        Ok(QueryBody {
+            query: Some(Query {
+                catalog: self.catalog,
+                size: self.size,
+                ..Default::default()
+            }),
+            kwargs: None,
+        })
+    }
+}
+
+// query string parameters of GET /query/count_documents
+#[derive(serde::Deserialize, Clone)]
+pub struct CountParams {
+    pub catalog: Option<String>,
+    // JSON encoded filter, defaults to {}
+    pub filter: Option<String>,
+}
+
+impl CountParams {
+    pub fn into_body(self) -> Result<QueryBody, String> {
+        Ok(QueryBody {
+            query: Some(Query {
+                catalog: self.catalog,
+                filter: parse_json_param("filter", self.filter)?,
+                ..Default::default()
+            }),
+            kwargs: None,
+        })
+    }
+}
+
+// query string parameters of GET /query/find
+#[derive(serde::Deserialize, Clone)]
+pub struct FindParams {
+    pub catalog: Option<String>,
+    // JSON encoded filter, defaults to {}
+    pub filter: Option<String>,
+    // JSON encoded projection
+    pub projection: Option<String>,
+    // JSON encoded sort
+    pub sort: Option<String>,
+    pub limit: Option<i64>,
+    pub skip: Option<u64>,
+    pub max_time_ms: Option<u64>,
+    pub paginate: Option<bool>,
+    pub after: Option<String>,
+    pub format: Option<String>,
+}
+
+impl FindParams {
+    pub fn into_body(self) -> Result<QueryBody, String> {
+        Ok(QueryBody {
+            query: Some(Query {
+                catalog: self.catalog,
+                filter: Some(parse_json_param("filter", self.filter)?.unwrap_or_default()),
+                projection: parse_json_param("projection", self.projection)?,
+                ..Default::default()
+            }),
+            kwargs: Some(QueryKwargs {
+                limit: self.limit,
+                skip: self.skip,
+                sort: parse_json_param("sort", self.sort)?,
+                max_time_ms: self.max_time_ms,
+                paginate: self.paginate,
+                after: self.after,
+                format: self.format,
+            }),
+        })
+    }
+}
+
+// query string parameters of GET /query/cone_search, for a single position
+#[derive(serde::Deserialize, Clone)]
+pub struct ConeSearchParams {
+    pub catalog: Option<String>,
+    // decimal degrees or sexagesimal
+    pub ra: Option<String>,
+    pub dec: Option<String>,
+    // frame of ra and dec, defaults to ICRS
+    pub frame: Option<Frame>,
+    pub radius: Option<f64>,
+    // defaults to Arcseconds
+    pub unit: Option<Unit>,
+    // key of the results, defaults to "object"
+    pub object_name: Option<String>,
+    // JSON encoded filter
+    pub filter: Option<String>,
+    // JSON encoded projection
+    pub projection: Option<String>,
+    pub limit: Option<i64>,
+    pub format: Option<String>,
+    pub separation: Option<bool>,
+    pub sort_by_separation: Option<bool>,
+    pub nearest_only: Option<bool>,
+    pub k: Option<usize>,
+}
+
+impl ConeSearchParams {
+    pub fn into_body(self) -> Result<ConeSearchBody, String> {
+        let coordinates = match (self.ra, self.dec) {
+            (Some(ra), Some(dec)) => match (ra.trim().parse(), dec.trim().parse()) {
+                (Ok(ra), Ok(dec)) => Coordinates::Degrees([ra, dec]),
+                _ => Coordinates::Pair([ra, dec]),
+            },
+            _ => return Err("ra and dec required for cone_search".to_string()),
+        };
+        let object_name = self.object_name.unwrap_or_else(|| "object".to_string());
+        Ok(ConeSearchBody {
+            radius: self.radius,
+            unit: Some(self.unit.unwrap_or(Unit::Arcseconds)),
+            object_coordinates: Some(HashMap::from([(object_name, coordinates)])),
+            frame: self.frame,
+            catalog: Some(CatalogDetails {
+                catalog_name: self.catalog,
+                filter: parse_json_param("filter", self.filter)?,
+                projection: parse_json_param("projection", self.projection)?,
+            }),
+            catalogs: None,
+            separation: self.separation,
+            sort_by_separation: self.sort_by_separation,
+            nearest_only: self.nearest_only,
+            k: self.k,
+            kwargs: Some(QueryKwargs {
+                limit: self.limit,
+                format: self.format,
+                ..Default::default()
+            }),
+        })
+    }
+}
+
+// query string parameters of GET /scs/{catalog}, as defined by IVOA Simple
+// Cone Search 1.03. Values are parsed by `into_query` so that invalid ones are
+// reported in a VOTable rather than by the extractor.
+#[derive(serde::Deserialize, Clone)]
+pub struct ScsParams {
+    #[serde(rename = "RA")]
+    pub ra: Option<String>,
+    #[serde(rename = "DEC")]
+    pub dec: Option<String>,
+    #[serde(rename = "SR")]
+    pub sr: Option<String>,
+    #[serde(rename = "VERB")]
+    pub verb: Option<String>,
+}
+
+// a validated cone search, in ICRS degrees
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScsQuery {
+    pub ra: f64,
+    pub dec: f64,
+    pub sr: f64,
+    // 1 (id and position), 2 (configured columns) or 3 (every field)
+    pub verb: u8,
+}
+
+fn parse_scs_param(name: &str, value: Option<String>) -> Result<f64, String> {
+    let value = match value {
+        Some(v) => v,
+        None => return Err(format!("{} is required", name)),
+    };
+    match value.trim().parse::<f64>() {
+        Ok(v) if v.is_finite() => Ok(v),
+        _ => Err(format!(
+            "{} must be a number of degrees, got {}",
+            name, value
+        )),
+    }
+}
+
+impl ScsParams {
+    pub fn into_query(self, max_sr: f64) -> Result<ScsQuery, String> {
+        let ra = parse_scs_param("RA", self.ra)?;
+        let dec = parse_scs_param("DEC", self.dec)?;
+        let sr = parse_scs_param("SR", self.sr)?;
+        if !(0.0..=360.0).contains(&ra) {
+            return Err("RA must be between 0 and 360 degrees".to_string());
+        }
+        if !(-90.0..=90.0).contains(&dec) {
+            return Err("DEC must be between -90 and 90 degrees".to_string());
+        }
+        if sr < 0.0 || sr > max_sr {
+            return Err(format!("SR must be between 0 and {} degrees", max_sr));
+        }
+        let verb = match self.verb.as_deref().map(str::trim) {
+            None | Some("") => 2,
+            Some("1") => 1,
+            Some("2") => 2,
+            Some("3") => 3,
+            Some(v) => return Err(format!("VERB must be 1, 2 or 3, got {}", v)),
+        };
+        Ok(ScsQuery { ra, dec, sr, verb })
+    }
+}
+
+// a validated TAP sync request
+#[derive(Clone, Debug, PartialEq)]
+pub struct TapQuery {
+    pub query: String,
+    pub format: OutputFormat,
+    pub maxrec: Option<i64>,
+}
+
+impl TapQuery {
+    // parameters of a TAP sync request, whose names are case-insensitive
+    // (TAP 1.1 section 2.3)
+    pub fn from_params(params: &HashMap<String, String>) -> Result<TapQuery, String> {
+        let params: HashMap<String, &str> = params
+            .iter()
+            .map(|(name, value)| (name.to_uppercase(), value.trim()))
+            .collect();
+        match params.get("REQUEST") {
+            None | Some(&"doQuery") => {}
+            Some(request) => {
+                return Err(format!("unsupported REQUEST {}, expected doQuery", request))
+            }
+        }
+        match params.get("LANG") {
+            Some(lang) if lang.to_uppercase().starts_with("ADQL") => {}
+            Some(lang) => return Err(format!("unsupported LANG {}, expected ADQL", lang)),
+            None => return Err("LANG is required".to_string()),
+        }
+        let query = match params.get("QUERY") {
+            Some(query) if !query.is_empty() => query.to_string(),
+            _ => return Err("QUERY is required".to_string()),
+        };
+        let format = match params.get("RESPONSEFORMAT").or(params.get("FORMAT")) {
+            None => OutputFormat::Votable,
+            Some(format) => match format.split(';').next().unwrap_or_default().trim() {
+                "votable" | "application/x-votable+xml" | "text/xml" => OutputFormat::Votable,
+                "csv" | "text/csv" => OutputFormat::Csv,
+                _ => {
+                    return Err(format!(
+                        "unsupported RESPONSEFORMAT {}, expected votable or csv",
+                        format
+                    ));
+                }
+            },
+        };
+        let maxrec = match params.get("MAXREC") {
+            None => None,
+            Some(maxrec) => match maxrec.parse::<i64>() {
+                Ok(m) if m >= 0 => Some(m),
+                _ => return Err(format!("MAXREC must be a positive integer, got {}", maxrec)),
+            },
+        };
+        Ok(TapQuery {
+            query,
+            format,
+            maxrec,
+        })
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct AggregateBody {
+    pub catalog: Option<String>,
+    pub pipeline: Option<Vec<Document>>,
+    pub kwargs: Option<QueryKwargs>,
+}
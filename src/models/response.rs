@@ -1,10 +1,13 @@
-use actix_web::HttpResponse;
+use actix_web::{http::header, HttpResponse};
 
 #[derive(serde::Serialize)]
 pub struct ApiResponseBody {
     pub status: String,
     pub message: String,
     pub data: serde_json::Value,
+    // continuation token of the next page, for paginated queries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
 }
 
 // ApiResponse constructors
@@ -14,6 +17,15 @@ impl ApiResponseBody {
             status: "success".to_string(),
             message: message.to_string(),
             data,
+            next: None,
+        }
+    }
+    pub fn ok_with_next(message: &str, data: serde_json::Value, next: Option<String>) -> Self {
+        Self {
+            status: "success".to_string(),
+            message: message.to_string(),
+            data,
+            next,
         }
     }
     pub fn internal_error(error_message: &str) -> Self {
@@ -21,6 +33,7 @@ impl ApiResponseBody {
             status: "error".to_string(),
             message: error_message.to_string(),
             data: serde_json::Value::Null,
+            next: None,
         }
     }
     pub fn bad_request(message: &str) -> Self {
@@ -28,6 +41,23 @@ impl ApiResponseBody {
             status: "error".to_string(),
             message: message.to_string(),
             data: serde_json::Value::Null,
+            next: None,
+        }
+    }
+    pub fn not_found(message: &str) -> Self {
+        Self {
+            status: "error".to_string(),
+            message: message.to_string(),
+            data: serde_json::Value::Null,
+            next: None,
+        }
+    }
+    pub fn unauthorized(message: &str) -> Self {
+        Self {
+            status: "error".to_string(),
+            message: message.to_string(),
+            data: serde_json::Value::Null,
+            next: None,
         }
     }
 }
@@ -37,6 +67,10 @@ pub fn ok(message: &str, data: serde_json::Value) -> HttpResponse {
     HttpResponse::Ok().json(ApiResponseBody::ok(message, data))
 }
 
+pub fn ok_with_next(message: &str, data: serde_json::Value, next: Option<String>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponseBody::ok_with_next(message, data, next))
+}
+
 pub fn internal_error(message: &str) -> HttpResponse {
     HttpResponse::InternalServerError().json(ApiResponseBody::internal_error(message))
 }
@@ -44,3 +78,26 @@ pub fn internal_error(message: &str) -> HttpResponse {
 pub fn bad_request(message: &str) -> HttpResponse {
     HttpResponse::BadRequest().json(ApiResponseBody::bad_request(message))
 }
+
+pub fn not_found(message: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(ApiResponseBody::not_found(message))
+}
+
+pub fn unauthorized(message: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().json(ApiResponseBody::unauthorized(message))
+}
+
+// marks a response as coming from a deprecated route, pointing to its successor
+pub fn deprecated(mut response: HttpResponse, successor: &str) -> HttpResponse {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::HeaderName::from_static("deprecation"),
+        header::HeaderValue::from_static("true"),
+    );
+    if let Ok(link) =
+        header::HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
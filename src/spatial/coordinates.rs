@@ -0,0 +1,236 @@
+// input coordinates of the searched objects: decimal degrees or sexagesimal
+// strings, in one of the supported frames, converted to ICRS degrees
+
+// frame of input coordinates
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Frame {
+    #[default]
+    #[serde(rename = "ICRS", alias = "icrs")]
+    Icrs,
+    // FK5 at equinox J2000, which agrees with ICRS to a few tens of
+    // milliarcseconds, well below the radius of any cone search
+    #[serde(rename = "FK5", alias = "fk5")]
+    Fk5,
+    #[serde(rename = "Galactic", alias = "galactic")]
+    Galactic,
+    // mean ecliptic and equinox of J2000
+    #[serde(rename = "Ecliptic", alias = "ecliptic")]
+    Ecliptic,
+}
+
+impl Frame {
+    fn is_equatorial(&self) -> bool {
+        matches!(self, Frame::Icrs | Frame::Fk5)
+    }
+
+    // names of the longitude and latitude, for error messages
+    fn axes(&self) -> (&'static str, &'static str) {
+        match self {
+            Frame::Icrs | Frame::Fk5 => ("ra", "dec"),
+            Frame::Galactic => ("l", "b"),
+            Frame::Ecliptic => ("lon", "lat"),
+        }
+    }
+}
+
+// position of an object as given by the user:
+// - [187.5, 12.3]: decimal degrees
+// - ["12:30:00", "+12:18:00"]: a pair of strings, sexagesimal or decimal
+// - "12:30:00 +12:18:00", "12h30m00s +12d18m00s", "12 30 00 +12 18 00" or
+//   "187.5, 12.3": both coordinates in one string
+#[derive(serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Coordinates {
+    Degrees([f64; 2]),
+    Pair([String; 2]),
+    Text(String),
+}
+
+// ICRS to galactic rotation matrix (Hipparcos, ESA 1997, vol. 1, section 1.5.3)
+const GALACTIC: [[f64; 3]; 3] = [
+    [-0.0548755604162154, -0.873437090234885, -0.4838350155487132],
+    [0.4941094278755837, -0.4448296299600112, 0.746982244497219],
+    [-0.8676661490190047, -0.1980763734312015, 0.4559837761750669],
+];
+
+// obliquity of the ecliptic at J2000 (IAU 2006), in degrees
+const OBLIQUITY: f64 = 23.439_279_444;
+
+fn to_cartesian(lon: f64, lat: f64) -> [f64; 3] {
+    let (lon, lat) = (lon.to_radians(), lat.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn to_spherical(v: [f64; 3]) -> (f64, f64) {
+    let lon = v[1].atan2(v[0]).to_degrees().rem_euclid(360.0);
+    let lat = v[2].atan2((v[0] * v[0] + v[1] * v[1]).sqrt()).to_degrees();
+    (lon, lat)
+}
+
+// galactic (l, b) to ICRS (ra, dec), in degrees
+pub fn galactic_to_icrs(l: f64, b: f64) -> (f64, f64) {
+    let g = to_cartesian(l, b);
+    // the inverse of a rotation is its transpose
+    let mut v = [0.0; 3];
+    for (i, row) in GALACTIC.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            v[j] += value * g[i];
+        }
+    }
+    to_spherical(v)
+}
+
+// ecliptic (lon, lat) to ICRS (ra, dec), in degrees
+pub fn ecliptic_to_icrs(lon: f64, lat: f64) -> (f64, f64) {
+    let e = to_cartesian(lon, lat);
+    let (sin, cos) = OBLIQUITY.to_radians().sin_cos();
+    to_spherical([e[0], e[1] * cos - e[2] * sin, e[1] * sin + e[2] * cos])
+}
+
+// an angle given as [+-]a:b:c, "a b c", "ahbmcs" or "adbmcs", or as a decimal
+// number. Returns the sign, the magnitude in units of the first field, and
+// whether the first field is in hours: "h", ":" or space separated fields
+// are, while "d" and "°" mark degrees.
+fn parse_sexagesimal(name: &str, value: &str) -> Result<(f64, f64, bool), String> {
+    let value = value.trim();
+    let invalid = || format!("invalid {} {:?}", name, value);
+    let (sign, unsigned) = match value.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let fields: Vec<&str> = unsigned
+        .split(|c: char| c == ':' || c.is_whitespace() || "hdms°'\"".contains(c))
+        .filter(|f| !f.is_empty())
+        .collect();
+    let degrees = unsigned.contains(['d', '°']);
+    if degrees && unsigned.contains(['h', ':']) {
+        return Err(invalid());
+    }
+    let hours = !degrees && (fields.len() > 1 || unsigned.contains([':', 'h', 'm', 's']));
+    if fields.is_empty() || fields.len() > 3 {
+        return Err(invalid());
+    }
+    let mut numbers = Vec::new();
+    for field in &fields {
+        match field.parse::<f64>() {
+            Ok(n) if n.is_finite() && n >= 0.0 => numbers.push(n),
+            _ => return Err(invalid()),
+        }
+    }
+    for n in &numbers[1..] {
+        if *n >= 60.0 {
+            return Err(format!(
+                "invalid {} {:?}, minutes and seconds must be below 60",
+                name, value
+            ));
+        }
+    }
+    // only the last field may have a fractional part
+    if numbers[..numbers.len() - 1]
+        .iter()
+        .any(|n| n.fract() != 0.0)
+    {
+        return Err(invalid());
+    }
+    let magnitude = numbers
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(n, scale)| n / scale)
+        .sum::<f64>();
+    Ok((sign, magnitude, hours))
+}
+
+// right ascension in degrees, converted from hours unless given in degrees
+fn parse_ra(value: &str) -> Result<f64, String> {
+    let (sign, magnitude, hours) = parse_sexagesimal("ra", value)?;
+    if sign < 0.0 {
+        return Err(format!("invalid ra {:?}, ra cannot be negative", value));
+    }
+    if hours {
+        if magnitude >= 24.0 {
+            return Err(format!("invalid ra {:?}, hours must be below 24", value));
+        }
+        Ok(magnitude * 15.0)
+    } else {
+        Ok(magnitude)
+    }
+}
+
+// any other angle, in degrees
+fn parse_degrees(name: &str, value: &str) -> Result<f64, String> {
+    let (sign, magnitude, _) = parse_sexagesimal(name, value)?;
+    Ok(sign * magnitude)
+}
+
+// longitude and latitude in degrees, the longitude being in hours when
+// sexagesimal (and not marked as degrees) in equatorial frames
+fn parse_fields(frame: &Frame, lon: &str, lat: &str) -> Result<(f64, f64), String> {
+    let (lon_name, lat_name) = frame.axes();
+    let lon = if frame.is_equatorial() {
+        parse_ra(lon)?
+    } else {
+        parse_degrees(lon_name, lon)?
+    };
+    Ok((lon, parse_degrees(lat_name, lat)?))
+}
+
+fn check_range(frame: &Frame, lon: f64, lat: f64) -> Result<(), String> {
+    let (lon_name, lat_name) = frame.axes();
+    if !(0.0..360.0).contains(&lon) {
+        return Err(format!(
+            "{} must be between 0 and 360 degrees, got {}",
+            lon_name, lon
+        ));
+    }
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(format!(
+            "{} must be between -90 and 90 degrees, got {}",
+            lat_name, lat
+        ));
+    }
+    Ok(())
+}
+
+impl Coordinates {
+    // the two coordinates as strings, split from a single string when needed
+    fn fields(text: &str) -> Result<(String, String), String> {
+        let tokens: Vec<&str> = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+            .collect();
+        match tokens.len() {
+            2 => Ok((tokens[0].to_string(), tokens[1].to_string())),
+            // "12 30 00 +12 18 00"
+            6 => Ok((tokens[..3].join(" "), tokens[3..].join(" "))),
+            _ => Err(format!(
+                "invalid coordinates {:?}, expected two coordinates such as \"12:30:00 +12:18:00\"",
+                text
+            )),
+        }
+    }
+
+    // position in ICRS degrees, validated
+    pub fn to_icrs(&self, frame: &Frame) -> Result<(f64, f64), String> {
+        let (lon_name, lat_name) = frame.axes();
+        let (lon, lat) = match self {
+            Coordinates::Degrees([lon, lat]) => (*lon, *lat),
+            Coordinates::Pair([lon, lat]) => parse_fields(frame, lon, lat)?,
+            Coordinates::Text(text) => {
+                let (lon, lat) = Coordinates::fields(text)?;
+                parse_fields(frame, &lon, &lat)?
+            }
+        };
+        if !lon.is_finite() || !lat.is_finite() {
+            return Err(format!(
+                "{} and {} must be finite numbers",
+                lon_name, lat_name
+            ));
+        }
+        check_range(frame, lon, lat)?;
+        Ok(match frame {
+            Frame::Icrs | Frame::Fk5 => (lon, lat),
+            Frame::Galactic => galactic_to_icrs(lon, lat),
+            Frame::Ecliptic => ecliptic_to_icrs(lon, lat),
+        })
+    }
+}
@@ -0,0 +1,144 @@
+// HEALPix pixels in the NESTED scheme (Górski et al. 2005), from and to
+// (ra, dec) in degrees
+use crate::spatial::angular_distance;
+use std::f64::consts::FRAC_PI_2;
+
+// deepest order, whose pixels still fit in a u64 with their order (NUNIQ)
+pub const MAX_ORDER: u8 = 29;
+
+// ring and longitude indices of the corners of the 12 base faces
+const JRLL: [i64; 12] = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+const JPLL: [i64; 12] = [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7];
+
+// interleaves the bits of a 32 bit value with zeros
+fn spread_bits(value: u64) -> u64 {
+    let mut x = value & 0xFFFF_FFFF;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+// the even bits of a value, packed
+fn compress_bits(value: u64) -> u64 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    (x | (x >> 16)) & 0x0000_0000_FFFF_FFFF
+}
+
+// number of pixels of the sky at `order`
+pub fn npix(order: u8) -> u64 {
+    12 << (2 * order as u64)
+}
+
+// pixel containing (ra, dec) at `order`
+pub fn pixel(order: u8, ra: f64, dec: f64) -> u64 {
+    let nside = 1i64 << order;
+    let z = dec.to_radians().sin();
+    let za = z.abs();
+    let tt = (ra / 90.0).rem_euclid(4.0);
+    let (face, ix, iy) = if za <= 2.0 / 3.0 {
+        // equatorial region
+        let temp1 = nside as f64 * (0.5 + tt);
+        let temp2 = nside as f64 * z * 0.75;
+        let jp = (temp1 - temp2) as i64;
+        let jm = (temp1 + temp2) as i64;
+        let (ifp, ifm) = (jp >> order, jm >> order);
+        let face = match ifp.cmp(&ifm) {
+            std::cmp::Ordering::Equal => ifp | 4,
+            std::cmp::Ordering::Less => ifp,
+            std::cmp::Ordering::Greater => ifm + 8,
+        };
+        (face, jm & (nside - 1), nside - (jp & (nside - 1)) - 1)
+    } else {
+        // polar caps, sqrt(3 (1 - |z|)) being computed from cos(dec) close
+        // to the poles for accuracy
+        let ntt = (tt as i64).min(3);
+        let tp = tt - ntt as f64;
+        let tmp = if za < 0.99 {
+            nside as f64 * (3.0 * (1.0 - za)).sqrt()
+        } else {
+            nside as f64 * dec.to_radians().cos() / ((1.0 + za) / 3.0).sqrt()
+        };
+        let jp = ((tp * tmp) as i64).min(nside - 1);
+        let jm = (((1.0 - tp) * tmp) as i64).min(nside - 1);
+        if z >= 0.0 {
+            (ntt, nside - jm - 1, nside - jp - 1)
+        } else {
+            (ntt + 8, jp, jm)
+        }
+    };
+    ((face as u64) << (2 * order as u64)) + spread_bits(ix as u64) + (spread_bits(iy as u64) << 1)
+}
+
+// (ra, dec) of the point at (dx, dy) within a pixel, both in [0, 1], the
+// center being at (0.5, 0.5)
+fn pixel_point(order: u8, pixel: u64, dx: f64, dy: f64) -> (f64, f64) {
+    let nside = (1u64 << order) as f64;
+    let face = (pixel >> (2 * order as u64)) as usize;
+    let index = pixel & ((1u64 << (2 * order as u64)) - 1);
+    let x = (compress_bits(index) as f64 + dx) / nside;
+    let y = (compress_bits(index >> 1) as f64 + dy) / nside;
+    let jr = JRLL[face] as f64 - x - y;
+    let (nr, z) = if jr < 1.0 {
+        (jr, 1.0 - jr * jr / 3.0)
+    } else if jr > 3.0 {
+        let nr = 4.0 - jr;
+        (nr, nr * nr / 3.0 - 1.0)
+    } else {
+        (1.0, (2.0 - jr) * 2.0 / 3.0)
+    };
+    let tmp = (JPLL[face] as f64 * nr + x - y).rem_euclid(8.0);
+    let phi = if nr < 1e-15 {
+        0.0
+    } else {
+        0.5 * FRAC_PI_2 * tmp / nr
+    };
+    (
+        phi.to_degrees().rem_euclid(360.0),
+        z.clamp(-1.0, 1.0).asin().to_degrees(),
+    )
+}
+
+// center of a pixel
+pub fn center(order: u8, pixel: u64) -> (f64, f64) {
+    pixel_point(order, pixel, 0.5, 0.5)
+}
+
+// radius of a circle around the center of a pixel containing it, in degrees.
+// The edges of pixels are not great circles, so they are sampled, and the
+// largest distance is widened by a margin.
+pub fn bounding_radius(order: u8, pixel: u64) -> f64 {
+    let (ra, dec) = center(order, pixel);
+    let steps = 8;
+    let mut radius: f64 = 0.0;
+    for i in 0..=steps {
+        let t = i as f64 / steps as f64;
+        for (dx, dy) in [(t, 0.0), (t, 1.0), (0.0, t), (1.0, t)] {
+            let (pra, pdec) = pixel_point(order, pixel, dx, dy);
+            radius = radius.max(angular_distance(ra, dec, pra, pdec));
+        }
+    }
+    radius * 1.05
+}
+
+// NUNIQ packing of a pixel with its order, as used by MOCs
+pub fn uniq(order: u8, pixel: u64) -> u64 {
+    (4u64 << (2 * order as u64)) + pixel
+}
+
+// order and pixel of a NUNIQ value
+pub fn from_uniq(uniq: u64) -> Result<(u8, u64), String> {
+    if uniq < 4 {
+        return Err(format!("invalid NUNIQ value {}", uniq));
+    }
+    let order = ((63 - uniq.leading_zeros()) / 2 - 1) as u8;
+    if order > MAX_ORDER {
+        return Err(format!("invalid NUNIQ value {}", uniq));
+    }
+    Ok((order, uniq - (4u64 << (2 * order as u64))))
+}
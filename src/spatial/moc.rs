@@ -0,0 +1,228 @@
+// IVOA Multi-Order Coverage maps (MOC 1.1, spatial): sets of HEALPix cells of
+// various orders, read and written as JSON or FITS (NUNIQ binary tables)
+use crate::{
+    formats::fits::{number_card, string_card, write_table, BinTable, TableColumn},
+    spatial::healpix::{self, MAX_ORDER},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::BTreeMap;
+
+// cells as sorted and disjoint ranges of pixels at MAX_ORDER, half open
+#[derive(serde::Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(try_from = "MocInput")]
+pub struct Moc {
+    ranges: Vec<(u64, u64)>,
+}
+
+// the forms in which a MOC can be sent, exactly one of:
+// - `moc`: the IVOA JSON serialization, {"<order>": [<pixels>]}
+// - `fits`: a base64 encoded FITS file with a NUNIQ column
+// - `order` and `pixels`: nested HEALPix pixels at a single order
+#[derive(serde::Deserialize)]
+pub struct MocInput {
+    pub moc: Option<BTreeMap<String, Vec<u64>>>,
+    pub fits: Option<String>,
+    pub order: Option<u8>,
+    pub pixels: Option<Vec<u64>>,
+}
+
+impl TryFrom<MocInput> for Moc {
+    type Error = String;
+
+    fn try_from(input: MocInput) -> Result<Moc, String> {
+        match input {
+            MocInput {
+                moc: Some(moc),
+                fits: None,
+                order: None,
+                pixels: None,
+            } => Moc::from_json(&moc),
+            MocInput {
+                moc: None,
+                fits: Some(fits),
+                order: None,
+                pixels: None,
+            } => match STANDARD.decode(fits.trim()) {
+                Ok(bytes) => Moc::from_fits(&bytes),
+                Err(_) => Err("fits must be base64 encoded".to_string()),
+            },
+            MocInput {
+                moc: None,
+                fits: None,
+                order: Some(order),
+                pixels: Some(pixels),
+            } => Moc::from_cells(pixels.into_iter().map(|pixel| (order, pixel))),
+            _ => Err("a MOC takes one of moc, fits, or order and pixels".to_string()),
+        }
+    }
+}
+
+// sorts ranges and merges those overlapping or touching
+fn normalize(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+impl Moc {
+    // union of (order, pixel) cells
+    pub fn from_cells(cells: impl IntoIterator<Item = (u8, u64)>) -> Result<Moc, String> {
+        let mut ranges = Vec::new();
+        for (order, pixel) in cells {
+            if order > MAX_ORDER {
+                return Err(format!(
+                    "invalid HEALPix order {}, must be at most {}",
+                    order, MAX_ORDER
+                ));
+            }
+            if pixel >= healpix::npix(order) {
+                return Err(format!(
+                    "invalid HEALPix pixel {} at order {}",
+                    pixel, order
+                ));
+            }
+            let shift = 2 * (MAX_ORDER - order) as u64;
+            ranges.push((pixel << shift, (pixel + 1) << shift));
+        }
+        Ok(Moc {
+            ranges: normalize(ranges),
+        })
+    }
+
+    // IVOA JSON serialization, {"<order>": [<pixels>]}
+    pub fn from_json(json: &BTreeMap<String, Vec<u64>>) -> Result<Moc, String> {
+        let mut cells = Vec::new();
+        for (order, pixels) in json {
+            let order: u8 = match order.parse() {
+                Ok(o) => o,
+                Err(_) => return Err(format!("invalid MOC order {:?}", order)),
+            };
+            cells.extend(pixels.iter().map(|pixel| (order, *pixel)));
+        }
+        Moc::from_cells(cells)
+    }
+
+    // FITS file whose first extension is a binary table of NUNIQ values
+    // (32 or 64 bit integers), as written by MOC 1.x tools
+    pub fn from_fits(bytes: &[u8]) -> Result<Moc, String> {
+        let table = BinTable::from_fits(bytes)?;
+        if let Some(ordering) = table.header.get("ORDERING") {
+            if ordering != "NUNIQ" {
+                return Err(format!(
+                    "unsupported MOC ordering {}, expected NUNIQ",
+                    ordering
+                ));
+            }
+        }
+        let mut cells = Vec::new();
+        for uniq in table.int_column(None)? {
+            if uniq < 0 {
+                return Err(format!("invalid NUNIQ value {}", uniq));
+            }
+            cells.push(healpix::from_uniq(uniq as u64)?);
+        }
+        Moc::from_cells(cells)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // whether (ra, dec) lies within one of the cells
+    pub fn contains(&self, ra: f64, dec: f64) -> bool {
+        let pixel = healpix::pixel(MAX_ORDER, ra, dec);
+        let index = self.ranges.partition_point(|(_, end)| *end <= pixel);
+        self.ranges
+            .get(index)
+            .is_some_and(|(start, _)| *start <= pixel)
+    }
+
+    // the fewest (order, pixel) cells covering exactly the MOC, by order
+    pub fn cells(&self) -> Vec<(u8, u64)> {
+        let mut cells = Vec::new();
+        for (start, end) in &self.ranges {
+            let mut start = *start;
+            while start < *end {
+                // the largest cell starting at `start` and ending before `end`
+                let mut depth = 0;
+                while depth < MAX_ORDER {
+                    let size = 1u64 << (2 * (depth + 1) as u64);
+                    if start % size != 0 || start + size > *end {
+                        break;
+                    }
+                    depth += 1;
+                }
+                cells.push((MAX_ORDER - depth, start >> (2 * depth as u64)));
+                start += 1u64 << (2 * depth as u64);
+            }
+        }
+        cells.sort_unstable();
+        cells
+    }
+
+    // deepest order of its cells
+    pub fn max_order(&self) -> u8 {
+        self.cells()
+            .iter()
+            .map(|(order, _)| *order)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // the smallest MOC of cells of at most `order` containing this one
+    pub fn degrade(&self, order: u8) -> Moc {
+        let shift = 2 * (MAX_ORDER - order.min(MAX_ORDER)) as u64;
+        let mask = (1u64 << shift) - 1;
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|(start, end)| (start & !mask, (end + mask) & !mask))
+            .collect();
+        Moc {
+            ranges: normalize(ranges),
+        }
+    }
+
+    // fraction of the sky covered
+    pub fn sky_fraction(&self) -> f64 {
+        let covered: u64 = self.ranges.iter().map(|(start, end)| end - start).sum();
+        covered as f64 / healpix::npix(MAX_ORDER) as f64
+    }
+
+    // IVOA JSON serialization, {"<order>": [<pixels>]}
+    pub fn to_json(&self) -> BTreeMap<u8, Vec<u64>> {
+        let mut json: BTreeMap<u8, Vec<u64>> = BTreeMap::new();
+        for (order, pixel) in self.cells() {
+            json.entry(order).or_default().push(pixel);
+        }
+        json
+    }
+
+    // FITS file with a NUNIQ binary table (MOC 1.1)
+    pub fn to_fits(&self) -> Vec<u8> {
+        let mut uniqs: Vec<i64> = self
+            .cells()
+            .into_iter()
+            .map(|(order, pixel)| healpix::uniq(order, pixel) as i64)
+            .collect();
+        uniqs.sort_unstable();
+        write_table(
+            &[("UNIQ", TableColumn::Int(&uniqs))],
+            vec![
+                string_card("PIXTYPE", "HEALPIX"),
+                string_card("ORDERING", "NUNIQ"),
+                string_card("COORDSYS", "C"),
+                number_card("MOCORDER", self.max_order()),
+                string_card("MOCVERS", "1.1"),
+                string_card("MOCDIM", "SPACE"),
+                string_card("MOCTOOL", "boom-api"),
+            ],
+        )
+    }
+}
@@ -0,0 +1,263 @@
+pub mod coordinates;
+pub mod healpix;
+pub mod moc;
+pub mod regions;
+pub mod skymap;
+
+use crate::models::query_models::Unit;
+use futures::{Stream, TryStreamExt};
+use mongodb::bson::{Bson, Document};
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+// field holding the GeoJSON position of the documents, with the longitude
+// being ra - 180
+pub const POSITION_FIELD: &str = "coordinates.radec_geojson";
+
+// field added to the matches of a cone search
+pub const SEPARATION_FIELD: &str = "separation";
+
+// great circle distance between two positions, in degrees (haversine, which
+// stays accurate at small separations)
+pub fn angular_distance(ra1: f64, dec1: f64, ra2: f64, dec2: f64) -> f64 {
+    let (ra1, dec1, ra2, dec2) = (
+        ra1.to_radians(),
+        dec1.to_radians(),
+        ra2.to_radians(),
+        dec2.to_radians(),
+    );
+    let a = ((dec2 - dec1) / 2.0).sin().powi(2)
+        + dec1.cos() * dec2.cos() * ((ra2 - ra1) / 2.0).sin().powi(2);
+    (2.0 * a.sqrt().min(1.0).asin()).to_degrees()
+}
+
+// an angle in degrees, converted to `unit`
+pub fn degrees_to(value: f64, unit: &Unit) -> f64 {
+    match unit {
+        Unit::Degrees => value,
+        Unit::Arcseconds => value * 3600.0,
+        Unit::Arcminutes => value * 60.0,
+        Unit::Radians => value.to_radians(),
+    }
+}
+
+// an angle in `unit`, converted to degrees
+pub fn degrees_from(value: f64, unit: &Unit) -> f64 {
+    match unit {
+        Unit::Degrees => value,
+        Unit::Arcseconds => value / 3600.0,
+        Unit::Arcminutes => value / 60.0,
+        Unit::Radians => value.to_degrees(),
+    }
+}
+
+// (ra, dec) of a document, in degrees, from its GeoJSON position
+pub fn document_position(document: &Document) -> Option<(f64, f64)> {
+    let coordinates = document
+        .get_document("coordinates")
+        .ok()?
+        .get_document("radec_geojson")
+        .ok()?
+        .get_array("coordinates")
+        .ok()?;
+    let number = |value: &Bson| match value {
+        Bson::Double(d) => Some(*d),
+        Bson::Int32(i) => Some(*i as f64),
+        Bson::Int64(i) => Some(*i as f64),
+        _ => None,
+    };
+    match coordinates.as_slice() {
+        [lon, lat] => Some((number(lon)? + 180.0, number(lat)?)),
+        _ => None,
+    }
+}
+
+// the projection of a cone search, extended to read the position of the
+// documents. Also returns the field to remove from the results, when the
+// position was not requested.
+pub fn projection_with_position(
+    projection: Option<Document>,
+) -> (Option<Document>, Option<String>) {
+    let mut projection = match projection {
+        Some(p) if !p.is_empty() => p,
+        // every field is returned
+        _ => return (projection, None),
+    };
+    let included = |value: &Bson| {
+        !matches!(
+            value,
+            Bson::Int32(0) | Bson::Int64(0) | Bson::Boolean(false) | Bson::Double(0.0)
+        )
+    };
+    let inclusive = projection
+        .iter()
+        .any(|(key, value)| key != "_id" && included(value));
+    if inclusive {
+        if projection.contains_key("coordinates") || projection.contains_key(POSITION_FIELD) {
+            return (Some(projection), None);
+        }
+        projection.insert(POSITION_FIELD, 1);
+        return (Some(projection), Some(POSITION_FIELD.to_string()));
+    }
+    // the exclusion of the position is lifted, and applied to the results
+    let strip = ["coordinates", POSITION_FIELD]
+        .into_iter()
+        .find(|key| projection.remove(*key).is_some())
+        .map(String::from);
+    if projection.is_empty() {
+        return (None, strip);
+    }
+    (Some(projection), strip)
+}
+
+// removes a (dotted) field from a document, along with the documents left
+// empty by its removal
+fn remove_field(document: &mut Document, path: &str) {
+    match path.split_once('.') {
+        None => {
+            document.remove(path);
+        }
+        Some((head, rest)) => {
+            if let Ok(child) = document.get_document_mut(head) {
+                remove_field(child, rest);
+                if child.is_empty() {
+                    document.remove(head);
+                }
+            }
+        }
+    }
+}
+
+// separation of the matches of a cone search from the searched position
+#[derive(Clone, Debug)]
+pub struct Separation {
+    pub unit: Unit,
+    // sort the matches by increasing separation
+    pub sort: bool,
+    // keep only the k closest matches
+    pub k: Option<usize>,
+    // field read to compute the separation but not requested, removed from
+    // the results
+    pub strip: Option<String>,
+}
+
+impl Separation {
+    // adds the separation to the matches of the cone search around `center`,
+    // sorting them and keeping the k closest ones if requested. Matches
+    // without a position have no separation, and come last when sorting.
+    pub fn apply(&self, center: (f64, f64), documents: Vec<Document>) -> Vec<Document> {
+        let mut matches = self.matches(center);
+        for document in documents {
+            matches.push(document);
+        }
+        matches.finish()
+    }
+
+    // reads the matches of a cursor, holding at most k of them when only the
+    // k closest are kept
+    pub async fn collect<S, E>(&self, center: (f64, f64), mut cursor: S) -> Result<Vec<Document>, E>
+    where
+        S: Stream<Item = Result<Document, E>> + Unpin,
+    {
+        let mut matches = self.matches(center);
+        while let Some(document) = cursor.try_next().await? {
+            matches.push(document);
+        }
+        Ok(matches.finish())
+    }
+
+    pub fn matches(&self, center: (f64, f64)) -> SeparatedMatches<'_> {
+        SeparatedMatches {
+            separation: self,
+            center,
+            matches: BinaryHeap::new(),
+            seen: 0,
+        }
+    }
+}
+
+// a match with its separation, ordered by separation then by arrival, the
+// matches without a separation coming last
+struct SeparatedMatch {
+    separation: Option<f64>,
+    index: usize,
+    document: Document,
+}
+
+impl SeparatedMatch {
+    fn cmp_key(&self, other: &Self) -> Ordering {
+        let by_separation = match (self.separation, other.separation) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        by_separation.then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialEq for SeparatedMatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp_key(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SeparatedMatch {}
+
+impl PartialOrd for SeparatedMatch {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SeparatedMatch {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_key(other)
+    }
+}
+
+// matches of a cone search being read. With k, the heap only keeps the k
+// closest ones seen so far, its top being the farthest of them.
+pub struct SeparatedMatches<'a> {
+    separation: &'a Separation,
+    center: (f64, f64),
+    matches: BinaryHeap<SeparatedMatch>,
+    seen: usize,
+}
+
+impl SeparatedMatches<'_> {
+    pub fn push(&mut self, mut document: Document) {
+        let (ra0, dec0) = self.center;
+        let separation = document_position(&document).map(|(ra, dec)| {
+            degrees_to(angular_distance(ra0, dec0, ra, dec), &self.separation.unit)
+        });
+        if let Some(field) = &self.separation.strip {
+            remove_field(&mut document, field);
+        }
+        if let Some(separation) = separation {
+            document.insert(SEPARATION_FIELD, separation);
+        }
+        self.matches.push(SeparatedMatch {
+            separation,
+            index: self.seen,
+            document,
+        });
+        self.seen += 1;
+        if let Some(k) = self.separation.k {
+            if self.matches.len() > k {
+                self.matches.pop();
+            }
+        }
+    }
+
+    // the matches, by increasing separation when sorting or keeping the k
+    // closest, in the order they were read otherwise
+    pub fn finish(self) -> Vec<Document> {
+        let mut matches = self.matches.into_vec();
+        if self.separation.sort || self.separation.k.is_some() {
+            matches.sort();
+        } else {
+            matches.sort_by_key(|m| m.index);
+        }
+        matches.into_iter().map(|m| m.document).collect()
+    }
+}
@@ -0,0 +1,347 @@
+// sky regions, translated to GeoJSON queries on the position of the documents.
+// Regions whose edges are not great circles are queried with a slightly larger
+// shape, and the matches are then filtered with `Region::contains`.
+use crate::{
+    api::query::build_cone_search_filter,
+    models::query_models::Unit,
+    spatial::{
+        angular_distance, degrees_from, document_position, healpix, moc::Moc, remove_field,
+        POSITION_FIELD,
+    },
+};
+use mongodb::bson::{doc, Bson, Document};
+
+// the edges of coordinate boxes along parallels are split in steps of at most
+// one degree
+const MAX_STEP: f64 = 1.0;
+
+// MOCs are queried with circles around their cells, degraded to lower orders
+// until there are at most this many
+const MAX_QUERY_CELLS: usize = 64;
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Region {
+    // center and radius
+    Circle {
+        ra: f64,
+        dec: f64,
+        radius: f64,
+    },
+    // ra and dec ranges, wrapping around ra = 0 when ra_min > ra_max
+    Box {
+        ra_min: f64,
+        ra_max: f64,
+        dec_min: f64,
+        dec_max: f64,
+    },
+    // vertices as [ra, dec], joined by great circles
+    Polygon {
+        vertices: Vec<[f64; 2]>,
+    },
+    // center, semi-axes and position angle of the major axis, in degrees
+    // east of north
+    Ellipse {
+        ra: f64,
+        dec: f64,
+        semi_major: f64,
+        semi_minor: f64,
+        #[serde(default)]
+        position_angle: f64,
+    },
+    // HEALPix cells, see `MocInput` for the accepted forms
+    Moc(Moc),
+}
+
+fn check_position(name: &str, ra: f64, dec: f64) -> Result<(), String> {
+    if !(0.0..=360.0).contains(&ra) || !(-90.0..=90.0).contains(&dec) {
+        return Err(format!(
+            "{} must have ra between 0 and 360 and dec between -90 and 90 degrees",
+            name
+        ));
+    }
+    Ok(())
+}
+
+// filter of the documents within a GeoJSON polygon of (ra, dec) vertices in
+// degrees, closing its ring
+pub fn polygon_filter(vertices: &[(f64, f64)]) -> Document {
+    let mut ring: Vec<Bson> = vertices
+        .iter()
+        .map(|(ra, dec)| Bson::Array(vec![Bson::Double(ra - 180.0), Bson::Double(*dec)]))
+        .collect();
+    ring.push(ring[0].clone());
+    doc! {
+        POSITION_FIELD: {
+            "$geoWithin": {
+                "$geometry": { "type": "Polygon", "coordinates": [ring] }
+            }
+        }
+    }
+}
+
+// corners of an ADQL BOX, whose edges are great circles: the box is a
+// rectangle in the tangent plane at its center (gnomonic projection, which
+// maps great circles to straight lines)
+pub fn box_vertices(ra: f64, dec: f64, width: f64, height: f64) -> Vec<(f64, f64)> {
+    let (ra0, dec0) = (ra.to_radians(), dec.to_radians());
+    let (x, y) = (
+        (width / 2.0).to_radians().tan(),
+        (height / 2.0).to_radians().tan(),
+    );
+    [(-x, -y), (x, -y), (x, y), (-x, y)]
+        .iter()
+        .map(|(xi, eta)| {
+            let denominator = dec0.cos() - eta * dec0.sin();
+            let ra = ra0 + xi.atan2(denominator);
+            let dec = ((dec0.sin() + eta * dec0.cos()) * (ra - ra0).cos()).atan2(denominator);
+            (ra.to_degrees().rem_euclid(360.0), dec.to_degrees())
+        })
+        .collect()
+}
+
+// vertices of a polygon containing a coordinate box. The great circle between
+// two points of a parallel bulges towards the pole, so the edges closer to the
+// equator are moved away from it by the largest bulge of a step.
+pub fn coordinate_box_vertices(
+    ra_min: f64,
+    ra_max: f64,
+    dec_min: f64,
+    dec_max: f64,
+) -> Vec<(f64, f64)> {
+    let width = (ra_max - ra_min).rem_euclid(360.0);
+    let steps = (width / MAX_STEP).ceil().max(1.0) as usize;
+    let step = width / steps as f64;
+    // the bulge is the largest at 45 degrees
+    let c = (step / 2.0).to_radians().cos();
+    let bulge = ((1.0 / c.sqrt()).atan() - c.sqrt().atan()).to_degrees();
+    let lower = if dec_min > 0.0 {
+        dec_min - bulge
+    } else {
+        dec_min
+    };
+    let upper = if dec_max < 0.0 {
+        dec_max + bulge
+    } else {
+        dec_max
+    };
+    let ra = |i: usize| (ra_min + i as f64 * step).rem_euclid(360.0);
+    let mut vertices: Vec<(f64, f64)> = (0..=steps).map(|i| (ra(i), lower)).collect();
+    vertices.extend((0..=steps).rev().map(|i| (ra(i), upper)));
+    vertices
+}
+
+// position angle of (ra, dec) as seen from (ra0, dec0), in degrees east of north
+fn position_angle(ra0: f64, dec0: f64, ra: f64, dec: f64) -> f64 {
+    let (ra0, dec0, ra, dec) = (
+        ra0.to_radians(),
+        dec0.to_radians(),
+        ra.to_radians(),
+        dec.to_radians(),
+    );
+    let y = (ra - ra0).sin() * dec.cos();
+    let x = dec0.cos() * dec.sin() - dec0.sin() * dec.cos() * (ra - ra0).cos();
+    y.atan2(x).to_degrees()
+}
+
+impl Region {
+    // the region with its circle radius or ellipse axes, given in `unit`,
+    // converted to degrees
+    pub fn in_degrees(self, unit: &Unit) -> Region {
+        match self {
+            Region::Circle { ra, dec, radius } => Region::Circle {
+                ra,
+                dec,
+                radius: degrees_from(radius, unit),
+            },
+            Region::Ellipse {
+                ra,
+                dec,
+                semi_major,
+                semi_minor,
+                position_angle,
+            } => Region::Ellipse {
+                ra,
+                dec,
+                semi_major: degrees_from(semi_major, unit),
+                semi_minor: degrees_from(semi_minor, unit),
+                position_angle,
+            },
+            region => region,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Region::Circle { ra, dec, radius } => {
+                check_position("circle center", *ra, *dec)?;
+                if !(*radius > 0.0 && *radius < 180.0) {
+                    return Err("circle radius must be between 0 and 180 degrees".to_string());
+                }
+            }
+            Region::Box {
+                ra_min,
+                ra_max,
+                dec_min,
+                dec_max,
+            } => {
+                check_position("box ra_min and dec_min", *ra_min, *dec_min)?;
+                check_position("box ra_max and dec_max", *ra_max, *dec_max)?;
+                let width = (ra_max - ra_min).rem_euclid(360.0);
+                // larger boxes would not fit in a GeoJSON polygon
+                if width <= 0.0 || width >= 180.0 {
+                    return Err("box ra range must be between 0 and 180 degrees wide".to_string());
+                }
+                if dec_min >= dec_max {
+                    return Err("box dec_min must be smaller than dec_max".to_string());
+                }
+                // the vertices of an edge at a pole would all be the same
+                if *dec_min <= -90.0 || *dec_max >= 90.0 {
+                    return Err(
+                        "box cannot reach a pole, use a circle around it instead".to_string()
+                    );
+                }
+            }
+            Region::Polygon { vertices } => {
+                if vertices.len() < 3 {
+                    return Err("polygon takes at least three vertices".to_string());
+                }
+                for [ra, dec] in vertices {
+                    check_position("polygon vertices", *ra, *dec)?;
+                }
+            }
+            Region::Ellipse {
+                ra,
+                dec,
+                semi_major,
+                semi_minor,
+                position_angle,
+            } => {
+                check_position("ellipse center", *ra, *dec)?;
+                if !(*semi_minor > 0.0 && semi_minor <= semi_major && *semi_major < 90.0) {
+                    return Err(
+                        "ellipse semi-axes must satisfy 0 < semi_minor <= semi_major < 90 degrees"
+                            .to_string(),
+                    );
+                }
+                if !position_angle.is_finite() {
+                    return Err("ellipse position_angle must be a number".to_string());
+                }
+            }
+            Region::Moc(moc) => {
+                if moc.is_empty() {
+                    return Err("moc is empty".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // whether the matches of `filter` must be checked with `contains`
+    pub fn needs_post_filter(&self) -> bool {
+        matches!(
+            self,
+            Region::Box { .. } | Region::Ellipse { .. } | Region::Moc(_)
+        )
+    }
+
+    // filter of the documents within the region, or within a larger region
+    // for those needing a post filter
+    pub fn filter(&self) -> Document {
+        match self {
+            Region::Circle { ra, dec, radius } => {
+                build_cone_search_filter(doc! {}, (*ra, *dec), *radius, Unit::Degrees)
+            }
+            Region::Box {
+                ra_min,
+                ra_max,
+                dec_min,
+                dec_max,
+            } => polygon_filter(&coordinate_box_vertices(
+                *ra_min, *ra_max, *dec_min, *dec_max,
+            )),
+            Region::Polygon { vertices } => {
+                let vertices: Vec<(f64, f64)> =
+                    vertices.iter().map(|[ra, dec]| (*ra, *dec)).collect();
+                polygon_filter(&vertices)
+            }
+            // the circle around the major axis
+            Region::Ellipse {
+                ra,
+                dec,
+                semi_major,
+                ..
+            } => build_cone_search_filter(doc! {}, (*ra, *dec), *semi_major, Unit::Degrees),
+            Region::Moc(moc) => {
+                let mut order = moc.max_order();
+                let mut cells = moc.cells();
+                while cells.len() > MAX_QUERY_CELLS && order > 0 {
+                    order -= 1;
+                    cells = moc.degrade(order).cells();
+                }
+                let mut circles: Vec<Document> = cells
+                    .into_iter()
+                    .map(|(order, pixel)| {
+                        build_cone_search_filter(
+                            doc! {},
+                            healpix::center(order, pixel),
+                            healpix::bounding_radius(order, pixel),
+                            Unit::Degrees,
+                        )
+                    })
+                    .collect();
+                if circles.len() == 1 {
+                    return circles.remove(0);
+                }
+                doc! { "$or": circles }
+            }
+        }
+    }
+
+    // whether (ra, dec) lies within the region, for the regions needing a
+    // post filter. The others are exactly matched by their filter.
+    pub fn contains(&self, ra: f64, dec: f64) -> bool {
+        match self {
+            Region::Box {
+                ra_min,
+                ra_max,
+                dec_min,
+                dec_max,
+            } => {
+                let width = (ra_max - ra_min).rem_euclid(360.0);
+                dec >= *dec_min && dec <= *dec_max && (ra - ra_min).rem_euclid(360.0) <= width
+            }
+            Region::Ellipse {
+                ra: ra0,
+                dec: dec0,
+                semi_major,
+                semi_minor,
+                position_angle: angle,
+            } => {
+                let distance = angular_distance(*ra0, *dec0, ra, dec);
+                let theta = (position_angle(*ra0, *dec0, ra, dec) - angle).to_radians();
+                // offsets along the major and minor axes
+                let (major, minor) = (distance * theta.cos(), distance * theta.sin());
+                (major / semi_major).powi(2) + (minor / semi_minor).powi(2) <= 1.0
+            }
+            Region::Moc(moc) => moc.contains(ra, dec),
+            _ => true,
+        }
+    }
+
+    // the document if it lies within the region, without the position field
+    // `strip` that was only read for the check
+    pub fn post_filter(&self, mut document: Document, strip: Option<&str>) -> Option<Document> {
+        if !self.needs_post_filter() {
+            return Some(document);
+        }
+        let (ra, dec) = document_position(&document)?;
+        if !self.contains(ra, dec) {
+            return None;
+        }
+        if let Some(field) = strip {
+            remove_field(&mut document, field);
+        }
+        Some(document)
+    }
+}
@@ -0,0 +1,144 @@
+// multi-order HEALPix skymaps, as distributed with gravitational-wave alerts:
+// a binary table of NUNIQ cells with their probability density (per steradian)
+use crate::{
+    formats::fits::BinTable,
+    spatial::{
+        healpix::{self, MAX_ORDER},
+        moc::Moc,
+    },
+};
+use std::{f64::consts::PI, path::Path};
+
+// a cell of the skymap and its share of the total probability
+#[derive(Clone, Debug)]
+struct Cell {
+    order: u8,
+    pixel: u64,
+    probdensity: f64,
+    probability: f64,
+    // probability of the cells at least as dense as this one, itself included
+    credible_level: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Skymap {
+    // by decreasing probability density
+    cells: Vec<Cell>,
+    // pixel ranges at MAX_ORDER of the cells, sorted, with the index of
+    // their cell
+    ranges: Vec<(u64, u64, usize)>,
+}
+
+impl Skymap {
+    // FITS file whose first extension has UNIQ and PROBDENSITY columns
+    pub fn from_fits(bytes: &[u8]) -> Result<Skymap, String> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            return Err("gzip compressed skymaps are not supported".to_string());
+        }
+        let table = BinTable::from_fits(bytes)?;
+        if let Some(ordering) = table.header.get("ORDERING") {
+            if ordering != "NUNIQ" {
+                return Err(format!(
+                    "unsupported skymap ordering {}, expected a multi-order (NUNIQ) skymap",
+                    ordering
+                ));
+            }
+        }
+        let uniqs = table.int_column(Some("UNIQ"))?;
+        let densities = table.column(Some("PROBDENSITY"))?;
+        let mut cells = Vec::with_capacity(uniqs.len());
+        for (uniq, probdensity) in uniqs.into_iter().zip(densities) {
+            if uniq < 0 {
+                return Err(format!("invalid NUNIQ value {}", uniq));
+            }
+            let (order, pixel) = healpix::from_uniq(uniq as u64)?;
+            if !(probdensity >= 0.0 && probdensity.is_finite()) {
+                return Err(format!("invalid probability density {}", probdensity));
+            }
+            cells.push(Cell {
+                order,
+                pixel,
+                probdensity,
+                probability: probdensity * 4.0 * PI / healpix::npix(order) as f64,
+                credible_level: 0.0,
+            });
+        }
+        Skymap::from_cells(cells)
+    }
+
+    // FITS file under `directory`, `path` being relative to it
+    pub fn from_path(directory: &str, path: &str) -> Result<Skymap, String> {
+        let directory = match Path::new(directory).canonicalize() {
+            Ok(d) => d,
+            Err(e) => return Err(format!("invalid skymap directory: {}", e)),
+        };
+        let file = match directory.join(path).canonicalize() {
+            Ok(f) => f,
+            Err(_) => return Err(format!("skymap {} not found", path)),
+        };
+        // `..` and symbolic links could lead outside of the directory
+        if !file.starts_with(&directory) {
+            return Err(format!("skymap {} not found", path));
+        }
+        match std::fs::read(&file) {
+            Ok(bytes) => Skymap::from_fits(&bytes),
+            Err(e) => Err(format!("error reading skymap {}: {}", path, e)),
+        }
+    }
+
+    // normalizes the probabilities, and sorts the cells to compute their
+    // credible levels
+    fn from_cells(mut cells: Vec<Cell>) -> Result<Skymap, String> {
+        let total: f64 = cells.iter().map(|cell| cell.probability).sum();
+        if total.is_nan() || total <= 0.0 {
+            return Err("skymap has no probability".to_string());
+        }
+        cells.sort_by(|a, b| b.probdensity.total_cmp(&a.probdensity));
+        let mut cumulative = 0.0;
+        for cell in cells.iter_mut() {
+            cell.probability /= total;
+            cumulative += cell.probability;
+            cell.credible_level = cumulative.min(1.0);
+        }
+        let mut ranges: Vec<(u64, u64, usize)> = cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                let shift = 2 * (MAX_ORDER - cell.order) as u64;
+                (cell.pixel << shift, (cell.pixel + 1) << shift, index)
+            })
+            .collect();
+        ranges.sort_unstable();
+        if ranges.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+            return Err("skymap cells overlap".to_string());
+        }
+        Ok(Skymap { cells, ranges })
+    }
+
+    // the smallest set of the densest cells holding at least `level` of the
+    // probability, level being in (0, 1]. Cells without probability are left
+    // out, rounding errors making the total slightly below 1.
+    pub fn credible_region(&self, level: f64) -> Result<Moc, String> {
+        Moc::from_cells(
+            self.cells
+                .iter()
+                .take_while(|cell| {
+                    cell.probability > 0.0 && cell.credible_level - cell.probability < level
+                })
+                .map(|cell| (cell.order, cell.pixel)),
+        )
+    }
+
+    // probability density and credible level at (ra, dec), None outside of
+    // the cells of the skymap
+    pub fn at(&self, ra: f64, dec: f64) -> Option<(f64, f64)> {
+        let pixel = healpix::pixel(MAX_ORDER, ra, dec);
+        let index = self.ranges.partition_point(|(_, end, _)| *end <= pixel);
+        let (start, _, cell) = self.ranges.get(index)?;
+        if *start > pixel {
+            return None;
+        }
+        let cell = &self.cells[*cell];
+        Some((cell.probdensity, cell.credible_level))
+    }
+}
@@ -0,0 +1,300 @@
+// the alert streams served by the api, and how their alerts are stored. Each
+// survey has a collection of alerts and a collection of aux entries (one per
+// object, holding its history and crossmatches).
+use mongodb::bson::{Bson, Document};
+
+// cutout fields of the alerts, by kind, the same for every survey stored by BOOM
+const CUTOUT_FIELDS: [(&str, &str); 3] = [
+    ("science", "cutoutScience"),
+    ("template", "cutoutTemplate"),
+    ("difference", "cutoutDifference"),
+];
+// arrays of the aux entries holding the previous candidates of an object
+const HISTORY_FIELDS: [&str; 3] = ["prv_candidates", "prv_nondetections", "fp_hists"];
+// filter bands of ZTF, by filter id from 1
+const ZTF_BANDS: [&str; 3] = ["ztfg", "ztfr", "ztfi"];
+// filter bands of LSST and DECam, the letter of their band field prefixed
+const LSST_BANDS: [&str; 6] = ["lsstu", "lsstg", "lsstr", "lssti", "lsstz", "lssty"];
+const DECAM_BANDS: [&str; 5] = ["desg", "desr", "desi", "desz", "desy"];
+
+// band of a candidate whose band field holds the letter of the band, e.g. r
+fn lettered_band(
+    candidate: &Document,
+    prefix: &str,
+    bands: &'static [&'static str],
+) -> Option<&'static str> {
+    let letter = candidate.get_str("band").ok()?;
+    bands
+        .iter()
+        .find(|name| {
+            name.strip_prefix(prefix)
+                .is_some_and(|name| name.eq_ignore_ascii_case(letter))
+        })
+        .copied()
+}
+
+// every field is given by each survey, no schema being assumed: a survey is
+// only registered once its alerts are stored by BOOM
+pub trait Survey: Send + Sync {
+    // name of the survey in paths and collection names, e.g. ZTF
+    fn name(&self) -> &'static str;
+
+    fn alerts_collection(&self) -> String;
+
+    fn aux_collection(&self) -> String;
+
+    // id of the alerts
+    fn alert_id_field(&self) -> &'static str;
+
+    // id of the object of the alerts, the _id of the aux entries
+    fn object_id_field(&self) -> &'static str;
+
+    // measurements of the alerts
+    fn candidate_field(&self) -> &'static str;
+
+    // time (jd) of the alerts, and of the elements of the aux arrays
+    fn time_field(&self) -> &'static str;
+
+    fn history_time_field(&self) -> &'static str;
+
+    // program id of the candidates (of the alerts and of the aux arrays), for
+    // surveys with proprietary data. The data rights of the survey
+    // collections are derived from it.
+    fn permissions_field(&self) -> Option<&'static str>;
+
+    // arrays of the aux entries holding candidates
+    fn history_fields(&self) -> &'static [&'static str];
+
+    // (kind, field) of the cutouts of the alerts
+    fn cutout_fields(&self) -> &'static [(&'static str, &'static str)];
+
+    // names of the filter bands of the candidates
+    fn bands(&self) -> &'static [&'static str];
+
+    // name of the filter band of a candidate
+    fn band(&self, candidate: &Document) -> Option<&'static str>;
+
+    // field of the cutouts of a kind, e.g. science
+    fn cutout_field(&self, kind: &str) -> Option<&'static str> {
+        self.cutout_fields()
+            .iter()
+            .find(|(name, _)| *name == kind)
+            .map(|(_, field)| *field)
+    }
+}
+
+pub struct Ztf;
+
+impl Survey for Ztf {
+    fn name(&self) -> &'static str {
+        "ZTF"
+    }
+
+    fn alerts_collection(&self) -> String {
+        "ZTF_alerts".to_string()
+    }
+
+    fn aux_collection(&self) -> String {
+        "ZTF_alerts_aux".to_string()
+    }
+
+    fn alert_id_field(&self) -> &'static str {
+        "candid"
+    }
+
+    fn object_id_field(&self) -> &'static str {
+        "objectId"
+    }
+
+    fn candidate_field(&self) -> &'static str {
+        "candidate"
+    }
+
+    fn time_field(&self) -> &'static str {
+        "candidate.jd"
+    }
+
+    fn history_time_field(&self) -> &'static str {
+        "jd"
+    }
+
+    fn permissions_field(&self) -> Option<&'static str> {
+        Some("programid")
+    }
+
+    fn cutout_fields(&self) -> &'static [(&'static str, &'static str)] {
+        &CUTOUT_FIELDS
+    }
+
+    fn history_fields(&self) -> &'static [&'static str] {
+        &HISTORY_FIELDS
+    }
+
+    fn bands(&self) -> &'static [&'static str] {
+        &ZTF_BANDS
+    }
+
+    // the filter id (fid) of the candidate
+    fn band(&self, candidate: &Document) -> Option<&'static str> {
+        let fid = match candidate.get("fid")? {
+            Bson::Int32(i) => *i as i64,
+            Bson::Int64(i) => *i,
+            _ => return None,
+        };
+        ZTF_BANDS.get(usize::try_from(fid - 1).ok()?).copied()
+    }
+}
+
+// public alerts of the Rubin Observatory, without program ids
+pub struct Lsst;
+
+impl Survey for Lsst {
+    fn name(&self) -> &'static str {
+        "LSST"
+    }
+
+    fn alerts_collection(&self) -> String {
+        "LSST_alerts".to_string()
+    }
+
+    fn aux_collection(&self) -> String {
+        "LSST_alerts_aux".to_string()
+    }
+
+    fn alert_id_field(&self) -> &'static str {
+        "candid"
+    }
+
+    fn object_id_field(&self) -> &'static str {
+        "objectId"
+    }
+
+    fn candidate_field(&self) -> &'static str {
+        "candidate"
+    }
+
+    fn time_field(&self) -> &'static str {
+        "candidate.jd"
+    }
+
+    fn history_time_field(&self) -> &'static str {
+        "jd"
+    }
+
+    fn permissions_field(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn cutout_fields(&self) -> &'static [(&'static str, &'static str)] {
+        &CUTOUT_FIELDS
+    }
+
+    fn history_fields(&self) -> &'static [&'static str] {
+        &HISTORY_FIELDS
+    }
+
+    fn bands(&self) -> &'static [&'static str] {
+        &LSST_BANDS
+    }
+
+    fn band(&self, candidate: &Document) -> Option<&'static str> {
+        lettered_band(candidate, "lsst", &LSST_BANDS)
+    }
+}
+
+// alerts of the Dark Energy Camera, without program ids
+pub struct Decam;
+
+impl Survey for Decam {
+    fn name(&self) -> &'static str {
+        "DECAM"
+    }
+
+    fn alerts_collection(&self) -> String {
+        "DECAM_alerts".to_string()
+    }
+
+    fn aux_collection(&self) -> String {
+        "DECAM_alerts_aux".to_string()
+    }
+
+    fn alert_id_field(&self) -> &'static str {
+        "candid"
+    }
+
+    fn object_id_field(&self) -> &'static str {
+        "objectId"
+    }
+
+    fn candidate_field(&self) -> &'static str {
+        "candidate"
+    }
+
+    fn time_field(&self) -> &'static str {
+        "candidate.jd"
+    }
+
+    fn history_time_field(&self) -> &'static str {
+        "jd"
+    }
+
+    fn permissions_field(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn cutout_fields(&self) -> &'static [(&'static str, &'static str)] {
+        &CUTOUT_FIELDS
+    }
+
+    fn history_fields(&self) -> &'static [&'static str] {
+        &HISTORY_FIELDS
+    }
+
+    fn bands(&self) -> &'static [&'static str] {
+        &DECAM_BANDS
+    }
+
+    fn band(&self, candidate: &Document) -> Option<&'static str> {
+        lettered_band(candidate, "des", &DECAM_BANDS)
+    }
+}
+
+const SURVEYS: [&dyn Survey; 3] = [&Ztf, &Lsst, &Decam];
+
+// the survey storing a collection, as its alerts or its aux entries
+pub fn collection_survey(collection: &str) -> Option<&'static dyn Survey> {
+    SURVEYS
+        .iter()
+        .find(|survey| {
+            survey.alerts_collection() == collection || survey.aux_collection() == collection
+        })
+        .copied()
+}
+
+// the survey of a filter, whose catalog is the name of the survey or, for the
+// filters stored before surveys, its alerts collection (e.g. ZTF_alerts)
+pub fn filter_survey(catalog: &str) -> Result<&'static dyn Survey, String> {
+    match SURVEYS
+        .iter()
+        .find(|survey| survey.alerts_collection() == catalog)
+    {
+        Some(survey) => Ok(*survey),
+        None => survey(catalog),
+    }
+}
+
+// a survey by name, case insensitive
+pub fn survey(name: &str) -> Result<&'static dyn Survey, String> {
+    SURVEYS
+        .iter()
+        .find(|survey| survey.name().eq_ignore_ascii_case(name))
+        .copied()
+        .ok_or_else(|| {
+            let names: Vec<&str> = SURVEYS.iter().map(|survey| survey.name()).collect();
+            format!(
+                "unknown survey {}, expected one of {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
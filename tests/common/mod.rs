@@ -0,0 +1,66 @@
+// fixtures shared by the integration tests, each test binary using a part of them
+#![allow(dead_code)]
+use actix_web::{
+    test::{self, TestRequest},
+    web, App, HttpMessage,
+};
+use boom_api::{conf::AppConfig, models::auth_models::AuthenticatedUser};
+use mongodb::Client;
+
+pub const TEST_SECRET_KEY: &str = "boom-api-test-secret";
+
+pub fn get_config() -> AppConfig {
+    AppConfig::load(None, vec![("auth.secret_key", TEST_SECRET_KEY.to_string())])
+        .expect("failed to load configuration")
+}
+
+// a user with the public program id only
+pub fn test_user() -> AuthenticatedUser {
+    AuthenticatedUser {
+        username: "tester".to_string(),
+        groups: vec![],
+        programids: vec![1],
+    }
+}
+
+pub async fn get_web_client() -> web::Data<Client> {
+    let client = get_config()
+        .database
+        .client()
+        .await
+        .expect("failed to connect");
+    web::Data::new(client)
+}
+
+// sends each request as the test user to an app serving `routes`, checking
+// that it is rejected with a 400 and the expected message: that of a JSON
+// response, or the plain text body. Requests are rejected before any query,
+// so the client never connects.
+pub async fn assert_bad_requests<F>(routes: F, cases: Vec<(TestRequest, &str)>)
+where
+    F: FnOnce(&mut web::ServiceConfig),
+{
+    let client = Client::with_uri_str("mongodb://localhost:27017")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(client))
+            .app_data(web::Data::new(get_config()))
+            .configure(routes),
+    )
+    .await;
+    for (req, message) in cases {
+        let req = req.to_request();
+        let uri = req.uri().to_string();
+        req.extensions_mut().insert(test_user());
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "{}", uri);
+        let body = test::read_body(resp).await;
+        let body_message = match serde_json::from_slice::<serde_json::Value>(&body) {
+            Ok(json) => json["message"].as_str().unwrap_or_default().to_string(),
+            Err(_) => String::from_utf8_lossy(&body).to_string(),
+        };
+        assert_eq!(body_message, message, "{}", uri);
+    }
+}
@@ -0,0 +1,182 @@
+use boom_api::{
+    adql::{parse_adql, SelectColumn},
+    api::query::build_cone_search_filter,
+    models::query_models::Unit,
+    spatial::regions::box_vertices,
+};
+use mongodb::bson::{doc, Bson};
+
+#[test]
+fn test_adql_filters() {
+    let query = parse_adql(
+        "SELECT TOP 10 objectId, a.candidate.magpsf AS mag FROM ZTF_alerts AS a \
+         WHERE a.candidate.magpsf < 18.5 AND 0.9 <= candidate.drb \
+         AND (candidate.fid = 1 OR candidate.fid IN (2, 3)) \
+         AND candidate.jd BETWEEN 2460000 AND 2460001 \
+         AND objectId LIKE 'ZTF24%' AND candidate.isdiffpos IS NOT NULL \
+         ORDER BY mag DESC, objectId",
+    )
+    .unwrap();
+    assert_eq!(query.table, vec!["ZTF_alerts".to_string()]);
+    assert_eq!(query.top, Some(10));
+    assert_eq!(
+        query.filter,
+        doc! { "$and": [
+            { "$and": [
+                { "$and": [
+                    { "$and": [
+                        { "$and": [
+                            { "candidate.magpsf": { "$lt": 18.5 } },
+                            { "candidate.drb": { "$gte": 0.9 } },
+                        ] },
+                        { "$or": [
+                            { "candidate.fid": { "$eq": 1_i64 } },
+                            { "candidate.fid": { "$in": [2_i64, 3_i64] } },
+                        ] },
+                    ] },
+                    { "candidate.jd": { "$gte": 2460000_i64, "$lte": 2460001_i64 } },
+                ] },
+                { "objectId": { "$regex": "^ZTF24.*$", "$options": "" } },
+            ] },
+            { "candidate.isdiffpos": { "$ne": Bson::Null } },
+        ] }
+    );
+    assert_eq!(
+        query.columns,
+        Some(vec![
+            SelectColumn::Field {
+                name: "objectId".to_string(),
+                path: "objectId".to_string()
+            },
+            SelectColumn::Field {
+                name: "mag".to_string(),
+                path: "candidate.magpsf".to_string()
+            },
+        ])
+    );
+    // ORDER BY follows the alias to the field
+    assert_eq!(
+        query.sort,
+        Some(doc! { "candidate.magpsf": -1, "objectId": 1 })
+    );
+    assert_eq!(
+        query.projection(),
+        Some(doc! { "objectId": 1, "candidate.magpsf": 1, "_id": 0 })
+    );
+
+    let query = parse_adql("SELECT * FROM boom.ZTF_alerts WHERE NOT candid = 5").unwrap();
+    assert_eq!(
+        query.table,
+        vec!["boom".to_string(), "ZTF_alerts".to_string()]
+    );
+    assert_eq!(query.columns, None);
+    assert_eq!(query.projection(), None);
+    assert_eq!(query.top, None);
+    assert_eq!(
+        query.filter,
+        doc! { "$nor": [{ "candid": { "$eq": 5_i64 } }] }
+    );
+}
+
+#[test]
+fn test_adql_regions() {
+    let circle = build_cone_search_filter(doc! {}, (150.0, 2.5), 0.1, Unit::Degrees);
+    for adql in [
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT('ICRS', candidate.ra, candidate.dec), CIRCLE('ICRS', 150, 2.5, 0.1)) = 1",
+        "SELECT * FROM ZTF_alerts WHERE 1 = CONTAINS(POINT(candidate.ra, candidate.dec), CIRCLE(POINT(150, 2.5), 0.1))",
+        "SELECT * FROM ZTF_alerts WHERE DISTANCE(POINT(candidate.ra, candidate.dec), POINT(150, 2.5)) < 0.1",
+    ] {
+        assert_eq!(parse_adql(adql).unwrap().filter, circle, "{}", adql);
+    }
+
+    let query = parse_adql(
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT(candidate.ra, candidate.dec), \
+         POLYGON(10, 10, 20, 10, 15, 20)) = 1",
+    )
+    .unwrap();
+    assert_eq!(
+        query.filter,
+        doc! { "coordinates.radec_geojson": { "$geoWithin": { "$geometry": {
+            "type": "Polygon",
+            "coordinates": [[[-170.0, 10.0], [-160.0, 10.0], [-165.0, 20.0], [-170.0, 10.0]]],
+        } } } }
+    );
+
+    let query = parse_adql(
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT(candidate.ra, candidate.dec), \
+         BOX(100, 0, 2, 1)) = 1",
+    )
+    .unwrap();
+    let ring = query
+        .filter
+        .get_document("coordinates.radec_geojson")
+        .and_then(|d| d.get_document("$geoWithin"))
+        .and_then(|d| d.get_document("$geometry"))
+        .and_then(|d| d.get_array("coordinates"))
+        .unwrap();
+    assert_eq!(ring[0].as_array().unwrap().len(), 5);
+
+    // the corners of a box on the equator are at half its width and height
+    let vertices = box_vertices(100.0, 0.0, 2.0, 1.0);
+    assert_eq!(vertices.len(), 4);
+    assert!((vertices[0].0 - 99.0).abs() < 1e-9);
+    assert!((vertices[0].1 + 0.49992).abs() < 1e-4);
+    assert!((vertices[2].0 - 101.0).abs() < 1e-9);
+    // and the box wraps around ra = 0
+    assert!(box_vertices(0.0, 0.0, 2.0, 2.0)[0].0 > 358.0);
+
+    let query = parse_adql(
+        "SELECT objectId, DISTANCE(POINT(candidate.ra, candidate.dec), POINT(150, 2.5)) AS dist \
+         FROM ZTF_alerts",
+    )
+    .unwrap();
+    assert_eq!(
+        query.columns.as_ref().unwrap()[1],
+        SelectColumn::Distance {
+            name: "dist".to_string(),
+            ra_field: "candidate.ra".to_string(),
+            dec_field: "candidate.dec".to_string(),
+            ra: 150.0,
+            dec: 2.5,
+        }
+    );
+    assert_eq!(
+        query.projection(),
+        Some(doc! { "objectId": 1, "candidate.ra": 1, "candidate.dec": 1, "_id": 0 })
+    );
+}
+
+#[test]
+fn test_adql_limits() {
+    assert_eq!(parse_adql("SELECT TOP 5 * FROM t").unwrap().top, Some(5));
+    assert_eq!(parse_adql("SELECT * FROM t LIMIT 7").unwrap().top, Some(7));
+    assert_eq!(
+        parse_adql("SELECT * FROM t WHERE name ILIKE 'a!_b%' ESCAPE '!'")
+            .unwrap()
+            .filter,
+        doc! { "name": { "$regex": "^a_b.*$", "$options": "i" } }
+    );
+}
+
+#[test]
+fn test_adql_unsupported() {
+    for adql in [
+        "not adql",
+        "SELECT DISTINCT objectId FROM ZTF_alerts",
+        "SELECT objectId FROM ZTF_alerts GROUP BY objectId",
+        "SELECT * FROM ZTF_alerts a JOIN ZTF_alerts_aux b ON a.objectId = b._id",
+        "SELECT * FROM ZTF_alerts, ZTF_alerts_aux",
+        "SELECT TOP 10 PERCENT * FROM ZTF_alerts",
+        "SELECT COUNT(*) FROM ZTF_alerts",
+        "SELECT * FROM ZTF_alerts WHERE candidate.magpsf < candidate.diffmaglim",
+        "SELECT * FROM ZTF_alerts WHERE \"$where\" = 1",
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT('GALACTIC', l, b), CIRCLE(10, 10, 1)) = 1",
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT(ra, dec), CIRCLE(10, 10, 1)) = 0",
+        "SELECT * FROM ZTF_alerts WHERE DISTANCE(POINT(ra, dec), POINT(10, 10)) > 1",
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT(ra, dec), BOX(10, 10, 200, 1)) = 1",
+        "SELECT * FROM ZTF_alerts WHERE CONTAINS(POINT(ra, dec), POLYGON(10, 10, 20, 10)) = 1",
+        "SELECT objectId FROM ZTF_alerts; SELECT objectId FROM ZTF_alerts",
+    ] {
+        assert!(parse_adql(adql).is_err(), "{}", adql);
+    }
+}
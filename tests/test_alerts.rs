@@ -0,0 +1,150 @@
+mod common;
+
+use boom_api::{
+    api::alerts::{alert_pipeline, parse_include},
+    models::alert_models::{ObjectView, Part},
+    surveys::Ztf,
+};
+use common::{get_config, test_user};
+use mongodb::bson::{doc, Bson};
+
+fn include(parts: &[&str]) -> Vec<String> {
+    parts.iter().map(|part| part.to_string()).collect()
+}
+
+#[test]
+fn test_object_view() {
+    let alert = doc! {
+        "_id": 42_i64,
+        "candidate": { "jd": 2460000.5, "magpsf": 18.5 },
+        "cutoutScience": { "stampData": "..." },
+        "cutoutTemplate": { "stampData": "..." },
+    };
+    let aux_entry = doc! {
+        "_id": "ZTF21aaaaaaa",
+        "prv_candidates": [{ "jd": 2459999.5 }],
+        "cross_matches": { "NED": [] },
+    };
+    let all = include(&["cutouts", "prv_candidates", "cross_matches", "fp_hists"]);
+    let view = ObjectView::new(&Ztf, "ZTF21aaaaaaa", Some(&alert), Some(&aux_entry), &all);
+    assert_eq!(
+        view.alert_metadata,
+        Some(doc! { "jd": 2460000.5, "magpsf": 18.5 })
+    );
+    assert_eq!(
+        view.prv_candidates,
+        Part::Found(vec![doc! { "jd": 2459999.5 }])
+    );
+    assert_eq!(view.cross_matches.found(), Some(&doc! { "NED": [] }));
+    // parts of a partially ingested object are reported as missing
+    assert_eq!(view.cutout_difference, Part::Missing);
+    assert_eq!(view.missing, vec!["cutoutDifference", "fp_hists"]);
+
+    let json = serde_json::json!(view);
+    assert_eq!(json["objectId"], "ZTF21aaaaaaa");
+    assert!(json["cutoutDifference"].is_null());
+    assert!(json["fp_hists"].is_null());
+    assert_eq!(json["prv_candidates"][0]["jd"], 2459999.5);
+
+    // parts not asked for are left out
+    let view = ObjectView::new(
+        &Ztf,
+        "ZTF21aaaaaaa",
+        Some(&alert),
+        None,
+        &include(&["cutouts"]),
+    );
+    let json = serde_json::json!(view);
+    assert!(json.get("prv_candidates").is_none());
+    assert!(json.get("cross_matches").is_none());
+    assert!(json.get("cutoutScience").is_some());
+    assert_eq!(view.missing, vec!["cutoutDifference"]);
+
+    // an object without alerts the user may see, or being ingested
+    let view = ObjectView::new(
+        &Ztf,
+        "ZTF21aaaaaaa",
+        None,
+        Some(&aux_entry),
+        &include(&["cutouts", "cross_matches"]),
+    );
+    assert_eq!(view.alert_metadata, None);
+    assert_eq!(
+        view.missing,
+        vec![
+            "alert_metadata",
+            "cutoutScience",
+            "cutoutTemplate",
+            "cutoutDifference"
+        ]
+    );
+    let alert = doc! { "candidate": Bson::Null, "cutoutScience": Bson::Null };
+    let view = ObjectView::new(
+        &Ztf,
+        "ZTF21aaaaaaa",
+        Some(&alert),
+        None,
+        &include(&["cutouts"]),
+    );
+    assert_eq!(view.missing.len(), 4);
+}
+
+#[test]
+fn test_alert_pipeline() {
+    let config = get_config();
+    let user = test_user();
+
+    // cutouts are left out unless included
+    let pipeline = alert_pipeline(&config, &Ztf, &user, 42, &[]);
+    assert_eq!(
+        pipeline,
+        vec![
+            doc! { "$match": { "$and": [{ "candid": 42_i64 }, { "candidate.programid": { "$in": [1] } }] } },
+            doc! { "$limit": 1 },
+            doc! { "$project": { "cutoutScience": 0, "cutoutTemplate": 0, "cutoutDifference": 0 } },
+        ]
+    );
+
+    let include = vec!["cutouts".to_string(), "prv_candidates".to_string()];
+    let pipeline = alert_pipeline(&config, &Ztf, &user, 42, &include);
+    assert_eq!(pipeline.len(), 5);
+    assert_eq!(
+        pipeline[2].get_document("$lookup").unwrap().get_str("from"),
+        Ok("ZTF_alerts_aux")
+    );
+    // the history up to the alert, trimmed to the program ids of the user
+    assert_eq!(
+        pipeline[3],
+        doc! {
+            "$set": {
+                "prv_candidates": {
+                    "$filter": {
+                        "input": {
+                            "$ifNull": [{ "$arrayElemAt": ["$aux.prv_candidates", 0] }, []]
+                        },
+                        "as": "x",
+                        "cond": { "$and": [
+                            { "$lte": ["$$x.jd", "$candidate.jd"] },
+                            { "$in": ["$$x.programid", [1]] },
+                        ] }
+                    }
+                }
+            }
+        }
+    );
+    assert_eq!(pipeline[4], doc! { "$project": { "aux": 0 } });
+}
+
+#[test]
+fn test_parse_include() {
+    let allowed = ["cutouts", "prv_candidates"];
+    assert_eq!(parse_include(&None, &allowed), Ok(vec![]));
+    assert_eq!(
+        parse_include(&Some(" prv_candidates,,cutouts".to_string()), &allowed),
+        Ok(vec!["prv_candidates".to_string(), "cutouts".to_string()])
+    );
+    assert_eq!(
+        parse_include(&Some("cutouts,fp_hists".to_string()), &allowed),
+        Err("unknown include fp_hists, expected one of cutouts, prv_candidates".to_string())
+    );
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use actix_web::{
+    get,
+    middleware::from_fn,
+    test::{self, TestRequest},
+    web, App, HttpResponse,
+};
+use boom_api::{
+    api::auth::{authenticate, create_jwt, hash_api_token},
+    models::auth_models::AuthenticatedUser,
+};
+use common::get_config;
+
+#[get("/whoami")]
+async fn whoami(user: web::ReqData<AuthenticatedUser>) -> HttpResponse {
+    HttpResponse::Ok().body(user.username.clone())
+}
+
+#[actix_rt::test]
+async fn test_missing_token_is_rejected() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_config()))
+            .wrap(from_fn(authenticate))
+            .service(whoami),
+    )
+    .await;
+    let resp = test::call_service(&app, TestRequest::get().uri("/whoami").to_request()).await;
+    assert_eq!(resp.status(), 401);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "error");
+}
+
+#[actix_rt::test]
+async fn test_valid_jwt_is_accepted() {
+    let config = get_config();
+    let token = create_jwt(&config.auth, "tester", vec!["boom".to_string()], vec![]).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .wrap(from_fn(authenticate))
+            .service(whoami),
+    )
+    .await;
+    let req = TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(test::read_body(resp).await, "tester");
+}
+
+#[actix_rt::test]
+async fn test_jwt_with_wrong_key_is_rejected() {
+    let mut other_config = get_config();
+    other_config.auth.secret_key = "another-secret".to_string();
+    let token = create_jwt(&other_config.auth, "tester", vec![], vec![]).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_config()))
+            .wrap(from_fn(authenticate))
+            .service(whoami),
+    )
+    .await;
+    let req = TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+#[test]
+fn test_hash_api_token() {
+    assert_eq!(
+        hash_api_token("abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[actix_rt::test]
+async fn test_anonymous_paths() {
+    let mut config = get_config();
+    config.auth.anonymous_paths = vec!["/who".to_string()];
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .wrap(from_fn(authenticate))
+            .service(whoami),
+    )
+    .await;
+    let resp = test::call_service(&app, TestRequest::get().uri("/whoami").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(test::read_body(resp).await, "anonymous");
+}
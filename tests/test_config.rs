@@ -0,0 +1,137 @@
+mod common;
+
+use boom_api::{conf::AppConfig, models::query_models::QueryKwargs};
+use common::TEST_SECRET_KEY;
+
+// config.yaml with overrides, the secret key being set in the environment
+fn load_config(mut overrides: Vec<(&str, String)>) -> AppConfig {
+    overrides.push(("auth.secret_key", TEST_SECRET_KEY.to_string()));
+    AppConfig::load(Some("config.yaml"), overrides).unwrap()
+}
+
+#[test]
+fn test_load_config_file() {
+    let config = load_config(vec![]);
+    assert_eq!(config.database.name, "boom");
+    assert_eq!(config.api.port, 4000);
+    assert!(config.catalogs.contains_key("ZTF_alerts"));
+}
+
+#[test]
+fn test_load_config_overrides() {
+    let config = load_config(vec![
+        ("api.port", "4242".to_string()),
+        ("database.name", "boom_test".to_string()),
+    ]);
+    assert_eq!(config.api.port, 4242);
+    assert_eq!(config.database.name, "boom_test");
+}
+
+#[test]
+fn test_secret_key_required() {
+    for key in ["", "  "] {
+        let error = AppConfig::load(
+            Some("config.yaml"),
+            vec![("auth.secret_key", key.to_string())],
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("BOOM_API__AUTH__SECRET_KEY"));
+    }
+    assert_eq!(load_config(vec![]).auth.secret_key, TEST_SECRET_KEY);
+}
+
+#[test]
+fn test_jobs_database() {
+    let config = load_config(vec![]);
+    assert_eq!(config.jobs.database, "boom_jobs");
+    // job results must not be exposed next to the catalogs
+    let error = AppConfig::load(
+        Some("config.yaml"),
+        vec![
+            ("auth.secret_key", TEST_SECRET_KEY.to_string()),
+            ("jobs.database", "boom".to_string()),
+        ],
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("jobs.database"));
+}
+
+#[test]
+fn test_connection_uri() {
+    let mut config = load_config(vec![]);
+    config.database.uri = None;
+    config.database.username = Some("user".to_string());
+    config.database.password = Some("pass".to_string());
+    config.database.host = "db".to_string();
+    config.database.port = 1234;
+    assert_eq!(
+        config.database.connection_uri(),
+        "mongodb://user:pass@db:1234"
+    );
+    config.database.uri = Some("mongodb://other:27017".to_string());
+    assert_eq!(config.database.connection_uri(), "mongodb://other:27017");
+}
+
+#[test]
+fn test_apply_catalog_limits() {
+    let config = load_config(vec![
+        ("catalogs.ZTF_alerts.max_limit", "100".to_string()),
+        ("api.default_max_time_ms", "500".to_string()),
+    ]);
+
+    // limit is capped by the catalog settings
+    let kwargs = config.apply_catalog_limits(
+        "ZTF_alerts",
+        QueryKwargs {
+            limit: Some(1000),
+            ..Default::default()
+        },
+    );
+    assert_eq!(kwargs.limit, Some(100));
+    assert_eq!(kwargs.max_time_ms, Some(500));
+
+    // missing limit defaults to the cap, explicit max_time_ms is kept
+    let kwargs = config.apply_catalog_limits(
+        "ZTF_alerts",
+        QueryKwargs {
+            max_time_ms: Some(10),
+            ..Default::default()
+        },
+    );
+    assert_eq!(kwargs.limit, Some(100));
+    assert_eq!(kwargs.max_time_ms, Some(10));
+
+    // catalogs without settings are untouched apart from the default timeout
+    let kwargs = config.apply_catalog_limits("NED", QueryKwargs::default());
+    assert_eq!(kwargs.limit, None);
+    assert_eq!(kwargs.max_time_ms, Some(500));
+
+    // bulk exports only get the timeout
+    let kwargs = config.apply_catalog_timeout("ZTF_alerts", QueryKwargs::default());
+    assert_eq!(kwargs.limit, None);
+    assert_eq!(kwargs.max_time_ms, Some(500));
+}
+
+#[test]
+fn test_catalog_units() {
+    let config = load_config(vec![]);
+    let units = config.catalog("ZTF_alerts").units;
+    assert_eq!(
+        units.get("candidate.magpsf").map(String::as_str),
+        Some("mag")
+    );
+    assert!(config.catalog("unknown").units.is_empty());
+}
+
+#[test]
+fn test_check_catalog() {
+    let config = load_config(vec![]);
+    assert!(config.check_catalog("ZTF_alerts").is_ok());
+    assert!(config.check_catalog("NED").is_ok());
+    for collection in ["users", "filters", "jobs", "ztf_alerts", "system.users"] {
+        assert_eq!(
+            config.check_catalog(collection),
+            Err(format!("unknown catalog {}", collection))
+        );
+    }
+}
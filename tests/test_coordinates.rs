@@ -0,0 +1,148 @@
+use boom_api::spatial::{
+    angular_distance,
+    coordinates::{ecliptic_to_icrs, galactic_to_icrs, Coordinates, Frame},
+};
+
+fn close(a: (f64, f64), b: (f64, f64), tolerance: f64) -> bool {
+    angular_distance(a.0, a.1, b.0, b.1) < tolerance
+}
+
+#[test]
+fn test_parse_coordinates() {
+    let arcsec = 1.0 / 3600.0;
+    let expected = (202.366276, 11.006276);
+    for json in [
+        r#"[202.366276, 11.006276]"#,
+        r#"["202.366276", "11.006276"]"#,
+        r#"["13:29:27.90624", "+11:00:22.5936"]"#,
+        r#""13:29:27.90624 +11:00:22.5936""#,
+        r#""13h29m27.90624s +11d00m22.5936s""#,
+        r#""13 29 27.90624 +11 00 22.5936""#,
+        r#""202.366276, 11.006276""#,
+    ] {
+        let coordinates: Coordinates = serde_json::from_str(json).unwrap();
+        let position = coordinates.to_icrs(&Frame::Icrs).unwrap();
+        assert!(close(position, expected, 0.01 * arcsec), "{}", json);
+    }
+
+    // right ascensions marked with "d" or "°" are in degrees, not hours
+    for ra in ["202d21m58.5936s", "202°21'58.5936\"", "202.366276d"] {
+        let coordinates = Coordinates::Pair([ra.to_string(), "+11d00m22.5936s".to_string()]);
+        let position = coordinates.to_icrs(&Frame::Icrs).unwrap();
+        assert!(close(position, expected, 0.01 * arcsec), "{}", ra);
+    }
+
+    // the sign of a declination below one degree is kept
+    let coordinates = Coordinates::Text("00:00:00 -00:30:00".to_string());
+    assert_eq!(coordinates.to_icrs(&Frame::Icrs), Ok((0.0, -0.5)));
+    // FK5 J2000 is treated as ICRS
+    assert_eq!(coordinates.to_icrs(&Frame::Fk5), Ok((0.0, -0.5)));
+
+    let frame: Frame = serde_json::from_str(r#""galactic""#).unwrap();
+    assert_eq!(frame, Frame::Galactic);
+    assert!(serde_json::from_str::<Frame>(r#""B1950""#).is_err());
+}
+
+#[test]
+fn test_invalid_coordinates() {
+    for (coordinates, message) in [
+        (
+            Coordinates::Degrees([360.0, 0.0]),
+            "ra must be between 0 and 360 degrees, got 360",
+        ),
+        (
+            Coordinates::Degrees([-1.0, 0.0]),
+            "ra must be between 0 and 360 degrees, got -1",
+        ),
+        (
+            Coordinates::Degrees([10.0, -90.5]),
+            "dec must be between -90 and 90 degrees, got -90.5",
+        ),
+        (
+            Coordinates::Degrees([f64::NAN, 0.0]),
+            "ra and dec must be finite numbers",
+        ),
+        (
+            Coordinates::Text("24:00:00 +10:00:00".to_string()),
+            "invalid ra \"24:00:00\", hours must be below 24",
+        ),
+        (
+            Coordinates::Text("12:60:00 +10:00:00".to_string()),
+            "invalid ra \"12:60:00\", minutes and seconds must be below 60",
+        ),
+        (
+            Coordinates::Text("-12:00:00 +10:00:00".to_string()),
+            "invalid ra \"-12:00:00\", ra cannot be negative",
+        ),
+        (
+            Coordinates::Text("12:00:00 +91:00:00".to_string()),
+            "dec must be between -90 and 90 degrees, got 91",
+        ),
+        (
+            Coordinates::Pair(["12h30d".to_string(), "+10".to_string()]),
+            "invalid ra \"12h30d\"",
+        ),
+        (
+            Coordinates::Pair(["12:00:00".to_string(), "ten".to_string()]),
+            "invalid dec \"ten\"",
+        ),
+        (
+            Coordinates::Text("12:00:00".to_string()),
+            "invalid coordinates \"12:00:00\", expected two coordinates such as \"12:30:00 +12:18:00\"",
+        ),
+    ] {
+        assert_eq!(
+            coordinates.to_icrs(&Frame::Icrs),
+            Err(message.to_string()),
+            "{:?}",
+            coordinates
+        );
+    }
+    assert_eq!(
+        Coordinates::Degrees([0.0, 95.0]).to_icrs(&Frame::Galactic),
+        Err("b must be between -90 and 90 degrees, got 95".to_string())
+    );
+}
+
+#[test]
+fn test_frame_conversions() {
+    let arcsec = 1.0 / 3600.0;
+    // galactic center and north galactic pole
+    assert!(close(
+        galactic_to_icrs(0.0, 0.0),
+        (266.404988, -28.936178),
+        0.1 * arcsec
+    ));
+    assert!(close(
+        galactic_to_icrs(0.0, 90.0),
+        (192.859480, 27.128251),
+        0.1 * arcsec
+    ));
+    // the ecliptic crosses the equator at the equinoxes, and reaches
+    // ra = 90 at the obliquity
+    assert!(close(ecliptic_to_icrs(0.0, 0.0), (0.0, 0.0), 1e-6 * arcsec));
+    assert!(close(
+        ecliptic_to_icrs(90.0, 0.0),
+        (90.0, 23.439279),
+        0.1 * arcsec
+    ));
+    assert!(close(
+        ecliptic_to_icrs(0.0, 90.0),
+        (270.0, 66.560721),
+        0.1 * arcsec
+    ));
+
+    // sexagesimal longitudes are in degrees outside of equatorial frames
+    let coordinates = Coordinates::Text("359:56:38.4 -00:02:52.8".to_string());
+    assert!(close(
+        coordinates.to_icrs(&Frame::Galactic).unwrap(),
+        galactic_to_icrs(359.944, -0.048),
+        1e-6 * arcsec
+    ));
+    let coordinates = Coordinates::Text("0 0".to_string());
+    assert!(close(
+        coordinates.to_icrs(&Frame::Galactic).unwrap(),
+        (266.404988, -28.936178),
+        0.1 * arcsec
+    ));
+}
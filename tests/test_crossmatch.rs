@@ -0,0 +1,118 @@
+use boom_api::{
+    api::crossmatch::target_positions,
+    formats::{csv::read_csv, table::TextTable, votable::read_votable},
+    spatial::coordinates::Frame,
+};
+use mongodb::bson::{doc, Bson};
+
+#[test]
+fn test_read_csv() {
+    let table = read_csv(
+        "# targets for tonight\r\nname,ra,dec,notes\r\nSN 2025a,10.5,-20.25,\"bright, \"\"blue\"\"\"\r\n\r\n\
+         007,\" 11.0 \",+30,\"two\nlines\"\r\n",
+    )
+    .unwrap();
+    assert_eq!(table.columns, vec!["name", "ra", "dec", "notes"]);
+    assert_eq!(
+        table.rows,
+        vec![
+            vec!["SN 2025a", "10.5", "-20.25", "bright, \"blue\""],
+            vec!["007", "11.0", "+30", "two\nlines"],
+        ]
+    );
+    assert_eq!(table.column("RA"), Some(1));
+    assert_eq!(table.column("ra_deg"), None);
+
+    // unterminated last line, empty values
+    let table = read_csv("ra,dec,mag\n1,2,\n3,4,5").unwrap();
+    assert_eq!(table.rows, vec![vec!["1", "2", ""], vec!["3", "4", "5"]]);
+
+    assert_eq!(read_csv("").unwrap_err(), "the CSV file is empty");
+    assert_eq!(
+        read_csv("ra,dec\n1,2\n3\n").unwrap_err(),
+        "row 2 has 1 values, expected 2"
+    );
+    assert_eq!(
+        read_csv("ra,dec\n\"1,2\n").unwrap_err(),
+        "unterminated quoted field on line 2"
+    );
+}
+
+#[test]
+fn test_read_votable() {
+    let votable = r#"<?xml version="1.0"?>
+<VOTABLE version="1.4" xmlns="http://www.ivoa.net/xml/VOTable/v1.3">
+<RESOURCE>
+<!-- a <comment> -->
+<TABLE name="targets">
+<FIELD name="id" datatype="char" arraysize="*"><DESCRIPTION>target id</DESCRIPTION></FIELD>
+<FIELD ID="col2" datatype="double" name='RAJ2000' unit="deg"/>
+<FIELD name="DEJ2000" datatype="double" unit="deg"/>
+<DATA><TABLEDATA>
+<TR><TD>a &amp; b</TD><TD> 10.5 </TD><TD>-20.25</TD></TR>
+<TR><TD/><TD>11</TD><TD>30</TD></TR>
+</TABLEDATA></DATA>
+</TABLE>
+<TABLE name="ignored"><FIELD name="x"/></TABLE>
+</RESOURCE>
+</VOTABLE>"#;
+    let table = read_votable(votable).unwrap();
+    assert_eq!(table.columns, vec!["id", "RAJ2000", "DEJ2000"]);
+    assert_eq!(
+        table.rows,
+        vec![vec!["a & b", "10.5", "-20.25"], vec!["", "11", "30"]]
+    );
+
+    // namespace prefixes
+    let table = read_votable(
+        "<v:VOTABLE><v:TABLE><v:FIELD name=\"ra\"/><v:DATA><v:TABLEDATA>\
+         <v:TR><v:TD>&#49;2</v:TD></v:TR></v:TABLEDATA></v:DATA></v:TABLE></v:VOTABLE>",
+    )
+    .unwrap();
+    assert_eq!(table.rows, vec![vec!["12"]]);
+
+    assert_eq!(
+        read_votable("<VOTABLE><TABLE><FIELD name=\"ra\"/><DATA><BINARY2/></DATA></TABLE>")
+            .unwrap_err(),
+        "only TABLEDATA serializations are supported"
+    );
+    assert_eq!(
+        read_votable("<VOTABLE><TABLE></TABLE></VOTABLE>").unwrap_err(),
+        "the table has no columns"
+    );
+}
+
+#[test]
+fn test_row_document() {
+    let table = TextTable {
+        columns: ["id", "ra", "n", "mag", "flag", "zero"]
+            .map(String::from)
+            .to_vec(),
+        rows: vec![],
+    };
+    let row = ["007", "10.5", "-42", "", "T", "0.5"].map(String::from);
+    assert_eq!(
+        table.row_document(&row),
+        doc! { "id": "007", "ra": 10.5, "n": -42_i64, "mag": Bson::Null, "flag": "T", "zero": 0.5 }
+    );
+}
+
+#[test]
+fn test_target_positions() {
+    let table = read_csv("name,RAJ2000,DEJ2000\nx,10.5,-20\ny,12:00:00,+45:00:00\n").unwrap();
+    let targets = target_positions(&table, "RAJ2000", "DEJ2000", &Frame::Icrs).unwrap();
+    assert_eq!(targets.len(), 2);
+    assert_eq!(targets[0].1, (10.5, -20.0));
+    assert_eq!(targets[1].1, (180.0, 45.0));
+    assert_eq!(targets[1].0.get_str("name"), Ok("y"));
+
+    assert_eq!(
+        target_positions(&table, "ra", "DEJ2000", &Frame::Icrs).unwrap_err(),
+        "missing column ra"
+    );
+    let table = read_csv("ra,dec\n1,2\n400,2\n").unwrap();
+    assert_eq!(
+        target_positions(&table, "ra", "dec", &Frame::Icrs).unwrap_err(),
+        "row 2: ra must be between 0 and 360 degrees, got 400"
+    );
+}
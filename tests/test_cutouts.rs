@@ -0,0 +1,116 @@
+use boom_api::{
+    api::alerts::cutout_fits,
+    formats::{
+        fits::{write_image, Image},
+        image::{render, write_png, zscale, Colormap, Stretch},
+    },
+};
+use flate2::{read::ZlibDecoder, write::GzEncoder, Compression};
+use mongodb::bson::{doc, spec::BinarySubtype, Binary, Bson};
+use std::io::{Read, Write};
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn binary(bytes: Vec<u8>) -> Bson {
+    Bson::Binary(Binary {
+        subtype: BinarySubtype::Generic,
+        bytes,
+    })
+}
+
+#[test]
+fn test_cutout_fits() {
+    // 3x2 image, the bottom row first
+    let pixels = [1.0, 2.0, 3.0, 4.0, f64::NAN, 6.0];
+    let fits = write_image(3, 2, &pixels);
+
+    // stamps are gzip compressed FITS files, within a document or not
+    for cutout in [
+        Bson::Document(
+            doc! { "fileName": "candid123_sci.fits.gz", "stampData": binary(gzip(&fits)) },
+        ),
+        binary(gzip(&fits)),
+        binary(fits.clone()),
+    ] {
+        assert_eq!(cutout_fits(&cutout).unwrap(), fits);
+    }
+    assert_eq!(
+        cutout_fits(&Bson::Document(doc! { "fileName": "x" })).unwrap_err(),
+        "the cutout has no stamp data"
+    );
+    assert!(cutout_fits(&binary(vec![0x1f, 0x8b, 0x08, 0x00])).is_err());
+
+    let image = Image::from_fits(&fits).unwrap();
+    assert_eq!((image.width, image.height), (3, 2));
+    assert_eq!(image.pixels[..4], pixels[..4]);
+    assert!(image.pixels[4].is_nan());
+    assert_eq!(
+        Image::from_fits(b"not a fits file").unwrap_err(),
+        "not a FITS file"
+    );
+}
+
+#[test]
+fn test_stretches() {
+    assert_eq!(
+        Stretch::Linear.limits(&[3.0, f64::NAN, -1.0]),
+        Some((-1.0, 3.0))
+    );
+    assert_eq!(Stretch::Log.limits(&[f64::NAN]), None);
+
+    // a flat background with a few bright pixels: zscale ignores them
+    let mut pixels: Vec<f64> = (0..400).map(|i| 100.0 + (i % 20) as f64 * 0.5).collect();
+    pixels.extend([5000.0, 8000.0, 10000.0]);
+    let (min, max) = zscale(&pixels).unwrap();
+    assert!((100.0..102.0).contains(&min), "{}", min);
+    assert!((107.0..200.0).contains(&max), "{}", max);
+    assert_eq!(zscale(&[]), None);
+    assert_eq!(zscale(&[2.0, 2.0]), Some((2.0, 2.0)));
+
+    assert_eq!(Colormap::Gray.color(0.0), [0, 0, 0]);
+    assert_eq!(Colormap::Gray.color(1.0), [255, 255, 255]);
+    assert_eq!(Colormap::Viridis.color(0.0), [68, 1, 84]);
+    assert_eq!(Colormap::Viridis.color(2.0), [253, 231, 37]);
+    assert_eq!(Colormap::Inferno.color(0.0625), [16, 6, 38]);
+}
+
+#[test]
+fn test_png() {
+    let image = Image {
+        width: 2,
+        height: 2,
+        pixels: vec![0.0, 1.0, 2.0, f64::NAN],
+    };
+    let rgba = render(&image, Stretch::Linear, Colormap::Gray);
+    // top row first, blank pixels transparent
+    assert_eq!(
+        rgba,
+        vec![255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 255, 128, 128, 128, 255]
+    );
+    let log = render(&image, Stretch::Log, Colormap::Gray);
+    assert!(log[12] > 128);
+
+    let png = write_png(2, 2, &rgba);
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[8..16], b"\0\0\0\x0dIHDR");
+    assert_eq!(&png[16..29], &[0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+    assert_eq!(
+        u32::from_be_bytes(png[29..33].try_into().unwrap()),
+        crc32fast::hash(&png[12..29])
+    );
+    let length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+    assert_eq!(&png[37..41], b"IDAT");
+    let mut rows = Vec::new();
+    ZlibDecoder::new(&png[41..41 + length])
+        .read_to_end(&mut rows)
+        .unwrap();
+    assert_eq!(rows[0], 0);
+    assert_eq!(&rows[1..9], &rgba[..8]);
+    assert_eq!(rows[9], 0);
+    assert_eq!(&rows[10..], &rgba[8..]);
+    assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xaeB`\x82");
+}
@@ -0,0 +1,365 @@
+use actix_web::{
+    get,
+    test::{self, TestRequest},
+    App, HttpRequest, HttpResponse,
+};
+use arrow::{
+    array::{Array, AsArray},
+    datatypes::{DataType, Float64Type, Int64Type},
+    ipc::reader::StreamReader,
+};
+use boom_api::formats::{
+    columnar::{columnar_response, infer_schema, record_batch, BATCH_SIZE},
+    csv::write_csv,
+    ndjson::{ndjson_response, Summary},
+    table::{Datatype, Table},
+    votable::write_votable,
+    OutputFormat,
+};
+use mongodb::bson::{doc, Document};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::{collections::HashMap, sync::Arc};
+
+#[get("/stream")]
+async fn stream() -> HttpResponse {
+    ndjson_response(|mut writer| async move {
+        for i in 0..3 {
+            if !writer.write(&serde_json::json!({ "i": i })).await {
+                return;
+            }
+        }
+        writer
+            .write(&Summary::new("done", 3, None, Some("token".to_string())))
+            .await;
+    })
+}
+
+fn get_documents() -> Vec<Document> {
+    vec![
+        doc! {
+            "objectId": "ZTF21aaaaaaa",
+            "candidate": { "jd": 2459000.5, "magpsf": 18.5, "fid": 1, "isdiffpos": "t" },
+        },
+        doc! {
+            "objectId": "ZTF21aaaaaab",
+            "candidate": { "jd": 2459001.5, "magpsf": 19, "fid": 2_i64, "drb": null },
+            "classifications": ["a", "b"],
+        },
+    ]
+}
+
+#[get("/columnar/{format}")]
+async fn columnar(path: actix_web::web::Path<String>) -> HttpResponse {
+    let output_format = OutputFormat::from_name(&path.into_inner()).unwrap();
+    let documents = get_documents().into_iter().map(Ok);
+    columnar_response(output_format, futures::stream::iter(documents))
+}
+
+#[get("/format")]
+async fn format(req: HttpRequest) -> HttpResponse {
+    HttpResponse::Ok().body(format!("{:?}", OutputFormat::from_request(&req)))
+}
+
+#[actix_rt::test]
+async fn test_output_format_negotiation() {
+    let app = test::init_service(App::new().service(format)).await;
+    for (accept, expected) in [
+        (None, "Json"),
+        (Some("application/json"), "Json"),
+        (Some("application/x-ndjson"), "Ndjson"),
+        (Some("text/html, application/x-ndjson;q=0.9"), "Ndjson"),
+        (Some("text/csv"), "Csv"),
+        (Some("application/x-votable+xml"), "Votable"),
+        (Some("text/csv, application/json"), "Csv"),
+    ] {
+        let mut req = TestRequest::get().uri("/format");
+        if let Some(accept) = accept {
+            req = req.insert_header(("Accept", accept));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(test::read_body(resp).await, expected);
+    }
+}
+
+#[actix_rt::test]
+async fn test_ndjson_response_streams_lines_and_summary() {
+    let app = test::init_service(App::new().service(stream)).await;
+    let resp = test::call_service(&app, TestRequest::get().uri("/stream").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/x-ndjson"
+    );
+    let body = test::read_body(resp).await;
+    let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0]["i"], 0);
+    assert_eq!(lines[2]["i"], 2);
+    assert_eq!(lines[3]["status"], "success");
+    assert_eq!(lines[3]["count"], 3);
+    assert_eq!(lines[3]["next"], "token");
+}
+
+#[test]
+fn test_summary_error_drops_next() {
+    let summary = Summary::new(
+        "done",
+        5,
+        Some("boom".to_string()),
+        Some("token".to_string()),
+    );
+    let value = serde_json::to_value(&summary).unwrap();
+    assert_eq!(value["status"], "error");
+    assert_eq!(value["message"], "boom");
+    assert_eq!(value["count"], 5);
+    assert!(value.get("next").is_none());
+}
+
+#[test]
+fn test_format_kwarg_overrides_accept() {
+    assert_eq!(
+        OutputFormat::Json.with_override(Some("VOTable")),
+        Ok(OutputFormat::Votable)
+    );
+    assert_eq!(OutputFormat::Csv.with_override(None), Ok(OutputFormat::Csv));
+    assert!(OutputFormat::Json.with_override(Some("fits")).is_err());
+}
+
+fn get_table() -> Table {
+    let units = HashMap::from([("candidate.magpsf".to_string(), "mag".to_string())]);
+    Table::from_documents("ZTF_alerts", &get_documents(), &units)
+}
+
+#[test]
+fn test_table_flattens_documents() {
+    let table = get_table();
+    let columns: Vec<(&str, Datatype)> = table
+        .columns
+        .iter()
+        .map(|c| (c.name.as_str(), c.datatype))
+        .collect();
+    assert_eq!(
+        columns,
+        vec![
+            ("objectId", Datatype::Char),
+            ("candidate.jd", Datatype::Double),
+            // an int and a double
+            ("candidate.magpsf", Datatype::Double),
+            // an int and a long
+            ("candidate.fid", Datatype::Long),
+            ("candidate.isdiffpos", Datatype::Char),
+            ("candidate.drb", Datatype::Char),
+            ("classifications", Datatype::Char),
+        ]
+    );
+    assert_eq!(table.columns[2].unit.as_deref(), Some("mag"));
+    assert_eq!(table.columns[1].unit, None);
+    assert_eq!(table.rows.len(), 2);
+}
+
+#[test]
+fn test_write_csv() {
+    let csv = write_csv(&get_table());
+    let lines: Vec<&str> = csv.split("\r\n").collect();
+    assert_eq!(
+        lines[0],
+        "objectId,candidate.jd,candidate.magpsf,candidate.fid,candidate.isdiffpos,candidate.drb,classifications"
+    );
+    assert_eq!(lines[1], "ZTF21aaaaaaa,2459000.5,18.5,1,t,,");
+    assert_eq!(
+        lines[2],
+        "ZTF21aaaaaab,2459001.5,19,2,,,\"[\"\"a\"\",\"\"b\"\"]\""
+    );
+}
+
+#[test]
+fn test_write_votable() {
+    let votable = write_votable(&get_table(), &[("next", "token")]);
+    assert!(votable.starts_with("<?xml"));
+    assert!(votable.contains("<INFO name=\"QUERY_STATUS\" value=\"OK\"/>"));
+    assert!(votable.contains("<INFO name=\"next\" value=\"token\"/>"));
+    assert!(votable.contains("<FIELD name=\"candidate.magpsf\" datatype=\"double\" unit=\"mag\"/>"));
+    assert!(votable.contains("<FIELD name=\"objectId\" datatype=\"char\" arraysize=\"*\"/>"));
+    assert!(votable.contains("<FIELD name=\"candidate.fid\" datatype=\"long\"/>"));
+    assert!(votable.contains(
+        "<TR><TD>ZTF21aaaaaaa</TD><TD>2459000.5</TD><TD>18.5</TD><TD>1</TD><TD>t</TD><TD></TD><TD></TD></TR>"
+    ));
+    assert!(votable.contains("<TD>[&quot;a&quot;,&quot;b&quot;]</TD>"));
+}
+
+#[test]
+fn test_infer_schema_and_record_batch() {
+    let documents = get_documents();
+    let schema = Arc::new(infer_schema(&documents));
+    let types: Vec<(&str, &DataType)> = schema
+        .fields()
+        .iter()
+        .map(|f| (f.name().as_str(), f.data_type()))
+        .collect();
+    assert_eq!(
+        types,
+        vec![
+            ("objectId", &DataType::Utf8),
+            ("candidate.jd", &DataType::Float64),
+            ("candidate.magpsf", &DataType::Float64),
+            ("candidate.fid", &DataType::Int64),
+            ("candidate.isdiffpos", &DataType::Utf8),
+            ("candidate.drb", &DataType::Utf8),
+            ("classifications", &DataType::Utf8),
+        ]
+    );
+
+    // fields unknown to the schema are dropped, missing ones are null
+    let later = vec![doc! { "objectId": "ZTF21aaaaaac", "extra": 1, "candidate": { "fid": 3 } }];
+    let batch = record_batch(&schema, &later).unwrap();
+    assert_eq!(batch.num_rows(), 1);
+    assert_eq!(batch.num_columns(), 7);
+    assert_eq!(batch.column(3).as_primitive::<Int64Type>().value(0), 3);
+    assert!(batch.column(1).is_null(0));
+}
+
+#[test]
+fn test_infer_schema_widens_types() {
+    let schema = infer_schema(&[
+        doc! { "fid": 1, "drb": null, "isdiffpos": 1 },
+        doc! { "fid": 2_i64, "drb": 0.9, "isdiffpos": "t" },
+    ]);
+    let types: Vec<(&str, &DataType)> = schema
+        .fields()
+        .iter()
+        .map(|f| (f.name().as_str(), f.data_type()))
+        .collect();
+    assert_eq!(
+        types,
+        vec![
+            ("fid", &DataType::Int64),
+            ("drb", &DataType::Float64),
+            ("isdiffpos", &DataType::Utf8),
+        ]
+    );
+}
+
+// a first batch of integers, then a document adding a field and a float
+#[get("/columnar_drift")]
+async fn columnar_drift() -> HttpResponse {
+    let mut documents: Vec<Document> = (0..BATCH_SIZE as i32)
+        .map(|i| doc! { "candidate": { "fid": i } })
+        .collect();
+    documents.push(doc! { "candidate": { "fid": 1.5 }, "extra": "x" });
+    columnar_response(
+        OutputFormat::Parquet,
+        futures::stream::iter(documents.into_iter().map(Ok)),
+    )
+}
+
+#[actix_rt::test]
+async fn test_parquet_response_schema_covers_later_batches() {
+    let app = test::init_service(App::new().service(columnar_drift)).await;
+    let resp =
+        test::call_service(&app, TestRequest::get().uri("/columnar_drift").to_request()).await;
+    let body = test::read_body(resp).await;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(body)
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+    assert_eq!(
+        batches.iter().map(|b| b.num_rows()).sum::<usize>(),
+        BATCH_SIZE + 1
+    );
+    let last = batches.last().unwrap();
+    let fid = last
+        .column_by_name("candidate.fid")
+        .unwrap()
+        .as_primitive::<Float64Type>();
+    assert_eq!(fid.value(fid.len() - 1), 1.5);
+    let extra = last.column_by_name("extra").unwrap().as_string::<i32>();
+    assert_eq!(extra.value(extra.len() - 1), "x");
+    assert!(batches[0].column_by_name("extra").unwrap().is_null(0));
+}
+
+#[actix_rt::test]
+async fn test_parquet_response() {
+    let app = test::init_service(App::new().service(columnar)).await;
+    let resp = test::call_service(
+        &app,
+        TestRequest::get().uri("/columnar/parquet").to_request(),
+    )
+    .await;
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/vnd.apache.parquet"
+    );
+    let body = test::read_body(resp).await;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(body)
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+    let magpsf = batches[0]
+        .column_by_name("candidate.magpsf")
+        .unwrap()
+        .as_primitive::<Float64Type>();
+    assert_eq!(magpsf.value(0), 18.5);
+    assert_eq!(magpsf.value(1), 19.0);
+}
+
+#[actix_rt::test]
+async fn test_arrow_stream_response() {
+    let app = test::init_service(App::new().service(columnar)).await;
+    let resp =
+        test::call_service(&app, TestRequest::get().uri("/columnar/arrow").to_request()).await;
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/vnd.apache.arrow.stream"
+    );
+    let body = test::read_body(resp).await;
+    let reader = StreamReader::try_new(std::io::Cursor::new(body), None).unwrap();
+    let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].num_rows(), 2);
+    let object_ids = batches[0]
+        .column_by_name("objectId")
+        .unwrap()
+        .as_string::<i32>();
+    assert_eq!(object_ids.value(1), "ZTF21aaaaaab");
+}
+
+#[get("/columnar_empty")]
+async fn columnar_empty() -> HttpResponse {
+    columnar_response(OutputFormat::Parquet, futures::stream::iter(Vec::new()))
+}
+
+#[actix_rt::test]
+async fn test_empty_parquet_response() {
+    let app = test::init_service(App::new().service(columnar_empty)).await;
+    let resp =
+        test::call_service(&app, TestRequest::get().uri("/columnar_empty").to_request()).await;
+    let body = test::read_body(resp).await;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(body).unwrap();
+    assert_eq!(builder.metadata().file_metadata().num_rows(), 0);
+}
+
+#[test]
+fn test_lead_columns() {
+    let mut table = get_table();
+    table.lead_columns(&[
+        ("candidate.fid", Datatype::Char),
+        ("candidate.ra", Datatype::Double),
+    ]);
+    let names: Vec<&str> = table.columns.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names[..3], ["candidate.fid", "candidate.ra", "objectId"]);
+    assert_eq!(table.columns[0].datatype, Datatype::Long);
+    assert_eq!(table.columns[1].datatype, Datatype::Double);
+    assert_eq!(table.rows[1][0], mongodb::bson::Bson::Int64(2));
+    assert_eq!(table.rows[1][1], mongodb::bson::Bson::Null);
+    assert_eq!(
+        table.rows[1][2],
+        mongodb::bson::Bson::String("ZTF21aaaaaab".to_string())
+    );
+}
@@ -0,0 +1,50 @@
+use boom_api::models::job_models::{Job, JobPhase};
+use mongodb::bson::{self, doc, DateTime};
+
+#[test]
+fn test_job_phases() {
+    for (phase, name) in [
+        (JobPhase::Queued, "QUEUED"),
+        (JobPhase::Executing, "EXECUTING"),
+        (JobPhase::Completed, "COMPLETED"),
+        (JobPhase::Error, "ERROR"),
+        (JobPhase::Aborted, "ABORTED"),
+    ] {
+        assert_eq!(phase.name(), name);
+        assert_eq!(serde_json::json!(phase), name);
+    }
+    assert!(JobPhase::Queued.is_active());
+    assert!(JobPhase::Executing.is_active());
+    assert!(!JobPhase::Completed.is_active());
+    assert!(!JobPhase::Aborted.is_active());
+}
+
+#[test]
+fn test_job_status() {
+    let stored = doc! {
+        "_id": "0b9c",
+        "owner": "tester",
+        "kind": "find",
+        "catalog": "ZTF_alerts",
+        "phase": "COMPLETED",
+        "created_at": DateTime::from_millis(1_700_000_000_000),
+        "started_at": DateTime::from_millis(1_700_000_001_000),
+        "finished_at": DateTime::from_millis(1_700_000_002_000),
+        "destruction": DateTime::from_millis(1_700_086_400_000),
+        "count": 12_i64,
+        "error": null,
+    };
+    let job: Job = bson::from_document(stored.clone()).unwrap();
+    assert_eq!(job.phase, JobPhase::Completed);
+    assert_eq!(bson::to_document(&job).unwrap(), stored);
+
+    let status = job.status();
+    assert_eq!(status["job_id"], "0b9c");
+    assert_eq!(status["phase"], "COMPLETED");
+    assert_eq!(status["count"], 12);
+    assert_eq!(status["created_at"], "2023-11-14T22:13:20Z");
+    assert_eq!(status["destruction"], "2023-11-15T22:13:20Z");
+    assert!(status["error"].is_null());
+    // the owner is implied by the request
+    assert!(status.get("owner").is_none());
+}
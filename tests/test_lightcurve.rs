@@ -0,0 +1,103 @@
+use boom_api::{
+    api::lightcurve::{lightcurve, Photometry},
+    surveys::{Lsst, Survey, Ztf},
+};
+use mongodb::bson::{doc, Bson};
+
+#[test]
+fn test_photometry_conversions() {
+    assert_eq!(Ztf.band(&doc! { "fid": 1 }), Some("ztfg"));
+    assert_eq!(Ztf.band(&doc! { "fid": 3 }), Some("ztfi"));
+    assert_eq!(Ztf.band(&doc! { "fid": 4 }), None);
+
+    let candidate = doc! {
+        "candid": 2_462_000_000_015_i64,
+        "jd": 2460000.5,
+        "fid": 2,
+        "magpsf": 18.9,
+        "sigmapsf": 0.1,
+        "diffmaglim": 20.5,
+        "programid": 1,
+        "isdiffpos": "t",
+    };
+    let point = Photometry::from_candidate(&Ztf, &candidate).unwrap();
+    assert!(point.is_detection());
+    assert_eq!(point.candid, Some(2_462_000_000_015));
+    assert_eq!(point.mjd, 60000.0);
+    assert_eq!(point.filter, "ztfr");
+    assert_eq!(point.limmag, Some(20.5));
+    assert_eq!(point.programid, Some(1));
+    // 5 magnitudes brighter than 23.9 is 100 times 1 µJy
+    let flux = point.flux.unwrap();
+    assert!((flux - 100.0).abs() < 1e-9);
+    let fluxerr = point.fluxerr.unwrap();
+    assert!((fluxerr - 100.0 * 0.4 * std::f64::consts::LN_10 * 0.1).abs() < 1e-9);
+
+    // sources fainter than in the reference image have negative fluxes
+    let mut faded = candidate.clone();
+    faded.insert("isdiffpos", "f");
+    let point = Photometry::from_candidate(&Ztf, &faded).unwrap();
+    assert!((point.flux.unwrap() + 100.0).abs() < 1e-9);
+    assert!(point.fluxerr.unwrap() > 0.0);
+
+    let nondetection = doc! { "jd": 2460001.5, "fid": 1, "diffmaglim": 20.1, "magpsf": Bson::Null };
+    let point = Photometry::from_candidate(&Ztf, &nondetection).unwrap();
+    assert!(!point.is_detection());
+    assert_eq!(point.limmag, Some(20.1));
+    assert_eq!((point.mag, point.flux, point.fluxerr), (None, None, None));
+
+    assert!(Photometry::from_candidate(&Ztf, &doc! { "fid": 1, "magpsf": 18.0 }).is_none());
+    assert!(Photometry::from_candidate(&Ztf, &doc! { "jd": 2460000.5, "fid": 9 }).is_none());
+
+    // LSST names its band, and flags negative differences with a boolean
+    let lsst_candidate = doc! {
+        "jd": 2460000.5,
+        "band": "r",
+        "magpsf": 18.9,
+        "isdiffpos": false,
+    };
+    let point = Photometry::from_candidate(&Lsst, &lsst_candidate).unwrap();
+    assert_eq!(point.filter, "lsstr");
+    assert!(point.flux.unwrap() < 0.0);
+    assert!(Photometry::from_candidate(&Lsst, &candidate).is_none());
+}
+
+#[test]
+fn test_lightcurve_merge() {
+    let latest =
+        doc! { "candid": 3_i64, "jd": 2460003.5, "fid": 1, "magpsf": 18.0, "sigmapsf": 0.1 };
+    let candidates = vec![
+        latest.clone(),
+        // the previous candidates of the latest alert, some of them also in
+        // the aux entry
+        doc! { "candid": 1_i64, "jd": 2460001.5, "fid": 1, "magpsf": 18.5, "sigmapsf": 0.1 },
+        doc! { "candid": 2_i64, "jd": 2460002.5, "fid": 2, "magpsf": 18.2, "sigmapsf": 0.1 },
+        doc! { "candid": 1_i64, "jd": 2460001.5, "fid": 1, "magpsf": 18.5, "sigmapsf": 0.1 },
+        latest,
+        // non-detections, one of them twice and one at the time of a detection
+        doc! { "candid": Bson::Null, "jd": 2460000.5, "fid": 1, "diffmaglim": 20.0 },
+        doc! { "jd": 2460000.5, "fid": 1, "diffmaglim": 20.0 },
+        doc! { "jd": 2460000.5, "fid": 2, "diffmaglim": 19.8 },
+        doc! { "jd": 2460002.5, "fid": 2, "diffmaglim": 19.5 },
+    ];
+    let points = lightcurve(&Ztf, &candidates);
+    let summary: Vec<(f64, &str, bool)> = points
+        .iter()
+        .map(|p| (p.jd, p.filter.as_str(), p.is_detection()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (2460000.5, "ztfg", false),
+            (2460000.5, "ztfr", false),
+            (2460001.5, "ztfg", true),
+            (2460002.5, "ztfr", true),
+            (2460003.5, "ztfg", true),
+        ]
+    );
+    assert_eq!(
+        points.iter().filter_map(|p| p.candid).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert!(lightcurve(&Ztf, &[]).is_empty());
+}
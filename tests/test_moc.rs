@@ -0,0 +1,134 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use boom_api::spatial::{angular_distance, healpix, moc::Moc, regions::Region};
+use std::collections::BTreeMap;
+
+#[test]
+fn test_healpix() {
+    // base pixels: 0 to 3 around the north pole, 4 to 7 on the equator
+    assert_eq!(healpix::pixel(0, 0.0, 90.0), 0);
+    assert_eq!(healpix::pixel(0, 0.0, 0.0), 4);
+    assert_eq!(healpix::pixel(0, 45.0, -90.0), 8);
+    let (ra, dec) = healpix::center(0, 0);
+    assert!((ra - 45.0).abs() < 1e-9);
+    assert!((dec - 41.810315).abs() < 1e-6);
+    assert_eq!(healpix::center(0, 4), (0.0, 0.0));
+    for pixel in 0..healpix::npix(3) {
+        let (ra, dec) = healpix::center(3, pixel);
+        assert_eq!(healpix::pixel(3, ra, dec), pixel);
+    }
+
+    // pixels contain their center, and are contained in their parents
+    for order in [1, 5, 10, 29] {
+        for (ra, dec) in [(0.0, 0.0), (123.4, 56.7), (359.99, -89.99), (270.0, 45.0)] {
+            let pixel = healpix::pixel(order, ra, dec);
+            assert!(pixel < healpix::npix(order));
+            assert_eq!(healpix::pixel(order - 1, ra, dec), pixel >> 2);
+            let (cra, cdec) = healpix::center(order, pixel);
+            assert_eq!(healpix::pixel(order, cra, cdec), pixel);
+            let distance = angular_distance(ra, dec, cra, cdec);
+            assert!(distance <= healpix::bounding_radius(order, pixel));
+        }
+    }
+
+    assert_eq!(healpix::uniq(0, 11), 15);
+    assert_eq!(healpix::from_uniq(15), Ok((0, 11)));
+    assert_eq!(
+        healpix::from_uniq(healpix::uniq(29, 12345)),
+        Ok((29, 12345))
+    );
+    assert!(healpix::from_uniq(3).is_err());
+}
+
+#[test]
+fn test_moc_cells() {
+    // the four children of a cell are merged into it
+    let moc = Moc::from_cells([(3, 4), (3, 5), (3, 6), (3, 7), (3, 100), (2, 25)]).unwrap();
+    assert_eq!(moc.cells(), vec![(2, 1), (2, 25)]);
+    assert_eq!(moc.max_order(), 2);
+    assert!((moc.sky_fraction() - 2.0 / 192.0).abs() < 1e-12);
+    let (ra, dec) = healpix::center(2, 25);
+    assert!(moc.contains(ra, dec));
+    let (ra, dec) = healpix::center(2, 26);
+    assert!(!moc.contains(ra, dec));
+
+    let moc = Moc::from_cells([(5, 1), (4, 3)]).unwrap();
+    assert_eq!(moc.degrade(4).cells(), vec![(4, 0), (4, 3)]);
+    assert_eq!(moc.degrade(0).cells(), vec![(0, 0)]);
+
+    let mut json = BTreeMap::new();
+    json.insert("5".to_string(), vec![1]);
+    json.insert("4".to_string(), vec![3]);
+    assert_eq!(Moc::from_json(&json), Ok(moc.clone()));
+    assert_eq!(moc.to_json(), BTreeMap::from([(4, vec![3]), (5, vec![1])]));
+
+    assert!(Moc::from_cells([(30, 0)]).is_err());
+    assert!(Moc::from_cells([(0, 12)]).is_err());
+    json.insert("x".to_string(), vec![1]);
+    assert!(Moc::from_json(&json).is_err());
+}
+
+#[test]
+fn test_moc_fits() {
+    let moc = Moc::from_cells([(3, 4), (7, 1000), (12, 123456)]).unwrap();
+    let fits = moc.to_fits();
+    assert_eq!(fits.len() % 2880, 0);
+    assert!(fits.starts_with(b"SIMPLE  =                    T"));
+    let header = String::from_utf8_lossy(&fits[2880..5760]);
+    assert!(header.contains("ORDERING= 'NUNIQ   '"));
+    assert!(header.contains("MOCORDER=                   12"));
+    assert_eq!(Moc::from_fits(&fits), Ok(moc.clone()));
+
+    // and as sent in a region, base64 encoded
+    let region: Region = serde_json::from_value(serde_json::json!({
+        "type": "moc", "fits": STANDARD.encode(&fits),
+    }))
+    .unwrap();
+    assert_eq!(region, Region::Moc(moc));
+
+    assert!(Moc::from_fits(b"not a fits file").is_err());
+    assert!(Moc::from_fits(&fits[..4000]).is_err());
+}
+
+#[test]
+fn test_moc_region() {
+    let region: Region = serde_json::from_value(serde_json::json!({
+        "type": "moc", "order": 4, "pixels": [100, 101],
+    }))
+    .unwrap();
+    assert!(region.validate().is_ok());
+    assert!(region.needs_post_filter());
+    let (ra, dec) = healpix::center(4, 101);
+    assert!(region.contains(ra, dec));
+    let (ra, dec) = healpix::center(4, 102);
+    assert!(!region.contains(ra, dec));
+    // one circle per cell
+    let filter = region.filter();
+    assert_eq!(filter.get_array("$or").unwrap().len(), 2);
+
+    let region: Region = serde_json::from_value(serde_json::json!({
+        "type": "moc", "moc": {"1": [0]},
+    }))
+    .unwrap();
+    assert!(region.filter().contains_key("coordinates.radec_geojson"));
+
+    // cells are degraded until there are few enough circles
+    let pixels: Vec<u64> = (0..1000).map(|i| i * 7).collect();
+    let region: Region = serde_json::from_value(serde_json::json!({
+        "type": "moc", "order": 6, "pixels": pixels,
+    }))
+    .unwrap();
+    assert!(region.filter().get_array("$or").unwrap().len() <= 64);
+
+    let region: Region =
+        serde_json::from_value(serde_json::json!({"type": "moc", "moc": {}})).unwrap();
+    assert_eq!(region.validate(), Err("moc is empty".to_string()));
+    for invalid in [
+        serde_json::json!({"type": "moc"}),
+        serde_json::json!({"type": "moc", "order": 4}),
+        serde_json::json!({"type": "moc", "moc": {"1": [0]}, "order": 1, "pixels": [0]}),
+        serde_json::json!({"type": "moc", "fits": "not base64!"}),
+        serde_json::json!({"type": "moc", "order": 2, "pixels": [192]}),
+    ] {
+        assert!(serde_json::from_value::<Region>(invalid).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use boom_api::{
+    api::pagination::{decode_token, encode_token, Pagination, Position},
+    models::query_models::QueryKwargs,
+};
+use mongodb::bson::{doc, oid::ObjectId, Bson};
+
+#[test]
+fn test_no_pagination_by_default() {
+    let kwargs = QueryKwargs {
+        limit: Some(10),
+        ..Default::default()
+    };
+    assert!(Pagination::from_kwargs(&kwargs).unwrap().is_none());
+}
+
+#[test]
+fn test_pagination_from_sort() {
+    let kwargs = QueryKwargs {
+        sort: Some(doc! { "candidate.jd": -1 }),
+        paginate: Some(true),
+        ..Default::default()
+    };
+    let (pagination, positions) = Pagination::from_kwargs(&kwargs).unwrap().unwrap();
+    assert_eq!(pagination.key, "candidate.jd");
+    assert_eq!(pagination.direction, -1);
+    assert!(positions.is_empty());
+    assert_eq!(pagination.sort(), doc! { "candidate.jd": -1, "_id": -1 });
+
+    // default to _id
+    let kwargs = QueryKwargs {
+        paginate: Some(true),
+        ..Default::default()
+    };
+    let (pagination, _) = Pagination::from_kwargs(&kwargs).unwrap().unwrap();
+    assert_eq!(pagination.sort(), doc! { "_id": 1 });
+
+    let kwargs = QueryKwargs {
+        sort: Some(doc! { "a": 1, "b": 1 }),
+        paginate: Some(true),
+        ..Default::default()
+    };
+    assert!(Pagination::from_kwargs(&kwargs).is_err());
+    let kwargs = QueryKwargs {
+        sort: Some(doc! { "$where": 1 }),
+        paginate: Some(true),
+        ..Default::default()
+    };
+    assert!(Pagination::from_kwargs(&kwargs).is_err());
+}
+
+#[test]
+fn test_token_round_trip() {
+    let pagination = Pagination {
+        key: "candidate.jd".to_string(),
+        direction: 1,
+    };
+    let position = Position {
+        value: Bson::Double(2460000.5),
+        id: Bson::ObjectId(ObjectId::new()),
+    };
+    let token = encode_token(&pagination, &[("obj".to_string(), position.clone())]);
+    let (decoded, positions) = decode_token(&token).unwrap();
+    assert_eq!(decoded, pagination);
+    assert_eq!(positions.get("obj"), Some(&position));
+
+    assert!(decode_token("not-a-token").is_err());
+
+    // forged tokens with operator keys or other directions are rejected
+    for (key, direction) in [
+        ("$where", 1),
+        ("a.$b", 1),
+        ("a..b", 1),
+        ("", 1),
+        ("a", 0),
+        ("a", 2),
+    ] {
+        let forged = Pagination {
+            key: key.to_string(),
+            direction,
+        };
+        assert!(decode_token(&encode_token(&forged, &[])).is_err());
+    }
+
+    // resuming with a conflicting sort is rejected
+    let kwargs = QueryKwargs {
+        sort: Some(doc! { "candidate.jd": -1 }),
+        after: Some(token.clone()),
+        ..Default::default()
+    };
+    assert!(Pagination::from_kwargs(&kwargs).is_err());
+    let kwargs = QueryKwargs {
+        after: Some(token),
+        ..Default::default()
+    };
+    let (_, positions) = Pagination::from_kwargs(&kwargs).unwrap().unwrap();
+    assert_eq!(positions.get("obj"), Some(&position));
+}
+
+#[test]
+fn test_continuation_filter() {
+    let pagination = Pagination {
+        key: "candidate.jd".to_string(),
+        direction: 1,
+    };
+    let position = Position {
+        value: Bson::Double(10.0),
+        id: Bson::Int64(5),
+    };
+    assert_eq!(
+        pagination
+            .continuation_filter(doc! {}, Some(&position))
+            .unwrap(),
+        doc! {
+            "$or": [
+                { "candidate.jd": { "$gt": 10.0 } },
+                { "candidate.jd": 10.0, "_id": { "$gt": 5_i64 } }
+            ]
+        }
+    );
+    assert_eq!(
+        pagination
+            .continuation_filter(doc! { "a": 1 }, None)
+            .unwrap(),
+        doc! { "a": 1 }
+    );
+
+    let pagination = Pagination {
+        key: "_id".to_string(),
+        direction: -1,
+    };
+    assert_eq!(
+        pagination
+            .continuation_filter(doc! { "a": 1 }, Some(&position))
+            .unwrap(),
+        doc! { "$and": [{ "a": 1 }, { "_id": { "$lt": 5_i64 } }] }
+    );
+
+    // positions come from the client and cannot smuggle operators in
+    let position = Position {
+        value: Bson::Int64(5),
+        id: Bson::Document(doc! { "$where": "sleep(1000)" }),
+    };
+    assert!(pagination
+        .continuation_filter(doc! {}, Some(&position))
+        .is_err());
+}
+
+#[test]
+fn test_position_of() {
+    let pagination = Pagination {
+        key: "candidate.jd".to_string(),
+        direction: 1,
+    };
+    let position = pagination
+        .position_of(&doc! { "_id": 1, "candidate": { "jd": 2.5 } })
+        .unwrap();
+    assert_eq!(position.value, Bson::Double(2.5));
+    assert_eq!(position.id, Bson::Int32(1));
+    assert!(pagination.position_of(&doc! { "_id": 1 }).is_err());
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use boom_api::{api::permissions::DataRights, models::auth_models::AuthenticatedUser};
+use common::{get_config, test_user};
+use mongodb::{bson::doc, options::FindOptions};
+
+fn user(groups: Vec<&str>, programids: Vec<i32>) -> AuthenticatedUser {
+    AuthenticatedUser {
+        groups: groups.into_iter().map(String::from).collect(),
+        programids,
+        ..test_user()
+    }
+}
+
+#[test]
+fn test_resolve_programids() {
+    let config = get_config();
+    // everyone gets public data
+    assert_eq!(
+        config.auth.resolve_programids(&user(vec![], vec![])),
+        vec![1]
+    );
+    // groups and user grants are merged
+    assert_eq!(
+        config
+            .auth
+            .resolve_programids(&user(vec!["partnership"], vec![3])),
+        vec![1, 2, 3]
+    );
+    assert_eq!(
+        config
+            .auth
+            .resolve_programids(&user(vec!["unknown_group"], vec![])),
+        vec![1]
+    );
+}
+
+#[test]
+fn test_restrict_filter() {
+    let config = get_config();
+    let rights = DataRights::new(&config, "ZTF_alerts", &user(vec![], vec![1, 2]));
+    assert_eq!(
+        rights.restrict_filter(doc! {}),
+        doc! { "candidate.programid": { "$in": [1, 2] } }
+    );
+    assert_eq!(
+        rights.restrict_filter(doc! { "objectId": "ZTF18aajpnun" }),
+        doc! {
+            "$and": [
+                { "objectId": "ZTF18aajpnun" },
+                { "candidate.programid": { "$in": [1, 2] } }
+            ]
+        }
+    );
+
+    // catalogs without data rights settings are not restricted
+    let rights = DataRights::new(&config, "NED", &user(vec![], vec![1]));
+    assert_eq!(rights.restrict_filter(doc! { "a": 1 }), doc! { "a": 1 });
+    assert!(rights.pipeline_stages().is_empty());
+}
+
+#[test]
+fn test_survey_rights() {
+    let config = get_config();
+    // survey collections take their rights from the program id of the survey
+    let rights = DataRights::new(&config, "ZTF_alerts_aux", &user(vec![], vec![1]));
+    let arrays: Vec<&str> = rights.arrays.iter().map(|(a, _)| a.as_str()).collect();
+    assert_eq!(arrays, ["fp_hists", "prv_candidates", "prv_nondetections"]);
+    assert!(rights.arrays.iter().all(|(_, field)| field == "programid"));
+    assert_eq!(rights.field, None);
+
+    // public surveys are not restricted
+    for collection in ["LSST_alerts", "DECAM_alerts_aux"] {
+        let rights = DataRights::new(&config, collection, &user(vec![], vec![1]));
+        assert_eq!(rights.restrict_filter(doc! { "a": 1 }), doc! { "a": 1 });
+        assert!(rights.pipeline_stages().is_empty());
+    }
+}
+
+#[test]
+fn test_array_expressions() {
+    let config = get_config();
+    let rights = DataRights::new(&config, "ZTF_alerts_aux", &user(vec![], vec![1]));
+    // aux documents are never filtered out, only their arrays are trimmed
+    assert_eq!(rights.restrict_filter(doc! {}), doc! {});
+    let expressions = rights.array_expressions();
+    let prv_candidates = expressions.get_document("prv_candidates").unwrap();
+    assert_eq!(
+        prv_candidates,
+        &doc! {
+            "$cond": [
+                { "$isArray": "$prv_candidates" },
+                {
+                    "$filter": {
+                        "input": "$prv_candidates",
+                        "as": "x",
+                        "cond": { "$in": ["$$x.programid", [1]] }
+                    }
+                },
+                "$$REMOVE"
+            ]
+        }
+    );
+    assert_eq!(rights.pipeline_stages(), vec![doc! { "$set": expressions }]);
+}
+
+#[test]
+fn test_find_pipeline() {
+    let config = get_config();
+    let rights = DataRights::new(&config, "ZTF_alerts_aux", &user(vec![], vec![1]));
+    let options = FindOptions::builder()
+        .sort(doc! { "_id": 1 })
+        .limit(10)
+        .projection(doc! { "prv_candidates": 1 })
+        .build();
+    let pipeline = rights.find_pipeline(doc! { "prv_candidates.programid": 2 }, &options);
+    // the arrays are trimmed before the user filter can match their elements
+    assert_eq!(pipeline[0], doc! { "$set": rights.array_expressions() });
+    assert_eq!(
+        pipeline[1..],
+        [
+            doc! { "$match": { "prv_candidates.programid": 2 } },
+            doc! { "$sort": { "_id": 1 } },
+            doc! { "$limit": 10_i64 },
+            doc! { "$project": { "prv_candidates": 1 } },
+        ]
+    );
+
+    // the document restriction comes first on catalogs with a program id
+    let rights = DataRights::new(&config, "ZTF_alerts", &user(vec![], vec![1]));
+    assert_eq!(
+        rights.match_stages(doc! {}),
+        vec![doc! { "$match": { "candidate.programid": { "$in": [1] } } }]
+    );
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use boom_api::api::pipeline::{restrict_pipeline, validate_pipeline};
+use common::{get_config, test_user};
+use mongodb::bson::doc;
+
+#[test]
+fn test_validate_pipeline_allowed() {
+    let pipeline = vec![
+        doc! { "$match": { "candidate.drb": { "$gt": 0.5 } } },
+        doc! { "$group": { "_id": "$objectId", "n": { "$sum": 1 } } },
+        doc! {
+            "$facet": {
+                "bright": [{ "$match": { "candidate.magpsf": { "$lt": 18 } } }],
+                "count": [{ "$count": "n" }]
+            }
+        },
+        doc! {
+            "$lookup": {
+                "from": "ZTF_alerts_aux",
+                "localField": "objectId",
+                "foreignField": "_id",
+                "as": "aux"
+            }
+        },
+    ];
+    assert!(validate_pipeline(&get_config(), &pipeline).is_ok());
+}
+
+#[test]
+fn test_validate_pipeline_rejected() {
+    let config = get_config();
+    let rejected = vec![
+        doc! { "$out": "stolen" },
+        doc! { "$merge": { "into": "stolen" } },
+        doc! { "$match": { "$where": "sleep(1000)" } },
+        doc! {
+            "$addFields": {
+                "x": { "$function": { "body": "function() {}", "args": [], "lang": "js" } }
+            }
+        },
+        doc! { "$lookup": { "from": { "db": "admin", "coll": "users" }, "as": "x" } },
+        doc! { "$lookup": { "from": "admin.users", "as": "x" } },
+        // collections of the database that are not catalogs
+        doc! { "$lookup": { "from": "users", "as": "x" } },
+        doc! { "$facet": { "a": [{ "$lookup": { "from": "filters", "as": "x" } }] } },
+        doc! { "$facet": { "a": [{ "$out": "stolen" }] } },
+        doc! { "$match": {}, "$limit": 1 },
+    ];
+    for stage in rejected {
+        assert!(
+            validate_pipeline(&config, std::slice::from_ref(&stage)).is_err(),
+            "stage should be rejected: {}",
+            stage
+        );
+    }
+    let error =
+        validate_pipeline(&config, &[doc! { "$match": {} }, doc! { "$out": "x" }]).unwrap_err();
+    assert!(error.contains("pipeline.1"), "{}", error);
+    let error = validate_pipeline(
+        &config,
+        &[doc! { "$lookup": { "from": "users", "as": "x" } }],
+    )
+    .unwrap_err();
+    assert_eq!(error, "unknown catalog users (pipeline.0.$lookup.from)");
+}
+
+#[test]
+fn test_restrict_pipeline() {
+    let config = get_config();
+    let pipeline = vec![
+        doc! { "$match": { "objectId": "ZTF18aajpnun" } },
+        doc! {
+            "$lookup": {
+                "from": "ZTF_alerts_aux",
+                "localField": "objectId",
+                "foreignField": "_id",
+                "as": "aux"
+            }
+        },
+    ];
+    let restricted = restrict_pipeline(&config, "ZTF_alerts", &test_user(), pipeline);
+    assert_eq!(
+        restricted[0],
+        doc! { "$match": { "candidate.programid": { "$in": [1] } } }
+    );
+    assert_eq!(
+        restricted[1],
+        doc! { "$match": { "objectId": "ZTF18aajpnun" } }
+    );
+    let lookup = restricted[2].get_document("$lookup").unwrap();
+    let lookup_pipeline = lookup.get_array("pipeline").unwrap();
+    assert_eq!(lookup_pipeline.len(), 1);
+    assert!(lookup_pipeline[0]
+        .as_document()
+        .unwrap()
+        .contains_key("$set"));
+
+    // unrestricted catalogs are left untouched
+    let pipeline = vec![doc! { "$match": {} }];
+    assert_eq!(
+        restrict_pipeline(&config, "NED", &test_user(), pipeline.clone()),
+        pipeline
+    );
+}
@@ -0,0 +1,166 @@
+use boom_api::{
+    models::query_models::Unit,
+    spatial::regions::{coordinate_box_vertices, Region},
+};
+use mongodb::bson::{doc, Document};
+
+// a document at (ra, dec), positioned like the catalogs
+fn at(ra: f64, dec: f64) -> Document {
+    doc! {
+        "name": "source",
+        "coordinates": { "radec_geojson": { "type": "Point", "coordinates": [ra - 180.0, dec] } },
+    }
+}
+
+#[test]
+fn test_parse_regions() {
+    let region: Region = serde_json::from_value(serde_json::json!({
+        "type": "ellipse", "ra": 10.0, "dec": 20.0, "semi_major": 60.0, "semi_minor": 30.0,
+    }))
+    .unwrap();
+    assert_eq!(
+        region.in_degrees(&Unit::Arcminutes),
+        Region::Ellipse {
+            ra: 10.0,
+            dec: 20.0,
+            semi_major: 1.0,
+            semi_minor: 0.5,
+            position_angle: 0.0,
+        }
+    );
+    let region: Region = serde_json::from_value(serde_json::json!({
+        "type": "polygon", "vertices": [[10.0, 10.0], [20.0, 10.0], [15.0, 20.0]],
+    }))
+    .unwrap();
+    assert_eq!(
+        region.filter(),
+        doc! { "coordinates.radec_geojson": { "$geoWithin": { "$geometry": {
+            "type": "Polygon",
+            "coordinates": [[[-170.0, 10.0], [-160.0, 10.0], [-165.0, 20.0], [-170.0, 10.0]]],
+        } } } }
+    );
+    assert!(!region.needs_post_filter());
+    assert!(serde_json::from_value::<Region>(serde_json::json!({"type": "hexagon"})).is_err());
+}
+
+#[test]
+fn test_invalid_regions() {
+    for (region, message) in [
+        (
+            Region::Circle {
+                ra: 10.0,
+                dec: 95.0,
+                radius: 1.0,
+            },
+            "circle center must have ra between 0 and 360 and dec between -90 and 90 degrees",
+        ),
+        (
+            Region::Box {
+                ra_min: 10.0,
+                ra_max: 10.0,
+                dec_min: 0.0,
+                dec_max: 1.0,
+            },
+            "box ra range must be between 0 and 180 degrees wide",
+        ),
+        (
+            Region::Box {
+                ra_min: 10.0,
+                ra_max: 20.0,
+                dec_min: 80.0,
+                dec_max: 90.0,
+            },
+            "box cannot reach a pole, use a circle around it instead",
+        ),
+        (
+            Region::Polygon {
+                vertices: vec![[10.0, 10.0], [20.0, 10.0]],
+            },
+            "polygon takes at least three vertices",
+        ),
+        (
+            Region::Ellipse {
+                ra: 10.0,
+                dec: 10.0,
+                semi_major: 1.0,
+                semi_minor: 2.0,
+                position_angle: 0.0,
+            },
+            "ellipse semi-axes must satisfy 0 < semi_minor <= semi_major < 90 degrees",
+        ),
+    ] {
+        assert_eq!(region.validate(), Err(message.to_string()), "{:?}", region);
+    }
+}
+
+#[test]
+fn test_coordinate_box() {
+    // across ra = 0
+    let region = Region::Box {
+        ra_min: 359.0,
+        ra_max: 1.5,
+        dec_min: 30.0,
+        dec_max: 31.0,
+    };
+    assert!(region.validate().is_ok());
+    assert!(region.needs_post_filter());
+    assert!(region.contains(359.5, 30.5));
+    assert!(region.contains(0.5, 30.0));
+    assert!(region.contains(1.5, 31.0));
+    assert!(!region.contains(2.0, 30.5));
+    assert!(!region.contains(180.0, 30.5));
+    assert!(!region.contains(0.5, 31.1));
+
+    // 3 steps along each parallel, the lower edge moved towards the equator
+    let vertices = coordinate_box_vertices(359.0, 1.5, 30.0, 31.0);
+    assert_eq!(vertices.len(), 8);
+    assert!((vertices[0].0 - 359.0).abs() < 1e-9);
+    assert!(vertices[0].1 < 30.0 && vertices[0].1 > 30.0 - 5.0 / 3600.0);
+    assert!((vertices[3].0 - 1.5).abs() < 1e-9);
+    assert_eq!(vertices[4], (vertices[3].0, 31.0));
+    // and towards it in the south
+    let vertices = coordinate_box_vertices(10.0, 11.0, -31.0, -30.0);
+    assert_eq!(vertices[0].1, -31.0);
+    assert!(vertices[2].1 > -30.0);
+
+    // matches are checked with their position, which is removed when it
+    // was not requested
+    let inside = region.post_filter(at(0.0, 30.5), Some("coordinates.radec_geojson"));
+    assert_eq!(inside, Some(doc! { "name": "source" }));
+    assert_eq!(region.post_filter(at(0.0, 29.9), None), None);
+    assert_eq!(region.post_filter(doc! { "name": "nowhere" }, None), None);
+}
+
+#[test]
+fn test_ellipse() {
+    // major axis along the parallel
+    let region = Region::Ellipse {
+        ra: 100.0,
+        dec: 0.0,
+        semi_major: 1.0,
+        semi_minor: 0.25,
+        position_angle: 90.0,
+    };
+    assert!(region.validate().is_ok());
+    assert_eq!(
+        region.filter(),
+        doc! { "coordinates.radec_geojson": { "$geoWithin": {
+            "$centerSphere": [[-80.0, 0.0], 1.0_f64.to_radians()]
+        } } }
+    );
+    assert!(region.contains(100.9, 0.0));
+    assert!(region.contains(99.1, 0.0));
+    assert!(region.contains(100.0, 0.2));
+    assert!(!region.contains(100.0, 0.3));
+    assert!(!region.contains(100.7, 0.2));
+    // rotated to the meridian
+    let region = Region::Ellipse {
+        ra: 100.0,
+        dec: 0.0,
+        semi_major: 1.0,
+        semi_minor: 0.25,
+        position_angle: 0.0,
+    };
+    assert!(region.contains(100.0, 0.9));
+    assert!(!region.contains(100.9, 0.0));
+}
@@ -0,0 +1,587 @@
+mod common;
+
+use actix_web::{
+    test::{self, TestRequest},
+    web, App,
+};
+use boom_api::{
+    api::{alerts, crossmatch, filters, jobs, lightcurve, query},
+    models::query_models::{ConeSearchParams, FindParams},
+    spatial::coordinates::{Coordinates, Frame},
+};
+use common::{assert_bad_requests, get_config, get_web_client};
+use mongodb::bson::doc;
+
+#[actix_rt::test]
+async fn test_info_routes() {
+    let app = test::init_service(
+        App::new()
+            .app_data(get_web_client().await)
+            .app_data(web::Data::new(get_config()))
+            .service(query::get_info)
+            .service(query::get_info_get)
+            .service(query::get_info_legacy),
+    )
+    .await;
+
+    // POST with a JSON body
+    let req = TestRequest::post()
+        .uri("/query/info")
+        .set_json(serde_json::json!({"command": "unknown"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    assert!(resp.headers().get("deprecation").is_none());
+
+    // GET with query string parameters
+    let req = TestRequest::get()
+        .uri("/query/info?command=unknown")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["message"], "Unknown command: unknown");
+
+    // legacy GET with a JSON body still works, flagged as deprecated
+    let req = TestRequest::get()
+        .uri("/query/info")
+        .set_json(serde_json::json!({"command": "unknown"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    assert_eq!(resp.headers().get("deprecation").unwrap(), "true");
+}
+
+#[test]
+fn test_find_params_into_body() {
+    let params: FindParams = serde_urlencoded::from_str(
+        "catalog=ZTF_alerts&filter=%7B%22candidate.drb%22%3A%7B%22%24gt%22%3A0.5%7D%7D&limit=5",
+    )
+    .unwrap();
+    let body = params.into_body().unwrap();
+    let query = body.query.unwrap();
+    assert_eq!(query.catalog.unwrap(), "ZTF_alerts");
+    assert_eq!(
+        query.filter.unwrap(),
+        doc! { "candidate.drb": { "$gt": 0.5 } }
+    );
+    assert_eq!(body.kwargs.unwrap().limit, Some(5));
+
+    // filter defaults to an empty document
+    let params: FindParams = serde_urlencoded::from_str("catalog=ZTF_alerts").unwrap();
+    assert_eq!(
+        params.into_body().unwrap().query.unwrap().filter.unwrap(),
+        doc! {}
+    );
+
+    let params: FindParams = serde_urlencoded::from_str("catalog=ZTF_alerts&filter=nope").unwrap();
+    assert!(params.into_body().is_err());
+}
+
+#[test]
+fn test_cone_search_params_into_body() {
+    let params: ConeSearchParams =
+        serde_urlencoded::from_str("catalog=NED&ra=202.366276&dec=11.006276&radius=1").unwrap();
+    let body = params.into_body().unwrap();
+    assert_eq!(body.radius, Some(1.0));
+    assert_eq!(
+        body.object_coordinates.unwrap().get("object"),
+        Some(&Coordinates::Degrees([202.366276, 11.006276]))
+    );
+    assert_eq!(body.catalog.unwrap().catalog_name.unwrap(), "NED");
+
+    let params: ConeSearchParams = serde_urlencoded::from_str(
+        "catalog=NED&ra=1&dec=2&radius=1&separation=true&nearest_only=true&k=3",
+    )
+    .unwrap();
+    let body = params.into_body().unwrap();
+    assert_eq!(body.separation, Some(true));
+    assert_eq!(body.nearest_only, Some(true));
+    assert_eq!(body.k, Some(3));
+
+    // sexagesimal coordinates are kept as strings, converted by the search
+    let params: ConeSearchParams = serde_urlencoded::from_str(
+        "catalog=NED&ra=13:29:27.9&dec=%2B11:00:22.6&frame=fk5&radius=1",
+    )
+    .unwrap();
+    let body = params.into_body().unwrap();
+    assert_eq!(body.frame, Some(Frame::Fk5));
+    assert_eq!(
+        body.object_coordinates.unwrap().get("object"),
+        Some(&Coordinates::Pair([
+            "13:29:27.9".to_string(),
+            "+11:00:22.6".to_string()
+        ]))
+    );
+
+    let params: ConeSearchParams = serde_urlencoded::from_str("catalog=NED&ra=1").unwrap();
+    assert!(params.into_body().is_err());
+}
+
+#[actix_rt::test]
+async fn test_region_search_validation() {
+    let cases = [
+        (
+            serde_json::json!({"catalog": {"catalog_name": "ZTF_alerts"}}),
+            "region required for region_search",
+        ),
+        (
+            serde_json::json!({
+                "region": {"type": "circle", "ra": 10.0, "dec": 10.0, "radius": 648000},
+                "unit": "Arcseconds",
+                "catalog": {"catalog_name": "ZTF_alerts"},
+            }),
+            "Invalid region: circle radius must be between 0 and 180 degrees",
+        ),
+        (
+            serde_json::json!({
+                "region": {"type": "box", "ra_min": 350, "ra_max": 10, "dec_min": -5, "dec_max": 5},
+                "catalog": {"catalog_name": "ZTF_alerts"},
+                "kwargs": {"paginate": true},
+            }),
+            "pagination is not supported by region_search",
+        ),
+        (
+            serde_json::json!({
+                "region": {"type": "polygon", "vertices": [[1, 1], [2, 1], [2, 2]]},
+                "catalog": {"catalog_name": "ZTF_alerts", "filter": {"$where": "1"}},
+            }),
+            "Invalid filter: operator $where is not allowed at catalog.filter.$where",
+        ),
+        (
+            serde_json::json!({
+                "region": {"type": "polygon", "vertices": [[1, 1], [2, 1], [2, 2]]},
+                "catalog": {"catalog_name": "filters"},
+            }),
+            "unknown catalog filters",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.service(query::region_search);
+        },
+        cases
+            .into_iter()
+            .map(|(body, message)| {
+                let req = TestRequest::post()
+                    .uri("/query/region_search")
+                    .set_json(body);
+                (req, message)
+            })
+            .collect(),
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_skymap_crossmatch_validation() {
+    let cases = [
+        (
+            serde_json::json!({"jd_min": 2460000.5}),
+            "skymap required for skymap_crossmatch",
+        ),
+        (
+            serde_json::json!({"skymap": {"path": "S250101a.fits"}, "credible_level": 90}),
+            "credible_level must be between 0 and 1",
+        ),
+        (
+            serde_json::json!({
+                "skymap": {"path": "S250101a.fits"},
+                "jd_min": 2460001.5,
+                "jd_max": 2460000.5,
+            }),
+            "jd_min must be smaller than jd_max",
+        ),
+        (
+            serde_json::json!({"skymap": {"path": "S250101a.fits"}}),
+            "Invalid skymap: skymap paths are not enabled on this server",
+        ),
+        (
+            serde_json::json!({"skymap": {}}),
+            "Invalid skymap: a skymap takes one of fits or path",
+        ),
+        (
+            serde_json::json!({"skymap": {"fits": "not base64!"}}),
+            "Invalid skymap: fits must be base64 encoded",
+        ),
+        (
+            serde_json::json!({
+                "skymap": {"path": "S250101a.fits"},
+                "kwargs": {"paginate": true},
+            }),
+            "pagination is not supported by skymap_crossmatch",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.service(query::skymap_crossmatch);
+        },
+        cases
+            .into_iter()
+            .map(|(body, message)| {
+                let req = TestRequest::post()
+                    .uri("/query/skymap_crossmatch")
+                    .set_json(body);
+                (req, message)
+            })
+            .collect(),
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_crossmatch_validation() {
+    let many = format!(
+        "ra,dec\n{}",
+        "1,2\n".repeat(crossmatch::MAX_SYNC_TARGETS + 1)
+    );
+    let cases = [
+        (
+            "/query/crossmatch?radius=2",
+            "ra,dec\n1,2\n",
+            "catalog required for crossmatch",
+        ),
+        (
+            "/query/crossmatch?catalog=ZTF_alerts&radius=-1",
+            "ra,dec\n1,2\n",
+            "radius must be a positive number",
+        ),
+        (
+            "/query/crossmatch?catalog=ZTF_alerts&radius=2&unit=Arcminutes",
+            "ra,dec\n1,2\n",
+            "radius must be at most 60 arcseconds",
+        ),
+        (
+            "/query/crossmatch?catalog=ZTF_alerts&radius=2&filter={\"$where\":\"1\"}",
+            "ra,dec\n1,2\n",
+            "Invalid filter: operator $where is not allowed at filter.$where",
+        ),
+        (
+            "/query/crossmatch?catalog=ZTF_alerts&radius=2",
+            "name,ra\nx,1\n",
+            "Invalid target list: missing column dec",
+        ),
+        (
+            "/query/crossmatch?catalog=ZTF_alerts&radius=2",
+            many.as_str(),
+            "lists of more than 5000 targets must be crossmatched with async=true",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.app_data(web::Data::new(jobs::JobRunner::new(1)))
+                .service(crossmatch::crossmatch);
+        },
+        cases
+            .into_iter()
+            .map(|(uri, body, message)| {
+                let req = TestRequest::post()
+                    .uri(&uri.replace('"', "%22"))
+                    .insert_header(("content-type", "text/csv"))
+                    .set_payload(body.to_string());
+                (req, message)
+            })
+            .collect(),
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_job_validation() {
+    let cases = [
+        (
+            serde_json::json!({}),
+            "a job takes one of find or aggregate",
+        ),
+        (
+            serde_json::json!({
+                "find": { "query": { "catalog": "ZTF_alerts", "filter": {} } },
+                "aggregate": { "catalog": "ZTF_alerts", "pipeline": [] },
+            }),
+            "a job takes one of find or aggregate",
+        ),
+        (
+            serde_json::json!({ "find": { "query": { "filter": {} } } }),
+            "catalog name required for find",
+        ),
+        (
+            serde_json::json!({ "find": { "query": { "catalog": "users", "filter": {} } } }),
+            "unknown catalog users",
+        ),
+        (
+            serde_json::json!({
+                "aggregate": {
+                    "catalog": "ZTF_alerts",
+                    "pipeline": [{ "$lookup": { "from": "jobs", "as": "jobs" } }],
+                }
+            }),
+            "Invalid pipeline: unknown catalog jobs (pipeline.0.$lookup.from)",
+        ),
+        (
+            serde_json::json!({
+                "find": { "query": { "catalog": "ZTF_alerts", "filter": { "$where": "1" } } }
+            }),
+            "Invalid filter: operator $where is not allowed at filter.$where",
+        ),
+        (
+            serde_json::json!({ "aggregate": { "catalog": "ZTF_alerts" } }),
+            "pipeline required for aggregate",
+        ),
+        (
+            serde_json::json!({
+                "find": {
+                    "query": { "catalog": "ZTF_alerts", "filter": {} },
+                    "kwargs": { "paginate": true },
+                }
+            }),
+            "pagination is not supported by jobs, their results are fetched by pages",
+        ),
+    ];
+    let mut requests: Vec<(TestRequest, &str)> = cases
+        .into_iter()
+        .map(|(body, message)| (TestRequest::post().uri("/jobs").set_json(body), message))
+        .collect();
+    for uri in ["/jobs/1/results?limit=0", "/jobs/1/results?limit=10001"] {
+        requests.push((
+            TestRequest::get().uri(uri),
+            "limit must be between 1 and 10000",
+        ));
+    }
+    assert_bad_requests(
+        |cfg| {
+            cfg.app_data(web::Data::new(jobs::JobRunner::new(1)))
+                .service(jobs::submit_job)
+                .service(jobs::get_job_results);
+        },
+        requests,
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_lightcurve_validation() {
+    let cases = [
+        (
+            "filters=ztfg,sdssu",
+            "unknown filter sdssu, expected one of ztfg, ztfr, ztfi",
+        ),
+        (
+            "jd_min=2460001&jd_max=2460000",
+            "jd_min must be smaller than jd_max",
+        ),
+        (
+            "format=parquet",
+            "parquet and arrow outputs are only supported by find and aggregate",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.service(lightcurve::get_lightcurve);
+        },
+        cases
+            .into_iter()
+            .map(|(query, message)| {
+                let uri = format!("/alerts/ztf/objects/ZTF21aaaaaaa/lightcurve?{}", query);
+                (TestRequest::get().uri(&uri), message)
+            })
+            .collect(),
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_filter_validation() {
+    let cases = [
+        (
+            serde_json::json!([{ "$match": {} }, { "$out": "stolen" }]),
+            "Invalid filter submitted: stage $out is not allowed (pipeline.1)",
+        ),
+        (
+            serde_json::json!([{ "$merge": { "into": "stolen" } }]),
+            "Invalid filter submitted: stage $merge is not allowed (pipeline.0)",
+        ),
+        (
+            serde_json::json!([{ "$lookup": { "from": "users", "as": "users" } }]),
+            "Invalid filter submitted: unknown catalog users (pipeline.0.$lookup.from)",
+        ),
+    ];
+    // both new filters and new versions are checked
+    let mut requests = Vec::new();
+    for (pipeline, message) in cases {
+        requests.push((
+            TestRequest::post()
+                .uri("/filters")
+                .set_json(serde_json::json!({
+                    "catalog": "ZTF",
+                    "id": 1,
+                    "permissions": [1],
+                    "pipeline": pipeline,
+                })),
+            message,
+        ));
+        requests.push((
+            TestRequest::patch()
+                .uri("/filters/1")
+                .set_json(serde_json::json!({ "pipeline": pipeline })),
+            message,
+        ));
+    }
+    assert_bad_requests(
+        |cfg| {
+            cfg.service(filters::post_filter)
+                .service(filters::add_filter_version);
+        },
+        requests,
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_cutout_validation() {
+    let cases = [
+        (
+            "/alerts/ptf/get_object/ZTF21aaaaaaa",
+            "unknown survey ptf, expected one of ZTF, LSST, DECAM",
+        ),
+        (
+            "/alerts/PTF/candid/2462000000015/cutout/science",
+            "unknown survey PTF, expected one of ZTF, LSST, DECAM",
+        ),
+        (
+            "/alerts/ztf/get_object/ZTF21aaaaaaa?include=cutouts,classifications",
+            "unknown include classifications, expected one of cutouts, prv_candidates, cross_matches, fp_hists",
+        ),
+        ("/alerts/ztf/candid/12a", "invalid candid 12a"),
+        (
+            "/alerts/ztf/candid/2462000000015?include=cutouts,fp_hists",
+            "unknown include fp_hists, expected one of cutouts, prv_candidates",
+        ),
+        (
+            "/alerts/ztf/candid/abc/cutout/science",
+            "invalid candid abc",
+        ),
+        (
+            "/alerts/ztf/candid/2462000000015/cutout/reference",
+            "unknown cutout reference, expected one of science, template, difference",
+        ),
+        (
+            "/alerts/ztf/candid/2462000000015/cutout/science?format=jpeg",
+            "unknown format jpeg, expected png or fits",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.service(alerts::get_cutout)
+                .service(alerts::get_alert)
+                .service(alerts::get_object);
+        },
+        cases
+            .into_iter()
+            .map(|(uri, message)| (TestRequest::get().uri(uri), message))
+            .collect(),
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_coverage_validation() {
+    let cases = [
+        (
+            "/query/coverage/ZTF_alerts?order=13",
+            "order must be at most 12",
+        ),
+        (
+            "/query/coverage/ZTF_alerts?format=xml",
+            "unknown format xml, expected json or fits",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.app_data(web::Data::new(query::CoverageCache::default()))
+                .service(query::coverage);
+        },
+        cases
+            .into_iter()
+            .map(|(uri, message)| (TestRequest::get().uri(uri), message))
+            .collect(),
+    )
+    .await;
+}
+
+#[actix_rt::test]
+async fn test_cone_search_catalogs() {
+    let search = serde_json::json!({
+        "radius": 2.0,
+        "unit": "Arcseconds",
+        "object_coordinates": {"target": [150.0, 2.2]},
+    });
+    let cases = [
+        (
+            serde_json::json!({
+                "catalog": {"catalog_name": "ZTF_alerts"},
+                "catalogs": {"PS1_DR1": {}},
+            }),
+            "catalog and catalogs cannot be used together",
+        ),
+        (
+            serde_json::json!({"catalogs": {}}),
+            "catalog(s) required for cone_search",
+        ),
+        (
+            serde_json::json!({
+                "catalogs": {"PS1_DR1": {}, "ZTF_alerts": {}},
+                "kwargs": {"paginate": true},
+            }),
+            "pagination is only supported on a single catalog",
+        ),
+        (
+            serde_json::json!({
+                "catalogs": {"PS1_DR1": {}, "Gaia_EDR3": {"filter": {"$where": "1"}}},
+            }),
+            "Invalid filter: operator $where is not allowed at catalogs.Gaia_EDR3.filter.$where",
+        ),
+        (
+            serde_json::json!({
+                "catalog": {"catalog_name": "ZTF_alerts"},
+                "object_coordinates": {"target": [150.0, 91.0]},
+            }),
+            "Invalid coordinates for target: dec must be between -90 and 90 degrees, got 91",
+        ),
+        (
+            serde_json::json!({
+                "catalog": {"catalog_name": "ZTF_alerts"},
+                "object_coordinates": {"target": "25:00:00 +10:00:00"},
+            }),
+            "Invalid coordinates for target: invalid ra \"25:00:00\", hours must be below 24",
+        ),
+        (
+            serde_json::json!({"catalog": {"catalog_name": "ZTF_alerts"}, "k": 0}),
+            "k must be a positive integer",
+        ),
+        (
+            serde_json::json!({
+                "catalog": {"catalog_name": "ZTF_alerts"},
+                "nearest_only": true,
+                "kwargs": {"paginate": true},
+            }),
+            "pagination cannot be combined with sort_by_separation, nearest_only or k",
+        ),
+    ];
+    assert_bad_requests(
+        |cfg| {
+            cfg.service(query::cone_search);
+        },
+        cases
+            .into_iter()
+            .map(|(extra, message)| {
+                let mut body = search.clone();
+                body.as_object_mut()
+                    .unwrap()
+                    .extend(extra.as_object().unwrap().clone());
+                let req = TestRequest::post().uri("/query/cone_search").set_json(body);
+                (req, message)
+            })
+            .collect(),
+    )
+    .await;
+}
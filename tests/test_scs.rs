@@ -0,0 +1,114 @@
+mod common;
+
+use actix_web::{
+    test::{self, TestRequest},
+    web, App, HttpMessage,
+};
+use boom_api::{
+    api::scs::{scs_capabilities, scs_query},
+    models::query_models::{ScsParams, ScsQuery},
+};
+use common::{get_config, test_user};
+
+fn params(ra: Option<&str>, dec: Option<&str>, sr: Option<&str>, verb: Option<&str>) -> ScsParams {
+    ScsParams {
+        ra: ra.map(String::from),
+        dec: dec.map(String::from),
+        sr: sr.map(String::from),
+        verb: verb.map(String::from),
+    }
+}
+
+#[test]
+fn test_scs_params() {
+    assert_eq!(
+        params(Some("10.5"), Some("-20"), Some("0.01"), None).into_query(1.0),
+        Ok(ScsQuery {
+            ra: 10.5,
+            dec: -20.0,
+            sr: 0.01,
+            verb: 2
+        })
+    );
+    let query = params(Some("10"), Some("20"), Some("0"), Some("3"))
+        .into_query(1.0)
+        .unwrap();
+    assert_eq!(query.verb, 3);
+
+    assert!(params(None, Some("20"), Some("0.1"), None)
+        .into_query(1.0)
+        .unwrap_err()
+        .contains("RA is required"));
+    assert!(params(Some("abc"), Some("20"), Some("0.1"), None)
+        .into_query(1.0)
+        .is_err());
+    assert!(params(Some("10"), Some("91"), Some("0.1"), None)
+        .into_query(1.0)
+        .is_err());
+    assert!(params(Some("10"), Some("20"), Some("2"), None)
+        .into_query(1.0)
+        .unwrap_err()
+        .contains("SR must be between 0 and 1"));
+    assert!(params(Some("10"), Some("20"), Some("0.1"), Some("4"))
+        .into_query(1.0)
+        .is_err());
+}
+
+#[actix_rt::test]
+async fn test_scs_capabilities() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_config()))
+            .service(scs_capabilities),
+    )
+    .await;
+    let req = TestRequest::get()
+        .uri("/scs/ZTF_alerts/capabilities")
+        .insert_header(("Host", "boom.example.org"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("standardID=\"ivo://ivoa.net/std/ConeSearch\""));
+    assert!(body
+        .contains("<accessURL use=\"base\">http://boom.example.org/scs/ZTF_alerts?</accessURL>"));
+    assert!(body.contains("<maxSR>1</maxSR>"));
+    assert!(body.contains("<maxRecords>10000</maxRecords>"));
+
+    let req = TestRequest::get()
+        .uri("/scs/ZTF_alerts_aux/capabilities")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_rt::test]
+async fn test_scs_errors_are_votables() {
+    let client = mongodb::Client::with_uri_str("mongodb://localhost:27017")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_config()))
+            .app_data(web::Data::new(client))
+            .service(scs_query),
+    )
+    .await;
+    for uri in [
+        "/scs/ZTF_alerts_aux?RA=10&DEC=20&SR=0.1",
+        "/scs/ZTF_alerts?RA=10&DEC=20",
+        "/scs/ZTF_alerts?RA=10&DEC=20&SR=5",
+    ] {
+        let req = TestRequest::get().uri(uri).to_request();
+        req.extensions_mut().insert(test_user());
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/x-votable+xml"
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("<INFO name=\"QUERY_STATUS\" value=\"ERROR\">"));
+        assert!(body.contains("<INFO ID=\"Error\" name=\"Error\""));
+    }
+}
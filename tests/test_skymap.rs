@@ -0,0 +1,180 @@
+use boom_api::{
+    formats::fits::{number_card, string_card, write_table, BinTable, TableColumn},
+    spatial::{healpix, skymap::Skymap},
+};
+
+// multi-order skymap of the base pixels, the fourth being split in 4 cells of
+// decreasing densities holding all of the probability
+fn skymap_fits(ordering: &str) -> Vec<u8> {
+    let mut uniqs = Vec::new();
+    let mut densities = Vec::new();
+    for pixel in (0..12).filter(|pixel| *pixel != 4) {
+        uniqs.push(healpix::uniq(0, pixel) as i64);
+        densities.push(0.0);
+    }
+    for (pixel, density) in [(16, 8.0), (17, 4.0), (18, 2.0), (19, 1.0)] {
+        uniqs.push(healpix::uniq(1, pixel) as i64);
+        densities.push(density);
+    }
+    write_table(
+        &[
+            ("UNIQ", TableColumn::Int(&uniqs)),
+            ("PROBDENSITY", TableColumn::Double(&densities)),
+        ],
+        vec![string_card("ORDERING", ordering)],
+    )
+}
+
+#[test]
+fn test_skymap_credible_levels() {
+    let skymap = Skymap::from_fits(&skymap_fits("NUNIQ")).unwrap();
+
+    let (ra, dec) = healpix::center(1, 16);
+    let (density, level) = skymap.at(ra, dec).unwrap();
+    assert_eq!(density, 8.0);
+    assert!((level - 8.0 / 15.0).abs() < 1e-12);
+    let (ra, dec) = healpix::center(1, 19);
+    assert_eq!(skymap.at(ra, dec).map(|(d, _)| d), Some(1.0));
+    let (ra, dec) = healpix::center(0, 0);
+    let (density, level) = skymap.at(ra, dec).unwrap();
+    assert_eq!(density, 0.0);
+    assert!((level - 1.0).abs() < 1e-12);
+
+    // the densest cells up to the level, the last one crossing it included
+    let region = skymap.credible_region(0.5).unwrap();
+    assert_eq!(region.cells(), vec![(1, 16)]);
+    let region = skymap.credible_region(0.75).unwrap();
+    assert_eq!(region.cells(), vec![(1, 16), (1, 17)]);
+    let region = skymap.credible_region(0.9).unwrap();
+    assert_eq!(region.cells(), vec![(1, 16), (1, 17), (1, 18)]);
+    let region = skymap.credible_region(0.95).unwrap();
+    assert_eq!(region.cells(), vec![(0, 4)]);
+    // cells without probability are never needed
+    let region = skymap.credible_region(1.0).unwrap();
+    assert_eq!(region.cells(), vec![(0, 4)]);
+    assert!((region.sky_fraction() - 1.0 / 12.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_skymap_errors() {
+    assert!(Skymap::from_fits(b"not a fits file").is_err());
+    assert_eq!(
+        Skymap::from_fits(&[0x1f, 0x8b, 0x08, 0x00]).unwrap_err(),
+        "gzip compressed skymaps are not supported"
+    );
+    assert_eq!(
+        Skymap::from_fits(&skymap_fits("NESTED")).unwrap_err(),
+        "unsupported skymap ordering NESTED, expected a multi-order (NUNIQ) skymap"
+    );
+
+    let uniqs = [healpix::uniq(0, 4) as i64, healpix::uniq(1, 17) as i64];
+    let fits = write_table(&[("UNIQ", TableColumn::Int(&uniqs))], vec![]);
+    assert_eq!(
+        Skymap::from_fits(&fits).unwrap_err(),
+        "missing column PROBDENSITY"
+    );
+    let fits = write_table(
+        &[
+            ("UNIQ", TableColumn::Int(&uniqs)),
+            ("PROBDENSITY", TableColumn::Double(&[1.0, 1.0])),
+        ],
+        vec![],
+    );
+    assert_eq!(
+        Skymap::from_fits(&fits).unwrap_err(),
+        "skymap cells overlap"
+    );
+    let fits = write_table(
+        &[
+            ("UNIQ", TableColumn::Int(&uniqs[..1])),
+            ("PROBDENSITY", TableColumn::Double(&[0.0])),
+        ],
+        vec![],
+    );
+    assert_eq!(
+        Skymap::from_fits(&fits).unwrap_err(),
+        "skymap has no probability"
+    );
+}
+
+// replaces a header card of a FITS file, both being 80 characters long
+fn replace_card(fits: &[u8], card: String, replacement: String) -> Vec<u8> {
+    let start = fits
+        .windows(card.len())
+        .position(|window| window == card.as_bytes())
+        .unwrap();
+    let mut fits = fits.to_vec();
+    fits[start..start + card.len()].copy_from_slice(replacement.as_bytes());
+    fits
+}
+
+#[test]
+fn test_malformed_tables() {
+    let fits = skymap_fits("NUNIQ");
+    let naxis1 = number_card("NAXIS1", 16);
+    let tform1 = string_card("TFORM1", "1K");
+    for (card, replacement, message) in [
+        (
+            &naxis1,
+            number_card("NAXIS1", 0),
+            "invalid FITS keyword NAXIS1",
+        ),
+        // the second column would be read past the end of the rows
+        (
+            &naxis1,
+            number_card("NAXIS1", 12),
+            "invalid FITS keyword NAXIS1",
+        ),
+        (&tform1, string_card("TFORM1", "0K"), "invalid TFORM 0K"),
+        (
+            &tform1,
+            string_card("TFORM1", "2305843009213693952K"),
+            "invalid TFORM 2305843009213693952K",
+        ),
+        (
+            &number_card("NAXIS2", 15),
+            number_card("NAXIS2", usize::MAX),
+            "truncated FITS data",
+        ),
+    ] {
+        let fits = replace_card(&fits, card.clone(), replacement);
+        assert_eq!(
+            BinTable::from_fits(&fits).err().as_deref(),
+            Some(message),
+            "{}",
+            card
+        );
+    }
+}
+
+#[test]
+fn test_skymap_path() {
+    let directory = std::env::temp_dir().join(format!("boom-skymaps-{}", std::process::id()));
+    std::fs::create_dir_all(directory.join("S250101a")).unwrap();
+    std::fs::write(
+        directory.join("S250101a/bayestar.multiorder.fits"),
+        skymap_fits("NUNIQ"),
+    )
+    .unwrap();
+    let root = directory.to_str().unwrap();
+
+    let skymap = Skymap::from_path(root, "S250101a/bayestar.multiorder.fits").unwrap();
+    let (ra, dec) = healpix::center(1, 17);
+    assert_eq!(skymap.at(ra, dec).map(|(d, _)| d), Some(4.0));
+    assert_eq!(
+        Skymap::from_path(root, "S250101a/missing.fits").unwrap_err(),
+        "skymap S250101a/missing.fits not found"
+    );
+    // `..` may be used within the directory, but files outside of it are
+    // not reachable
+    assert!(Skymap::from_path(root, "S250101a/../S250101a/bayestar.multiorder.fits").is_ok());
+    let events = format!("{}/S250101a", root);
+    std::fs::write(directory.join("outside.fits"), skymap_fits("NUNIQ")).unwrap();
+    assert!(Skymap::from_path(root, "outside.fits").is_ok());
+    assert_eq!(
+        Skymap::from_path(&events, "../outside.fits").unwrap_err(),
+        "skymap ../outside.fits not found"
+    );
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
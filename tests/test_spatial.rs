@@ -0,0 +1,140 @@
+use boom_api::{
+    models::query_models::Unit,
+    spatial::{
+        angular_distance, degrees_to, document_position, projection_with_position, Separation,
+    },
+};
+use mongodb::bson::{doc, Document};
+
+// a document at (ra, dec), positioned like the catalogs
+fn at(name: &str, ra: f64, dec: f64) -> Document {
+    doc! {
+        "name": name,
+        "coordinates": { "radec_geojson": { "type": "Point", "coordinates": [ra - 180.0, dec] } },
+    }
+}
+
+#[test]
+fn test_angular_distance() {
+    assert!((angular_distance(10.0, 0.0, 11.0, 0.0) - 1.0).abs() < 1e-9);
+    assert!((angular_distance(0.0, 90.0, 123.0, 89.0) - 1.0).abs() < 1e-9);
+    assert!((angular_distance(359.5, 0.0, 0.5, 0.0) - 1.0).abs() < 1e-9);
+    // accurate at small separations
+    let arcsec = 1.0 / 3600.0;
+    assert!((angular_distance(150.0, 2.0, 150.0, 2.0 + arcsec) - arcsec).abs() < 1e-12);
+
+    assert_eq!(degrees_to(1.5, &Unit::Arcseconds), 5400.0);
+    assert_eq!(degrees_to(1.5, &Unit::Arcminutes), 90.0);
+    assert_eq!(degrees_to(1.5, &Unit::Degrees), 1.5);
+    assert!((degrees_to(180.0, &Unit::Radians) - std::f64::consts::PI).abs() < 1e-12);
+
+    assert_eq!(document_position(&at("a", 10.0, -5.0)), Some((10.0, -5.0)));
+    assert_eq!(document_position(&doc! { "ra": 10.0 }), None);
+}
+
+#[test]
+fn test_projection_with_position() {
+    assert_eq!(projection_with_position(None), (None, None));
+    assert_eq!(
+        projection_with_position(Some(doc! { "name": 1 })),
+        (
+            Some(doc! { "name": 1, "coordinates.radec_geojson": 1 }),
+            Some("coordinates.radec_geojson".to_string())
+        )
+    );
+    assert_eq!(
+        projection_with_position(Some(doc! { "name": 1, "coordinates": 1 })),
+        (Some(doc! { "name": 1, "coordinates": 1 }), None)
+    );
+    // exclusions of the position are lifted, and applied to the results
+    assert_eq!(
+        projection_with_position(Some(doc! { "_id": 0, "coordinates": 0 })),
+        (Some(doc! { "_id": 0 }), Some("coordinates".to_string()))
+    );
+    assert_eq!(
+        projection_with_position(Some(doc! { "coordinates.radec_geojson": 0 })),
+        (None, Some("coordinates.radec_geojson".to_string()))
+    );
+    assert_eq!(
+        projection_with_position(Some(doc! { "_id": 0, "cutout": 0 })),
+        (Some(doc! { "_id": 0, "cutout": 0 }), None)
+    );
+}
+
+#[test]
+fn test_separation() {
+    let center = (150.0, 2.0);
+    let documents = vec![
+        at("far", 150.0, 2.0 + 3.0 / 3600.0),
+        doc! { "name": "nowhere" },
+        at("near", 150.0, 2.0 + 1.0 / 3600.0),
+        at("middle", 150.0, 2.0 - 2.0 / 3600.0),
+    ];
+    let names = |documents: &[Document]| -> Vec<String> {
+        documents
+            .iter()
+            .map(|d| d.get_str("name").unwrap().to_string())
+            .collect()
+    };
+
+    // annotated in place, in the requested unit
+    let separation = Separation {
+        unit: Unit::Arcseconds,
+        sort: false,
+        k: None,
+        strip: None,
+    };
+    let annotated = separation.apply(center, documents.clone());
+    assert_eq!(names(&annotated), vec!["far", "nowhere", "near", "middle"]);
+    assert!((annotated[0].get_f64("separation").unwrap() - 3.0).abs() < 1e-6);
+    assert!(!annotated[1].contains_key("separation"));
+    assert!(annotated[2].contains_key("coordinates"));
+
+    // sorted, matches without a position last
+    let separation = Separation {
+        sort: true,
+        ..separation
+    };
+    let sorted = separation.apply(center, documents.clone());
+    assert_eq!(names(&sorted), vec!["near", "middle", "far", "nowhere"]);
+
+    // k closest, without the position that was not requested
+    let separation = Separation {
+        sort: false,
+        k: Some(2),
+        strip: Some("coordinates.radec_geojson".to_string()),
+        ..separation
+    };
+    let nearest = separation.apply(center, documents);
+    assert_eq!(names(&nearest), vec!["near", "middle"]);
+    assert!(!nearest[0].contains_key("coordinates"));
+    assert!((nearest[1].get_f64("separation").unwrap() - 2.0).abs() < 1e-6);
+}
+
+#[actix_rt::test]
+async fn test_separation_collects_k_closest() {
+    let center = (150.0, 2.0);
+    // the closest matches come last, after more than k others
+    let documents: Vec<Result<Document, String>> = (0..100)
+        .rev()
+        .map(|i| Ok(at(&i.to_string(), 150.0, 2.0 + i as f64 / 3600.0)))
+        .collect();
+    let separation = Separation {
+        unit: Unit::Arcseconds,
+        sort: false,
+        k: Some(3),
+        strip: None,
+    };
+    let nearest = separation
+        .collect(center, futures::stream::iter(documents))
+        .await
+        .unwrap();
+    let names: Vec<&str> = nearest.iter().map(|d| d.get_str("name").unwrap()).collect();
+    assert_eq!(names, vec!["0", "1", "2"]);
+
+    let failing = futures::stream::iter(vec![Ok(at("a", 150.0, 2.0)), Err("boom".to_string())]);
+    assert_eq!(
+        separation.collect(center, failing).await.unwrap_err(),
+        "boom"
+    );
+}
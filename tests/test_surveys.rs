@@ -0,0 +1,61 @@
+use boom_api::surveys::{collection_survey, filter_survey, survey, Decam, Lsst, Survey, Ztf};
+use mongodb::bson::doc;
+
+#[test]
+fn test_survey_registry() {
+    // names are case insensitive
+    for name in ["ZTF", "ztf", "Ztf"] {
+        assert_eq!(survey(name).unwrap().name(), "ZTF");
+    }
+    assert_eq!(survey("lsst").unwrap().name(), "LSST");
+    assert_eq!(survey("DECam").unwrap().name(), "DECAM");
+    // only the surveys whose alerts are stored are known
+    assert_eq!(
+        survey("PTF").err().unwrap(),
+        "unknown survey PTF, expected one of ZTF, LSST, DECAM"
+    );
+
+    let ztf = survey("ztf").unwrap();
+    assert_eq!(ztf.alerts_collection(), "ZTF_alerts");
+    assert_eq!(ztf.aux_collection(), "ZTF_alerts_aux");
+    assert_eq!(ztf.time_field(), "candidate.jd");
+    assert_eq!(ztf.permissions_field(), Some("programid"));
+    assert_eq!(Ztf.cutout_field("difference"), Some("cutoutDifference"));
+    assert_eq!(Ztf.cutout_field("reference"), None);
+    assert_eq!(ztf.band(&doc! { "fid": 2 }), Some("ztfr"));
+    assert_eq!(ztf.band(&doc! { "fid": 4 }), None);
+
+    let lsst = survey("lsst").unwrap();
+    assert_eq!(lsst.alerts_collection(), "LSST_alerts");
+    assert_eq!(lsst.permissions_field(), None);
+    assert_eq!(Lsst.band(&doc! { "band": "y" }), Some("lssty"));
+    assert_eq!(Lsst.band(&doc! { "band": "x" }), None);
+    assert_eq!(Lsst.band(&doc! { "fid": 1 }), None);
+    assert_eq!(Decam.band(&doc! { "band": "Y" }), Some("desy"));
+    assert_eq!(Decam.band(&doc! { "band": "u" }), None);
+}
+
+#[test]
+fn test_collection_survey() {
+    for collection in ["ZTF_alerts", "ZTF_alerts_aux"] {
+        assert_eq!(collection_survey(collection).unwrap().name(), "ZTF");
+    }
+    assert_eq!(
+        collection_survey("DECAM_alerts_aux").unwrap().name(),
+        "DECAM"
+    );
+    assert!(collection_survey("ZTF").is_none());
+    assert!(collection_survey("NED").is_none());
+}
+
+#[test]
+fn test_filter_survey() {
+    // filters name their survey, or its alerts collection before surveys
+    for catalog in ["ZTF", "ztf", "ZTF_alerts"] {
+        assert_eq!(filter_survey(catalog).unwrap().name(), "ZTF");
+    }
+    assert_eq!(
+        filter_survey("ZTF_alerts_aux").err().unwrap(),
+        "unknown survey ZTF_alerts_aux, expected one of ZTF, LSST, DECAM"
+    );
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use actix_web::{
+    test::{self, TestRequest},
+    web, App, HttpMessage,
+};
+use boom_api::{
+    adql::parse_adql,
+    api::tap::{
+        matches_filter, resolve_catalog, tap_capabilities, tap_schema_documents, tap_sync,
+        tap_sync_get, write_tableset, SchemaColumn, SchemaTable,
+    },
+    formats::{table::Datatype, OutputFormat},
+    models::query_models::TapQuery,
+};
+use common::{get_config, test_user};
+use mongodb::bson::doc;
+use std::collections::HashMap;
+
+fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn schema() -> Vec<SchemaTable> {
+    vec![SchemaTable {
+        schema: "boom".to_string(),
+        name: "ZTF_alerts".to_string(),
+        columns: vec![
+            SchemaColumn {
+                name: "objectId".to_string(),
+                datatype: Datatype::Char,
+                unit: None,
+                indexed: true,
+            },
+            SchemaColumn {
+                name: "candidate.magpsf".to_string(),
+                datatype: Datatype::Double,
+                unit: Some("mag".to_string()),
+                indexed: false,
+            },
+        ],
+    }]
+}
+
+#[test]
+fn test_tap_params() {
+    let query = TapQuery::from_params(&params(&[
+        ("lang", "ADQL"),
+        ("Query", "SELECT * FROM ZTF_alerts"),
+        ("MAXREC", "100"),
+        ("responseformat", "csv"),
+    ]))
+    .unwrap();
+    assert_eq!(
+        query,
+        TapQuery {
+            query: "SELECT * FROM ZTF_alerts".to_string(),
+            format: OutputFormat::Csv,
+            maxrec: Some(100),
+        }
+    );
+    let query =
+        TapQuery::from_params(&params(&[("LANG", "ADQL-2.0"), ("QUERY", "SELECT 1")])).unwrap();
+    assert_eq!(query.format, OutputFormat::Votable);
+    assert_eq!(query.maxrec, None);
+
+    for invalid in [
+        params(&[("QUERY", "SELECT * FROM t")]),
+        params(&[("LANG", "PQL"), ("QUERY", "SELECT * FROM t")]),
+        params(&[("LANG", "ADQL")]),
+        params(&[
+            ("LANG", "ADQL"),
+            ("QUERY", "SELECT * FROM t"),
+            ("MAXREC", "-1"),
+        ]),
+        params(&[
+            ("LANG", "ADQL"),
+            ("QUERY", "SELECT * FROM t"),
+            ("FORMAT", "fits"),
+        ]),
+        params(&[
+            ("REQUEST", "getCapabilities"),
+            ("LANG", "ADQL"),
+            ("QUERY", "q"),
+        ]),
+    ] {
+        assert!(TapQuery::from_params(&invalid).is_err());
+    }
+}
+
+#[test]
+fn test_tap_schema() {
+    let tables = schema();
+    let rows = tap_schema_documents(&tables, "columns").unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get_str("table_name").unwrap(), "boom.ZTF_alerts");
+    assert_eq!(rows[0].get_str("arraysize").unwrap(), "*");
+    assert_eq!(rows[0].get_i32("indexed").unwrap(), 1);
+    assert_eq!(rows[1].get_str("datatype").unwrap(), "double");
+    assert_eq!(rows[1].get_str("unit").unwrap(), "mag");
+    assert_eq!(tap_schema_documents(&tables, "schemas").unwrap().len(), 1);
+    assert!(tap_schema_documents(&tables, "keys").unwrap().is_empty());
+    assert!(tap_schema_documents(&tables, "nope").is_none());
+
+    // TAP_SCHEMA is queried in memory with the translated filters
+    for (adql, expected) in [
+        (
+            "SELECT * FROM TAP_SCHEMA.columns WHERE datatype = 'double'",
+            1,
+        ),
+        (
+            "SELECT * FROM TAP_SCHEMA.columns WHERE column_name LIKE 'cand%'",
+            1,
+        ),
+        (
+            "SELECT * FROM TAP_SCHEMA.columns WHERE column_name ILIKE 'OBJECT_D'",
+            1,
+        ),
+        (
+            "SELECT * FROM TAP_SCHEMA.columns WHERE column_index >= 0 AND NOT indexed = 1",
+            1,
+        ),
+        (
+            "SELECT * FROM TAP_SCHEMA.columns WHERE unit IS NULL OR unit IN ('mag')",
+            2,
+        ),
+        (
+            "SELECT * FROM TAP_SCHEMA.columns WHERE table_name <> 'boom.ZTF_alerts'",
+            0,
+        ),
+    ] {
+        let filter = parse_adql(adql).unwrap().filter;
+        let matched = rows
+            .iter()
+            .filter(|row| matches_filter(row, &filter))
+            .count();
+        assert_eq!(matched, expected, "{}", adql);
+    }
+
+    let tableset = write_tableset(&tables);
+    assert!(tableset.contains("<schema>\n<name>boom</name>"));
+    assert!(tableset.contains("<name>boom.ZTF_alerts</name>"));
+    assert!(tableset.contains(
+        "<dataType xsi:type=\"vs:VOTableType\" arraysize=\"*\">char</dataType>\n<flag>indexed</flag>"
+    ));
+    assert!(tableset.contains("<unit>mag</unit>"));
+}
+
+#[test]
+fn test_resolve_catalog() {
+    let catalogs = vec!["ZTF_alerts".to_string(), "ZTF_alerts_aux".to_string()];
+    let table = |name: &str| name.split('.').map(String::from).collect::<Vec<String>>();
+    assert_eq!(
+        resolve_catalog(&table("ztf_alerts"), "boom", &catalogs),
+        Ok("ZTF_alerts".to_string())
+    );
+    assert_eq!(
+        resolve_catalog(&table("boom.ZTF_alerts_aux"), "boom", &catalogs),
+        Ok("ZTF_alerts_aux".to_string())
+    );
+    assert!(resolve_catalog(&table("other.ZTF_alerts"), "boom", &catalogs).is_err());
+    assert!(resolve_catalog(&table("ZTF_missing"), "boom", &catalogs).is_err());
+}
+
+#[actix_rt::test]
+async fn test_tap_capabilities() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_config()))
+            .service(tap_capabilities),
+    )
+    .await;
+    let req = TestRequest::get()
+        .uri("/tap/capabilities")
+        .insert_header(("Host", "boom.example.org"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("standardID=\"ivo://ivoa.net/std/TAP\""));
+    assert!(body.contains("<accessURL use=\"base\">http://boom.example.org/tap</accessURL>"));
+    assert!(body
+        .contains("<accessURL use=\"full\">http://boom.example.org/tap/availability</accessURL>"));
+    assert!(body.contains("<name>ADQL</name>"));
+    assert!(body.contains("<default unit=\"row\">10000</default>"));
+}
+
+#[actix_rt::test]
+async fn test_tap_errors_are_votables() {
+    let client = mongodb::Client::with_uri_str("mongodb://localhost:27017")
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(get_config()))
+            .app_data(web::Data::new(client))
+            .service(tap_sync)
+            .service(tap_sync_get),
+    )
+    .await;
+    let user = test_user();
+    let requests = [
+        TestRequest::get().uri("/tap/sync?QUERY=SELECT%20*%20FROM%20ZTF_alerts"),
+        TestRequest::get().uri("/tap/sync?LANG=ADQL&QUERY=SELECT%20DISTINCT%20a%20FROM%20t"),
+        TestRequest::post().uri("/tap/sync").set_form(params(&[
+            ("LANG", "ADQL"),
+            ("QUERY", "SELECT * FROM TAP_SCHEMA.tables WHERE"),
+        ])),
+    ];
+    for req in requests {
+        let req = req.to_request();
+        req.extensions_mut().insert(user.clone());
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/x-votable+xml"
+        );
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.contains("<INFO name=\"QUERY_STATUS\" value=\"ERROR\">"));
+    }
+}
+
+#[test]
+fn test_tap_schema_filter_types() {
+    // comparisons between numbers of different types
+    let row = doc! { "column_index": 3, "size": 1.5 };
+    let filter = parse_adql("SELECT * FROM t WHERE column_index > 2.5 AND size < 2")
+        .unwrap()
+        .filter;
+    assert!(matches_filter(&row, &filter));
+}
@@ -0,0 +1,61 @@
+use boom_api::api::validation::{
+    validate_document, validate_documents, PIPELINE_RULES, QUERY_RULES,
+};
+use mongodb::bson::{doc, Bson, Document};
+
+#[test]
+fn test_valid_filter() {
+    let filter = doc! {
+        "candidate.drb": { "$gt": 0.5 },
+        "$or": [{ "candidate.fid": 1 }, { "candidate.magpsf": { "$lt": 18 } }]
+    };
+    assert!(validate_document(&filter, "filter", &QUERY_RULES).is_ok());
+}
+
+#[test]
+fn test_forbidden_operators() {
+    let filter = doc! { "$or": [{ "a": 1 }, { "$where": "sleep(100)" }] };
+    assert_eq!(
+        validate_document(&filter, "filter", &QUERY_RULES).unwrap_err(),
+        "operator $where is not allowed at filter.$or.1.$where"
+    );
+
+    let filter = doc! { "$expr": { "$gt": ["$a", "$b"] } };
+    assert!(validate_document(&filter, "filter", &QUERY_RULES).is_err());
+    // $expr is fine in pipelines
+    let pipeline = vec![doc! { "$match": filter }];
+    assert!(validate_documents(&pipeline, "pipeline", &PIPELINE_RULES).is_ok());
+
+    let pipeline = vec![
+        doc! { "$match": {} },
+        doc! {
+            "$group": {
+                "_id": null,
+                "x": { "$accumulator": { "init": "function() {}" } }
+            }
+        },
+    ];
+    assert_eq!(
+        validate_documents(&pipeline, "pipeline", &PIPELINE_RULES).unwrap_err(),
+        "operator $accumulator is not allowed at pipeline.1.$group.x.$accumulator"
+    );
+
+    let filter = doc! { "a": Bson::JavaScriptCode("1".to_string()) };
+    assert!(validate_document(&filter, "filter", &QUERY_RULES).is_err());
+}
+
+#[test]
+fn test_max_depth() {
+    let mut filter = doc! { "a": 1 };
+    for _ in 0..QUERY_RULES.max_depth {
+        filter = doc! { "$and": [filter] };
+    }
+    let error = validate_document(&filter, "filter", &QUERY_RULES).unwrap_err();
+    assert!(error.starts_with("maximum nesting depth"), "{}", error);
+
+    let mut filter = Document::new();
+    for _ in 0..(QUERY_RULES.max_depth - 1) {
+        filter = doc! { "a": filter };
+    }
+    assert!(validate_document(&filter, "filter", &QUERY_RULES).is_ok());
+}